@@ -29,7 +29,7 @@ impl Dex {
     }
 
     // Parse logs and extract pools
-    pub fn new_pool_from_event(&self, log: Log, provider: Arc<Provider<Ws>>) -> Option<Pool> {
+    pub fn new_pool_from_event<M: Middleware>(&self, log: Log, provider: Arc<M>) -> Option<Pool> {
         match self.pool_variant {
             PoolVariant::UniswapV2 => {
                 let uniswap_v2_factory = UniswapV2Factory::new(self.factory_address, provider);
@@ -89,9 +89,9 @@ impl Dex {
 }
 
 // get all pairs for a given dex between `start_block` and `current_block`
-pub async fn sync_dex(
+pub async fn sync_dex<M: Middleware + 'static>(
     dexes: Vec<Dex>,
-    client: &Arc<Provider<Ws>>,
+    client: &Arc<M>,
     current_block: U64,
     start_block: Option<BlockNumber>,
 ) -> Result<Vec<Pool>, PairSyncError> {
@@ -147,9 +147,9 @@ pub async fn sync_dex(
 }
 
 /// function to get all pair created events for a given Dex factory address
-async fn get_all_pools(
+async fn get_all_pools<M: Middleware + 'static>(
     dex: Dex,
-    provider: Arc<Provider<Ws>>,
+    provider: Arc<M>,
     current_block: BlockNumber,
     start_block: Option<BlockNumber>,
     progress_bar: ProgressBar,
@@ -195,7 +195,8 @@ async fn get_all_pools(
                         .from_block(BlockNumber::Number(U64([from_block])))
                         .to_block(BlockNumber::Number(U64([to_block]))),
                 )
-                .await?;
+                .await
+                .map_err(|e| PairSyncError::MiddlewareError(Box::new(e)))?;
 
             // increment the progres bar by the step
             progress_bar.inc(step as u64);
@@ -208,7 +209,7 @@ async fn get_all_pools(
                 }
             }
 
-            Ok::<Vec<Pool>, ProviderError>(pools)
+            Ok::<Vec<Pool>, PairSyncError>(pools)
         }));
     }
 
@@ -3,3 +3,6 @@ pub use dex::*;
 
 pub mod pool;
 pub use pool::*;
+
+pub mod pool_state;
+pub use pool_state::*;
@@ -14,7 +14,10 @@ pub struct Pool {
     pub token_1: Address,
     pub swap_fee: U256,
     pub pool_variant: PoolVariant,
-    pub has_dust: bool
+    pub has_dust: bool,
+    // pruned by pool ranking, victims on ignored pools are not simulated
+    #[serde(default)]
+    pub ignored: bool,
 }
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
@@ -38,6 +41,7 @@ impl Pool {
             (token_b, token_a)
         };
         let has_dust = false;
+        let ignored = false;
 
         Pool {
             address,
@@ -45,7 +49,8 @@ impl Pool {
             token_1,
             swap_fee,
             pool_variant,
-            has_dust
+            has_dust,
+            ignored,
         }
     }
 }
@@ -0,0 +1,444 @@
+use dashmap::DashMap;
+use ethers::{contract::parse_log, prelude::*};
+use hashbrown::HashMap;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::abi::{uniswap_v2_pair, uniswap_v3_pool};
+use crate::prelude::{Pool, PoolVariant};
+use crate::utils;
+
+/// Latest known price / liquidity of a pool (as emitted by its logs)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PoolState {
+    UniswapV2 {
+        reserve_0: U256,
+        reserve_1: U256,
+    },
+    UniswapV3 {
+        sqrt_price_x96: U256,
+        liquidity: u128,
+        tick: i32,
+    },
+}
+
+impl PoolState {
+    // Check if pool has nothing to trade against
+    pub fn is_drained(&self) -> bool {
+        match self {
+            PoolState::UniswapV2 {
+                reserve_0,
+                reserve_1,
+            } => reserve_0.is_zero() || reserve_1.is_zero(),
+            PoolState::UniswapV3 { liquidity, .. } => *liquidity == 0,
+        }
+    }
+
+    // Weth held by `pool` (v3: virtual reserve of in range liquidity)
+    //
+    // Returns:
+    // Some(U256): weth reserve
+    // None: if weth is not one of the pool's tokens
+    pub fn weth_reserve(&self, pool: &Pool) -> Option<U256> {
+        let weth = utils::constants::get_weth_address();
+        let weth_is_token_0 = match (pool.token_0 == weth, pool.token_1 == weth) {
+            (true, _) => true,
+            (_, true) => false,
+            _ => return None,
+        };
+
+        match *self {
+            PoolState::UniswapV2 {
+                reserve_0,
+                reserve_1,
+            } => Some(if weth_is_token_0 {
+                reserve_0
+            } else {
+                reserve_1
+            }),
+            PoolState::UniswapV3 {
+                sqrt_price_x96,
+                liquidity,
+                ..
+            } => {
+                if sqrt_price_x96.is_zero() {
+                    return Some(U256::zero());
+                }
+                let liquidity = U256::from(liquidity);
+                // x = L / sqrt(P), y = L * sqrt(P)
+                let reserve = if weth_is_token_0 {
+                    (liquidity << 96) / sqrt_price_x96
+                } else {
+                    U256::try_from(liquidity.full_mul(sqrt_price_x96) >> 96).unwrap_or(U256::MAX)
+                };
+                Some(reserve)
+            }
+        }
+    }
+}
+
+/// Pool state together with the block it was last updated in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TrackedPoolState {
+    pub state: PoolState,
+    pub block_number: U64,
+    pub block_hash: H256,
+}
+
+/// Pool states before a block was applied, used to undo the block on reorg
+struct BlockUndo {
+    number: U64,
+    hash: H256,
+    previous: HashMap<Address, Option<TrackedPoolState>>,
+}
+
+/// Keeps reserves (v2) and sqrtPrice / liquidity / tick (v3) of all known pools up to date from logs
+///
+/// Pools become known with their first `Sync` (v2) or `Swap` (v3) log. Logs of blocks that got
+/// reorged out (different hash for a block number, or `removed` logs) roll the affected pools back.
+pub struct PoolStateTracker {
+    states: DashMap<Address, TrackedPoolState>,
+    /// weth swapped through each pool since last `take_weth_volumes` (not rolled back on reorg)
+    weth_volumes: DashMap<Address, U256>,
+    /// oldest block first
+    undo: Mutex<VecDeque<BlockUndo>>,
+    max_reorg_depth: usize,
+}
+
+impl PoolStateTracker {
+    // Create a new `PoolStateTracker`
+    //
+    // Arguments:
+    // * `max_reorg_depth`: number of blocks that can be rolled back
+    //
+    // Returns: a new `PoolStateTracker` instance
+    pub fn new(max_reorg_depth: usize) -> Self {
+        Self {
+            states: DashMap::new(),
+            weth_volumes: DashMap::new(),
+            undo: Mutex::new(VecDeque::new()),
+            max_reorg_depth: max_reorg_depth.max(1),
+        }
+    }
+
+    // Latest known state of `pool`
+    pub fn get(&self, pool: &Address) -> Option<TrackedPoolState> {
+        self.states.get(pool).map(|state| *state)
+    }
+
+    // Weth volume of every pool since last call (resets the volumes)
+    pub fn take_weth_volumes(&self) -> HashMap<Address, U256> {
+        let pools: Vec<Address> = self.weth_volumes.iter().map(|entry| *entry.key()).collect();
+        pools
+            .into_iter()
+            .filter_map(|pool| self.weth_volumes.remove(&pool))
+            .collect()
+    }
+
+    // Number of pools with known state
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    // Topic0 of all logs the tracker consumes
+    pub fn event_signatures() -> Vec<H256> {
+        vec![
+            uniswap_v2_pair::SyncFilter::signature(),
+            uniswap_v3_pool::SwapFilter::signature(),
+            uniswap_v3_pool::MintFilter::signature(),
+            uniswap_v3_pool::BurnFilter::signature(),
+        ]
+    }
+
+    // Update pool state from a log emitted by `pool`
+    //
+    // Arguments:
+    // * `pool`: pool that emitted the log
+    // * `log`: `Sync`, `Swap`, `Mint` or `Burn` log (possibly `removed` because of a reorg)
+    //
+    // Returns:
+    // bool: true if state of `pool` changed
+    pub fn apply_log(&self, pool: &Pool, log: &Log) -> bool {
+        let (number, hash) = match (log.block_number, log.block_hash) {
+            (Some(number), Some(hash)) => (number, hash),
+            // pending logs are not tracked
+            _ => return false,
+        };
+
+        let mut undo = self.undo.lock().unwrap();
+
+        if log.removed == Some(true) {
+            return self.rollback(&mut undo, |block| block.hash == hash) > 0;
+        }
+
+        // log from a block we saw with another hash (or from an earlier block) means a reorg
+        let reorged = undo
+            .iter()
+            .any(|block| block.number > number || (block.number == number && block.hash != hash));
+        if reorged {
+            self.rollback(&mut undo, |block| block.number >= number);
+        }
+
+        let previous = self.get(&pool.address);
+        let state = match decode_state(pool, log, previous.map(|tracked| tracked.state)) {
+            Some(state) => state,
+            None => return false,
+        };
+
+        let volume = weth_volume(pool, log, previous.map(|tracked| tracked.state), state);
+        if !volume.is_zero() {
+            let mut total = self.weth_volumes.entry(pool.address).or_default();
+            *total = total.saturating_add(volume);
+        }
+
+        if undo.back().map(|block| block.hash) != Some(hash) {
+            undo.push_back(BlockUndo {
+                number,
+                hash,
+                previous: HashMap::new(),
+            });
+            while undo.len() > self.max_reorg_depth {
+                undo.pop_front();
+            }
+        }
+        // only the state before the first log of a block is needed to undo it
+        undo.back_mut()
+            .unwrap()
+            .previous
+            .entry(pool.address)
+            .or_insert(previous);
+
+        self.states.insert(
+            pool.address,
+            TrackedPoolState {
+                state,
+                block_number: number,
+                block_hash: hash,
+            },
+        );
+        previous.map(|tracked| tracked.state) != Some(state)
+    }
+
+    // Undo every block (newest first) that matches `should_undo`, returns number of undone blocks
+    fn rollback(
+        &self,
+        undo: &mut VecDeque<BlockUndo>,
+        should_undo: impl Fn(&BlockUndo) -> bool,
+    ) -> usize {
+        let mut undone = 0;
+        let mut kept = VecDeque::new();
+
+        while let Some(block) = undo.pop_back() {
+            if !should_undo(&block) {
+                kept.push_front(block);
+                continue;
+            }
+
+            for (address, previous) in block.previous {
+                match previous {
+                    Some(previous) => self.states.insert(address, previous),
+                    None => self.states.remove(&address).map(|(_, state)| state),
+                };
+            }
+            undone += 1;
+        }
+
+        *undo = kept;
+        if undone > 0 {
+            log::info!("Rolled back pool state of {} reorged block(s)", undone);
+        }
+        undone
+    }
+}
+
+// Decode the pool state after `log` was emitted
+//
+// Arguments:
+// * `pool`: pool that emitted the log
+// * `log`: log to decode
+// * `previous`: state before the log (needed for v3 `Mint` / `Burn`)
+//
+// Returns:
+// Some(PoolState): new state of the pool
+// None: if log is not a tracked event or state can't be derived yet
+fn decode_state(pool: &Pool, log: &Log, previous: Option<PoolState>) -> Option<PoolState> {
+    match pool.pool_variant {
+        PoolVariant::UniswapV2 => {
+            let sync = parse_log::<uniswap_v2_pair::SyncFilter>(log.clone()).ok()?;
+            Some(PoolState::UniswapV2 {
+                reserve_0: U256::from(sync.reserve_0),
+                reserve_1: U256::from(sync.reserve_1),
+            })
+        }
+        PoolVariant::UniswapV3 => {
+            if let Ok(swap) = parse_log::<uniswap_v3_pool::SwapFilter>(log.clone()) {
+                return Some(PoolState::UniswapV3 {
+                    sqrt_price_x96: swap.sqrt_price_x96,
+                    liquidity: swap.liquidity,
+                    tick: swap.tick,
+                });
+            }
+
+            // liquidity changes only move in range liquidity if the position covers current tick
+            let (tick_lower, tick_upper, delta) =
+                if let Ok(mint) = parse_log::<uniswap_v3_pool::MintFilter>(log.clone()) {
+                    (mint.tick_lower, mint.tick_upper, mint.amount as i128)
+                } else if let Ok(burn) = parse_log::<uniswap_v3_pool::BurnFilter>(log.clone()) {
+                    (burn.tick_lower, burn.tick_upper, -(burn.amount as i128))
+                } else {
+                    return None;
+                };
+
+            match previous? {
+                PoolState::UniswapV3 {
+                    sqrt_price_x96,
+                    liquidity,
+                    tick,
+                } => {
+                    let liquidity = if tick_lower <= tick && tick < tick_upper {
+                        (liquidity as i128).saturating_add(delta).max(0) as u128
+                    } else {
+                        liquidity
+                    };
+                    Some(PoolState::UniswapV3 {
+                        sqrt_price_x96,
+                        liquidity,
+                        tick,
+                    })
+                }
+                PoolState::UniswapV2 { .. } => None,
+            }
+        }
+    }
+}
+
+// Weth moved by the swap that emitted `log` (zero if it was no swap or amounts are unknown)
+fn weth_volume(pool: &Pool, log: &Log, previous: Option<PoolState>, state: PoolState) -> U256 {
+    let weth = utils::constants::get_weth_address();
+
+    match (previous, state) {
+        // v2 only emits reserves, every change of weth reserve is counted
+        (
+            Some(PoolState::UniswapV2 {
+                reserve_0: previous_0,
+                reserve_1: previous_1,
+            }),
+            PoolState::UniswapV2 {
+                reserve_0,
+                reserve_1,
+            },
+        ) => {
+            let (previous, current) = match pool.token_0 == weth {
+                true => (previous_0, reserve_0),
+                false => (previous_1, reserve_1),
+            };
+            if current > previous {
+                current - previous
+            } else {
+                previous - current
+            }
+        }
+        (_, PoolState::UniswapV3 { .. }) => {
+            match parse_log::<uniswap_v3_pool::SwapFilter>(log.clone()) {
+                Ok(swap) if pool.token_0 == weth => swap.amount_0.unsigned_abs(),
+                Ok(swap) if pool.token_1 == weth => swap.amount_1.unsigned_abs(),
+                _ => U256::zero(),
+            }
+        }
+        _ => U256::zero(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ethers::abi::{encode, Token};
+
+    fn v2_pool() -> Pool {
+        Pool::new(
+            Address::from_low_u64_be(1),
+            Address::from_low_u64_be(2),
+            utils::constants::get_weth_address(),
+            U256::from(3000),
+            PoolVariant::UniswapV2,
+        )
+    }
+
+    fn sync_log(pool: &Pool, reserve_0: u64, reserve_1: u64, number: u64, hash: u64) -> Log {
+        Log {
+            address: pool.address,
+            topics: vec![uniswap_v2_pair::SyncFilter::signature()],
+            data: encode(&[
+                Token::Uint(U256::from(reserve_0)),
+                Token::Uint(U256::from(reserve_1)),
+            ])
+            .into(),
+            block_number: Some(U64::from(number)),
+            block_hash: Some(H256::from_low_u64_be(hash)),
+            ..Default::default()
+        }
+    }
+
+    fn reserves(tracker: &PoolStateTracker, pool: &Pool) -> Option<(U256, U256)> {
+        match tracker.get(&pool.address)?.state {
+            PoolState::UniswapV2 {
+                reserve_0,
+                reserve_1,
+            } => Some((reserve_0, reserve_1)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn tracks_v2_reserves_from_sync_logs() {
+        let tracker = PoolStateTracker::new(8);
+        let pool = v2_pool();
+
+        assert!(tracker.apply_log(&pool, &sync_log(&pool, 100, 200, 10, 10)));
+        assert!(tracker.apply_log(&pool, &sync_log(&pool, 110, 190, 11, 11)));
+
+        assert_eq!(
+            reserves(&tracker, &pool),
+            Some((U256::from(110), U256::from(190)))
+        );
+        assert_eq!(
+            tracker.get(&pool.address).unwrap().block_number,
+            U64::from(11)
+        );
+
+        // weth is token_1
+        let volumes = tracker.take_weth_volumes();
+        assert_eq!(volumes.get(&pool.address), Some(&U256::from(10)));
+        assert!(tracker.take_weth_volumes().is_empty());
+    }
+
+    #[test]
+    fn rolls_back_block_with_different_hash() {
+        let tracker = PoolStateTracker::new(8);
+        let pool = v2_pool();
+        tracker.apply_log(&pool, &sync_log(&pool, 100, 200, 10, 10));
+        tracker.apply_log(&pool, &sync_log(&pool, 110, 190, 11, 11));
+        tracker.apply_log(&pool, &sync_log(&pool, 120, 180, 12, 12));
+
+        // block 11 got replaced, state of block 11 and 12 is dropped before applying the new block
+        tracker.apply_log(&pool, &sync_log(&pool, 90, 210, 11, 111));
+
+        assert_eq!(
+            reserves(&tracker, &pool),
+            Some((U256::from(90), U256::from(210)))
+        );
+
+        // removing the new block 11 restores block 10
+        let mut removed = sync_log(&pool, 90, 210, 11, 111);
+        removed.removed = Some(true);
+        assert!(tracker.apply_log(&pool, &removed));
+        assert_eq!(
+            reserves(&tracker, &pool),
+            Some((U256::from(100), U256::from(200)))
+        );
+    }
+}
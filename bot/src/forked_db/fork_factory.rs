@@ -5,6 +5,8 @@ use super::{
     database_error::DatabaseResult,
     fork_db::ForkDB,
     global_backend::{BackendFetchRequest, GlobalBackend},
+    state_cache::BlockStateCache,
+    state_fetcher::StateFetchMode,
 };
 use ethers::prelude::*;
 use ethers::types::BlockId;
@@ -27,19 +29,28 @@ impl ForkFactory {
     // Create a new `ForkFactory` instance
     //
     // Arguments:
-    // * `provider`: Websocket client used for fetching missing state
+    // * `provider`: Client used for fetching missing state
     // * `initial_db`: Database with initial state
     // * `fork_block`: Block to fork from when making rpc calls
+    // * `shared_cache`: State shared with other factories forking from `fork_block`
     //
     // Returns:
     // `(ForkFactory, GlobalBackend)`: ForkFactory instance and the GlobalBackend it talks to
-    fn new(
-        provider: Arc<Provider<Ws>>,
+    fn new<M: Middleware + 'static>(
+        provider: Arc<M>,
         initial_db: CacheDB<EmptyDB>,
         fork_block: Option<BlockId>,
-    ) -> (Self, GlobalBackend) {
+        shared_cache: Option<Arc<BlockStateCache>>,
+    ) -> (Self, GlobalBackend<M>) {
         let (backend, backend_rx) = channel(1);
-        let handler = GlobalBackend::new(backend_rx, fork_block, provider, initial_db.clone());
+        let handler = GlobalBackend::new(
+            backend_rx,
+            fork_block,
+            provider,
+            initial_db.clone(),
+            StateFetchMode::from_env(),
+            shared_cache,
+        );
         (
             Self {
                 backend,
@@ -60,12 +71,13 @@ impl ForkFactory {
     }
 
     // Create a new sandbox environment with backend running on own thread
-    pub fn new_sandbox_factory(
-        provider: Arc<Provider<Ws>>,
+    pub fn new_sandbox_factory<M: Middleware + 'static>(
+        provider: Arc<M>,
         initial_db: CacheDB<EmptyDB>,
         fork_block: Option<BlockId>,
+        shared_cache: Option<Arc<BlockStateCache>>,
     ) -> Self {
-        let (shared, handler) = Self::new(provider, initial_db, fork_block);
+        let (shared, handler) = Self::new(provider, initial_db, fork_block, shared_cache);
 
         // spawn a light-weight thread with a thread-local async runtime just for
         // sending and receiving data from the remote client
@@ -120,3 +132,90 @@ impl ForkFactory {
         self.initial_db.insert_account_info(address, info);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ethers::providers::{JsonRpcError, MockResponse};
+    use revm::Database;
+    use tokio::runtime::Runtime;
+
+    fn proof_unsupported() -> MockResponse {
+        MockResponse::Error(JsonRpcError {
+            code: -32601,
+            message: "the method eth_getProof does not exist/is not available".to_string(),
+            data: None,
+        })
+    }
+
+    #[test]
+    fn fetches_missing_state_through_mock_middleware() {
+        let rt = Runtime::new().unwrap();
+        let (provider, mock) = Provider::mocked();
+
+        // mock responses are returned last in first out (requests: proof, balance, nonce, code)
+        mock.push::<Bytes, _>(Bytes::from(vec![0x60, 0x00])).unwrap();
+        mock.push(U256::from(7)).unwrap();
+        mock.push(U256::from(1000)).unwrap();
+        // node without proofs, account is fetched with individual calls instead
+        mock.push_response(proof_unsupported());
+
+        let factory = ForkFactory::new_sandbox_factory(
+            Arc::new(provider),
+            CacheDB::new(EmptyDB::default()),
+            None,
+            None,
+        );
+        let mut fork_db = factory.new_sandbox_fork();
+        let address = rAddress::from([1u8; 20]);
+
+        rt.block_on(async {
+            let info = fork_db.basic(address).unwrap().unwrap();
+            assert_eq!(info.balance, rU256::from(1000));
+            assert_eq!(info.nonce, 7);
+
+            // second lookup is served from cache (no responses left in mock)
+            let cached = fork_db.basic(address).unwrap().unwrap();
+            assert_eq!(cached.nonce, 7);
+        });
+    }
+
+    #[test]
+    fn shares_fetched_state_between_factories_of_same_block() {
+        let rt = Runtime::new().unwrap();
+        let (provider, mock) = Provider::mocked();
+        let (unused_provider, _) = Provider::mocked();
+        let shared_cache = Arc::new(BlockStateCache::new(U64::from(10)));
+        let address = rAddress::from([1u8; 20]);
+
+        // only the first factory gets responses, last in first out
+        // (requests: proof, balance, nonce, code, storage)
+        mock.push(H256::from_low_u64_be(42)).unwrap();
+        mock.push::<Bytes, _>(Bytes::default()).unwrap();
+        mock.push(U256::from(0)).unwrap();
+        mock.push(U256::from(1)).unwrap();
+        mock.push_response(proof_unsupported());
+
+        let first = ForkFactory::new_sandbox_factory(
+            Arc::new(provider),
+            CacheDB::new(EmptyDB::default()),
+            None,
+            Some(shared_cache.clone()),
+        );
+        let second = ForkFactory::new_sandbox_factory(
+            Arc::new(unused_provider),
+            CacheDB::new(EmptyDB::default()),
+            None,
+            Some(shared_cache.clone()),
+        );
+
+        rt.block_on(async {
+            let fetched = first.new_sandbox_fork().storage(address, rU256::from(8));
+            assert_eq!(fetched.unwrap(), rU256::from(42));
+
+            // second victim reads the same slot without making rpc calls
+            let cached = second.new_sandbox_fork().storage(address, rU256::from(8));
+            assert_eq!(cached.unwrap(), rU256::from(42));
+        });
+    }
+}
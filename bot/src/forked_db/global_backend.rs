@@ -1,9 +1,8 @@
 // credit to Foundry's SharedBackend implmenetation:
 // https://github.com/foundry-rs/foundry/blob/master/evm/src/executor/fork/backend.rs
 use ethers::{
-    providers::{Middleware, Provider, ProviderError, Ws},
-    types::{Address, BigEndianHash, BlockId, H256, U256},
-    utils::keccak256,
+    providers::Middleware,
+    types::{Address, BlockId, U256},
 };
 use eyre::Result;
 use futures::{
@@ -14,18 +13,19 @@ use futures::{
 use hashbrown::{hash_map::Entry, HashMap};
 use revm::{
     db::{CacheDB, EmptyDB},
-    primitives::{
-        bytes, AccountInfo, Bytecode, Bytes as rBytes, B160 as rAddress, B256, KECCAK_EMPTY,
-        U256 as rU256,
-    },
+    primitives::{AccountInfo, B160 as rAddress, B256, KECCAK_EMPTY, U256 as rU256},
 };
 use std::{
     collections::VecDeque,
     pin::Pin,
     sync::{mpsc::Sender as OneshotSender, Arc},
+    time::Instant,
 };
 
 use super::database_error::{DatabaseError, DatabaseResult};
+use super::state_cache::BlockStateCache;
+use super::state_fetcher::{self, FetchedAccount, StateFetchMode};
+use crate::metrics;
 
 // **incoming req and outcoming req handled using revm types
 // all logic internal to this module handled using ethers types (because of provider)
@@ -33,19 +33,24 @@ type AccountInfoSender = OneshotSender<DatabaseResult<AccountInfo>>;
 type StorageSender = OneshotSender<DatabaseResult<rU256>>;
 type BlockHashSender = OneshotSender<DatabaseResult<B256>>;
 
-type BasicFuture<Err> =
-    Pin<Box<dyn Future<Output = (Result<(rU256, u64, rBytes), Err>, rAddress)> + Send>>;
-type StorageFuture<Err> =
-    Pin<Box<dyn Future<Output = (Result<rU256, Err>, rAddress, rU256)> + Send>>;
+type AccountFuture<Err> = Pin<
+    Box<dyn Future<Output = (Result<FetchedAccount, Err>, rAddress, AccountBatch)> + Send>,
+>;
 type BlockHashFuture<Err> = Pin<Box<dyn Future<Output = (Result<B256, Err>, rU256)> + Send>>;
 
 /// Request variants that are executed by the provider
 enum FetchRequestFuture<Err> {
-    Basic(BasicFuture<Err>),
-    Storage(StorageFuture<Err>),
+    Account(AccountFuture<Err>),
     BlockHash(BlockHashFuture<Err>),
 }
 
+/// Missing state of a single account that is fetched together
+#[derive(Default)]
+struct AccountBatch {
+    basic: bool,
+    slots: Vec<rU256>,
+}
+
 /// The Request type the Backend listens for
 #[derive(Debug)]
 pub enum BackendFetchRequest {
@@ -59,13 +64,19 @@ pub enum BackendFetchRequest {
 
 /// Holds db and provdier_db to fallback on so that
 /// we can make rpc calls for missing data
-pub struct GlobalBackend {
+pub struct GlobalBackend<M: Middleware> {
     db: CacheDB<EmptyDB>,
     // used to make calls for missing data
-    provider: Arc<Provider<Ws>>,
+    provider: Arc<M>,
     block_num: Option<BlockId>,
+    /// How missing account state is fetched
+    fetch_mode: StateFetchMode,
+    /// State fetched by all forks of the same block, checked before making rpc calls
+    shared: Option<Arc<BlockStateCache>>,
     /// Requests currently in progress
-    pending_requests: Vec<FetchRequestFuture<ProviderError>>,
+    pending_requests: Vec<FetchRequestFuture<M::Error>>,
+    /// Account requests received during this poll, grouped by account
+    account_batches: HashMap<rAddress, AccountBatch>,
     /// Listeners that wait for a `get_account` related response
     account_requests: HashMap<rAddress, Vec<AccountInfoSender>>,
     /// Listeners that wait for a `get_storage_at` response
@@ -78,19 +89,24 @@ pub struct GlobalBackend {
     queued_requests: VecDeque<BackendFetchRequest>,
 }
 
-impl GlobalBackend {
+impl<M: Middleware + 'static> GlobalBackend<M> {
     // not so elegeant but create sim env from state diffs
     pub fn new(
         rx: Receiver<BackendFetchRequest>,
         block_num: Option<BlockId>,
-        provider: Arc<Provider<Ws>>,
+        provider: Arc<M>,
         initial_db: CacheDB<EmptyDB>,
+        fetch_mode: StateFetchMode,
+        shared: Option<Arc<BlockStateCache>>,
     ) -> Self {
         Self {
             db: initial_db,
             provider,
             block_num,
+            fetch_mode,
+            shared,
             pending_requests: Default::default(),
+            account_batches: Default::default(),
             account_requests: Default::default(),
             storage_requests: Default::default(),
             block_requests: Default::default(),
@@ -103,14 +119,16 @@ impl GlobalBackend {
     ///
     /// We always check:
     ///  1. if the requested value is already stored in the cache, then answer the sender
-    ///  2. otherwise, fetch it via the provider but check if a request for that value is already in
+    ///  2. if another fork of the same block already fetched the value, then answer the sender
+    ///  3. otherwise, fetch it via the provider but check if a request for that value is already in
     /// progress (e.g. another Sender just requested the same account)
     fn on_request(&mut self, req: BackendFetchRequest) {
         match req {
             BackendFetchRequest::Basic(addr, sender) => {
-                let acc = self.db.accounts.get(&addr);
+                let acc = self.db.accounts.get(&addr).map(|acc| acc.info.clone());
+                let acc = acc.or_else(|| self.shared.as_ref()?.account(addr));
                 if let Some(acc) = acc {
-                    let _ = sender.send(Ok(acc.info.clone()));
+                    let _ = sender.send(Ok(acc));
                 } else {
                     self.request_account(addr, sender);
                 }
@@ -120,16 +138,18 @@ impl GlobalBackend {
                     .db
                     .accounts
                     .get(&addr)
-                    .and_then(|acc| acc.storage.get(&idx));
+                    .and_then(|acc| acc.storage.get(&idx).copied());
+                let value = value.or_else(|| self.shared.as_ref()?.storage(addr, idx));
                 if let Some(value) = value {
-                    let _ = sender.send(Ok(*value));
+                    let _ = sender.send(Ok(value));
                 } else {
                     // account present but not storage -> fetch storage
                     self.request_account_storage(addr.0.into(), idx, sender)
                 }
             }
             BackendFetchRequest::BlockHash(number, sender) => {
-                let hash = self.db.block_hashes.get(&number);
+                let hash = self.db.block_hashes.get(&number).copied();
+                let hash = hash.or_else(|| self.shared.as_ref()?.block_hash(number));
                 if let Some(hash) = hash {
                     let _ = sender.send(Ok(hash.0.into()));
                 } else {
@@ -147,21 +167,7 @@ impl GlobalBackend {
             }
             Entry::Vacant(entry) => {
                 entry.insert(vec![listener]);
-                let provider = self.provider.clone();
-                let block_num = self.block_num;
-                let fut = Box::pin(async move {
-                    // convert from revm to ethers
-                    let address_ethers: Address = address.0.into();
-
-                    let balance = provider.get_balance(address_ethers, block_num);
-                    let nonce = provider.get_transaction_count(address_ethers, block_num);
-                    let code = provider.get_code(address_ethers, block_num);
-                    let resp = tokio::try_join!(balance, nonce, code);
-
-                    let resp = resp.map(|(b, n, c)| (b.into(), n.as_u64(), c.0));
-                    (resp, address)
-                });
-                self.pending_requests.push(FetchRequestFuture::Basic(fut));
+                self.account_batches.entry(address).or_default().basic = true;
             }
         }
     }
@@ -174,25 +180,53 @@ impl GlobalBackend {
             }
             Entry::Vacant(entry) => {
                 entry.insert(vec![listener]);
-                let provider = self.provider.clone();
-                let block_num = self.block_num;
-                let fut = Box::pin(async move {
-                    // convert from revm to ethers type
-                    let idx_ethers = H256::from_uint(&U256::from(idx));
-                    let address_ethers: Address = address.0.into();
-
-                    let storage = provider
-                        .get_storage_at(address_ethers, idx_ethers, block_num)
-                        .await;
-                    let storage = storage.map(|storage| storage.into_uint());
+                self.account_batches
+                    .entry(address)
+                    .or_default()
+                    .slots
+                    .push(idx);
+            }
+        }
+    }
 
-                    // convert ethers types to revm types
-                    let storage = storage.map(|s| s.into());
-                    // convert back to revm types
-                    (storage, address, idx)
-                });
-                self.pending_requests.push(FetchRequestFuture::Storage(fut));
+    // Start one fetch per account for all requests received since last flush
+    //
+    // Requests are not sent as a JSON-RPC batch, a fetch is a single `eth_getProof` in proof mode
+    // (individual calls if the node fails the proof) and concurrent individual calls otherwise
+    fn flush_account_batches(&mut self) {
+        for (address, batch) in self.account_batches.drain() {
+            metrics::STATE_FETCH_ACCOUNTS.inc();
+            if self.fetch_mode == StateFetchMode::Proof {
+                let requests = batch.basic as u64 + batch.slots.len() as u64;
+                metrics::STATE_FETCH_PROOF_MERGED.add(requests.saturating_sub(1));
             }
+
+            let provider = self.provider.clone();
+            let block_num = self.block_num;
+            let fetch_mode = self.fetch_mode;
+            let fut = Box::pin(async move {
+                // convert from revm to ethers
+                let address_ethers: Address = address.0.into();
+                let slots: Vec<U256> = batch.slots.iter().map(|slot| (*slot).into()).collect();
+
+                let started = Instant::now();
+                let resp = state_fetcher::fetch_account(
+                    provider.as_ref(),
+                    address_ethers,
+                    batch.basic,
+                    &slots,
+                    block_num,
+                    fetch_mode,
+                )
+                .await;
+                match batch.basic {
+                    true => metrics::RPC_LATENCY_ACCOUNT.record(started.elapsed()),
+                    false => metrics::RPC_LATENCY_STORAGE.record(started.elapsed()),
+                }
+
+                (resp, address, batch)
+            });
+            self.pending_requests.push(FetchRequestFuture::Account(fut));
         }
     }
 
@@ -208,7 +242,9 @@ impl GlobalBackend {
                 let fut = Box::pin(async move {
                     // convert from revm to ethers type
                     let number_ethers: u64 = U256::from(number).as_u64();
+                    let started = Instant::now();
                     let block = provider.get_block(number_ethers).await;
+                    metrics::RPC_LATENCY_BLOCK_HASH.record(started.elapsed());
 
                     let block_hash = match block {
                         Ok(Some(block)) => Ok(block
@@ -233,7 +269,7 @@ impl GlobalBackend {
     }
 }
 
-impl Future for GlobalBackend {
+impl<M: Middleware + 'static> Future for GlobalBackend<M> {
     type Output = ();
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
@@ -243,6 +279,7 @@ impl Future for GlobalBackend {
             while let Some(req) = pin.queued_requests.pop_front() {
                 pin.on_request(req)
             }
+            pin.flush_account_batches();
 
             // receive new requests to delegate to the underlying provider
             loop {
@@ -261,80 +298,76 @@ impl Future for GlobalBackend {
             for n in (0..pin.pending_requests.len()).rev() {
                 let mut request = pin.pending_requests.swap_remove(n);
                 match &mut request {
-                    FetchRequestFuture::Basic(fut) => {
-                        if let Poll::Ready((resp, addr)) = fut.poll_unpin(cx) {
-                            // get the response
-                            let (balance, nonce, code) = match resp {
-                                Ok(res) => res,
+                    FetchRequestFuture::Account(fut) => {
+                        if let Poll::Ready((resp, addr, batch)) = fut.poll_unpin(cx) {
+                            let fetched = match resp {
+                                Ok(fetched) => fetched,
                                 Err(err) => {
+                                    // notify all listeners
                                     let err = Arc::new(eyre::Error::new(err));
-                                    if let Some(listeners) = pin.account_requests.remove(&addr) {
-                                        listeners.into_iter().for_each(|l| {
-                                            let _ = l.send(Err(DatabaseError::GetAccount(
-                                                addr,
-                                                Arc::clone(&err),
-                                            )));
-                                        })
+                                    if batch.basic {
+                                        if let Some(listeners) = pin.account_requests.remove(&addr)
+                                        {
+                                            listeners.into_iter().for_each(|l| {
+                                                let _ = l.send(Err(DatabaseError::GetAccount(
+                                                    addr,
+                                                    Arc::clone(&err),
+                                                )));
+                                            })
+                                        }
+                                    }
+                                    for idx in batch.slots {
+                                        if let Some(listeners) =
+                                            pin.storage_requests.remove(&(addr, idx))
+                                        {
+                                            listeners.into_iter().for_each(|l| {
+                                                let _ = l.send(Err(DatabaseError::GetStorage(
+                                                    addr,
+                                                    idx,
+                                                    Arc::clone(&err),
+                                                )));
+                                            })
+                                        }
                                     }
                                     continue;
                                 }
                             };
 
-                            // convert it to revm-style types
-                            let (code, code_hash) = if !code.is_empty() {
-                                (Some(code.clone()), keccak256(&code).into())
-                            } else {
-                                (Some(bytes::Bytes::default()), KECCAK_EMPTY)
-                            };
+                            if let Some(shared) = pin.shared.as_ref() {
+                                shared.insert_fetched(addr, &fetched);
+                            }
 
-                            // update the cache
-                            let acc = AccountInfo {
-                                nonce,
-                                balance,
-                                code: code.map(|bytes| Bytecode::new_raw(bytes).to_checked()),
-                                code_hash,
-                            };
-                            pin.db.insert_account_info(addr, acc.clone());
+                            // update the cache (info first so storage lands on the fetched account)
+                            if let Some(acc) = fetched.info {
+                                pin.db.insert_account_info(addr, acc.clone());
 
-                            // notify all listeners
-                            if let Some(listeners) = pin.account_requests.remove(&addr) {
-                                listeners.into_iter().for_each(|l| {
-                                    let _ = l.send(Ok(acc.clone()));
-                                })
-                            }
-                            continue;
-                        }
-                    }
-                    FetchRequestFuture::Storage(fut) => {
-                        if let Poll::Ready((resp, addr, idx)) = fut.poll_unpin(cx) {
-                            let value = match resp {
-                                Ok(value) => value,
-                                Err(err) => {
-                                    // notify all listeners
-                                    let err = Arc::new(eyre::Error::new(err));
-                                    if let Some(listeners) =
-                                        pin.storage_requests.remove(&(addr, idx))
-                                    {
-                                        listeners.into_iter().for_each(|l| {
-                                            let _ = l.send(Err(DatabaseError::GetStorage(
-                                                addr,
-                                                idx,
-                                                Arc::clone(&err),
-                                            )));
-                                        })
-                                    }
-                                    continue;
+                                // notify all listeners
+                                if let Some(listeners) = pin.account_requests.remove(&addr) {
+                                    listeners.into_iter().for_each(|l| {
+                                        let _ = l.send(Ok(acc.clone()));
+                                    })
                                 }
-                            };
-
-                            // update the cache
-                            pin.db.insert_account_storage(addr, idx, value).unwrap();
+                            }
+                            for (idx, value) in fetched.storage {
+                                pin.db.insert_account_storage(addr, idx, value).unwrap();
 
-                            // notify all listeners
-                            if let Some(listeners) = pin.storage_requests.remove(&(addr, idx)) {
-                                listeners.into_iter().for_each(|l| {
-                                    let _ = l.send(Ok(value));
-                                })
+                                // notify all listeners
+                                if let Some(listeners) = pin.storage_requests.remove(&(addr, idx)) {
+                                    listeners.into_iter().for_each(|l| {
+                                        let _ = l.send(Ok(value));
+                                    })
+                                }
+                            }
+                            // never leave a listener waiting on a slot the node did not return
+                            for idx in batch.slots {
+                                if let Some(listeners) = pin.storage_requests.remove(&(addr, idx)) {
+                                    listeners.into_iter().for_each(|l| {
+                                        let _ = l.send(Err(DatabaseError::msg(format!(
+                                            "Storage slot {:?} of {:?} missing from response",
+                                            idx, addr
+                                        ))));
+                                    })
+                                }
                             }
                             continue;
                         }
@@ -360,6 +393,9 @@ impl Future for GlobalBackend {
 
                             // update the cache
                             pin.db.block_hashes.insert(number, value);
+                            if let Some(shared) = pin.shared.as_ref() {
+                                shared.insert_block_hash(number, value);
+                            }
 
                             // notify all listeners
                             if let Some(listeners) = pin.block_requests.remove(&number) {
@@ -6,3 +6,6 @@ pub use global_backend::*;
 
 pub mod fork_db;
 pub mod fork_factory;
+pub mod prefetch;
+pub mod state_cache;
+pub mod state_fetcher;
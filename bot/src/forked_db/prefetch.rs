@@ -0,0 +1,135 @@
+use ethers::prelude::*;
+use futures::future::join_all;
+use hashbrown::HashMap;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use super::state_cache::BlockStateCache;
+use super::state_fetcher::{self, StateFetchMode};
+use crate::metrics;
+use crate::prelude::{Pool, PoolVariant};
+use crate::utils;
+
+// UniswapV2Pair: reserve0, reserve1, blockTimestampLast
+const V2_RESERVES_SLOT: u64 = 8;
+// UniswapV3Pool: sqrtPriceX96, tick, observation info, feeProtocol, unlocked
+const V3_SLOT0_SLOT: u64 = 0;
+// UniswapV3Pool: in range liquidity
+const V3_LIQUIDITY_SLOT: u64 = 4;
+
+/// Prefetches storage that every simulation on recently sandwiched pools reads
+/// (pool reserves / slot0, weth balances) so victims don't have to wait on rpc calls for it
+pub struct HotStatePrefetcher {
+    capacity: usize,
+    /// most recently sandwiched pool is last
+    hot_pools: Mutex<VecDeque<Pool>>,
+}
+
+impl HotStatePrefetcher {
+    // Create a new `HotStatePrefetcher`
+    //
+    // Arguments:
+    // * `capacity`: max number of pools that are prefetched
+    //
+    // Returns: a new `HotStatePrefetcher` instance
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            hot_pools: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    // Create a new `HotStatePrefetcher` using the capacity set in env
+    pub fn from_env() -> Self {
+        Self::new(utils::dotenv::get_prefetch_hot_pools())
+    }
+
+    // Prefetch `pool` from next block on (least recently used pool is dropped when full)
+    pub fn mark_hot(&self, pool: Pool) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut hot_pools = self.hot_pools.lock().unwrap();
+        hot_pools.retain(|hot| hot.address != pool.address);
+        hot_pools.push_back(pool);
+        while hot_pools.len() > self.capacity {
+            hot_pools.pop_front();
+        }
+    }
+
+    // Fetch account info and hot slots of all hot pools that are missing from `cache`
+    //
+    // Arguments:
+    // * `provider`: client used to make rpc calls
+    // * `cache`: shared cache of the block that simulations fork from
+    //
+    // Returns:
+    // usize: number of accounts prefetched (accounts that failed to fetch are skipped)
+    pub async fn prefetch<M: Middleware>(&self, provider: &M, cache: &BlockStateCache) -> usize {
+        // most hot state is carried over from previous block, only fetch what a tx changed
+        let mut missing: HashMap<Address, (bool, Vec<U256>)> = HashMap::new();
+        for (address, slot) in self.hot_slots() {
+            let with_info = !cache.contains_account(address.0.into());
+            let is_cached = cache.contains_storage(address.0.into(), slot.into());
+            if !with_info && is_cached {
+                continue;
+            }
+
+            let (fetch_info, slots) = missing.entry(address).or_default();
+            *fetch_info |= with_info;
+            if !is_cached && !slots.contains(&slot) {
+                slots.push(slot);
+            }
+        }
+
+        let block_id = Some(BlockId::Number(BlockNumber::Number(cache.block())));
+        let fetch_mode = StateFetchMode::from_env();
+        let started = Instant::now();
+        let fetched = join_all(missing.into_iter().map(
+            |(address, (with_info, slots))| async move {
+                let fetched = state_fetcher::fetch_account(
+                    provider, address, with_info, &slots, block_id, fetch_mode,
+                )
+                .await;
+                (address, fetched)
+            },
+        ))
+        .await;
+        metrics::RPC_LATENCY_PREFETCH.record(started.elapsed());
+
+        let mut prefetched = 0;
+        for (address, fetched) in fetched {
+            match fetched {
+                Ok(account) => {
+                    cache.insert_fetched(address.0.into(), &account);
+                    prefetched += 1;
+                }
+                Err(e) => log::error!("Failed to prefetch {:?}: {:?}", address, e),
+            }
+        }
+        prefetched
+    }
+
+    // (account, slot) pairs that are read by every sandwich on the hot pools
+    fn hot_slots(&self) -> Vec<(Address, U256)> {
+        let weth = utils::constants::get_weth_address();
+        let sandwich = utils::dotenv::get_sandwich_contract_address();
+
+        let mut hot_slots = vec![(weth, utils::get_weth_balance_slot(sandwich))];
+        for pool in self.hot_pools.lock().unwrap().iter() {
+            match pool.pool_variant {
+                PoolVariant::UniswapV2 => {
+                    hot_slots.push((pool.address, U256::from(V2_RESERVES_SLOT)));
+                }
+                PoolVariant::UniswapV3 => {
+                    hot_slots.push((pool.address, U256::from(V3_SLOT0_SLOT)));
+                    hot_slots.push((pool.address, U256::from(V3_LIQUIDITY_SLOT)));
+                }
+            }
+            hot_slots.push((weth, utils::get_weth_balance_slot(pool.address)));
+        }
+        hot_slots
+    }
+}
@@ -0,0 +1,488 @@
+use dashmap::DashMap;
+use ethers::prelude::*;
+use revm::primitives::{AccountInfo, B160 as rAddress, B256, U256 as rU256};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, RwLock};
+
+use super::state_fetcher::FetchedAccount;
+use crate::metrics;
+use crate::utils;
+
+/// Read-through cache of node state shared by every fork of the same block
+///
+/// Simulations read state in three layers:
+/// 1. `ForkDB`: writes made by the simulation itself (one per fork)
+/// 2. `GlobalBackend` db: starting state of slots the victim touches (one per victim)
+/// 3. `BlockStateCache`: everything fetched from the node for this block (shared)
+///
+/// Only layer 3 goes to the node, and whatever it fetches is reused by all other victims.
+#[derive(Debug)]
+pub struct BlockStateCache {
+    /// block id that forks using this cache fetch state at
+    block: U64,
+    /// hash of the landed block whose state was carried over, None if nothing was carried over
+    landed_hash: Option<H256>,
+    accounts: DashMap<rAddress, AccountInfo>,
+    storage: DashMap<(rAddress, rU256), rU256>,
+    block_hashes: DashMap<rU256, B256>,
+}
+
+impl BlockStateCache {
+    // Create an empty cache for `block`
+    pub fn new(block: U64) -> Self {
+        Self {
+            block,
+            landed_hash: None,
+            accounts: DashMap::new(),
+            storage: DashMap::new(),
+            block_hashes: DashMap::new(),
+        }
+    }
+
+    // Block id that state in this cache belongs to
+    pub fn block(&self) -> U64 {
+        self.block
+    }
+
+    // Number of cached accounts, slots and block hashes
+    pub fn len(&self) -> usize {
+        self.accounts.len() + self.storage.len() + self.block_hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn account(&self, address: rAddress) -> Option<AccountInfo> {
+        let account = self.accounts.get(&address).map(|info| info.clone());
+        record_lookup(account.is_some());
+        account
+    }
+
+    pub fn storage(&self, address: rAddress, slot: rU256) -> Option<rU256> {
+        let value = self.storage.get(&(address, slot)).map(|value| *value);
+        record_lookup(value.is_some());
+        value
+    }
+
+    pub fn block_hash(&self, number: rU256) -> Option<B256> {
+        let hash = self.block_hashes.get(&number).map(|hash| *hash);
+        record_lookup(hash.is_some());
+        hash
+    }
+
+    // Check if `slot` is cached without counting it as a lookup
+    pub fn contains_storage(&self, address: rAddress, slot: rU256) -> bool {
+        self.storage.contains_key(&(address, slot))
+    }
+
+    // Check if account info is cached without counting it as a lookup
+    pub fn contains_account(&self, address: rAddress) -> bool {
+        self.accounts.contains_key(&address)
+    }
+
+    pub fn insert_account(&self, address: rAddress, info: AccountInfo) {
+        self.accounts.insert(address, info);
+    }
+
+    pub fn insert_storage(&self, address: rAddress, slot: rU256, value: rU256) {
+        self.storage.insert((address, slot), value);
+    }
+
+    pub fn insert_block_hash(&self, number: rU256, hash: B256) {
+        self.block_hashes.insert(number, hash);
+    }
+
+    // Copy every entry of `other` into this cache, entries of `other` win
+    fn merge(&self, other: &BlockStateCache) {
+        for entry in other.accounts.iter() {
+            self.accounts.insert(*entry.key(), entry.value().clone());
+        }
+        for entry in other.storage.iter() {
+            self.storage.insert(*entry.key(), *entry.value());
+        }
+        for entry in other.block_hashes.iter() {
+            self.block_hashes.insert(*entry.key(), *entry.value());
+        }
+    }
+
+    // Cache everything that was fetched for an account
+    pub fn insert_fetched(&self, address: rAddress, fetched: &FetchedAccount) {
+        if let Some(info) = &fetched.info {
+            self.insert_account(address, info.clone());
+        }
+        for (slot, value) in fetched.storage.iter() {
+            self.insert_storage(address, *slot, *value);
+        }
+    }
+
+    // Carry cached state over to the next block
+    //
+    // Arguments:
+    // * `block`: block id of the new cache
+    // * `landed_hash`: hash of the block the diffs belong to
+    // * `block_diffs`: state diff of every tx in the block that landed (in block order)
+    // * `evicted`: accounts whose balance changed outside of txs (e.g. withdrawals)
+    //
+    // Returns:
+    // BlockStateCache: new cache holding all entries that are still valid at `block`
+    pub fn advance(
+        &self,
+        block: U64,
+        landed_hash: H256,
+        block_diffs: &[BTreeMap<Address, AccountDiff>],
+        evicted: &[Address],
+    ) -> Self {
+        let next = Self {
+            block,
+            landed_hash: Some(landed_hash),
+            accounts: self.accounts.clone(),
+            storage: self.storage.clone(),
+            block_hashes: self.block_hashes.clone(),
+        };
+
+        // storage of destroyed contracts is wiped, collected so the cache is only walked once
+        let mut destroyed = HashSet::new();
+        for state_diff in block_diffs {
+            for (address, account_diff) in state_diff.iter() {
+                next.apply_account_diff(address.0.into(), account_diff, &mut destroyed);
+            }
+        }
+        if !destroyed.is_empty() {
+            next.storage
+                .retain(|(cached, _), _| !destroyed.contains(cached));
+        }
+        for address in evicted {
+            next.accounts.remove(&address.0.into());
+        }
+
+        next
+    }
+
+    // Update cached entries of `address` to the values after a tx
+    //
+    // Accounts that were created or destroyed are added to `destroyed`, their storage has to be
+    // dropped by the caller
+    fn apply_account_diff(
+        &self,
+        address: rAddress,
+        account_diff: &AccountDiff,
+        destroyed: &mut HashSet<rAddress>,
+    ) {
+        let created_or_destroyed = matches!(account_diff.balance, Diff::Born(_) | Diff::Died(_))
+            || matches!(account_diff.nonce, Diff::Born(_) | Diff::Died(_));
+        if created_or_destroyed {
+            self.accounts.remove(&address);
+            destroyed.insert(address);
+        } else if !matches!(account_diff.code, Diff::Same) {
+            self.accounts.remove(&address);
+        } else if let Some(mut info) = self.accounts.get_mut(&address) {
+            if let Diff::Changed(balance) = &account_diff.balance {
+                info.balance = balance.to.into();
+            }
+            if let Diff::Changed(nonce) = &account_diff.nonce {
+                info.nonce = nonce.to.as_u64();
+            }
+        }
+
+        for (slot, storage_diff) in account_diff.storage.iter() {
+            let value = match storage_diff {
+                Diff::Same => continue,
+                Diff::Born(value) => *value,
+                Diff::Died(_) => H256::zero(),
+                Diff::Changed(value) => value.to,
+            };
+            let slot: U256 = slot.0.into();
+            let value: U256 = value.0.into();
+            self.insert_storage(address, slot.into(), value.into());
+        }
+    }
+}
+
+/// Holds the `BlockStateCache` of the block that simulations currently fork from
+pub struct SharedStateCache {
+    current: RwLock<Arc<BlockStateCache>>,
+    /// cache shared by forks of the next block until the block that landed is applied
+    pending: RwLock<Option<Arc<BlockStateCache>>>,
+    /// caches growing past this are dropped instead of carried over to next block
+    max_entries: usize,
+}
+
+impl SharedStateCache {
+    // Create a new `SharedStateCache`
+    //
+    // Arguments:
+    // * `max_entries`: max number of entries carried over between blocks
+    //
+    // Returns: a new `SharedStateCache` instance
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(BlockStateCache::new(U64::zero()))),
+            pending: RwLock::new(None),
+            max_entries,
+        }
+    }
+
+    // Create a new `SharedStateCache` using the size set in env
+    pub fn from_env() -> Self {
+        Self::new(utils::dotenv::get_state_cache_max_entries())
+    }
+
+    // Cache to use for forks of `block`
+    //
+    // Forks of older blocks get a private cache so they can't pollute the current one, forks of
+    // a newer block share a pending cache that is merged in once the landed block is applied
+    pub fn for_block(&self, block: U64) -> Arc<BlockStateCache> {
+        let current = self.current.read().unwrap().clone();
+        if current.block() == block {
+            return current;
+        }
+        if current.block() > block {
+            return Arc::new(BlockStateCache::new(block));
+        }
+
+        let mut pending = self.pending.write().unwrap();
+        match pending.as_ref() {
+            Some(cache) if cache.block() == block => cache.clone(),
+            Some(cache) if cache.block() > block => Arc::new(BlockStateCache::new(block)),
+            _ => {
+                let cache = Arc::new(BlockStateCache::new(block));
+                *pending = Some(cache.clone());
+                cache
+            }
+        }
+    }
+
+    // Move to the cache for `block` after the block before it landed
+    //
+    // Arguments:
+    // * `block`: block id that simulations fork from from now on
+    // * `landed_hash`: hash of the block that landed
+    // * `parent_hash`: parent hash of the block that landed
+    // * `block_diffs`: state diffs of the block that landed, None if they could not be traced
+    // * `evicted`: accounts whose balance changed outside of txs (e.g. withdrawals)
+    //
+    // Returns: This function returns nothing
+    pub fn advance(
+        &self,
+        block: U64,
+        landed_hash: H256,
+        parent_hash: H256,
+        block_diffs: Option<&[BTreeMap<Address, AccountDiff>]>,
+        evicted: &[Address],
+    ) {
+        let mut current = self.current.write().unwrap();
+        if current.block() > block
+            || (current.block() == block && current.landed_hash == Some(landed_hash))
+        {
+            return;
+        }
+
+        // state can only be carried over when nothing in between was missed and the landed
+        // block builds on the one the cache holds, anything else (e.g. a reorg) starts over
+        let builds_on_current = current.block() + 1 == block
+            && current.landed_hash.map_or(true, |hash| hash == parent_hash);
+        let mut next = match block_diffs {
+            Some(block_diffs) if builds_on_current && current.len() <= self.max_entries => {
+                current.advance(block, landed_hash, block_diffs, evicted)
+            }
+            _ => BlockStateCache::new(block),
+        };
+        next.landed_hash = Some(landed_hash);
+
+        // state fetched by forks that started before the landed block was applied
+        let mut pending = self.pending.write().unwrap();
+        if let Some(cache) = pending.take() {
+            match cache.block().cmp(&block) {
+                Ordering::Equal => next.merge(&cache),
+                Ordering::Greater => *pending = Some(cache),
+                Ordering::Less => {}
+            }
+        }
+
+        metrics::STATE_CACHE_ENTRIES.set(next.len() as u64);
+        *current = Arc::new(next);
+    }
+}
+
+fn record_lookup(hit: bool) {
+    match hit {
+        true => metrics::STATE_CACHE_HITS.inc(),
+        false => metrics::STATE_CACHE_MISSES.inc(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ethers::types::ChangedType;
+
+    fn address() -> Address {
+        Address::from_low_u64_be(0xbeef)
+    }
+
+    fn hash(block: u64) -> H256 {
+        H256::from_low_u64_be(block)
+    }
+
+    fn cached(block: u64) -> BlockStateCache {
+        let cache = BlockStateCache::new(U64::from(block));
+        cache.insert_account(
+            address().0.into(),
+            AccountInfo {
+                balance: rU256::from(100),
+                nonce: 1,
+                ..Default::default()
+            },
+        );
+        cache.insert_storage(address().0.into(), rU256::from(8), rU256::from(1));
+        cache.insert_storage(address().0.into(), rU256::from(9), rU256::from(2));
+        cache
+    }
+
+    fn account_diff(storage: BTreeMap<H256, Diff<H256>>) -> AccountDiff {
+        AccountDiff {
+            balance: Diff::Changed(ChangedType {
+                from: U256::from(100),
+                to: U256::from(70),
+            }),
+            nonce: Diff::Same,
+            code: Diff::Same,
+            storage,
+        }
+    }
+
+    #[test]
+    fn advance_applies_block_state_diff() {
+        let cache = cached(10);
+        let storage = BTreeMap::from([(
+            H256::from_low_u64_be(8),
+            Diff::Changed(ChangedType {
+                from: H256::from_low_u64_be(1),
+                to: H256::from_low_u64_be(5),
+            }),
+        )]);
+        let block_diffs = vec![BTreeMap::from([(address(), account_diff(storage))])];
+
+        let next = cache.advance(U64::from(11), hash(10), &block_diffs, &[]);
+
+        assert_eq!(next.block(), U64::from(11));
+        let info = next.account(address().0.into()).unwrap();
+        assert_eq!(info.balance, rU256::from(70));
+        assert_eq!(info.nonce, 1);
+        assert_eq!(
+            next.storage(address().0.into(), rU256::from(8)),
+            Some(rU256::from(5))
+        );
+        assert_eq!(
+            next.storage(address().0.into(), rU256::from(9)),
+            Some(rU256::from(2))
+        );
+        // previous block is untouched
+        assert_eq!(
+            cache.storage(address().0.into(), rU256::from(8)),
+            Some(rU256::from(1))
+        );
+    }
+
+    #[test]
+    fn advance_evicts_accounts_changed_outside_txs() {
+        let cache = cached(10);
+
+        let next = cache.advance(U64::from(11), hash(10), &[], &[address()]);
+
+        assert!(next.account(address().0.into()).is_none());
+        assert_eq!(
+            next.storage(address().0.into(), rU256::from(9)),
+            Some(rU256::from(2))
+        );
+    }
+
+    #[test]
+    fn advance_drops_storage_of_destroyed_accounts() {
+        let cache = cached(10);
+        let other: rAddress = Address::from_low_u64_be(0xcafe).0.into();
+        cache.insert_storage(other, rU256::from(8), rU256::from(3));
+        let mut destroyed = account_diff(BTreeMap::new());
+        destroyed.balance = Diff::Died(U256::from(100));
+        let block_diffs = vec![BTreeMap::from([(address(), destroyed)])];
+
+        let next = cache.advance(U64::from(11), hash(10), &block_diffs, &[]);
+
+        assert!(next.account(address().0.into()).is_none());
+        assert!(!next.contains_storage(address().0.into(), rU256::from(8)));
+        assert!(!next.contains_storage(address().0.into(), rU256::from(9)));
+        assert_eq!(next.storage(other, rU256::from(8)), Some(rU256::from(3)));
+    }
+
+    #[test]
+    fn shared_cache_drops_state_after_missed_block() {
+        let shared = SharedStateCache::new(usize::MAX);
+        shared.advance(U64::from(10), hash(9), hash(8), Some(&[]), &[]);
+        let first = shared.for_block(U64::from(10));
+        first.insert_storage(address().0.into(), rU256::from(8), rU256::from(1));
+
+        shared.advance(U64::from(11), hash(10), hash(9), Some(&[]), &[]);
+        assert!(shared
+            .for_block(U64::from(11))
+            .contains_storage(address().0.into(), rU256::from(8)));
+
+        // block 12 was never seen
+        shared.advance(U64::from(13), hash(12), hash(11), Some(&[]), &[]);
+        assert!(shared.for_block(U64::from(13)).is_empty());
+        // forks of old blocks don't replace the current cache
+        let stale = shared.for_block(U64::from(11));
+        stale.insert_storage(address().0.into(), rU256::from(8), rU256::from(1));
+        assert_eq!(stale.block(), U64::from(11));
+        assert!(shared.for_block(U64::from(13)).is_empty());
+    }
+
+    #[test]
+    fn shared_cache_drops_state_after_reorg() {
+        let shared = SharedStateCache::new(usize::MAX);
+        shared.advance(U64::from(10), hash(9), hash(8), Some(&[]), &[]);
+        shared.for_block(U64::from(10)).insert_storage(
+            address().0.into(),
+            rU256::from(8),
+            rU256::from(1),
+        );
+
+        // block 10 doesn't build on the block 9 the cache holds
+        shared.advance(U64::from(11), hash(10), hash(0x99), Some(&[]), &[]);
+        assert!(shared.for_block(U64::from(11)).is_empty());
+
+        shared.for_block(U64::from(11)).insert_storage(
+            address().0.into(),
+            rU256::from(8),
+            rU256::from(1),
+        );
+        // the same block again is a no-op
+        shared.advance(U64::from(11), hash(10), hash(9), Some(&[]), &[]);
+        assert!(!shared.for_block(U64::from(11)).is_empty());
+        // a sibling of block 10 replaced it
+        shared.advance(U64::from(11), hash(0x10), hash(9), Some(&[]), &[]);
+        assert!(shared.for_block(U64::from(11)).is_empty());
+    }
+
+    #[test]
+    fn shared_cache_keeps_state_fetched_before_advance() {
+        let shared = SharedStateCache::new(usize::MAX);
+        shared.advance(U64::from(10), hash(9), hash(8), Some(&[]), &[]);
+        shared.for_block(U64::from(10)).insert_storage(
+            address().0.into(),
+            rU256::from(8),
+            rU256::from(1),
+        );
+
+        // forks of the next block started before block 10 was applied share a cache
+        let early = shared.for_block(U64::from(11));
+        early.insert_storage(address().0.into(), rU256::from(9), rU256::from(2));
+        assert!(Arc::ptr_eq(&early, &shared.for_block(U64::from(11))));
+
+        shared.advance(U64::from(11), hash(10), hash(9), Some(&[]), &[]);
+        let current = shared.for_block(U64::from(11));
+        assert!(current.contains_storage(address().0.into(), rU256::from(8)));
+        assert!(current.contains_storage(address().0.into(), rU256::from(9)));
+    }
+}
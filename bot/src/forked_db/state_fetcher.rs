@@ -0,0 +1,172 @@
+use dashmap::DashMap;
+use ethers::prelude::*;
+use futures::future::try_join_all;
+use revm::primitives::{AccountInfo, Bytecode, B256, KECCAK_EMPTY, U256 as rU256};
+use std::sync::OnceLock;
+
+use crate::metrics;
+use crate::utils;
+
+/// How account state that is missing locally gets fetched from the node
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateFetchMode {
+    /// `eth_getBalance` + `eth_getTransactionCount` + `eth_getCode` + one `eth_getStorageAt` per slot
+    Individual,
+    /// single `eth_getProof` for account fields and all slots (code fetched once per code hash),
+    /// falls back to individual calls if the node fails the proof
+    Proof,
+}
+
+impl StateFetchMode {
+    // Mode configured through `STATE_FETCH_WITH_PROOF` (read once, proofs unless disabled)
+    pub fn from_env() -> Self {
+        static MODE: OnceLock<StateFetchMode> = OnceLock::new();
+        *MODE.get_or_init(|| match utils::dotenv::get_state_fetch_with_proof() {
+            true => StateFetchMode::Proof,
+            false => StateFetchMode::Individual,
+        })
+    }
+}
+
+/// Account state fetched in one go (revm types so it can go straight into a `CacheDB`)
+#[derive(Clone, Debug, Default)]
+pub struct FetchedAccount {
+    /// only set if account info was requested
+    pub info: Option<AccountInfo>,
+    /// (slot, value) for every requested slot
+    pub storage: Vec<(rU256, rU256)>,
+}
+
+// Contract code never changes for a code hash, so it is only fetched once
+fn code_cache() -> &'static DashMap<H256, Bytes> {
+    static CODE_CACHE: OnceLock<DashMap<H256, Bytes>> = OnceLock::new();
+    CODE_CACHE.get_or_init(DashMap::new)
+}
+
+// Fetch account info and storage slots of a single account
+//
+// Arguments:
+// * `provider`: client used to make rpc calls
+// * `address`: account to fetch
+// * `with_info`: fetch balance, nonce and code
+// * `slots`: storage slots to fetch
+// * `block`: block to fetch state at
+// * `mode`: individual calls or `eth_getProof`
+//
+// Returns:
+// Ok(FetchedAccount): fetched state
+// Err(M::Error): if any rpc call failed (a failed proof is retried with individual calls)
+pub async fn fetch_account<M: Middleware>(
+    provider: &M,
+    address: Address,
+    with_info: bool,
+    slots: &[U256],
+    block: Option<BlockId>,
+    mode: StateFetchMode,
+) -> Result<FetchedAccount, M::Error> {
+    match mode {
+        StateFetchMode::Individual => {
+            fetch_individually(provider, address, with_info, slots, block).await
+        }
+        StateFetchMode::Proof => {
+            let locations = slots.iter().map(|slot| H256::from_uint(slot)).collect();
+            let proof = match provider.get_proof(address, locations, block).await {
+                Ok(proof) => proof,
+                Err(e) => {
+                    // e.g. node doesn't serve proofs for this block or limits the number of slots
+                    log::debug!(
+                        "eth_getProof for {:?} failed, fetching it with individual calls: {:?}",
+                        address,
+                        e
+                    );
+                    metrics::STATE_FETCH_PROOF_FALLBACK.inc();
+                    return fetch_individually(provider, address, with_info, slots, block).await;
+                }
+            };
+
+            let info = if with_info {
+                let code = get_code_by_hash(provider, address, proof.code_hash, block).await?;
+                Some(to_account_info(proof.balance, proof.nonce.as_u64(), code))
+            } else {
+                None
+            };
+
+            // proofs are returned in the same order as the requested slots
+            let storage = slots
+                .iter()
+                .zip(proof.storage_proof.iter())
+                .map(|(slot, storage_proof)| ((*slot).into(), storage_proof.value.into()))
+                .collect();
+
+            Ok(FetchedAccount { info, storage })
+        }
+    }
+}
+
+// Fetch account fields and slots with one call each
+async fn fetch_individually<M: Middleware>(
+    provider: &M,
+    address: Address,
+    with_info: bool,
+    slots: &[U256],
+    block: Option<BlockId>,
+) -> Result<FetchedAccount, M::Error> {
+    let info = async {
+        if !with_info {
+            return Ok(None);
+        }
+        let (balance, nonce, code) = tokio::try_join!(
+            provider.get_balance(address, block),
+            provider.get_transaction_count(address, block),
+            provider.get_code(address, block),
+        )?;
+        Ok(Some(to_account_info(balance, nonce.as_u64(), code)))
+    };
+    let storage = try_join_all(slots.iter().map(|slot| async move {
+        let value = provider
+            .get_storage_at(address, H256::from_uint(slot), block)
+            .await?;
+        Ok::<_, M::Error>(((*slot).into(), value.into_uint().into()))
+    }));
+
+    let (info, storage) = tokio::try_join!(info, storage)?;
+    Ok(FetchedAccount { info, storage })
+}
+
+// Get code of `address`, only making an rpc call if `code_hash` has not been seen before
+async fn get_code_by_hash<M: Middleware>(
+    provider: &M,
+    address: Address,
+    code_hash: H256,
+    block: Option<BlockId>,
+) -> Result<Bytes, M::Error> {
+    // accounts without code (some nodes return zero hash for non existing accounts)
+    if code_hash.is_zero() || code_hash.0 == KECCAK_EMPTY.0 {
+        return Ok(Bytes::default());
+    }
+
+    if let Some(code) = code_cache().get(&code_hash) {
+        return Ok(code.clone());
+    }
+
+    let code = provider.get_code(address, block).await?;
+    code_cache().insert(code_hash, code.clone());
+    Ok(code)
+}
+
+// Convert ethers account fields into revm `AccountInfo`
+fn to_account_info(balance: U256, nonce: u64, code: Bytes) -> AccountInfo {
+    let (code, code_hash) = if !code.is_empty() {
+        let code_hash: B256 = ethers::utils::keccak256(&code).into();
+        (code.0, code_hash)
+    } else {
+        (Default::default(), KECCAK_EMPTY)
+    };
+
+    AccountInfo {
+        nonce,
+        balance: balance.into(),
+        code: Some(Bytecode::new_raw(code).to_checked()),
+        code_hash,
+    }
+}
@@ -1,6 +1,7 @@
 pub mod abi;
 pub mod cfmm;
 pub mod forked_db;
+pub mod metrics;
 pub mod relay;
 pub mod rpc_extensions;
 pub mod runner;
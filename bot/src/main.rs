@@ -10,7 +10,12 @@ use fern::colors::{Color, ColoredLevelConfig};
 
 use sando_rs::{
     prelude::{sync_dex, AllPoolsInfo, Dex, Pool, PoolVariant},
-    runner::Bot,
+    relay,
+    runner::{
+        admin::{self, AdminCommand, ADMIN_USAGE},
+        pool_ranking::{PoolRankingReport, POOL_RANKING_FILE},
+        Bot,
+    },
     utils::{self, dotenv::read_env_vars},
 };
 
@@ -19,6 +24,12 @@ async fn main() -> Result<()> {
     log::info!("Starting Bot Initialization");
     dotenv().ok();
 
+    // run subcommand instead of bot if one was passed
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        return run_command(&args).await;
+    }
+
     // setup logger configs
     let mut colors = ColoredLevelConfig::new();
     colors.trace = Color::Cyan;
@@ -51,8 +62,12 @@ async fn main() -> Result<()> {
         format!("{}", utils::constants::get_banner().green().bold())
     );
 
-    // Create the websocket client
-    let client = utils::create_websocket_client().await.unwrap();
+    // Create the rpc client
+    let provider_manager = utils::get_provider_manager();
+    let client = provider_manager.get_with_retry().await;
+
+    // fail early on invalid relay endpoints or signer keys
+    relay::get_all_relay_endpoints().await?;
 
     ///////////////////////////////////////
     //  Setup all dexes and their pools  //
@@ -153,7 +168,7 @@ async fn main() -> Result<()> {
         }
     };
 
-    let current_block = client.get_block_number().await.unwrap();
+    let current_block = client.get_block_number().await?;
     let mut pools = sync_dex(dexes.clone(), &client, current_block, start_block)
         .await
         .unwrap();
@@ -189,13 +204,59 @@ async fn main() -> Result<()> {
 
     // Execution loop (reconnect bot if it dies)
     loop {
-        let client = utils::create_websocket_client().await.unwrap();
-        let mut bot = Bot::new(client, all_pools.clone(), dexes.clone())
-            .await
-            .unwrap();
+        let client = provider_manager.get_with_retry().await;
+        let mut bot = match Bot::new(client.clone(), all_pools.clone(), dexes.clone()).await {
+            Ok(bot) => bot,
+            Err(e) => {
+                log::error!("Failed to create bot: {:?}", e);
+                provider_manager.report_failure(&client).await;
+                tokio::time::sleep(provider_manager.backoff().initial).await;
+                continue;
+            }
+        };
+
+        if let Err(e) = bot.run().await {
+            log::error!("Bot stopped: {:?}", e);
+        }
+        log::error!("Rpc connection lost");
+        provider_manager.report_failure(&client).await;
+    }
+}
 
-        bot.run().await.unwrap();
-        log::error!("Websocket disconnected");
+// Run a cli subcommand
+//
+// Arguments:
+// * `args`: command line arguments (without binary name)
+//
+// Returns:
+// Ok(()) if command ran successfully
+// Err(eyre::Error) if command is unknown or failed
+async fn run_command(args: &[String]) -> Result<()> {
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+
+    match args.as_slice() {
+        ["report", "pools", rest @ ..] => {
+            let limit = match rest.first() {
+                Some(limit) => limit
+                    .parse()
+                    .map_err(|_| eyre::eyre!("{} is not a valid limit", limit))?,
+                None => 50,
+            };
+            let report = PoolRankingReport::load(POOL_RANKING_FILE)?;
+            print!("{}", report.table(limit));
+            Ok(())
+        }
+        ["admin", rest @ ..] => {
+            let send = rest.contains(&"--send");
+            let rest: Vec<&str> = rest.iter().copied().filter(|arg| *arg != "--send").collect();
+            let command = AdminCommand::parse(&rest)?;
+            admin::run(&command, send).await
+        }
+        _ => Err(eyre::eyre!(
+            "unknown command `{}`\nusage:\n  sando-rs                      run bot\n  sando-rs report pools [limit] print latest pool ranking\n{}",
+            args.join(" "),
+            ADMIN_USAGE
+        )),
     }
 }
 
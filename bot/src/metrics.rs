@@ -0,0 +1,251 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Monotonic counter that can be shared as a static across tasks
+pub struct Counter {
+    name: &'static str,
+    value: AtomicU64,
+}
+
+impl Counter {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            value: AtomicU64::new(0),
+        }
+    }
+
+    // Increase counter by one
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    // Increase counter by `amount`
+    pub fn add(&self, amount: u64) {
+        self.value.fetch_add(amount, Ordering::Relaxed);
+    }
+
+    // Current value of counter
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+/// Value that can go up and down (e.g. queue depth)
+pub struct Gauge {
+    name: &'static str,
+    value: AtomicU64,
+}
+
+impl Gauge {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            value: AtomicU64::new(0),
+        }
+    }
+
+    // Set gauge to `value`
+    pub fn set(&self, value: u64) {
+        self.value.store(value, Ordering::Relaxed);
+    }
+
+    // Current value of gauge
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+/// Count, average and max of observed durations (e.g. rpc latency)
+pub struct Latency {
+    name: &'static str,
+    count: AtomicU64,
+    total_micros: AtomicU64,
+    max_micros: AtomicU64,
+}
+
+impl Latency {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            count: AtomicU64::new(0),
+            total_micros: AtomicU64::new(0),
+            max_micros: AtomicU64::new(0),
+        }
+    }
+
+    // Record a single observation
+    pub fn record(&self, elapsed: Duration) {
+        let micros = elapsed.as_micros() as u64;
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_micros.fetch_add(micros, Ordering::Relaxed);
+        self.max_micros.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    // Number of observations
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    // Average observation in microseconds (0 if nothing was recorded)
+    pub fn avg_micros(&self) -> u64 {
+        self.total_micros.load(Ordering::Relaxed) / self.count().max(1)
+    }
+
+    // Largest observation in microseconds
+    pub fn max_micros(&self) -> u64 {
+        self.max_micros.load(Ordering::Relaxed)
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+/*´:°•.°+.*•´.*:˚.°*.˚•´.°:°•.°•.*•´.*:˚.°*.˚•´.°:°•.°+.*•´.*:*/
+/*                      VICTIM ADMISSION                      */
+/*.•°:°.´+˚.*°.˚:*.´•*.+°.•°:´*.´•*.•°.•°:°.´:•˚°.*°.˚:*.´+°.•*/
+
+pub static VICTIMS_SEEN: Counter = Counter::new("victims_seen");
+pub static VICTIMS_ADMITTED: Counter = Counter::new("victims_admitted");
+pub static VICTIMS_REJECTED_BASE_FEE: Counter = Counter::new("victims_rejected_base_fee");
+pub static VICTIMS_REJECTED_INCLUSION: Counter = Counter::new("victims_rejected_inclusion");
+pub static VICTIMS_REJECTED_DEADLINE: Counter = Counter::new("victims_rejected_deadline");
+pub static VICTIMS_REJECTED_MISSING_NONCE: Counter = Counter::new("victims_rejected_missing_nonce");
+
+/*´:°•.°+.*•´.*:˚.°*.˚•´.°:°•.°•.*•´.*:˚.°*.˚•´.°:°•.°+.*•´.*:*/
+/*                    PROCESSING PIPELINE                     */
+/*.•°:°.´+˚.*°.˚:*.´•*.+°.•°:´*.´•*.•°.•°:°.´:•˚°.*°.˚:*.´+°.•*/
+
+pub static WORK_DROPPED_STALE: Counter = Counter::new("work_dropped_stale");
+pub static WORK_DROPPED_QUEUE_FULL: Counter = Counter::new("work_dropped_queue_full");
+pub static QUEUE_DEPTH_TRACE: Gauge = Gauge::new("queue_depth_trace");
+pub static QUEUE_DEPTH_EXTRACT: Gauge = Gauge::new("queue_depth_extract");
+pub static QUEUE_DEPTH_OPTIMIZE: Gauge = Gauge::new("queue_depth_optimize");
+pub static QUEUE_DEPTH_SEND: Gauge = Gauge::new("queue_depth_send");
+
+/*´:°•.°+.*•´.*:˚.°*.˚•´.°:°•.°•.*•´.*:˚.°*.˚•´.°:°•.°+.*•´.*:*/
+/*                        STATE LOADING                       */
+/*.•°:°.´+˚.*°.˚:*.´•*.+°.•°:´*.´•*.•°.•°:°.´:•˚°.*°.˚:*.´+°.•*/
+
+pub static STATE_FETCH_ACCOUNTS: Counter = Counter::new("state_fetch_accounts");
+pub static STATE_FETCH_PROOF_MERGED: Counter = Counter::new("state_fetch_proof_merged");
+pub static STATE_FETCH_PROOF_FALLBACK: Counter = Counter::new("state_fetch_proof_fallback");
+pub static RPC_LATENCY_ACCOUNT: Latency = Latency::new("rpc_latency_account");
+pub static RPC_LATENCY_STORAGE: Latency = Latency::new("rpc_latency_storage");
+pub static RPC_LATENCY_BLOCK_HASH: Latency = Latency::new("rpc_latency_block_hash");
+pub static RPC_LATENCY_CACHE_DB: Latency = Latency::new("rpc_latency_cache_db");
+pub static RPC_LATENCY_PREFETCH: Latency = Latency::new("rpc_latency_prefetch");
+pub static STATE_CACHE_HITS: Counter = Counter::new("state_cache_hits");
+pub static STATE_CACHE_MISSES: Counter = Counter::new("state_cache_misses");
+pub static STATE_CACHE_ENTRIES: Gauge = Gauge::new("state_cache_entries");
+
+/*´:°•.°+.*•´.*:˚.°*.˚•´.°:°•.°•.*•´.*:˚.°*.˚•´.°:°•.°+.*•´.*:*/
+/*                         POOL STATE                         */
+/*.•°:°.´+˚.*°.˚:*.´•*.+°.•°:´*.´•*.•°.•°:°.´:•˚°.*°.˚:*.´+°.•*/
+
+pub static POOL_STATE_UPDATES: Counter = Counter::new("pool_state_updates");
+pub static POOL_STATES_TRACKED: Gauge = Gauge::new("pool_states_tracked");
+pub static POOLS_IGNORED: Gauge = Gauge::new("pools_ignored");
+pub static DUST_TOKENS: Gauge = Gauge::new("dust_tokens");
+
+/*´:°•.°+.*•´.*:˚.°*.˚•´.°:°•.°•.*•´.*:˚.°*.˚•´.°:°•.°+.*•´.*:*/
+/*                            RISK                            */
+/*.•°:°.´+˚.*°.˚:*.´•*.+°.•°:´*.´•*.•°.•°:°.´:•˚°.*°.˚:*.´+°.•*/
+
+pub static POOLS_SKIPPED_RISK: Counter = Counter::new("pools_skipped_risk");
+pub static RISK_VERDICTS: Gauge = Gauge::new("risk_verdicts");
+
+/*´:°•.°+.*•´.*:˚.°*.˚•´.°:°•.°•.*•´.*:˚.°*.˚•´.°:°•.°+.*•´.*:*/
+/*                             GAS                            */
+/*.•°:°.´+˚.*°.˚:*.´•*.+°.•°:´*.´•*.•°.•°:°.´:•˚°.*°.˚:*.´+°.•*/
+
+pub static ACCESS_LIST_GAS_SAVED: Counter = Counter::new("access_list_gas_saved");
+
+/*´:°•.°+.*•´.*:˚.°*.˚•´.°:°•.°•.*•´.*:˚.°*.˚•´.°:°•.°+.*•´.*:*/
+/*                          ENCODING                          */
+/*.•°:°.´+˚.*°.˚:*.´•*.+°.•°:´*.´•*.•°.•°:°.´:•˚°.*°.˚:*.´+°.•*/
+
+pub static ENCODING_LOSS_GWEI: Counter = Counter::new("encoding_loss_gwei");
+
+// All registered counters
+pub fn counters() -> Vec<&'static Counter> {
+    vec![
+        &VICTIMS_SEEN,
+        &VICTIMS_ADMITTED,
+        &VICTIMS_REJECTED_BASE_FEE,
+        &VICTIMS_REJECTED_INCLUSION,
+        &VICTIMS_REJECTED_DEADLINE,
+        &VICTIMS_REJECTED_MISSING_NONCE,
+        &WORK_DROPPED_STALE,
+        &WORK_DROPPED_QUEUE_FULL,
+        &STATE_FETCH_ACCOUNTS,
+        &STATE_FETCH_PROOF_MERGED,
+        &STATE_FETCH_PROOF_FALLBACK,
+        &STATE_CACHE_HITS,
+        &STATE_CACHE_MISSES,
+        &POOL_STATE_UPDATES,
+        &POOLS_SKIPPED_RISK,
+        &ACCESS_LIST_GAS_SAVED,
+        &ENCODING_LOSS_GWEI,
+    ]
+}
+
+// All registered gauges
+pub fn gauges() -> Vec<&'static Gauge> {
+    vec![
+        &QUEUE_DEPTH_TRACE,
+        &QUEUE_DEPTH_EXTRACT,
+        &QUEUE_DEPTH_OPTIMIZE,
+        &QUEUE_DEPTH_SEND,
+        &STATE_CACHE_ENTRIES,
+        &POOL_STATES_TRACKED,
+        &POOLS_IGNORED,
+        &DUST_TOKENS,
+        &RISK_VERDICTS,
+    ]
+}
+
+// All registered latencies
+pub fn latencies() -> Vec<&'static Latency> {
+    vec![
+        &RPC_LATENCY_ACCOUNT,
+        &RPC_LATENCY_STORAGE,
+        &RPC_LATENCY_BLOCK_HASH,
+        &RPC_LATENCY_CACHE_DB,
+        &RPC_LATENCY_PREFETCH,
+    ]
+}
+
+// One line summary of all metrics (e.g. "victims_seen=10 victims_admitted=4 ...")
+pub fn summary() -> String {
+    let counters = counters()
+        .into_iter()
+        .map(|counter| format!("{}={}", counter.name(), counter.get()));
+    let gauges = gauges()
+        .into_iter()
+        .map(|gauge| format!("{}={}", gauge.name(), gauge.get()));
+    let latencies = latencies().into_iter().map(|latency| {
+        format!(
+            "{}=(n:{} avg:{}us max:{}us)",
+            latency.name(),
+            latency.count(),
+            latency.avg_micros(),
+            latency.max_micros()
+        )
+    });
+
+    counters
+        .chain(gauges)
+        .chain(latencies)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
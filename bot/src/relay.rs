@@ -1,28 +1,30 @@
 use std::sync::Arc;
 
+use crate::types::RelayError;
 use crate::utils;
+use crate::utils::transport::RpcClient;
 use ethers::prelude::*;
 use ethers_flashbots::*;
 use reqwest::Url;
+use tokio::sync::OnceCell;
 
-pub struct BundleRelay {
-    pub flashbots_client:
-        SignerMiddleware<FlashbotsMiddleware<Arc<Provider<Ws>>, LocalWallet>, LocalWallet>,
+// Relay clients, built once and shared by every bundle sent
+static RELAYS: OnceCell<Vec<BundleRelay<RpcClient>>> = OnceCell::const_new();
+
+pub struct BundleRelay<M: Middleware> {
+    pub flashbots_client: SignerMiddleware<FlashbotsMiddleware<Arc<M>, LocalWallet>, LocalWallet>,
     pub relay_name: String,
 }
 
-impl BundleRelay {
+impl<M: Middleware> BundleRelay<M> {
     pub fn new(
         relay_end_point: Url,
         relay_name: String,
-        client: &Arc<Provider<Ws>>,
-    ) -> Result<BundleRelay, url::ParseError> {
+        client: &Arc<M>,
+    ) -> Result<BundleRelay<M>, RelayError> {
         // Extract wallets from .env keys
-        let bundle_private_key = std::env::var("FLASHBOTS_AUTH_KEY").unwrap();
-        let searcher_private_key = std::env::var("SEARCHER_PRIVATE_KEY").unwrap();
-
-        let bundle_signer = bundle_private_key.parse::<LocalWallet>().unwrap();
-        let searcher_signer = searcher_private_key.parse::<LocalWallet>().unwrap();
+        let bundle_signer = wallet_from_env("FLASHBOTS_AUTH_KEY")?;
+        let searcher_signer = wallet_from_env("SEARCHER_PRIVATE_KEY")?;
 
         // Setup the Ethereum client with flashbots middleware
         let flashbots_middleware =
@@ -39,6 +41,14 @@ impl BundleRelay {
     }
 }
 
+// Parse the private key held by env var `name`
+fn wallet_from_env(name: &'static str) -> Result<LocalWallet, RelayError> {
+    std::env::var(name)
+        .map_err(|_| RelayError::MissingKey(name))?
+        .parse::<LocalWallet>()
+        .map_err(|e| RelayError::InvalidKey(name, e))
+}
+
 pub fn construct_bundle(
     signed_txs: Vec<Bytes>,
     target_block: U64, // Current block number
@@ -62,31 +72,49 @@ pub fn construct_bundle(
     bundle_request
 }
 
-pub async fn get_all_relay_endpoints() -> Vec<BundleRelay> {
-    let client = utils::create_websocket_client().await.unwrap();
-
-    let endpoints = vec![
-        ("builder0x69", "https://builder0x69.io/"),
-        ("rsync-builder", "https://rsync-builder.xyz/"),
-        ("beaverbuild", "https://rpc.beaverbuild.org/"),
-        ("titanbuilder", "https://rpc.titanbuilder.xyz"),
-        ("flashbots", "https://relay.flashbots.net/"),
-        ("eth-builder", "https://eth-builder.com/"),
-        // ("lightspeedbuilder", "https://rpc.lightspeedbuilder.info/"),
-        ("gambitlabs", "https://builder.gmbit.co/rpc"),
-        // ("bloxroute", "https://mev.api.blxrbdn.com"),
-        ("boba-builder", "https://boba-builder.com/searcher"),
-        ("nfactorial", "https://rpc.nfactorial.xyz"),
-        ("buildAI", "https://buildai.net"),
-        ("payload", "https://rpc.payload.de"),
-    ];
-
-    let mut relays: Vec<BundleRelay> = vec![];
-
-    for (name, endpoint) in endpoints {
-        let relay = BundleRelay::new(Url::parse(endpoint).unwrap(), name.into(), &client).unwrap();
-        relays.push(relay);
+// Relays that bundles are sent to (name, endpoint)
+const RELAY_ENDPOINTS: [(&str, &str); 11] = [
+    ("builder0x69", "https://builder0x69.io/"),
+    ("rsync-builder", "https://rsync-builder.xyz/"),
+    ("beaverbuild", "https://rpc.beaverbuild.org/"),
+    ("titanbuilder", "https://rpc.titanbuilder.xyz"),
+    ("flashbots", "https://relay.flashbots.net/"),
+    ("eth-builder", "https://eth-builder.com/"),
+    // ("lightspeedbuilder", "https://rpc.lightspeedbuilder.info/"),
+    ("gambitlabs", "https://builder.gmbit.co/rpc"),
+    // ("bloxroute", "https://mev.api.blxrbdn.com"),
+    ("boba-builder", "https://boba-builder.com/searcher"),
+    ("nfactorial", "https://rpc.nfactorial.xyz"),
+    ("buildAI", "https://buildai.net"),
+    ("payload", "https://rpc.payload.de"),
+];
+
+// Names of all relays that bundles are sent to
+pub fn get_relay_names() -> Vec<&'static str> {
+    RELAY_ENDPOINTS.iter().map(|(name, _)| *name).collect()
+}
+
+// Clients of all relays, built on first use (bot startup) and reused afterwards
+//
+// Returns:
+// Ok(&[BundleRelay]): a client per relay endpoint
+// Err(RelayError): if an endpoint or signer key is invalid or no provider is available
+pub async fn get_all_relay_endpoints() -> Result<&'static [BundleRelay<RpcClient>], RelayError> {
+    let relays = RELAYS.get_or_try_init(build_relays).await?;
+    Ok(relays.as_slice())
+}
+
+// Create a client for every relay endpoint
+async fn build_relays() -> Result<Vec<BundleRelay<RpcClient>>, RelayError> {
+    let client = utils::create_rpc_client().await?;
+
+    let mut relays: Vec<BundleRelay<RpcClient>> = vec![];
+
+    for (name, endpoint) in RELAY_ENDPOINTS {
+        let endpoint =
+            Url::parse(endpoint).map_err(|e| RelayError::InvalidEndpoint(name.to_string(), e))?;
+        relays.push(BundleRelay::new(endpoint, name.into(), &client)?);
     }
 
-    relays
+    Ok(relays)
 }
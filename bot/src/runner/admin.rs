@@ -0,0 +1,661 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use colored::Colorize;
+use ethers::prelude::*;
+use ethers::types::transaction::eip2930::AccessList;
+use ethers::utils::format_ether;
+use eyre::eyre;
+use revm::db::{CacheDB, DatabaseRef, EmptyDB};
+use revm::primitives::{CreateScheme, ExecutionResult, Output, TransactTo};
+use revm::EVM;
+
+use crate::prelude::fork_db::ForkDB;
+use crate::prelude::fork_factory::ForkFactory;
+use crate::prelude::gas_estimator::estimate_gas_limit;
+use crate::prelude::{get_amount_out_evm_v2, get_balance_of_evm, setup_block_state};
+use crate::relay;
+use crate::types::BlockInfo;
+use crate::utils;
+use crate::utils::constants::{get_metamorphic_factory_address, get_weth_address};
+use crate::utils::dotenv;
+use crate::utils::transport::RpcClient;
+use crate::utils::tx_builder::sandwich::{admin, v2, JumpTable, SandwichPayload};
+
+use super::ledger::{self, LedgerEvent};
+
+/// Usage of `sando-rs admin`
+pub const ADMIN_USAGE: &str = "\
+  sando-rs admin deploy <runtime file> <salt>  deploy huffc --bin-runtime output via metamorphic factory
+  sando-rs admin deposit-eth <eth>             wrap eth from searcher into weth held by contract
+  sando-rs admin deposit-weth <weth>           transfer weth from searcher to contract
+  sando-rs admin withdraw-weth <weth|all>      unwrap weth held by contract and send it to searcher
+  sando-rs admin withdraw-eth                  send eth held by contract to searcher
+  sando-rs admin withdraw-dust <token> <pair>  sell all of token held by contract for weth on v2 pair
+  sando-rs admin seppuku                       self destruct contract (code stays since EIP-6780)
+  (admin commands only simulate, add --send to send them to relays)";
+
+// Upper bound of the gas limit search for admin txs (deploys are ~1m gas)
+const ADMIN_MAX_GAS_LIMIT: u64 = 5_000_000;
+
+// Number of blocks an admin bundle is resent for before giving up
+const ADMIN_SEND_ATTEMPTS: usize = 3;
+
+/// Operation on the sandwich contract run by `sando-rs admin`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdminCommand {
+    /// deploy runtime code and clone it to the metamorphic address of `salt`
+    Deploy { runtime_code: Bytes, salt: H256 },
+    /// send eth from searcher, contract wraps it into weth
+    DepositEth { amount: U256 },
+    /// transfer weth held by searcher to contract
+    DepositWeth { amount: U256 },
+    /// unwrap weth held by contract and send it to searcher (None withdraws all)
+    WithdrawWeth { amount: Option<U256> },
+    /// send eth held by contract to searcher
+    WithdrawEth,
+    /// sell all of `token` held by contract for weth on v2 `pair`
+    WithdrawDust { token: Address, pair: Address },
+    /// self destruct contract so that new code can be deployed to its address
+    Seppuku,
+}
+
+impl AdminCommand {
+    // Parse admin command from cli arguments
+    //
+    // Arguments:
+    // * `args`: arguments after `admin` (without `--send`)
+    //
+    // Returns:
+    // Ok(AdminCommand): if arguments are a valid admin command
+    // Err(eyre::Error): if command is unknown, an argument is invalid or runtime file can't be read
+    pub fn parse(args: &[&str]) -> eyre::Result<Self> {
+        match args {
+            ["deploy", runtime_file, salt] => {
+                let runtime_code = std::fs::read_to_string(runtime_file)
+                    .map_err(|e| eyre!("Failed to read {}: {}", runtime_file, e))?;
+                Ok(Self::Deploy {
+                    runtime_code: Bytes::from_str(runtime_code.trim())
+                        .map_err(|_| eyre!("{} does not hold hex bytecode", runtime_file))?,
+                    salt: H256::from_str(salt)
+                        .map_err(|_| eyre!("{} is not a valid salt", salt))?,
+                })
+            }
+            ["deposit-eth", amount] => Ok(Self::DepositEth {
+                amount: parse_amount(amount)?,
+            }),
+            ["deposit-weth", amount] => Ok(Self::DepositWeth {
+                amount: parse_amount(amount)?,
+            }),
+            ["withdraw-weth", "all"] => Ok(Self::WithdrawWeth { amount: None }),
+            ["withdraw-weth", amount] => Ok(Self::WithdrawWeth {
+                amount: Some(parse_amount(amount)?),
+            }),
+            ["withdraw-eth"] => Ok(Self::WithdrawEth),
+            ["withdraw-dust", token, pair] => Ok(Self::WithdrawDust {
+                token: parse_address(token)?,
+                pair: parse_address(pair)?,
+            }),
+            ["seppuku"] => Ok(Self::Seppuku),
+            _ => Err(eyre!("unknown admin command `{}`", args.join(" "))),
+        }
+    }
+}
+
+impl fmt::Display for AdminCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Deploy { runtime_code, salt } => {
+                write!(
+                    f,
+                    "deploy {} bytes with salt {:?}",
+                    runtime_code.len(),
+                    salt
+                )
+            }
+            Self::DepositEth { amount } => write!(f, "deposit-eth {}", format_ether(*amount)),
+            Self::DepositWeth { amount } => write!(f, "deposit-weth {}", format_ether(*amount)),
+            Self::WithdrawWeth { amount: None } => write!(f, "withdraw-weth all"),
+            Self::WithdrawWeth {
+                amount: Some(amount),
+            } => write!(f, "withdraw-weth {}", format_ether(*amount)),
+            Self::WithdrawEth => write!(f, "withdraw-eth"),
+            Self::WithdrawDust { token, pair } => {
+                write!(f, "withdraw-dust {:?} {:?}", token, pair)
+            }
+            Self::Seppuku => write!(f, "seppuku"),
+        }
+    }
+}
+
+/// Tx of an admin command after it was simulated
+pub(super) struct AdminTx {
+    /// None creates a contract
+    pub to: Option<Address>,
+    pub data: Bytes,
+    pub value: U256,
+    pub gas_limit: u64,
+    pub gas_used: u64,
+    /// return data (or deployed code)
+    pub output: Bytes,
+}
+
+/// Balances touched by admin commands
+pub(super) struct Balances {
+    pub contract_eth: U256,
+    pub contract_weth: U256,
+    pub searcher_eth: U256,
+    pub searcher_weth: U256,
+}
+
+/// Admin command simulated in the block after latest
+pub(super) struct SimulatedCommand {
+    pub next_block: BlockInfo,
+    pub txs: Vec<AdminTx>,
+    /// balances before the first tx
+    pub before: Balances,
+    /// balances after the last tx
+    pub after: Balances,
+}
+
+impl Balances {
+    // Read balances from evm state
+    fn read(
+        evm: &mut EVM<ForkDB>,
+        next_block: &BlockInfo,
+        sandwich: Address,
+        searcher: Address,
+    ) -> eyre::Result<Self> {
+        let weth = get_weth_address();
+        let weth_balance = |owner, evm: &mut EVM<ForkDB>| {
+            get_balance_of_evm(weth, owner, next_block, evm)
+                .map_err(|e| eyre!("Failed to read weth balance of {:?}: {:?}", owner, e))
+        };
+        Ok(Self {
+            contract_eth: eth_balance(evm, sandwich)?,
+            contract_weth: weth_balance(sandwich, evm)?,
+            searcher_eth: eth_balance(evm, searcher)?,
+            searcher_weth: weth_balance(searcher, evm)?,
+        })
+    }
+}
+
+// Simulate `command` in revm, then (if `send`) send it to all relays until it lands
+//
+// Arguments:
+// * `command`: admin command to run
+// * `send`: only simulate if false
+//
+// Returns:
+// Ok(()): if simulation succeeded (and bundle landed when sending)
+// Err(eyre::Error): if contract doesn't match `sandwich.huff`, simulation reverted, every relay
+// rejected the bundle or it did not land within `ADMIN_SEND_ATTEMPTS` blocks
+pub async fn run(command: &AdminCommand, send: bool) -> eyre::Result<()> {
+    let client = utils::create_rpc_client().await?;
+    let searcher_wallet = dotenv::get_searcher_wallet();
+    let searcher = searcher_wallet.address();
+    let sandwich = dotenv::get_sandwich_contract_address();
+
+    // payloads jump into the contract, encode them against the code they are sent to
+    let jump_table = match command {
+        AdminCommand::Deploy { runtime_code, salt } => {
+            if salt.as_bytes()[..20] != searcher.as_bytes()[..] {
+                return Err(eyre!(
+                    "Salt {:?} has to start with the searcher address {:?}",
+                    salt,
+                    searcher
+                ));
+            }
+            JumpTable::from_contract(runtime_code)?
+        }
+        _ => JumpTable::from_contract(&client.get_code(sandwich, None).await?)?,
+    };
+    jump_table.verify(searcher)?;
+
+    for attempt in 1..=ADMIN_SEND_ATTEMPTS {
+        let nonce = client.get_transaction_count(searcher, None).await?;
+        let simulated = simulate_in_next_block(&client, command, &jump_table, nonce).await?;
+        let next_block = simulated.next_block.clone();
+        print_report(command, &simulated);
+
+        if !send {
+            println!("{}", "Simulation only, add --send to send it".yellow());
+            return Ok(());
+        }
+
+        let (tx_hashes, rejected) =
+            send_txs(&simulated.txs, &next_block, nonce, &searcher_wallet).await?;
+        ledger::global().record(LedgerEvent::AdminSubmitted {
+            target_block: next_block.number,
+            nonce,
+            command: command.to_string(),
+            txs: tx_hashes.clone(),
+        });
+        for rejection in rejected {
+            println!("{}", rejection);
+        }
+        println!(
+            "{}",
+            format!("Sent {:?} targeting block {}", tx_hashes, next_block.number).blue()
+        );
+
+        // wait for target block before looking for the txs
+        while client.get_block_number().await? < next_block.number {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        match client.get_transaction_receipt(tx_hashes[0]).await? {
+            Some(receipt) => {
+                if receipt.status != Some(U64::from(1)) {
+                    return Err(eyre!("{:?} reverted on chain", tx_hashes[0]));
+                }
+                println!(
+                    "{}",
+                    format!(
+                        "Included in block {:?}",
+                        receipt.block_number.unwrap_or_default()
+                    )
+                    .green()
+                    .bold()
+                );
+                return Ok(());
+            }
+            None => println!(
+                "{}",
+                format!(
+                    "Not included in block {} ({}/{})",
+                    next_block.number, attempt, ADMIN_SEND_ATTEMPTS
+                )
+                .yellow()
+            ),
+        }
+    }
+
+    Err(eyre!(
+        "`{}` not included after {} blocks",
+        command,
+        ADMIN_SEND_ATTEMPTS
+    ))
+}
+
+// Fork latest block and simulate `command` in the block after it
+//
+// Arguments:
+// * `client`: provider the fork fetches state from
+// * `command`: admin command to simulate
+// * `jump_table`: jump table of the contract the txs are sent to
+// * `nonce`: searcher nonce of the first tx
+//
+// Returns:
+// Ok(SimulatedCommand): simulated txs with balances before and after them
+// Err(eyre::Error): if latest block can't be fetched or a tx does not succeed
+pub(super) async fn simulate_in_next_block(
+    client: &Arc<RpcClient>,
+    command: &AdminCommand,
+    jump_table: &JumpTable,
+    nonce: U256,
+) -> eyre::Result<SimulatedCommand> {
+    let searcher = dotenv::get_searcher_wallet().address();
+    let sandwich = dotenv::get_sandwich_contract_address();
+
+    let latest_block = client
+        .get_block(BlockNumber::Latest)
+        .await?
+        .ok_or_else(|| eyre!("Latest block not found"))?;
+    let fork_block = Some(BlockId::Number(BlockNumber::Number(
+        latest_block.number.unwrap_or_default(),
+    )));
+    let next_block = BlockInfo::find_next_block_info(latest_block);
+
+    let fork_factory = ForkFactory::new_sandbox_factory(
+        client.clone(),
+        CacheDB::new(EmptyDB::default()),
+        fork_block,
+        None,
+    );
+    let mut evm = revm::EVM::new();
+    evm.database(fork_factory.new_sandbox_fork());
+    setup_block_state(&mut evm, &next_block);
+
+    let before = Balances::read(&mut evm, &next_block, sandwich, searcher)?;
+    let txs = simulate(command, jump_table, &next_block, nonce, &before, &mut evm)?;
+    let after = Balances::read(&mut evm, &next_block, sandwich, searcher)?;
+
+    Ok(SimulatedCommand {
+        next_block,
+        txs,
+        before,
+        after,
+    })
+}
+
+// Build the txs of `command` and run them in `evm` one after another
+//
+// Arguments:
+// * `command`: admin command to build txs for
+// * `jump_table`: jump table of the contract the txs are sent to
+// * `next_block`: block the txs are simulated in
+// * `nonce`: searcher nonce of the first tx
+// * `before`: balances before the first tx
+// * `evm`: evm forked from latest block
+//
+// Returns:
+// Ok(Vec<AdminTx>): simulated txs with their gas limits
+// Err(eyre::Error): if a tx can't be built or does not succeed
+fn simulate(
+    command: &AdminCommand,
+    jump_table: &JumpTable,
+    next_block: &BlockInfo,
+    nonce: U256,
+    before: &Balances,
+    evm: &mut EVM<ForkDB>,
+) -> eyre::Result<Vec<AdminTx>> {
+    let searcher = dotenv::get_searcher_wallet().address();
+    let sandwich = dotenv::get_sandwich_contract_address();
+    let weth = get_weth_address();
+    let block_number = next_block.number;
+
+    let calls: Vec<(Option<Address>, Bytes, U256)> = match command {
+        AdminCommand::Deploy { runtime_code, salt } => {
+            let implementation = ethers::utils::get_contract_address(searcher, nonce);
+            vec![
+                (None, admin::init_code(runtime_code), U256::zero()),
+                (
+                    Some(get_metamorphic_factory_address()),
+                    admin::metamorphic_deploy_calldata(*salt, implementation),
+                    U256::zero(),
+                ),
+            ]
+        }
+        AdminCommand::DepositEth { amount } => {
+            let (payload, value) = admin::deposit_weth_payload(jump_table, block_number, *amount);
+            vec![(Some(sandwich), payload.into(), value)]
+        }
+        AdminCommand::DepositWeth { amount } => vec![(
+            Some(weth),
+            admin::erc20_transfer_calldata(sandwich, *amount),
+            U256::zero(),
+        )],
+        AdminCommand::WithdrawWeth { amount } => {
+            let amount = amount.unwrap_or(before.contract_weth);
+            let (payload, value) = admin::recover_weth_payload(jump_table, block_number, amount);
+            vec![(Some(sandwich), payload.into(), value)]
+        }
+        AdminCommand::WithdrawEth => {
+            let (payload, value) =
+                admin::recover_weth_payload(jump_table, block_number, U256::zero());
+            vec![(Some(sandwich), payload.into(), value)]
+        }
+        AdminCommand::WithdrawDust { token, pair } => {
+            let balance = get_balance_of_evm(*token, sandwich, next_block, evm)
+                .map_err(|e| eyre!("Failed to read balance of {:?}: {:?}", token, e))?;
+            // sell as much as the payload can encode
+            let amount_in = v2::decode_intermediary(balance, false, *token);
+            if amount_in.is_zero() {
+                return Err(eyre!("Contract holds no {:?} that can be sold", token));
+            }
+            let amount_out = get_amount_out_evm_v2(amount_in, *pair, *token, weth, evm)
+                .map_err(|e| eyre!("Failed to quote {:?}: {:?}", pair, e))?;
+            let (payload, value) = SandwichPayload::V2WethOutput {
+                block_number: block_number.low_u32() as u8,
+                pair: *pair,
+                token: *token,
+                weth_is_token0: weth < *token,
+                amount_in,
+                amount_out,
+            }
+            .encode(jump_table);
+            vec![(Some(sandwich), payload.into(), value)]
+        }
+        AdminCommand::Seppuku => vec![(
+            Some(sandwich),
+            admin::seppuku_payload(jump_table, block_number).into(),
+            U256::zero(),
+        )],
+    };
+
+    let mut txs = vec![];
+    for (to, data, value) in calls {
+        evm.env.tx.caller = searcher.0.into();
+        evm.env.tx.transact_to = match to {
+            Some(to) => TransactTo::Call(to.0.into()),
+            None => TransactTo::Create(CreateScheme::Create),
+        };
+        evm.env.tx.data = data.0.clone();
+        evm.env.tx.value = value.into();
+        evm.env.tx.gas_price = next_block.base_fee.into();
+        evm.env.tx.gas_limit = ADMIN_MAX_GAS_LIMIT;
+        evm.env.tx.access_list = Vec::default();
+
+        let gas_limit = estimate_gas_limit(evm, dotenv::get_gas_limit_margin_bps())
+            .map_err(|e| eyre!("Failed to estimate gas limit: {:?}", e))?;
+        let (gas_used, output) = match evm
+            .transact_commit()
+            .map_err(|e| eyre!("Failed to simulate: {:?}", e))?
+        {
+            ExecutionResult::Success {
+                gas_used, output, ..
+            } => match output {
+                Output::Call(o) => (gas_used, o),
+                Output::Create(o, _) => (gas_used, o),
+            },
+            ExecutionResult::Revert { output, .. } => {
+                return Err(eyre!(
+                    "`{}` reverted in simulation: 0x{}",
+                    command,
+                    hex::encode(output)
+                ))
+            }
+            ExecutionResult::Halt { reason, .. } => {
+                return Err(eyre!("`{}` halted in simulation: {:?}", command, reason))
+            }
+        };
+
+        txs.push(AdminTx {
+            to,
+            data,
+            value,
+            gas_limit,
+            gas_used,
+            output: output.into(),
+        });
+    }
+
+    Ok(txs)
+}
+
+// Sign simulated txs and send them as one bundle targeting `next_block` to all relays
+//
+// Returns:
+// Ok((Vec<H256>, Vec<String>)): hashes of the sent txs (never empty), errors of relays that
+// rejected the bundle
+// Err(eyre::Error): if there are no txs, txs could not be signed, relays could not be set up or
+// every relay rejected the bundle
+pub(super) async fn send_txs(
+    txs: &[AdminTx],
+    next_block: &BlockInfo,
+    nonce: U256,
+    searcher_wallet: &LocalWallet,
+) -> eyre::Result<(Vec<H256>, Vec<String>)> {
+    if txs.is_empty() {
+        return Err(eyre!("No txs to send"));
+    }
+
+    let max_priority_fee = U256::from(dotenv::get_admin_priority_fee_gwei()) * U256::exp10(9);
+    // bundle only targets `next_block`, whose base fee is already known
+    let max_fee = next_block.base_fee + max_priority_fee;
+
+    let mut signed_txs = vec![];
+    for (index, tx) in txs.iter().enumerate() {
+        let request = Eip1559TransactionRequest {
+            to: tx.to.map(NameOrAddress::Address),
+            from: Some(searcher_wallet.address()),
+            data: Some(tx.data.clone()),
+            chain_id: Some(U64::from(1)),
+            max_priority_fee_per_gas: Some(max_priority_fee),
+            max_fee_per_gas: Some(max_fee),
+            gas: Some(U256::from(tx.gas_limit)),
+            nonce: Some(nonce + index),
+            value: Some(tx.value),
+            access_list: AccessList::default(),
+        };
+        signed_txs.push(utils::sign_eip1559(request, searcher_wallet).await?);
+    }
+    let tx_hashes: Vec<H256> = signed_txs
+        .iter()
+        .map(|tx| H256::from(ethers::utils::keccak256(tx)))
+        .collect();
+
+    let bundle =
+        relay::construct_bundle(signed_txs, next_block.number, next_block.timestamp.as_u64());
+
+    let relays = relay::get_all_relay_endpoints().await?;
+    let sends = relays.iter().map(|relay| {
+        let bundle = bundle.clone();
+        async move {
+            let sent = relay.flashbots_client.inner().send_bundle(&bundle).await;
+            (relay.relay_name.clone(), sent.map(|_| ()))
+        }
+    });
+    let rejected: Vec<String> = futures::future::join_all(sends)
+        .await
+        .into_iter()
+        .filter_map(|(relay_name, sent)| {
+            let e = sent.err()?;
+            Some(format!("{} Failed to send bundle: {:?}", relay_name, e))
+        })
+        .collect();
+    if rejected.len() == relays.len() {
+        return Err(eyre!(
+            "Every relay rejected the bundle:\n{}",
+            rejected.join("\n")
+        ));
+    }
+
+    Ok((tx_hashes, rejected))
+}
+
+// Print what the simulated txs do
+fn print_report(command: &AdminCommand, simulated: &SimulatedCommand) {
+    let SimulatedCommand {
+        next_block,
+        txs,
+        before,
+        after,
+    } = simulated;
+    println!(
+        "{}",
+        format!("`{}` simulated in block {}", command, next_block.number)
+            .green()
+            .bold()
+    );
+    for tx in txs {
+        let to = match tx.to {
+            Some(to) => format!("{:?}", to),
+            None => "contract creation".to_string(),
+        };
+        println!(
+            "  {} value {} ETH gas used {} gas limit {}",
+            to,
+            format_ether(tx.value),
+            tx.gas_used,
+            tx.gas_limit
+        );
+    }
+    if let AdminCommand::Deploy { .. } = command {
+        // factory returns the address the code was cloned to
+        if let Some(factory_tx) = txs.last().filter(|tx| tx.output.len() == 32) {
+            let deployed = Address::from_slice(&factory_tx.output[12..32]);
+            let sandwich = dotenv::get_sandwich_contract_address();
+            println!("  metamorphic contract deployed to {:?}", deployed);
+            if deployed != sandwich {
+                println!(
+                    "{}",
+                    format!("  SANDWICH_CONTRACT is {:?}, update it", sandwich).red()
+                );
+            }
+        }
+    }
+
+    if let AdminCommand::Seppuku = command {
+        // revm simulates selfdestruct as it was before cancun
+        println!(
+            "{}",
+            "  since EIP-6780 selfdestruct only sends the contract's eth to the searcher, \
+             its code stays and the metamorphic address can't be redeployed (deploy with a new salt)"
+                .yellow()
+        );
+    }
+
+    let rows = [
+        ("contract eth", before.contract_eth, after.contract_eth),
+        ("contract weth", before.contract_weth, after.contract_weth),
+        ("searcher eth", before.searcher_eth, after.searcher_eth),
+        ("searcher weth", before.searcher_weth, after.searcher_weth),
+    ];
+    for (name, before, after) in rows {
+        println!(
+            "  {:<14} {} -> {}",
+            name,
+            format_ether(before),
+            format_ether(after)
+        );
+    }
+}
+
+// Eth balance of `address` in evm state
+fn eth_balance(evm: &EVM<ForkDB>, address: Address) -> eyre::Result<U256> {
+    let db = evm
+        .db
+        .as_ref()
+        .ok_or_else(|| eyre!("Evm has no database"))?;
+    let account = DatabaseRef::basic(db, address.0.into())
+        .map_err(|e| eyre!("Failed to read account {:?}: {:?}", address, e))?;
+    Ok(account
+        .map(|account| account.balance.into())
+        .unwrap_or_default())
+}
+
+fn parse_amount(amount: &str) -> eyre::Result<U256> {
+    ethers::utils::parse_ether(amount).map_err(|_| eyre!("{} is not a valid amount", amount))
+}
+
+fn parse_address(address: &str) -> eyre::Result<Address> {
+    Address::from_str(address).map_err(|_| eyre!("{} is not a valid address", address))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_admin_commands() {
+        assert_eq!(
+            AdminCommand::parse(&["deposit-eth", "1.5"]).unwrap(),
+            AdminCommand::DepositEth {
+                amount: U256::exp10(17) * 15
+            }
+        );
+        assert_eq!(
+            AdminCommand::parse(&["withdraw-weth", "all"]).unwrap(),
+            AdminCommand::WithdrawWeth { amount: None }
+        );
+        assert_eq!(
+            AdminCommand::parse(&[
+                "withdraw-dust",
+                "0x6B175474E89094C44Da98b954EedeAC495271d0F",
+                "0xA478c2975Ab1Ea89e8196811F51A7B7Ade33eB11"
+            ])
+            .unwrap()
+            .to_string(),
+            "withdraw-dust 0x6b175474e89094c44da98b954eedeac495271d0f 0xa478c2975ab1ea89e8196811f51a7b7ade33eb11"
+        );
+        assert_eq!(
+            AdminCommand::parse(&["seppuku"]).unwrap(),
+            AdminCommand::Seppuku
+        );
+        assert!(AdminCommand::parse(&["withdraw-weth", "lots"]).is_err());
+        assert!(AdminCommand::parse(&["seppuku", "now"]).is_err());
+    }
+}
@@ -0,0 +1,353 @@
+use ethers::prelude::*;
+use std::collections::VecDeque;
+
+use crate::metrics::{self, Counter};
+use crate::types::BlockInfo;
+use crate::utils;
+use crate::utils::swap_decoder;
+
+use super::backlog::VictimKey;
+use super::mempool_index::MempoolIndex;
+
+// Number of recent blocks whose tips are used to estimate inclusion probability
+const RECENT_BLOCKS: usize = 10;
+// Percentile of a block's tips taken as the tip needed to make it into that block
+const INCLUSION_TIP_PERCENTILE: usize = 10;
+
+/// Reason a pending tx was not admitted as a victim
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rejection {
+    // max fee can't cover next block's base fee
+    BaseFee,
+    // tip is too low compared to recently included txs
+    UnlikelyInclusion,
+    // swap deadline is before next block's timestamp
+    DeadlinePassed,
+    // a nonce between the sender's on-chain nonce and the tx's nonce was never seen pending
+    MissingNonce,
+}
+
+impl Rejection {
+    // Metric counting txs rejected for this reason
+    pub fn counter(&self) -> &'static Counter {
+        match self {
+            Rejection::BaseFee => &metrics::VICTIMS_REJECTED_BASE_FEE,
+            Rejection::UnlikelyInclusion => &metrics::VICTIMS_REJECTED_INCLUSION,
+            Rejection::DeadlinePassed => &metrics::VICTIMS_REJECTED_DEADLINE,
+            Rejection::MissingNonce => &metrics::VICTIMS_REJECTED_MISSING_NONCE,
+        }
+    }
+}
+
+/// Decides which pending txs are worth simulating and how likely they are to land
+pub struct VictimAdmission {
+    /// tip needed to be included in each of the most recent blocks (newest last)
+    recent_inclusion_tips: VecDeque<U256>,
+    min_inclusion_probability: f64,
+}
+
+impl VictimAdmission {
+    // Create a new `VictimAdmission`
+    //
+    // Arguments:
+    // * `min_inclusion_probability`: victims estimated below this are rejected
+    //
+    // Returns: a new `VictimAdmission` instance
+    pub fn new(min_inclusion_probability: f64) -> Self {
+        Self {
+            recent_inclusion_tips: VecDeque::with_capacity(RECENT_BLOCKS),
+            min_inclusion_probability,
+        }
+    }
+
+    // Create a `VictimAdmission` with threshold from env
+    pub fn from_env() -> Self {
+        Self::new(utils::dotenv::get_min_inclusion_probability())
+    }
+
+    // Record the tips paid by txs in a newly mined block
+    //
+    // Arguments:
+    // * `&mut self`: mutable reference to self
+    // * `block`: mined block including its transactions
+    //
+    // Returns: This function returns nothing
+    pub fn record_block(&mut self, block: &Block<Transaction>) {
+        let base_fee = block.base_fee_per_gas.unwrap_or_default();
+        let mut tips: Vec<U256> = block
+            .transactions
+            .iter()
+            .map(|tx| effective_tip(tx, base_fee))
+            .collect();
+
+        if tips.is_empty() {
+            return;
+        }
+
+        tips.sort();
+        let index = (tips.len() * INCLUSION_TIP_PERCENTILE) / 100;
+        let inclusion_tip = tips[index.min(tips.len() - 1)];
+
+        if self.recent_inclusion_tips.len() == RECENT_BLOCKS {
+            self.recent_inclusion_tips.pop_front();
+        }
+        self.recent_inclusion_tips.push_back(inclusion_tip);
+    }
+
+    // Estimate chance that `tx` lands in next block (share of recent blocks it would have made it into)
+    //
+    // Arguments:
+    // * `tx`: pending transaction
+    // * `next_block`: holds information about next block
+    //
+    // Returns:
+    // f64: probability between 0 and 1 (1 if no blocks were recorded yet)
+    pub fn inclusion_probability(&self, tx: &Transaction, next_block: &BlockInfo) -> f64 {
+        if self.recent_inclusion_tips.is_empty() {
+            return 1.0;
+        }
+
+        let tip = effective_tip(tx, next_block.base_fee);
+        let included_in = self
+            .recent_inclusion_tips
+            .iter()
+            .filter(|inclusion_tip| tip >= **inclusion_tip)
+            .count();
+
+        included_in as f64 / self.recent_inclusion_tips.len() as f64
+    }
+
+    // Cheap checks that only need the tx's fee fields (run before recovering sender)
+    //
+    // Arguments:
+    // * `tx`: pending transaction
+    // * `next_block`: holds information about next block
+    //
+    // Returns:
+    // Ok(f64): estimated inclusion probability, used to prioritize victims
+    // Err(Rejection): if tx should not be simulated
+    pub fn check_fees(&self, tx: &Transaction, next_block: &BlockInfo) -> Result<f64, Rejection> {
+        // enhancement: simulate all txs, store result, and use result when tx can included
+        if get_max_fee(tx) < next_block.base_fee {
+            return Err(Rejection::BaseFee);
+        }
+
+        let probability = self.inclusion_probability(tx, next_block);
+        if probability < self.min_inclusion_probability {
+            return Err(Rejection::UnlikelyInclusion);
+        }
+
+        Ok(probability)
+    }
+
+    // Checks that need the decoded swap and the tx's sender (`from` must be recovered)
+    //
+    // Arguments:
+    // * `tx`: pending transaction
+    // * `next_block`: holds information about next block
+    // * `mempool_index`: index of pending txs by sender + nonce
+    // * `onchain_nonce`: next nonce of the tx's sender according to the latest block
+    //
+    // Returns:
+    // Ok(()): if tx can be sandwiched in next block
+    // Err(Rejection): if tx should not be simulated
+    pub fn check_victim(
+        &self,
+        tx: &Transaction,
+        next_block: &BlockInfo,
+        mempool_index: &MempoolIndex,
+        onchain_nonce: U256,
+    ) -> Result<(), Rejection> {
+        if let Some(deadline) = swap_decoder::decode_swap(tx).and_then(|swap| swap.deadline) {
+            if deadline < next_block.timestamp {
+                return Err(Rejection::DeadlinePassed);
+            }
+        }
+
+        if mempool_index.has_missing_nonce(&VictimKey::from_tx(tx), onchain_nonce) {
+            return Err(Rejection::MissingNonce);
+        }
+
+        Ok(())
+    }
+}
+
+// Max fee a tx is willing to pay per gas (gas price for legacy txs)
+fn get_max_fee(tx: &Transaction) -> U256 {
+    tx.max_fee_per_gas.or(tx.gas_price).unwrap_or_default()
+}
+
+// Tip per gas that `tx` pays to the builder given `base_fee`
+fn effective_tip(tx: &Transaction, base_fee: U256) -> U256 {
+    let max_tip = get_max_fee(tx).saturating_sub(base_fee);
+    match tx.max_priority_fee_per_gas {
+        Some(priority_fee) => priority_fee.min(max_tip),
+        None => max_tip,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::abi::Token;
+
+    use super::*;
+    use crate::utils::swap_decoder::{encode_call, router_address, SwapRouter};
+
+    fn gwei(amount: u64) -> U256 {
+        U256::from(amount) * U256::exp10(9)
+    }
+
+    fn next_block() -> BlockInfo {
+        BlockInfo::new(U64::from(101), U256::from(1_700_000_012), gwei(10))
+    }
+
+    fn tx(max_fee_gwei: u64, tip_gwei: u64) -> Transaction {
+        Transaction {
+            from: Address::repeat_byte(1),
+            nonce: U256::from(5),
+            max_fee_per_gas: Some(gwei(max_fee_gwei)),
+            max_priority_fee_per_gas: Some(gwei(tip_gwei)),
+            ..Default::default()
+        }
+    }
+
+    // mined block whose txs paid `tips_gwei` on top of a 10 gwei base fee
+    fn block(tips_gwei: &[u64]) -> Block<Transaction> {
+        Block {
+            base_fee_per_gas: Some(gwei(10)),
+            transactions: tips_gwei.iter().map(|tip| tx(100, *tip)).collect(),
+            ..Default::default()
+        }
+    }
+
+    // swap from `tx` to a uniswap v2 router expiring at `deadline`
+    fn swap(deadline: U256) -> Transaction {
+        let mut swap = tx(100, 2);
+        swap.to = Some(router_address(SwapRouter::UniswapV2Router02));
+        swap.input = encode_call(
+            "swapExactTokensForTokens(uint256,uint256,address[],address,uint256)",
+            &[
+                Token::Uint(U256::from(100)),
+                Token::Uint(U256::from(90)),
+                Token::Array(vec![
+                    Token::Address(Address::from_low_u64_be(2)),
+                    Token::Address(Address::from_low_u64_be(3)),
+                ]),
+                Token::Address(swap.from),
+                Token::Uint(deadline),
+            ],
+        )
+        .into();
+        swap
+    }
+
+    #[test]
+    fn inclusion_tip_is_a_low_percentile_of_the_block() {
+        let mut admission = VictimAdmission::new(0.5);
+        // nothing recorded yet
+        assert_eq!(
+            admission.inclusion_probability(&tx(100, 0), &next_block()),
+            1.0
+        );
+
+        // 10th percentile of 1..=20 gwei is 3 gwei
+        admission.record_block(&block(&(1..=20).rev().collect::<Vec<u64>>()));
+        assert_eq!(
+            admission.inclusion_probability(&tx(100, 3), &next_block()),
+            1.0
+        );
+        assert_eq!(
+            admission.inclusion_probability(&tx(100, 2), &next_block()),
+            0.0
+        );
+        // tip is capped by max fee
+        assert_eq!(
+            admission.inclusion_probability(&tx(12, 3), &next_block()),
+            0.0
+        );
+
+        // empty blocks are ignored
+        admission.record_block(&block(&[]));
+        assert_eq!(admission.recent_inclusion_tips.len(), 1);
+    }
+
+    #[test]
+    fn inclusion_probability_uses_recent_blocks_only() {
+        let mut admission = VictimAdmission::new(0.5);
+        admission.record_block(&block(&[100]));
+        admission.record_block(&block(&[100]));
+        assert_eq!(
+            admission.inclusion_probability(&tx(100, 5), &next_block()),
+            0.0
+        );
+
+        // pushes both expensive blocks out of the window
+        for tip in 1..=RECENT_BLOCKS as u64 {
+            admission.record_block(&block(&[tip]));
+        }
+        assert_eq!(admission.recent_inclusion_tips.len(), RECENT_BLOCKS);
+        assert_eq!(
+            admission.inclusion_probability(&tx(100, 5), &next_block()),
+            0.5
+        );
+    }
+
+    #[test]
+    fn rejects_txs_by_fees() {
+        let mut admission = VictimAdmission::new(0.5);
+        for tip in 1..=RECENT_BLOCKS as u64 {
+            admission.record_block(&block(&[tip]));
+        }
+
+        assert_eq!(
+            admission.check_fees(&tx(9, 1), &next_block()),
+            Err(Rejection::BaseFee)
+        );
+        assert_eq!(
+            admission.check_fees(&tx(100, 4), &next_block()),
+            Err(Rejection::UnlikelyInclusion)
+        );
+        assert_eq!(admission.check_fees(&tx(100, 8), &next_block()), Ok(0.8));
+
+        // legacy txs tip whatever is above base fee
+        let legacy = Transaction {
+            gas_price: Some(gwei(20)),
+            ..Default::default()
+        };
+        assert_eq!(admission.check_fees(&legacy, &next_block()), Ok(1.0));
+    }
+
+    #[test]
+    fn rejects_expired_swaps_and_missing_nonces() {
+        let admission = VictimAdmission::new(0.5);
+        let next_block = next_block();
+        let mut mempool_index = MempoolIndex::new();
+
+        let expired = swap(next_block.timestamp - 1);
+        assert_eq!(
+            admission.check_victim(&expired, &next_block, &mempool_index, expired.nonce),
+            Err(Rejection::DeadlinePassed)
+        );
+        let victim = swap(next_block.timestamp);
+        assert_eq!(
+            admission.check_victim(&victim, &next_block, &mempool_index, victim.nonce),
+            Ok(())
+        );
+
+        // sender's previous nonce was never seen
+        let onchain_nonce = victim.nonce - 1;
+        assert_eq!(
+            admission.check_victim(&victim, &next_block, &mempool_index, onchain_nonce),
+            Err(Rejection::MissingNonce)
+        );
+        // it is pending, both can land in the next block
+        let mut predecessor = tx(100, 2);
+        predecessor.nonce = onchain_nonce;
+        predecessor.hash = H256::repeat_byte(0xa1);
+        mempool_index.observe(&predecessor, U64::from(100));
+        assert_eq!(
+            admission.check_victim(&victim, &next_block, &mempool_index, onchain_nonce),
+            Ok(())
+        );
+    }
+}
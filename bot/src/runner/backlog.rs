@@ -0,0 +1,406 @@
+use ethers::prelude::*;
+use hashbrown::HashMap;
+
+use crate::prelude::{sandwich_types::OptimalRecipe, Pool};
+
+// Recipes that were not reused within this many blocks are dropped (victim most likely got evicted)
+const MAX_BACKLOG_AGE: u64 = 25;
+
+/// Identifies a victim independently of its tx hash (stays the same across gas bumps)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VictimKey {
+    pub from: Address,
+    pub nonce: U256,
+}
+
+impl VictimKey {
+    // Create a new `VictimKey` from a victim transaction
+    pub fn from_tx(tx: &Transaction) -> Self {
+        Self {
+            from: tx.from,
+            nonce: tx.nonce,
+        }
+    }
+}
+
+/// A recipe waiting in the backlog to be used in a mega sandwich
+#[derive(Clone, Debug)]
+pub struct BacklogEntry {
+    pub recipe: OptimalRecipe,
+    pub pool: Pool,
+    pub victim: Transaction,
+    /// block that the recipe was last simulated against
+    pub simulated_for: U64,
+    /// block that the recipe was first added to the backlog
+    pub first_seen: U64,
+}
+
+impl BacklogEntry {
+    // Is the recipe simulated against an older block than `target_block`
+    pub fn is_stale(&self, target_block: U64) -> bool {
+        self.simulated_for < target_block
+    }
+}
+
+/// Holds recipes across block boundaries, keyed by victim (sender, nonce)
+#[derive(Default)]
+pub struct SandwichBacklog {
+    entries: HashMap<VictimKey, Vec<BacklogEntry>>,
+}
+
+impl SandwichBacklog {
+    // Create a new empty backlog
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+        }
+    }
+
+    // Add a recipe to the backlog, replacing older recipes for the same victim and pool
+    //
+    // Arguments:
+    // * `&mut self`: mutable reference to backlog
+    // * `recipe`: recipe to store (expected to hold a single meat)
+    // * `pool`: pool that the recipe targets
+    // * `simulated_for`: block that the recipe was simulated against
+    //
+    // Returns: This function returns nothing
+    pub fn insert(&mut self, recipe: OptimalRecipe, pool: Pool, simulated_for: U64) {
+        let victim = match recipe.meats.first() {
+            Some(meat) => meat.clone(),
+            None => return,
+        };
+        let key = VictimKey::from_tx(&victim);
+
+        let recipes = self.entries.entry(key).or_default();
+
+        // victim got replaced by a tx with same sender + nonce, old recipes are useless
+        recipes.retain(|entry| entry.victim.hash == victim.hash);
+
+        let first_seen = match recipes.iter().find(|entry| entry.pool.address == pool.address) {
+            Some(entry) => entry.first_seen,
+            None => simulated_for,
+        };
+        recipes.retain(|entry| entry.pool.address != pool.address);
+
+        recipes.push(BacklogEntry {
+            recipe,
+            pool,
+            victim,
+            simulated_for,
+            first_seen,
+        });
+    }
+
+    // Remove recipes whose victims were mined or replaced in `block`
+    //
+    // Arguments:
+    // * `&mut self`: mutable reference to backlog
+    // * `block`: latest block including its transactions
+    //
+    // Returns:
+    // `(usize, usize)`: number of victims that were (mined, replaced)
+    pub fn prune_included(&mut self, block: &Block<Transaction>) -> (usize, usize) {
+        let mut mined = 0;
+        let mut replaced = 0;
+
+        for tx in block.transactions.iter() {
+            // any backlogged tx from sender with nonce <= mined nonce can never land
+            let stale_keys: Vec<VictimKey> = self
+                .entries
+                .keys()
+                .filter(|key| key.from == tx.from && key.nonce <= tx.nonce)
+                .copied()
+                .collect();
+
+            for key in stale_keys {
+                if let Some(recipes) = self.entries.remove(&key) {
+                    match recipes.iter().any(|entry| entry.victim.hash == tx.hash) {
+                        true => mined += 1,
+                        false => replaced += 1,
+                    }
+                }
+            }
+        }
+
+        // drop victims that are sitting in the backlog for too long
+        let current_block = block.number.unwrap_or_default();
+        self.entries.retain(|_, recipes| {
+            recipes.retain(|entry| entry.first_seen + MAX_BACKLOG_AGE > current_block);
+            !recipes.is_empty()
+        });
+
+        (mined, replaced)
+    }
+
+    // Remove all recipes that sandwich the victim tx `victim_hash`
+    //
+    // Arguments:
+    // * `&mut self`: mutable reference to backlog
+    // * `key`: (sender, nonce) of victim
+    // * `victim_hash`: hash of the victim tx that got replaced
+    //
+    // Returns:
+    // `usize`: number of recipes removed
+    pub fn remove_victim(&mut self, key: &VictimKey, victim_hash: TxHash) -> usize {
+        let recipes = match self.entries.get_mut(key) {
+            Some(recipes) => recipes,
+            None => return 0,
+        };
+
+        let before = recipes.len();
+        recipes.retain(|entry| entry.victim.hash != victim_hash);
+        let removed = before - recipes.len();
+
+        if recipes.is_empty() {
+            self.entries.remove(key);
+        }
+
+        removed
+    }
+
+    // Return copies of all recipes that were simulated against an older block than `target_block`
+    // (entries stay in the backlog until they are restored or removed)
+    pub fn stale_entries(&self, target_block: U64) -> Vec<BacklogEntry> {
+        self.entries
+            .values()
+            .flatten()
+            .filter(|entry| entry.is_stale(target_block))
+            .cloned()
+            .collect()
+    }
+
+    // Remove a single entry (victim + pool) from the backlog
+    pub fn remove_entry(&mut self, entry: &BacklogEntry) {
+        let key = VictimKey::from_tx(&entry.victim);
+        if let Some(recipes) = self.entries.get_mut(&key) {
+            recipes.retain(|e| e.pool.address != entry.pool.address);
+            if recipes.is_empty() {
+                self.entries.remove(&key);
+            }
+        }
+    }
+
+    // Put a (revalidated) entry back into the backlog
+    pub fn restore(&mut self, entry: BacklogEntry) {
+        let key = VictimKey::from_tx(&entry.victim);
+        let recipes = self.entries.entry(key).or_default();
+        recipes.retain(|e| e.pool.address != entry.pool.address);
+        recipes.push(entry);
+    }
+
+    // Apply the result of revalidating `stale` (a copy returned by `stale_entries`), the backlog is
+    // not locked while recipes are revalidated so the entry may have been pruned, replaced or
+    // refreshed in the meantime, in which case the result is dropped
+    //
+    // Arguments:
+    // * `&mut self`: mutable reference to backlog
+    // * `stale`: entry as it was when revalidation started
+    // * `revalidated`: updated entry, None if the recipe can no longer be used
+    //
+    // Returns:
+    // bool: true if the backlog was updated
+    pub fn apply_revalidation(
+        &mut self,
+        stale: &BacklogEntry,
+        revalidated: Option<BacklogEntry>,
+    ) -> bool {
+        let key = VictimKey::from_tx(&stale.victim);
+        let unchanged = self.entries.get(&key).map_or(false, |recipes| {
+            recipes.iter().any(|entry| {
+                entry.pool.address == stale.pool.address
+                    && entry.victim.hash == stale.victim.hash
+                    && entry.simulated_for == stale.simulated_for
+            })
+        });
+        if !unchanged {
+            return false;
+        }
+
+        match revalidated {
+            Some(entry) => self.restore(entry),
+            None => self.remove_entry(stale),
+        }
+        true
+    }
+
+    // Group all backlogged recipes by the pool they target
+    pub fn recipes_by_pool(&self) -> HashMap<Pool, Vec<OptimalRecipe>> {
+        let mut by_pool: HashMap<Pool, Vec<OptimalRecipe>> = HashMap::new();
+
+        for entry in self.entries.values().flatten() {
+            by_pool
+                .entry(entry.pool)
+                .or_default()
+                .push(entry.recipe.clone());
+        }
+
+        by_pool
+    }
+
+    // Number of victims held in backlog
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    // Is the backlog empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::PoolVariant;
+    use std::collections::BTreeMap;
+
+    fn pool(byte: u8) -> Pool {
+        Pool::new(
+            Address::repeat_byte(byte),
+            Address::repeat_byte(0xee),
+            Address::repeat_byte(byte + 1),
+            U256::from(3000),
+            PoolVariant::UniswapV2,
+        )
+    }
+
+    fn victim(from: u8, nonce: u64, hash: u8) -> Transaction {
+        Transaction {
+            hash: H256::repeat_byte(hash),
+            from: Address::repeat_byte(from),
+            nonce: U256::from(nonce),
+            ..Default::default()
+        }
+    }
+
+    fn recipe(victim: &Transaction, revenue: u64) -> OptimalRecipe {
+        OptimalRecipe::new(
+            Bytes::default(),
+            U256::zero(),
+            0,
+            0,
+            Default::default(),
+            Bytes::default(),
+            U256::zero(),
+            0,
+            0,
+            Default::default(),
+            vec![victim.clone()],
+            U256::from(revenue),
+            vec![],
+            BTreeMap::new(),
+        )
+    }
+
+    fn block(number: u64, txs: Vec<Transaction>) -> Block<Transaction> {
+        Block {
+            number: Some(U64::from(number)),
+            transactions: txs,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn insert_keeps_one_recipe_per_victim_and_pool() {
+        let mut backlog = SandwichBacklog::new();
+        let tx = victim(1, 5, 0xa1);
+
+        backlog.insert(recipe(&tx, 1), pool(0x10), U64::from(100));
+        backlog.insert(recipe(&tx, 2), pool(0x20), U64::from(100));
+        // newer recipe for same pool replaces the old one but keeps when it was first seen
+        backlog.insert(recipe(&tx, 3), pool(0x10), U64::from(101));
+        assert_eq!(backlog.len(), 1);
+
+        let entries = backlog.stale_entries(U64::from(200));
+        assert_eq!(entries.len(), 2);
+        let entry = entries
+            .iter()
+            .find(|entry| entry.pool.address == pool(0x10).address)
+            .unwrap();
+        assert_eq!(entry.recipe.revenue, U256::from(3));
+        assert_eq!(entry.first_seen, U64::from(100));
+        assert_eq!(entry.simulated_for, U64::from(101));
+
+        // replacement (same sender + nonce, new hash) drops recipes of the old tx
+        let replacement = victim(1, 5, 0xa2);
+        backlog.insert(recipe(&replacement, 4), pool(0x30), U64::from(102));
+        let entries = backlog.stale_entries(U64::from(200));
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].victim.hash, replacement.hash);
+    }
+
+    #[test]
+    fn prune_included_counts_mined_and_replaced() {
+        let mut backlog = SandwichBacklog::new();
+        let mined = victim(1, 5, 0xa1);
+        let replaced = victim(2, 7, 0xb1);
+        let later_nonce = victim(1, 6, 0xa3);
+        let pending = victim(3, 1, 0xc1);
+        for tx in [&mined, &replaced, &later_nonce, &pending] {
+            backlog.insert(recipe(tx, 1), pool(0x10), U64::from(100));
+        }
+
+        let (mined_count, replaced_count) =
+            backlog.prune_included(&block(101, vec![mined.clone(), victim(2, 7, 0xb2)]));
+        assert_eq!((mined_count, replaced_count), (1, 1));
+        // next nonce of the same sender can still land
+        assert_eq!(backlog.len(), 2);
+
+        // victims that sat in the backlog for too long are dropped
+        backlog.prune_included(&block(100 + MAX_BACKLOG_AGE, vec![]));
+        assert!(backlog.is_empty());
+    }
+
+    #[test]
+    fn stale_entries_are_simulated_for_older_blocks() {
+        let mut backlog = SandwichBacklog::new();
+        backlog.insert(recipe(&victim(1, 5, 0xa1), 1), pool(0x10), U64::from(100));
+        backlog.insert(recipe(&victim(2, 5, 0xb1), 1), pool(0x10), U64::from(101));
+
+        assert!(backlog.stale_entries(U64::from(100)).is_empty());
+        assert_eq!(backlog.stale_entries(U64::from(101)).len(), 1);
+        assert_eq!(backlog.stale_entries(U64::from(102)).len(), 2);
+        // copies are returned, backlog is unchanged
+        assert_eq!(backlog.len(), 2);
+    }
+
+    #[test]
+    fn restore_replaces_entry_of_same_pool() {
+        let mut backlog = SandwichBacklog::new();
+        let tx = victim(1, 5, 0xa1);
+        backlog.insert(recipe(&tx, 1), pool(0x10), U64::from(100));
+
+        let mut entry = backlog.stale_entries(U64::from(101)).remove(0);
+        entry.recipe = recipe(&tx, 9);
+        entry.simulated_for = U64::from(101);
+        backlog.restore(entry);
+
+        assert!(backlog.stale_entries(U64::from(101)).is_empty());
+        let by_pool = backlog.recipes_by_pool();
+        assert_eq!(by_pool[&pool(0x10)].len(), 1);
+        assert_eq!(by_pool[&pool(0x10)][0].revenue, U256::from(9));
+    }
+
+    #[test]
+    fn revalidation_is_dropped_when_backlog_moved_on() {
+        let mut backlog = SandwichBacklog::new();
+        let tx = victim(1, 5, 0xa1);
+        backlog.insert(recipe(&tx, 1), pool(0x10), U64::from(100));
+        let stale = backlog.stale_entries(U64::from(102)).remove(0);
+
+        // pipeline refreshed the recipe while it was revalidated
+        backlog.insert(recipe(&tx, 2), pool(0x10), U64::from(102));
+        assert!(!backlog.apply_revalidation(&stale, None));
+        assert_eq!(backlog.len(), 1);
+
+        // unchanged entry that is no longer profitable is removed
+        let stale = backlog.stale_entries(U64::from(103)).remove(0);
+        assert!(backlog.apply_revalidation(&stale, None));
+        assert!(backlog.is_empty());
+
+        // victim got pruned while it was revalidated
+        assert!(!backlog.apply_revalidation(&stale, Some(stale.clone())));
+        assert!(backlog.is_empty());
+    }
+}
@@ -1,25 +1,37 @@
 use colored::Colorize;
 use ethers::prelude::{rand::Rng, *};
 use ethers::utils::format_units;
+use futures::stream::{self, StreamExt};
 use hashbrown::HashMap;
 use std::{collections::BTreeMap, sync::Arc};
 use tokio::sync::RwLock;
 
 use crate::{
+    metrics,
     prelude::{
         fork_factory::ForkFactory,
         make_sandwich,
         sandwich_types::{OptimalRecipe, RawIngredients},
+        state_cache::{BlockStateCache, SharedStateCache},
         BlockInfo, Pool, SendBundleError,
     },
-    relay, utils,
-    utils::tx_builder::SandwichMaker,
+    relay,
+    types::BlockOracle,
+    utils,
+    utils::{transport::RpcClient, tx_builder::SandwichMaker},
 };
 
+use super::backlog::{BacklogEntry, SandwichBacklog, VictimKey};
+use super::ledger::{self, LedgerEvent};
+use super::slot_scheduler::SlotScheduler;
 use super::state::BotState;
 
 pub struct BundleSender {
-    pub pending_sandwiches: HashMap<Pool, Arc<RwLock<Vec<OptimalRecipe>>>>,
+    pub backlog: SandwichBacklog,
+    // victim tx hash -> latest block that a bundle including the victim was sent for
+    pub in_flight_bundles: HashMap<TxHash, U64>,
+    // frontrun nonce -> (latest target block, pools) of sent bundles, used to find pools whose sandwich reverted
+    pub in_flight_nonces: HashMap<U256, (U64, Vec<Pool>)>,
 }
 
 impl BundleSender {
@@ -30,44 +42,237 @@ impl BundleSender {
     // Returns: a new `TxSender` instance
     pub async fn new() -> Self {
         Self {
-            pending_sandwiches: HashMap::new(),
+            backlog: SandwichBacklog::new(),
+            in_flight_bundles: HashMap::new(),
+            in_flight_nonces: HashMap::new(),
         }
     }
 
-    // Add a recipe to the pending transaction list for a given pool
+    // Record that a bundle built from `recipe` was sent to relays
+    //
+    // Arguments:
+    // * `&mut self`: a mutable reference to the `TxSender` instance
+    // * `recipe`: recipe used to build the bundle
+    // * `target_block`: block number that the bundle targets
+    // * `nonce`: nonce of the bundle's frontrun tx
+    //
+    // Returns: This function returns nothing
+    pub fn track_in_flight(&mut self, recipe: &OptimalRecipe, target_block: U64, nonce: U256) {
+        for meat in recipe.meats.iter() {
+            let latest_target = self.in_flight_bundles.entry(meat.hash).or_default();
+            *latest_target = (*latest_target).max(target_block);
+        }
+
+        let (latest_target, pools) = self.in_flight_nonces.entry(nonce).or_default();
+        *latest_target = (*latest_target).max(target_block);
+        for pool in recipe.target_pools.iter() {
+            if !pools.iter().any(|sent| sent.address == pool.address) {
+                pools.push(*pool);
+            }
+        }
+    }
+
+    // Pools of in flight bundles whose frontrun or backrun used `nonce`
+    pub fn pools_sent_with_nonce(&self, nonce: U256) -> Vec<Pool> {
+        let frontrun = self.in_flight_nonces.get(&nonce);
+        let backrun = match nonce.is_zero() {
+            true => None,
+            false => self.in_flight_nonces.get(&(nonce - 1)),
+        };
+        frontrun
+            .into_iter()
+            .chain(backrun)
+            .flat_map(|(_, pools)| pools.iter().copied())
+            .collect()
+    }
+
+    // Forget in flight bundles that targeted `latest_block` or earlier
+    pub fn prune_in_flight(&mut self, latest_block: U64) {
+        self.in_flight_bundles
+            .retain(|_, target_block| *target_block > latest_block);
+        self.in_flight_nonces
+            .retain(|_, (target_block, _)| *target_block > latest_block);
+    }
+
+    // Remove all recipes that include a replaced victim tx so it is not sandwiched again
+    //
+    // Bundles already sent to relays can't be recalled (they fail on their own as the victim can't
+    // land), their in flight entry is only forgotten. If one still targets an upcoming block the
+    // caller resends by rebuilding the mega sandwich from the backlog, which no longer holds the
+    // replaced tx.
+    //
+    // Arguments:
+    // * `&mut self`: a mutable reference to the `TxSender` instance
+    // * `key`: (sender, nonce) of victim
+    // * `replaced_hash`: hash of the victim tx that is no longer valid
+    //
+    // Returns:
+    // `(usize, Option<U64>)`: number of recipes removed, target block of a bundle already sent
+    // with the replaced tx (if any)
+    pub fn invalidate_victim(
+        &mut self,
+        key: &VictimKey,
+        replaced_hash: TxHash,
+    ) -> (usize, Option<U64>) {
+        let removed_recipes = self.backlog.remove_victim(key, replaced_hash);
+        let in_flight_target = self.in_flight_bundles.remove(&replaced_hash);
+        (removed_recipes, in_flight_target)
+    }
+
+    // Add a recipe to the backlog for a given pool
     //
     // Arguments:
     // * `&mut self`: a mutable reference to the `TxSender` instance
     // * `recipe`: an `OptimalRecipe` instance representing the recipe to add
+    // * `pool`: pool that the recipe targets
+    // * `simulated_for`: block number that the recipe was simulated against
     //
     // Returns: This function returns nothing
-    pub async fn add_recipe(&mut self, recipe: OptimalRecipe, pool: Pool) {
-        if let Some(sandwich_vec) = self.pending_sandwiches.get(&pool) {
-            sandwich_vec.write().await.push(recipe);
-        } else {
-            let new_vector = Arc::new(RwLock::new(vec![recipe]));
-            self.pending_sandwiches.insert(pool, new_vector);
+    pub async fn add_recipe(&mut self, recipe: OptimalRecipe, pool: Pool, simulated_for: U64) {
+        self.backlog.insert(recipe, pool, simulated_for);
+    }
+
+    // Re-simulate backlogged recipes that were created against an older block,
+    // recipes that are no longer profitable are dropped from the backlog
+    //
+    // The lock is only held to snapshot the stale entries and to apply the results, so the
+    // pipeline can keep adding recipes while they are re-simulated (`REVALIDATION_CONCURRENCY`
+    // at a time). Once a new block arrives `next_block` has passed, remaining recipes are skipped
+    // and left as they are for the next mega sandwich.
+    //
+    // Arguments:
+    // * `bundle_sender`: holds the backlog to revalidate
+    // * `oracle`: latest block, used to notice that `next_block` already passed
+    // * `next_block`: block that the recipes should be valid for
+    // * `weth_balance`: current weth balance of sandwich contract
+    // * `sandwich_maker`: handles encoding of transaction for sandwich contract
+    // * `client`: provider used for state diffs and missing state
+    // * `state_cache`: state shared by all forks of `next_block`
+    //
+    // Returns: This function returns nothing
+    async fn revalidate_stale_recipes(
+        bundle_sender: &RwLock<BundleSender>,
+        oracle: &RwLock<BlockOracle>,
+        next_block: &BlockInfo,
+        weth_balance: U256,
+        sandwich_maker: &SandwichMaker,
+        client: &Arc<RpcClient>,
+        state_cache: &Arc<BlockStateCache>,
+    ) {
+        // entries are only replaced once their revalidation finished, so cancelling midway loses nothing
+        let stale_entries = bundle_sender
+            .read()
+            .await
+            .backlog
+            .stale_entries(next_block.number);
+        if stale_entries.is_empty() {
+            return;
+        }
+
+        let results: Vec<(&BacklogEntry, Option<BacklogEntry>)> =
+            stream::iter(stale_entries.iter())
+                .map(|stale| async move {
+                    if oracle.read().await.latest_block.number >= next_block.number {
+                        return None;
+                    }
+                    let revalidated = revalidate_entry(
+                        stale.clone(),
+                        next_block,
+                        weth_balance,
+                        sandwich_maker,
+                        client,
+                        state_cache,
+                    )
+                    .await;
+                    Some((stale, revalidated))
+                })
+                .buffer_unordered(utils::dotenv::get_revalidation_concurrency().max(1))
+                .filter_map(|result| async move { result })
+                .collect()
+                .await;
+        if results.len() < stale_entries.len() {
+            log::info!(
+                "Skipped {} stale recipes, block {} already passed",
+                stale_entries.len() - results.len(),
+                next_block.number
+            );
         }
+
+        let mut revalidated_count = 0;
+        let mut bundle_sender = bundle_sender.write().await;
+        for (stale, revalidated) in results {
+            let profitable = revalidated.is_some();
+            if bundle_sender.backlog.apply_revalidation(stale, revalidated) && profitable {
+                revalidated_count += 1;
+            }
+        }
+
+        log::info!(
+            "Revalidated {}/{} stale recipes for block {}",
+            revalidated_count,
+            stale_entries.len(),
+            next_block.number
+        );
     }
 
     // enchanement: proof of concept atm, a lot of room for improvement
-    // bump victim transactions if detect tx with same sender + nonce
-    // don't clear at end and just remove recipes based on mined txs in latest block (collect backlog)
+    // Build a mega sandwich from all backlogged recipes and send it to relays
+    //
+    // The `bundle_sender` lock is not held while simulating, only to read the backlog and to
+    // track the sent bundle
+    //
+    // Arguments:
+    // * `bundle_sender`: holds the recipe backlog and in flight bundles
+    // * `oracle`: latest block info
+    // * `next_block`: block the mega sandwich targets
+    // * `sandwich_state`: holds the weth balance of the sandwich contract
+    // * `sandwich_maker`: handles encoding of transaction for sandwich contract
+    // * `state_cache`: state shared by all simulations of a block
+    // * `slot_scheduler`: decides which relays still accept the bundle
+    //
+    // Returns: This function returns nothing
     pub async fn make_mega_sandwich(
-        &mut self,
+        bundle_sender: Arc<RwLock<BundleSender>>,
+        oracle: Arc<RwLock<BlockOracle>>,
         next_block: BlockInfo,
         sandwich_state: Arc<BotState>,
         sandwich_maker: Arc<SandwichMaker>,
+        state_cache: Arc<SharedStateCache>,
+        slot_scheduler: Arc<SlotScheduler>,
     ) {
         log::info!("Making mega sandwich for {}", &next_block.number);
+
+        let client = match utils::create_rpc_client().await {
+            Ok(client) => client,
+            Err(e) => {
+                log::error!("Skipping mega sandwich for {}: {}", &next_block.number, e);
+                return;
+            }
+        };
+        let weth_balance = {
+            let read_lock = sandwich_state.weth_balance.read().await;
+            (*read_lock).clone()
+        };
+
+        let state_cache = state_cache.for_block(next_block.number);
+
+        // recipes from previous blocks need to be checked against latest state before reuse
+        Self::revalidate_stale_recipes(
+            &bundle_sender,
+            &oracle,
+            &next_block,
+            weth_balance,
+            &sandwich_maker,
+            &client,
+            &state_cache,
+        )
+        .await;
+
+        let recipes_by_pool = bundle_sender.read().await.backlog.recipes_by_pool();
+
         let mut multi_ingredients: Vec<RawIngredients> = Vec::<RawIngredients>::new();
         let mut multi_combined_state_diffs: BTreeMap<H160, AccountDiff> = BTreeMap::new();
-        for (target_pool, recipes) in self.pending_sandwiches.iter() {
-            let mut recipes = {
-                let read_lock = recipes.read().await;
-                (*read_lock).clone()
-            };
-
+        for (target_pool, mut recipes) in recipes_by_pool {
             // // we alr sent this tx
             // if recipes.len() <= 1 {
             //     continue;
@@ -75,7 +280,7 @@ impl BundleSender {
 
             let target_pool = target_pool.clone();
 
-            // sort recipes by revenue
+            // sort recipes by revenue (starting order, victim order is searched during simulation)
             recipes.sort_by(|a, b| a.revenue.cmp(&b.revenue));
 
             // let has_dust = recipes[0].has_dust;
@@ -111,26 +316,38 @@ impl BundleSender {
             };
         }
 
-        let sandwich_state = sandwich_state.clone();
         let next_block = next_block.clone();
         let sandwich_maker = sandwich_maker.clone();
 
-        let client = utils::create_websocket_client().await.unwrap();
         let fork_block = Some(BlockId::Number(BlockNumber::Number(next_block.number)));
 
         // create evm simulation handler by setting up `fork_factory`
-        let initial_db =
-            utils::state_diff::to_cache_db(&multi_combined_state_diffs, fork_block, &client)
-                .await
-                .unwrap();
-        let mut fork_factory =
-            ForkFactory::new_sandbox_factory(client.clone(), initial_db, fork_block);
+        let initial_db = match utils::state_diff::to_cache_db(
+            &multi_combined_state_diffs,
+            fork_block,
+            &client,
+            Some(&state_cache),
+        )
+        .await
+        {
+            Ok(initial_db) => initial_db,
+            Err(e) => {
+                log::error!(
+                    "Skipping mega sandwich for {}: failed to fetch state: {}",
+                    &next_block.number,
+                    e
+                );
+                return;
+            }
+        };
+        let mut fork_factory = ForkFactory::new_sandbox_factory(
+            client.clone(),
+            initial_db,
+            fork_block,
+            Some(state_cache.clone()),
+        );
 
         //// find optimal input to for multi sandwich
-        let weth_balance = {
-            let read_lock = sandwich_state.weth_balance.read().await;
-            (*read_lock).clone()
-        };
         let optimal_sandwich = match make_sandwich::create_optimal_sandwich(
             &mut multi_ingredients,
             weth_balance,
@@ -162,11 +379,16 @@ impl BundleSender {
                 &optimal_sandwich,
                 next_block,
                 sandwich_maker,
+                &slot_scheduler,
                 // sandwich_state,
             )
             .await
             {
-                Ok(_) => { /* all reporting already done inside of send_bundle */ }
+                Ok(nonce) => bundle_sender.write().await.track_in_flight(
+                    &optimal_sandwich,
+                    next_block_two.number,
+                    nonce,
+                ),
                 Err(e) => {
                     log::info!(
                         "{}",
@@ -185,26 +407,101 @@ impl BundleSender {
     }
 }
 
+// Re-simulate a single backlogged recipe against the latest state
+//
+// Arguments:
+// * `entry`: backlog entry to revalidate
+// * `next_block`: block that the recipe should be valid for
+// * `weth_balance`: current weth balance of sandwich contract
+// * `sandwich_maker`: handles encoding of transaction for sandwich contract
+// * `client`: provider used for state diffs and missing state
+// * `state_cache`: state shared by all forks of `next_block`
+//
+// Returns:
+// Some(BacklogEntry): entry holding the updated recipe if still profitable
+// None: if recipe can no longer be used
+async fn revalidate_entry(
+    mut entry: BacklogEntry,
+    next_block: &BlockInfo,
+    weth_balance: U256,
+    sandwich_maker: &SandwichMaker,
+    client: &Arc<RpcClient>,
+    state_cache: &Arc<BlockStateCache>,
+) -> Option<BacklogEntry> {
+    let latest_block = next_block.number - 1;
+    let victims = vec![entry.victim.clone()];
+
+    let state_diffs =
+        utils::state_diff::get_from_txs(client, &victims, BlockNumber::Number(latest_block))
+            .await?;
+
+    let fork_block = Some(BlockId::Number(BlockNumber::Number(next_block.number)));
+    let initial_db =
+        utils::state_diff::to_cache_db(&state_diffs, fork_block, client, Some(state_cache))
+            .await
+            .ok()?;
+    let mut fork_factory = ForkFactory::new_sandbox_factory(
+        client.clone(),
+        initial_db,
+        fork_block,
+        Some(state_cache.clone()),
+    );
+
+    let ingredients = RawIngredients::new(
+        &entry.pool,
+        victims,
+        utils::constants::get_weth_address(),
+        state_diffs,
+    )
+    .await
+    .ok()?;
+
+    let recipe = make_sandwich::create_optimal_sandwich(
+        &mut vec![ingredients],
+        weth_balance,
+        next_block,
+        &mut fork_factory,
+        sandwich_maker,
+    )
+    .await
+    .ok()?;
+
+    if recipe.revenue.is_zero() {
+        return None;
+    }
+
+    entry.recipe = recipe;
+    entry.simulated_for = next_block.number;
+    Some(entry)
+}
+
 // Construct and send bundle based on recipe
 //
 // Arguments:
 // * `&recipe`: information on how to construct sandwich bundle
 // * `target_block`: holds basefee and timestamp of target block
 // * `sandwich_maker`: holds signer, bot address for constructing frontslice and backslice
+// * `slot_scheduler`: bundle is only sent to relays whose cutoff for the target slot has not passed
 //
 // Returns:
-// Ok(()): return nothing if sent succesful
+// Ok(U256): nonce of frontrun tx if sent succesful
 // Err(SendBundleError): return error if send bundle fails
 pub async fn send_bundle(
     recipe: &OptimalRecipe,
     target_block: BlockInfo,
     sandwich_maker: Arc<SandwichMaker>,
+    slot_scheduler: &SlotScheduler,
     // sandwich_state: Arc<BotState>,
-) -> Result<(), SendBundleError> {
-    let nonce = {
-        let read_lock = sandwich_maker.nonce.read().await;
-        (*read_lock).clone()
-    };
+) -> Result<U256, SendBundleError> {
+    let open_relays = slot_scheduler.open_relays_for(target_block.timestamp.as_u64());
+    if open_relays.is_empty() {
+        return Err(SendBundleError::RelayCutoffPassed(target_block.number));
+    }
+
+    let nonce = sandwich_maker
+        .sandwich_nonce(target_block.number)
+        .await
+        .ok_or(SendBundleError::NonceReserved(target_block.number))?;
 
     let front_slice_request = Eip1559TransactionRequest {
         to: Some(NameOrAddress::Address(sandwich_maker.sandwich_address)),
@@ -213,7 +510,7 @@ pub async fn send_bundle(
         chain_id: Some(U64::from(1)),
         max_priority_fee_per_gas: Some(U256::from(0)),
         max_fee_per_gas: Some(target_block.base_fee),
-        gas: Some((U256::from(recipe.frontrun_gas_used) * 10) / 7), // gasused = 70% gaslimit
+        gas: Some(U256::from(recipe.frontrun_gas_limit)),
         nonce: Some(nonce),
         value: Some(recipe.frontrun_value),
         access_list: recipe.frontrun_access_list.clone(),
@@ -233,7 +530,7 @@ pub async fn send_bundle(
         chain_id: Some(U64::from(1)),
         max_priority_fee_per_gas: Some(max_fee),
         max_fee_per_gas: Some(max_fee),
-        gas: Some((U256::from(recipe.backrun_gas_used) * 10) / 7), // gasused = 70% gaslimit
+        gas: Some(U256::from(recipe.backrun_gas_limit)),
         nonce: Some(nonce + 1),
         value: Some(recipe.backrun_value),
         access_list: recipe.backrun_access_list.clone(),
@@ -313,6 +610,31 @@ pub async fn send_bundle(
         .yellow()
         .on_bright_green()
     );
+    log::info!(
+        "{}",
+        format!(
+            "{:?} Access list pruning saved {:?} gas",
+            recipe.print_meats(),
+            recipe.access_list_gas_saved
+        )
+        .bold()
+        .yellow()
+        .on_bright_green()
+    );
+    metrics::ACCESS_LIST_GAS_SAVED.add(recipe.access_list_gas_saved);
+    log::info!(
+        "{}",
+        format!(
+            "{:?} Encoding lost {:?} ETH",
+            recipe.print_meats(),
+            format_units(recipe.encoding_loss, "ether").unwrap()
+        )
+        .bold()
+        .yellow()
+        .on_bright_green()
+    );
+    let encoding_loss_gwei = recipe.encoding_loss / U256::exp10(9);
+    metrics::ENCODING_LOSS_GWEI.add(encoding_loss_gwei.min(U256::from(u64::MAX)).as_u64());
     log::info!(
         "{}",
         format!(
@@ -325,8 +647,41 @@ pub async fn send_bundle(
         .on_bright_green()
     );
 
-    // send bundle to all relay endpoints (concurrently)
-    for relay in relay::get_all_relay_endpoints().await {
+    let frontrun_explained =
+        sandwich_maker.explain_calldata(&recipe.frontrun_data, recipe.frontrun_value);
+    let backrun_explained =
+        sandwich_maker.explain_calldata(&recipe.backrun_data, recipe.backrun_value);
+    log::info!(
+        "{}",
+        format!(
+            "{:?} Frontrun: {}",
+            recipe.print_meats(),
+            frontrun_explained
+        )
+        .bold()
+        .on_bright_green()
+    );
+    log::info!(
+        "{}",
+        format!("{:?} Backrun: {}", recipe.print_meats(), backrun_explained)
+            .bold()
+            .on_bright_green()
+    );
+    ledger::global().record(LedgerEvent::SandwichSubmitted {
+        target_block: target_block.number,
+        nonce,
+        meats: recipe.meats.iter().map(|meat| meat.hash).collect(),
+        revenue: recipe.revenue,
+        frontrun: frontrun_explained,
+        backrun: backrun_explained,
+    });
+
+    // send bundle to all relay endpoints that still accept it (concurrently)
+    for relay in relay::get_all_relay_endpoints()
+        .await?
+        .iter()
+        .filter(|relay| open_relays.contains(&relay.relay_name))
+    {
         let bundle = bundle.clone();
 
         tokio::spawn(async move {
@@ -344,7 +699,7 @@ pub async fn send_bundle(
             };
         });
     }
-    Ok(())
+    Ok(nonce)
 }
 
 // calculates the optimal bribe for a given opportunity
@@ -381,6 +736,8 @@ fn calculate_bribe_for_max_fee(
     // enchanement: make bribe adaptive based on competitors
     let bribe_amount =
         (revenue_minus_frontrun_tx_fee * (990000000 + rng.gen_range(0..10000000))) / 1000000000;
+    // risky sandwiches (per safety policy) keep part of the bribe as risk premium
+    let bribe_amount = bribe_amount * (10000 - recipe.bribe_haircut_bps.min(10000)) / 10000;
 
     // calculating bribe amount
     let max_fee: U256 = bribe_amount / recipe.backrun_gas_used;
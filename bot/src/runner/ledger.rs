@@ -0,0 +1,163 @@
+use ethers::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::utils;
+
+/// Something the bot did on chain (or tried to)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LedgerEvent {
+    /// sandwich bundle sent to relays
+    SandwichSubmitted {
+        target_block: U64,
+        nonce: U256,
+        meats: Vec<H256>,
+        revenue: U256,
+        /// what the frontrun does, see `SandwichPayload`
+        frontrun: String,
+        /// what the backrun does, see `SandwichPayload`
+        backrun: String,
+    },
+    /// `sando-rs admin` command sent to relays
+    AdminSubmitted {
+        target_block: U64,
+        /// nonce of the first tx
+        nonce: U256,
+        /// the command that was run, see `AdminCommand`
+        command: String,
+        txs: Vec<H256>,
+    },
+    /// treasury transfer between sandwich contract and searcher sent to relays
+    TreasurySubmitted {
+        target_block: U64,
+        nonce: U256,
+        /// what the transfer does, see `TreasuryMove`
+        movement: String,
+        /// weth held by contract and eth held by searcher before the transfer
+        contract_weth: U256,
+        searcher_eth: U256,
+        tx: H256,
+    },
+    /// treasury transfer landed on chain
+    TreasuryIncluded {
+        block: U64,
+        movement: String,
+        tx: H256,
+    },
+}
+
+/// One line of the ledger file
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LedgerEntry {
+    /// unix timestamp (seconds)
+    pub recorded_at: u64,
+    #[serde(flatten)]
+    pub event: LedgerEvent,
+}
+
+/// Append only record (one json object per line) of everything the bot submitted
+pub struct Ledger {
+    /// file that entries are appended to (None only logs them)
+    path: Option<String>,
+    /// serializes writes to `path`
+    write_lock: Mutex<()>,
+}
+
+impl Ledger {
+    // Create a new `Ledger` appending to `path` (None only logs entries)
+    pub fn new(path: Option<String>) -> Self {
+        Self {
+            path,
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    // Create a new `Ledger` using file set in env
+    pub fn from_env() -> Self {
+        Self::new(Some(utils::dotenv::get_ledger_file()))
+    }
+
+    // Append `event` to the ledger, failures are logged and never stop the bot
+    pub fn record(&self, event: LedgerEvent) {
+        let recorded_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or_default();
+        let entry = LedgerEntry { recorded_at, event };
+
+        let path = match &self.path {
+            Some(path) => path,
+            None => {
+                log::info!("Ledger: {:?}", entry);
+                return;
+            }
+        };
+
+        let _guard = self.write_lock.lock().unwrap();
+        let written = serde_json::to_string(&entry)
+            .map_err(eyre::Error::from)
+            .and_then(|line| {
+                let mut writer = OpenOptions::new().create(true).append(true).open(path)?;
+                Ok(writeln!(writer, "{}", line)?)
+            });
+        if let Err(e) = written {
+            log::error!("Failed to write ledger entry to {}: {:?}", path, e);
+        }
+    }
+
+    // Read back all entries (lines that can't be parsed are skipped)
+    pub fn entries(&self) -> eyre::Result<Vec<LedgerEntry>> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(vec![]),
+        };
+        let reader = BufReader::new(OpenOptions::new().read(true).open(path)?);
+        Ok(reader
+            .lines()
+            .filter_map(|line| serde_json::from_str(&line.ok()?).ok())
+            .collect())
+    }
+}
+
+// Ledger set in env, shared by everything that submits txs
+pub fn global() -> &'static Ledger {
+    static LEDGER: OnceLock<Ledger> = OnceLock::new();
+    LEDGER.get_or_init(Ledger::from_env)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn entries_are_appended() {
+        let path = std::env::temp_dir().join(format!("ledger-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let ledger = Ledger::new(Some(path.to_string_lossy().to_string()));
+
+        let event = |nonce: u64| LedgerEvent::SandwichSubmitted {
+            target_block: U64::from(17_000_000),
+            nonce: U256::from(nonce),
+            meats: vec![H256::from_low_u64_be(1)],
+            revenue: U256::exp10(16),
+            frontrun: "v2 frontrun".to_string(),
+            backrun: "v2 backrun".to_string(),
+        };
+        ledger.record(event(1));
+        ledger.record(event(3));
+
+        let entries = ledger.entries().unwrap();
+        assert_eq!(
+            entries
+                .into_iter()
+                .map(|entry| entry.event)
+                .collect::<Vec<_>>(),
+            vec![event(1), event(3)]
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+}
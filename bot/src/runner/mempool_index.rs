@@ -0,0 +1,295 @@
+use ethers::prelude::*;
+use hashbrown::HashMap;
+
+use super::backlog::VictimKey;
+
+// Entries that were not mined or replaced within this many blocks are dropped
+const MAX_INDEX_AGE: u64 = 50;
+
+// Nodes only accept a replacement that bumps both fee cap and tip by this many percent
+// (geth `txpool.pricebump`), cheaper replacements don't propagate and never land
+const REPLACEMENT_BUMP_PERCENT: u64 = 10;
+
+/// Latest tx seen in the mempool for a given (sender, nonce)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IndexedTx {
+    pub hash: TxHash,
+    /// max fee per gas (gas price for legacy txs)
+    pub fee_cap: U256,
+    /// max priority fee per gas (gas price for legacy txs)
+    pub tip_cap: U256,
+    /// block number at which the tx was first seen
+    pub seen_at: U64,
+}
+
+impl IndexedTx {
+    // Would nodes accept this tx as replacement of `old` (same sender + nonce)
+    pub fn is_valid_replacement_of(&self, old: &IndexedTx) -> bool {
+        let bumped = |fee: U256| fee * (100 + REPLACEMENT_BUMP_PERCENT) / 100;
+        self.fee_cap > old.fee_cap
+            && self.tip_cap > old.tip_cap
+            && self.fee_cap >= bumped(old.fee_cap)
+            && self.tip_cap >= bumped(old.tip_cap)
+    }
+}
+
+/// Result of adding a pending tx to the mempool index
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxObservation {
+    // first tx seen for this (sender, nonce)
+    New,
+    // tx already indexed
+    Duplicate,
+    // tx replaced an older tx with the same (sender, nonce) (speed-up or cancel)
+    Replaced(IndexedTx),
+    // tx doesn't bump fee cap and tip of the indexed tx with the same (sender, nonce) enough,
+    // nodes won't accept it
+    Underpriced,
+}
+
+/// Tracks the latest pending tx for every (sender, nonce) pair that we have seen
+#[derive(Default)]
+pub struct MempoolIndex {
+    txs: HashMap<VictimKey, IndexedTx>,
+    /// on-chain nonce of senders fetched since the latest block (cleared every block)
+    onchain_nonces: HashMap<Address, U256>,
+}
+
+impl MempoolIndex {
+    // Create a new empty mempool index
+    pub fn new() -> Self {
+        Self {
+            txs: HashMap::new(),
+            onchain_nonces: HashMap::new(),
+        }
+    }
+
+    // Record a pending tx (`from` field must be recovered before calling)
+    //
+    // Arguments:
+    // * `&mut self`: mutable reference to index
+    // * `tx`: pending transaction
+    // * `seen_at`: latest block number when tx was received
+    //
+    // Returns:
+    // `TxObservation`: how the tx relates to the txs already in the index
+    pub fn observe(&mut self, tx: &Transaction, seen_at: U64) -> TxObservation {
+        let key = VictimKey::from_tx(tx);
+        let (fee_cap, tip_cap) = get_fee_caps(tx);
+        let indexed = IndexedTx {
+            hash: tx.hash,
+            fee_cap,
+            tip_cap,
+            seen_at,
+        };
+
+        match self.txs.get(&key).copied() {
+            None => {
+                self.txs.insert(key, indexed);
+                TxObservation::New
+            }
+            Some(old) if old.hash == indexed.hash => TxObservation::Duplicate,
+            Some(old) if indexed.is_valid_replacement_of(&old) => {
+                self.txs.insert(key, indexed);
+                TxObservation::Replaced(old)
+            }
+            Some(_) => TxObservation::Underpriced,
+        }
+    }
+
+    // Get the latest indexed tx for (sender, nonce)
+    pub fn get(&self, key: &VictimKey) -> Option<&IndexedTx> {
+        self.txs.get(key)
+    }
+
+    // On-chain nonce of `from` if it was fetched since the latest block
+    pub fn onchain_nonce(&self, from: Address) -> Option<U256> {
+        self.onchain_nonces.get(&from).copied()
+    }
+
+    // Remember the on-chain nonce of `from` until the next block
+    pub fn record_onchain_nonce(&mut self, from: Address, nonce: U256) {
+        self.onchain_nonces.insert(from, nonce);
+    }
+
+    // Is a nonce between the sender's on-chain nonce and `key` missing from the mempool
+    //
+    // Pending txs that were seen for the nonces right before `key` can land in the same block,
+    // the victim only can't land if a nonce was never seen
+    //
+    // Arguments:
+    // * `key`: sender + nonce of the victim
+    // * `onchain_nonce`: next nonce of the sender according to the latest block
+    //
+    // Returns:
+    // bool: true if `key.nonce > onchain_nonce + pending_seen`
+    pub fn has_missing_nonce(&self, key: &VictimKey, onchain_nonce: U256) -> bool {
+        let mut pending_seen = U256::zero();
+        while key.nonce > onchain_nonce + pending_seen {
+            let predecessor = VictimKey {
+                from: key.from,
+                nonce: key.nonce - pending_seen - 1,
+            };
+            if !self.txs.contains_key(&predecessor) {
+                break;
+            }
+            pending_seen += U256::one();
+        }
+        key.nonce > onchain_nonce + pending_seen
+    }
+
+    // Remove all entries that can no longer be included after `block` was mined
+    //
+    // Arguments:
+    // * `&mut self`: mutable reference to index
+    // * `block`: latest block including its transactions
+    //
+    // Returns: This function returns nothing
+    pub fn prune_included(&mut self, block: &Block<Transaction>) {
+        let mined_nonces: HashMap<Address, U256> = block
+            .transactions
+            .iter()
+            .fold(HashMap::new(), |mut acc, tx| {
+                let nonce = acc.entry(tx.from).or_insert(tx.nonce);
+                *nonce = (*nonce).max(tx.nonce);
+                acc
+            });
+
+        let current_block = block.number.unwrap_or_default();
+        self.onchain_nonces.clear();
+        self.txs.retain(|key, indexed| {
+            if indexed.seen_at + MAX_INDEX_AGE <= current_block {
+                return false;
+            }
+            match mined_nonces.get(&key.from) {
+                Some(mined_nonce) => key.nonce > *mined_nonce,
+                None => true,
+            }
+        });
+    }
+
+    // Number of (sender, nonce) pairs held in index
+    pub fn len(&self) -> usize {
+        self.txs.len()
+    }
+
+    // Is the index empty
+    pub fn is_empty(&self) -> bool {
+        self.txs.is_empty()
+    }
+}
+
+// Fee cap and tip used to compare replacements (both are the gas price for legacy txs)
+fn get_fee_caps(tx: &Transaction) -> (U256, U256) {
+    let gas_price = tx.gas_price.unwrap_or(U256::zero());
+    (
+        tx.max_fee_per_gas.unwrap_or(gas_price),
+        tx.max_priority_fee_per_gas.unwrap_or(gas_price),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tx(from: u8, nonce: u64, hash: u8, max_fee_gwei: u64, tip_gwei: u64) -> Transaction {
+        Transaction {
+            hash: H256::repeat_byte(hash),
+            from: Address::repeat_byte(from),
+            nonce: U256::from(nonce),
+            max_fee_per_gas: Some(U256::from(max_fee_gwei) * U256::exp10(9)),
+            max_priority_fee_per_gas: Some(U256::from(tip_gwei) * U256::exp10(9)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn replacements_need_fee_bump() {
+        let mut index = MempoolIndex::new();
+        let block = U64::from(100);
+
+        assert_eq!(
+            index.observe(&tx(1, 5, 0xa1, 50, 2), block),
+            TxObservation::New
+        );
+        assert_eq!(
+            index.observe(&tx(1, 5, 0xa1, 50, 2), block),
+            TxObservation::Duplicate
+        );
+        // fee cap bumped but tip is not
+        assert_eq!(
+            index.observe(&tx(1, 5, 0xa2, 100, 2), block),
+            TxObservation::Underpriced
+        );
+        let replacement = tx(1, 5, 0xa3, 55, 3);
+        match index.observe(&replacement, block) {
+            TxObservation::Replaced(old) => assert_eq!(old.hash, H256::repeat_byte(0xa1)),
+            observation => panic!("expected replacement, got {:?}", observation),
+        }
+        let key = VictimKey::from_tx(&replacement);
+        assert_eq!(index.get(&key).unwrap().hash, replacement.hash);
+    }
+
+    #[test]
+    fn legacy_replacements_bump_gas_price() {
+        let legacy = |hash: u8, gas_price: u64| Transaction {
+            hash: H256::repeat_byte(hash),
+            from: Address::repeat_byte(1),
+            gas_price: Some(U256::from(gas_price)),
+            ..Default::default()
+        };
+        let mut index = MempoolIndex::new();
+        index.observe(&legacy(0xa1, 100), U64::from(1));
+
+        assert_eq!(
+            index.observe(&legacy(0xa2, 109), U64::from(1)),
+            TxObservation::Underpriced
+        );
+        assert!(matches!(
+            index.observe(&legacy(0xa3, 110), U64::from(1)),
+            TxObservation::Replaced(_)
+        ));
+    }
+
+    #[test]
+    fn missing_nonce_is_detected() {
+        let mut index = MempoolIndex::new();
+        index.observe(&tx(1, 5, 0xa1, 50, 2), U64::from(100));
+        index.observe(&tx(1, 6, 0xa2, 50, 2), U64::from(100));
+        let key = |nonce: u64| VictimKey::from_tx(&tx(1, nonce, 0xa3, 50, 2));
+
+        // next nonce on chain
+        assert!(!index.has_missing_nonce(&key(5), U256::from(5)));
+        // nonces 5 and 6 are pending and can land first
+        assert!(!index.has_missing_nonce(&key(7), U256::from(5)));
+        // nonce 4 was never seen
+        assert!(index.has_missing_nonce(&key(7), U256::from(4)));
+        // nonce 7 was never seen
+        assert!(index.has_missing_nonce(&key(8), U256::from(5)));
+        // nonce already used on chain, tx can't land but that's not a gap
+        assert!(!index.has_missing_nonce(&key(3), U256::from(5)));
+    }
+
+    #[test]
+    fn prune_removes_mined_nonces_and_old_entries() {
+        let mut index = MempoolIndex::new();
+        index.observe(&tx(1, 5, 0xa1, 50, 2), U64::from(100));
+        index.observe(&tx(1, 6, 0xa2, 50, 2), U64::from(100));
+        index.observe(&tx(2, 1, 0xb1, 50, 2), U64::from(100));
+        index.observe(&tx(3, 1, 0xc1, 50, 2), U64::from(120));
+
+        // sender 1 mined nonce 5 (through some other tx), sender 2 nothing
+        index.prune_included(&Block {
+            number: Some(U64::from(101)),
+            transactions: vec![tx(1, 5, 0xa9, 60, 3)],
+            ..Default::default()
+        });
+        assert_eq!(index.len(), 3);
+
+        index.prune_included(&Block {
+            number: Some(U64::from(100 + MAX_INDEX_AGE)),
+            ..Default::default()
+        });
+        assert_eq!(index.len(), 1);
+    }
+}
@@ -1,21 +1,42 @@
 use dashmap::DashMap;
 use std::sync::Arc;
 
-use crate::prelude::fork_factory::ForkFactory;
-use crate::prelude::sandwich_types::RawIngredients;
-use crate::prelude::{make_sandwich, Dex, Pool};
+use crate::metrics;
+use crate::prelude::{
+    prefetch::HotStatePrefetcher, state_cache::SharedStateCache, Dex, Pool, PoolStateTracker,
+};
 use crate::rpc_extensions;
-use crate::simulate::helpers::get_sandwich_weth_balance_evm;
 use crate::types::BlockOracle;
 use crate::utils;
+use crate::utils::transport::RpcClient;
 use crate::utils::tx_builder::SandwichMaker;
 use colored::Colorize;
 use ethers::prelude::*;
 use eyre::Result;
+use futures::FutureExt;
 use log;
 
+pub mod admin;
+mod admission;
+mod backlog;
+pub mod ledger;
+mod mempool_index;
 mod oracles;
-use tokio::sync::RwLock;
+mod pipeline;
+pub mod pool_ranking;
+pub mod risk_registry;
+mod slot_scheduler;
+mod treasury;
+mod work_queue;
+use admission::VictimAdmission;
+use backlog::VictimKey;
+use mempool_index::{MempoolIndex, TxObservation};
+use pipeline::{Pipeline, PipelineContext};
+use pool_ranking::PoolRanker;
+use risk_registry::RiskRegistry;
+use slot_scheduler::SlotScheduler;
+use tokio::sync::{Mutex, RwLock};
+use treasury::Treasury;
 
 mod state;
 use state::BotState;
@@ -23,13 +44,28 @@ use state::BotState;
 mod bundle_sender;
 use bundle_sender::*;
 
+// Max number of already received mempool txs that are admitted together
+const MAX_ADMISSION_BATCH: usize = 64;
+
+// Max number of blocks that pool state can be rolled back on reorg
+const POOL_STATE_REORG_DEPTH: usize = 64;
+
 pub struct Bot {
     sandwich_state: Arc<BotState>,
     latest_block_oracle: Arc<RwLock<BlockOracle>>,
-    client: Arc<Provider<Ws>>,
+    client: Arc<RpcClient>,
     all_pools: Arc<DashMap<Address, Pool>>,
     sandwich_maker: Arc<SandwichMaker>,
     bundle_sender: Arc<RwLock<BundleSender>>,
+    mempool_index: Arc<RwLock<MempoolIndex>>,
+    victim_admission: Arc<RwLock<VictimAdmission>>,
+    hot_state: Arc<HotStatePrefetcher>,
+    state_cache: Arc<SharedStateCache>,
+    pool_state: Arc<PoolStateTracker>,
+    pool_ranker: Arc<PoolRanker>,
+    risk_registry: Arc<RiskRegistry>,
+    treasury: Arc<Mutex<Treasury>>,
+    slot_scheduler: Arc<SlotScheduler>,
     dexes: Vec<Dex>,
 }
 
@@ -37,14 +73,14 @@ impl Bot {
     // Create new bot instance
     //
     // Arguments:
-    // * `client`: websocket provider used to make calls
+    // * `client`: provider used to make calls
     // * `pool_vec`: vector of pools that the bot will monitor
     //
     // Returns:
     // * Ok(Bot) if successful
     // * Err(eyre::Error) if not successful
     pub async fn new(
-        client: Arc<Provider<Ws>>,
+        client: Arc<RpcClient>,
         pool_vec: Vec<Pool>,
         dexes: Vec<Dex>,
     ) -> Result<Bot> {
@@ -54,19 +90,42 @@ impl Bot {
             all_pools.insert(pool.address, pool);
         }
 
+        // pools ignored by last ranking stay ignored until next ranking
+        let pool_ranker = Arc::new(PoolRanker::from_env(&all_pools));
+
         let all_pools = Arc::new(all_pools);
 
         let sandwich_inception_block = utils::dotenv::get_sandwich_inception_block();
         let sandwich_state = BotState::new(sandwich_inception_block, &client).await?;
         let sandwich_state = Arc::new(sandwich_state);
 
-        let sandwich_maker = Arc::new(SandwichMaker::new().await);
+        // pools trading tokens the contract already holds don't overpay to leave dust
+        let pools_with_dust = sandwich_state.flag_pools_with_dust(&all_pools).await;
+        log::info!("{} pools trade tokens with dust", pools_with_dust);
+
+        let sandwich_maker = Arc::new(SandwichMaker::new().await?);
 
         let latest_block_oracle = BlockOracle::new(&client).await?;
         let latest_block_oracle = Arc::new(RwLock::new(latest_block_oracle));
 
         let bundle_sender = Arc::new(RwLock::new(BundleSender::new().await));
 
+        let mempool_index = Arc::new(RwLock::new(MempoolIndex::new()));
+
+        let victim_admission = Arc::new(RwLock::new(VictimAdmission::from_env()));
+
+        let hot_state = Arc::new(HotStatePrefetcher::from_env());
+
+        let state_cache = Arc::new(SharedStateCache::from_env());
+
+        let pool_state = Arc::new(PoolStateTracker::new(POOL_STATE_REORG_DEPTH));
+
+        let risk_registry = Arc::new(RiskRegistry::from_env());
+
+        let treasury = Arc::new(Mutex::new(Treasury::from_env()));
+
+        let slot_scheduler = Arc::new(SlotScheduler::from_env());
+
         Ok(Bot {
             client,
             all_pools,
@@ -74,6 +133,15 @@ impl Bot {
             sandwich_state,
             sandwich_maker,
             bundle_sender,
+            mempool_index,
+            victim_admission,
+            hot_state,
+            state_cache,
+            pool_state,
+            pool_ranker,
+            risk_registry,
+            treasury,
+            slot_scheduler,
             dexes,
         })
     }
@@ -89,45 +157,127 @@ impl Bot {
     pub async fn run(&mut self) -> Result<()> {
         log::info!("Starting bot");
 
-        oracles::start_add_new_pools(&mut self.all_pools, self.dexes.clone());
+        oracles::start_add_new_pools(
+            &mut self.all_pools,
+            self.dexes.clone(),
+            self.sandwich_state.clone(),
+        );
+        oracles::start_pool_state_oracle(self.all_pools.clone(), self.pool_state.clone());
+        oracles::start_pool_ranking_oracle(
+            self.all_pools.clone(),
+            self.pool_state.clone(),
+            self.pool_ranker.clone(),
+        );
+        oracles::start_safety_policy_oracle();
         oracles::start_block_oracle(
             self.bundle_sender.clone(),
+            self.mempool_index.clone(),
+            self.victim_admission.clone(),
+            self.hot_state.clone(),
+            self.state_cache.clone(),
+            self.risk_registry.clone(),
+            self.treasury.clone(),
             &mut self.latest_block_oracle,
             self.sandwich_state.clone(),
+            self.all_pools.clone(),
             self.sandwich_maker.clone()
         );
-        // oracles::start_mega_sandwich_oracle(
-        //     self.bundle_sender.clone(),
-        //     self.sandwich_state.clone(),
-        //     self.sandwich_maker.clone(),
-        // );
-
-        let mut mempool_stream = if let Ok(stream) =
-            rpc_extensions::subscribe_pending_txs_with_body(&self.client).await
-        {
-            stream
-        } else {
-            panic!("Failed to create mempool stream");
-        };
-
-        while let Some(mut victim_tx) = mempool_stream.next().await {
-            let client = match utils::create_websocket_client().await {
-                Ok(ws_client) => ws_client,
-                Err(_) => continue,
-            };
+        oracles::start_mega_sandwich_oracle(
+            self.bundle_sender.clone(),
+            self.latest_block_oracle.clone(),
+            self.sandwich_state.clone(),
+            self.sandwich_maker.clone(),
+            self.slot_scheduler.clone(),
+            self.state_cache.clone(),
+        );
+
+        // workers are stopped when pipeline is dropped (bot restarts on rpc disconnect)
+        let pipeline = Pipeline::start(
+            PipelineContext {
+                client: self.client.clone(),
+                all_pools: self.all_pools.clone(),
+                latest_block_oracle: self.latest_block_oracle.clone(),
+                sandwich_state: self.sandwich_state.clone(),
+                sandwich_maker: self.sandwich_maker.clone(),
+                bundle_sender: self.bundle_sender.clone(),
+                hot_state: self.hot_state.clone(),
+                state_cache: self.state_cache.clone(),
+                pool_state: self.pool_state.clone(),
+                pool_ranker: self.pool_ranker.clone(),
+                risk_registry: self.risk_registry.clone(),
+                slot_scheduler: self.slot_scheduler.clone(),
+            },
+            utils::dotenv::get_pipeline_workers(),
+            utils::dotenv::get_pipeline_queue_capacity(),
+        );
+
+        let mut mempool_stream = rpc_extensions::subscribe_pending_txs_with_body(&self.client)
+            .await
+            .map_err(|e| eyre::eyre!("Failed to create mempool stream: {:?}", e))?;
+
+        while let Some(first_tx) = mempool_stream.next().await {
+            // take every tx that is already waiting instead of one tx per poll
+            let mut pending_txs = vec![first_tx];
+            while pending_txs.len() < MAX_ADMISSION_BATCH {
+                match mempool_stream.next().now_or_never() {
+                    Some(Some(tx)) => pending_txs.push(tx),
+                    _ => break,
+                }
+            }
+
             let block_oracle = {
                 let read_lock = self.latest_block_oracle.read().await;
                 (*read_lock).clone()
             };
-            let all_pools = &self.all_pools;
+
+            // biggest and most likely to land victims are processed first
+            let victims = self.admit_victims(pending_txs, &block_oracle).await;
+            for (inclusion_probability, victim_tx) in victims {
+                pipeline.submit(
+                    victim_tx,
+                    inclusion_probability,
+                    block_oracle.next_block.number,
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    // Filter out pending txs that can't or likely won't land in next block
+    //
+    // Arguments:
+    // * `&self`: reference to self
+    // * `pending_txs`: txs received from mempool
+    // * `block_oracle`: holds information about latest and next block
+    //
+    // Returns:
+    // Vec<(f64, Transaction)>: admitted victims (with `from` recovered) and their inclusion probability
+    async fn admit_victims(
+        &self,
+        pending_txs: Vec<Transaction>,
+        block_oracle: &BlockOracle,
+    ) -> Vec<(f64, Transaction)> {
+        let mut victims = vec![];
+        // sent bundles that held a replaced tx can't land, they are resent without it
+        let mut resend_bundles = false;
+
+        for mut victim_tx in pending_txs {
+            metrics::VICTIMS_SEEN.inc();
 
             // ignore txs that we can't include in next block
-            // enhancement: simulate all txs, store result, and use result when tx can included
-            if victim_tx.max_fee_per_gas.unwrap_or(U256::zero()) < block_oracle.next_block.base_fee
+            let inclusion_probability = match self
+                .victim_admission
+                .read()
+                .await
+                .check_fees(&victim_tx, &block_oracle.next_block)
             {
-                // log::info!("{}", format!("{:?} mf<nbf", victim_tx.hash).cyan());
-                continue;
-            }
+                Ok(probability) => probability,
+                Err(rejection) => {
+                    rejection.counter().inc();
+                    continue;
+                }
+            };
 
             // recover from field from vrs (ECDSA)
             // enhancement: expensive operation, can avoid by modding rpc to share `from` field
@@ -141,153 +291,111 @@ impl Bot {
                 continue;
             };
 
-            // get all state diffs that this tx produces
-            let state_diffs = if let Some(sd) = utils::state_diff::get_from_txs(
-                &self.client,
-                &vec![victim_tx.clone()],
-                BlockNumber::Number(block_oracle.latest_block.number),
-            )
-            .await
-            {
-                sd
-            } else {
-                // log::info!("{:?}", victim_tx.hash);
-                continue;
+            // check if tx replaces a pending tx with same sender + nonce (speed-up or cancel)
+            let observation = self
+                .mempool_index
+                .write()
+                .await
+                .observe(&victim_tx, block_oracle.latest_block.number);
+            match observation {
+                TxObservation::New => {}
+                TxObservation::Duplicate | TxObservation::Underpriced => continue,
+                TxObservation::Replaced(old_tx) => {
+                    // recipes and bundles holding the old tx can never land, treat replacement as
+                    // new victim (it goes through admission and the pipeline like any other tx)
+                    let key = VictimKey::from_tx(&victim_tx);
+                    let (removed_recipes, in_flight_target) = self
+                        .bundle_sender
+                        .write()
+                        .await
+                        .invalidate_victim(&key, old_tx.hash);
+                    log::info!(
+                        "{}",
+                        format!(
+                            "{:?} replaced by {:?} (removed recipes: {}, already sent bundle for block: {:?})",
+                            old_tx.hash, victim_tx.hash, removed_recipes, in_flight_target
+                        )
+                        .cyan()
+                    );
+                    resend_bundles |= matches!(
+                        in_flight_target,
+                        Some(target) if target >= block_oracle.next_block.number
+                    );
+                }
+            }
+
+            let onchain_nonce = self.get_onchain_nonce(&victim_tx).await;
+            let admitted = {
+                let mempool_index = self.mempool_index.read().await;
+                self.victim_admission.read().await.check_victim(
+                    &victim_tx,
+                    &block_oracle.next_block,
+                    &mempool_index,
+                    onchain_nonce,
+                )
             };
+            if let Err(rejection) = admitted {
+                rejection.counter().inc();
+                continue;
+            }
 
-            // if tx has statediff on pool addr then record it in `sandwichable_pools`
-            let sandwichable_pools =
-                if let Some(sp) = utils::state_diff::extract_pools(&state_diffs, &all_pools) {
-                    sp
-                } else {
-                    // log::info!("{:?}", victim_tx.hash);
-                    continue;
-                };
+            metrics::VICTIMS_ADMITTED.inc();
+            victims.push((inclusion_probability, victim_tx));
+        }
 
-            let fork_block = Some(BlockId::Number(BlockNumber::Number(
-                block_oracle.next_block.number,
-            )));
+        // backlog no longer holds the replaced txs, rebuilding the mega sandwich leaves them out
+        if resend_bundles {
+            tokio::spawn(BundleSender::make_mega_sandwich(
+                self.bundle_sender.clone(),
+                self.latest_block_oracle.clone(),
+                block_oracle.next_block.clone(),
+                self.sandwich_state.clone(),
+                self.sandwich_maker.clone(),
+                self.state_cache.clone(),
+                self.slot_scheduler.clone(),
+            ));
+        }
 
-            // create evm simulation handler by setting up `fork_factory`
-            let initial_db = utils::state_diff::to_cache_db(&state_diffs, fork_block, &self.client)
-                .await
-                .unwrap();
-            let fork_factory =
-                ForkFactory::new_sandbox_factory(client.clone(), initial_db, fork_block);
-            // update sandwich weth balance
-            let fork_db = fork_factory.new_sandbox_fork();
-            let sandwich_balance =
-                get_sandwich_weth_balance_evm(&block_oracle.next_block, fork_db).unwrap();
-
-            self.sandwich_state
-                .update_weth_balance(sandwich_balance)
-                .await;
-            // let sandwich_balance = {
-            //     let read_lock = self.sandwich_state.weth_balance.read().await;
-            //     (*read_lock).clone()
-            // };
-            // search for opportunities in all pools that the tx touches (concurrently)
-            for sandwichable_pool in sandwichable_pools {
-                if !sandwichable_pool.is_weth_input {
-                    // enhancement: increase opportunities by handling swaps in pools with stables
-                    // log::info!("{:?} [weth_is_output]", victim_tx.hash);
-                    continue;
-                } else {
-                    // log::info!(
-                    //     "{}",
-                    //     format!("{:?} [weth_is_input]", victim_tx.hash).green()
-                    // );
-                }
+        victims
+    }
 
-                // prepare variables for new thread
-                let victim_tx = victim_tx.clone();
-                let sandwichable_pool = sandwichable_pool.clone();
-                let mut fork_factory = fork_factory.clone();
-                let block_oracle = block_oracle.clone();
-                let sandwich_maker = self.sandwich_maker.clone();
-                let bundle_sender = self.bundle_sender.clone();
-                let state_diffs = state_diffs.clone();
-
-                tokio::spawn(async move {
-                    // enhancement: increase opportunities by handling swaps in pools with stables
-                    let input_token = utils::constants::get_weth_address();
-                    let victim_hash = victim_tx.hash;
-                    // variables used when searching for opportunity
-                    let raw_ingredients = if let Ok(data) = RawIngredients::new(
-                        &sandwichable_pool.pool,
-                        vec![victim_tx],
-                        input_token,
-                        state_diffs,
-                    )
-                    .await
-                    {
-                        data
-                    } else {
-                        log::error!("Failed to create raw ingredients for: {:?}", &victim_hash);
-                        return;
-                    };
-
-                    // find optimal input to sandwich tx
-                    let optimal_sandwich = match make_sandwich::create_optimal_sandwich(
-                        &mut vec![raw_ingredients],
-                        sandwich_balance,
-                        &block_oracle.next_block,
-                        &mut fork_factory,
-                        &sandwich_maker,
-                    )
+    // Next nonce of the victim's sender according to the latest block (cached until next block)
+    //
+    // Arguments:
+    // * `&self`: reference to self
+    // * `victim_tx`: pending tx with recovered `from`
+    //
+    // Returns:
+    // U256: sender's on-chain nonce (the tx's own nonce if it can't be fetched, so no gap is assumed)
+    async fn get_onchain_nonce(&self, victim_tx: &Transaction) -> U256 {
+        if victim_tx.nonce.is_zero() {
+            return victim_tx.nonce;
+        }
+        if let Some(nonce) = self
+            .mempool_index
+            .read()
+            .await
+            .onchain_nonce(victim_tx.from)
+        {
+            return nonce;
+        }
+
+        match self
+            .client
+            .get_transaction_count(victim_tx.from, None)
+            .await
+        {
+            Ok(nonce) => {
+                self.mempool_index
+                    .write()
                     .await
-                    {
-                        Ok(optimal) => optimal,
-                        Err(e) => {
-                            log::info!(
-                                "{}",
-                                format!("[{:?}] sim failed due to {:?}", &victim_hash, e).yellow()
-                            );
-                            return;
-                        }
-                    };
-
-                    // spawn thread to send tx to builders
-                    let optimal_sandwich = optimal_sandwich.clone();
-                    let optimal_sandwich_two = optimal_sandwich.clone();
-                    // let sandwich_maker = sandwich_maker.clone();
-                    if optimal_sandwich.revenue > U256::zero() {
-                        let bundle_sender = bundle_sender.clone();
-                        // tokio::spawn(async move {
-                        bundle_sender
-                            .write()
-                            .await
-                            .add_recipe(optimal_sandwich_two, sandwichable_pool.pool)
-                            .await;
-                        // });
-                        tokio::spawn(async move {
-                            match bundle_sender::send_bundle(
-                                &optimal_sandwich,
-                                block_oracle.next_block,
-                                sandwich_maker,
-                                // sandwich_state.clone(),
-                            )
-                            .await
-                            {
-                                Ok(_) => { /* all reporting already done inside of send_bundle */ }
-                                Err(e) => {
-                                    log::info!(
-                                        "{}",
-                                        format!(
-                                            "{:?} failed to send bundle, due to {:?}",
-                                            optimal_sandwich.print_meats(),
-                                            e
-                                        )
-                                        .bright_magenta()
-                                    );
-                                }
-                            };
-                        });
-                    }
-                });
+                    .record_onchain_nonce(victim_tx.from, nonce);
+                nonce
+            }
+            Err(e) => {
+                log::debug!("Failed to fetch nonce of {:?}: {:?}", victim_tx.from, e);
+                victim_tx.nonce
             }
         }
-
-        Ok(())
     }
 }
@@ -1,58 +1,84 @@
 use colored::Colorize;
 use dashmap::DashMap;
 use ethers::prelude::*;
+use hashbrown::HashMap;
 // use ethers::types::transaction::eip2930::AccessList;
 // use ethers::types::TransactionRequest;
 use std::sync::Arc;
 // use std::thread;
-use std::time::Duration;
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, RwLock};
 
-use crate::prelude::{sync_dex, BlockInfo, Dex, Pool};
+use crate::metrics;
+use crate::prelude::{
+    prefetch::HotStatePrefetcher, safety_policy::SafetyPolicyWatcher,
+    state_cache::SharedStateCache, sync_dex, BlockInfo, Dex, Pool, PoolStateTracker,
+};
 use crate::types::BlockOracle;
 use crate::utils;
 use crate::utils::tx_builder::SandwichMaker;
 
+use super::admission::VictimAdmission;
 use super::bundle_sender::BundleSender;
+use super::mempool_index::MempoolIndex;
+use super::pool_ranking::{PoolRanker, POOL_RANKING_FILE};
+use super::risk_registry::{RiskReason, RiskRegistry};
+use super::slot_scheduler::SlotScheduler;
 use super::state::BotState;
+use super::treasury::Treasury;
 
 // Update latest block variable whenever we recieve a new block
 //
 // Arguments:
+// * `bundle_sender`: holds recipe backlog and in flight bundles that are pruned every block
+// * `mempool_index`: index of pending txs by sender + nonce that is pruned every block
+// * `victim_admission`: learns recent block tips to estimate victim inclusion
+// * `hot_state`: hot pool state that is prefetched for every new block
+// * `state_cache`: state shared by simulations, carried over to next block with its state diff
+// * `risk_registry`: pools whose sandwich reverted on chain are flagged in it
+// * `treasury`: rebalances sandwich contract and searcher balances every block
 // * `oracle`: oracle to update
+// * `sandwich_state`: dust collected (or sold) by our included txs is updated in it
+// * `all_pools`: pools whose `has_dust` is updated when dust changes
+// * `sandwich_maker`: holds the searcher nonce that is refreshed every block
 pub fn start_block_oracle(
     bundle_sender: Arc<RwLock<BundleSender>>,
+    mempool_index: Arc<RwLock<MempoolIndex>>,
+    victim_admission: Arc<RwLock<VictimAdmission>>,
+    hot_state: Arc<HotStatePrefetcher>,
+    state_cache: Arc<SharedStateCache>,
+    risk_registry: Arc<RiskRegistry>,
+    treasury: Arc<Mutex<Treasury>>,
     oracle: &mut Arc<RwLock<BlockOracle>>,
     sandwich_state: Arc<BotState>,
+    all_pools: Arc<DashMap<Address, Pool>>,
     sandwich_maker: Arc<SandwichMaker>,
 ) {
     let next_block_clone = oracle.clone();
 
     tokio::spawn(async move {
+        let provider_manager = utils::get_provider_manager();
+
         // loop so we can reconnect if the websocket connection is lost
         loop {
-            let client = utils::create_websocket_client().await.unwrap();
+            let client = provider_manager.get_with_retry().await;
 
-            let mut block_stream = if let Ok(stream) = client.subscribe_blocks().await {
-                stream
-            } else {
-                panic!("Failed to create new block stream");
+            let mut block_stream = match client.subscribe_blocks().await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    log::error!("Failed to create new block stream: {:?}", e);
+                    provider_manager.report_failure(&client).await;
+                    tokio::time::sleep(provider_manager.backoff().initial).await;
+                    continue;
+                }
             };
             while let Some(block) = block_stream.next().await {
-                //update searcher nonce
-                sandwich_maker.update_searcher_nonce().await;
-                // clear all recipes
-                // enchanement: don't do this step but keep recipes because they can be used in future
-                {
-                    let bundle_sender = bundle_sender.clone();
-                    let mut bundle_sender_guard = bundle_sender.write().await;
-                    bundle_sender_guard.pending_sandwiches.clear();
-                } // lock removed here
-                  // lock the RwLock for write access and update the variable
+                let block_number = block.number.unwrap_or_default();
+                // lock the RwLock for write access and update the variable first so new
+                // simulations target the right block while the bookkeeping below runs
                 {
                     let block = block.clone();
                     let mut write_lock = next_block_clone.write().await;
-                    write_lock.update_block_number(block.number.unwrap());
+                    write_lock.update_block_number(block_number);
                     write_lock.update_block_timestamp(block.timestamp);
                     write_lock.update_base_fee(block);
 
@@ -68,98 +94,391 @@ pub fn start_block_oracle(
                     .on_black()
                     );
                 } // remove write lock due to being out of scope here
+
+                // update searcher nonce and release reservations of bundles that targeted this block
+                sandwich_maker.update_searcher_nonce(block_number).await;
                 {
-                    let sandwich_balance = {
-                        let read_lock = sandwich_state.weth_balance.read().await;
-                        (*read_lock).clone()
-                    };
-                    if sandwich_balance > U256::from(4500000000000000000u128) {
-                        let sandwich_address = utils::dotenv::get_sandwich_contract_address();
-                        let searcher_wallet = utils::dotenv::get_searcher_wallet();
-                        let recover_amount = U256::from(500000000000000000i64);
-                        let (payload, value) = get_recover_weth_payload_value(recover_amount);
-                        let tx = TransactionRequest::new()
-                            .to(NameOrAddress::Address(sandwich_address))
-                            .value(value)
-                            .from(searcher_wallet.address())
-                            .data(payload.clone());
-                        // let recover_weth_tx_request = Eip1559TransactionRequest {
-                        //     to: Some(NameOrAddress::Address(sandwich_address)),
-                        //     from: Some(searcher_wallet.address()),
-                        //     data: Some(payload.clone().into()),
-                        //     chain_id: Some(U64::from(1)),
-                        //     max_priority_fee_per_gas: Some(U256::from(0)),
-                        //     max_fee_per_gas: Some(next_block.base_fee),
-                        //     gas: Some(U256::from(70000)), // gasused = 70% gaslimit
-                        //     nonce: Some(nonce),
-                        //     value: Some(value),
-                        //     access_list: AccessList::default(),
-                        // };
-                        // let signed_raw_tx =
-                        //     utils::sign_eip1559(recover_weth_tx_request, &searcher_wallet)
-                        //         .await
-                        //         .unwrap();
-                        let pending_tx = client.send_transaction(tx, None).await.unwrap();
+                    // simulations fork from next block, prefetch state at the same block id
+                    let fork_block = next_block_clone.read().await.next_block.number;
+                    let cache = state_cache.for_block(fork_block);
+                    let hot_state = hot_state.clone();
+                    let client = client.clone();
+                    tokio::spawn(async move {
+                        hot_state.prefetch(client.as_ref(), &cache).await;
+                    });
+                }
+                if let Some(block_hash) = block.hash {
+                    let parent_hash = block.parent_hash;
+                    // tracing and fetching receipts of the block runs in the background so it
+                    // can't hold up the block oracle
+                    let client = client.clone();
+                    let bundle_sender = bundle_sender.clone();
+                    let mempool_index = mempool_index.clone();
+                    let victim_admission = victim_admission.clone();
+                    let state_cache = state_cache.clone();
+                    let risk_registry = risk_registry.clone();
+                    let sandwich_state = sandwich_state.clone();
+                    let all_pools = all_pools.clone();
+                    let sandwich_maker = sandwich_maker.clone();
+                    tokio::spawn(async move {
+                        // remove recipes whose victims got mined (or replaced) in this block
+                        // all other recipes are kept so they can be used in future mega sandwiches
+                        let full_block = match client.get_block_with_txs(block_hash).await {
+                            Ok(Some(full_block)) => full_block,
+                            _ => return,
+                        };
+                        // move shared state to the block that simulations fork from next
+                        // (balances changed outside of txs can't be traced so are refetched)
+                        let block_diffs = utils::state_diff::get_from_block(
+                            &client,
+                            BlockNumber::Number(block_number),
+                        )
+                        .await;
+                        let mut evicted: Vec<Address> = full_block
+                            .withdrawals
+                            .iter()
+                            .flatten()
+                            .map(|withdrawal| withdrawal.address)
+                            .collect();
+                        evicted.extend(full_block.author);
+                        state_cache.advance(
+                            block_number + 1,
+                            block_hash,
+                            parent_hash,
+                            block_diffs.as_deref(),
+                            &evicted,
+                        );
+
+                        let receipts =
+                            get_sandwich_receipts(&client, &full_block, &sandwich_maker).await;
+                        let reverted_nonces = receipts
+                            .iter()
+                            .filter(|(_, receipt)| receipt.status == Some(U64::zero()))
+                            .map(|(nonce, _)| *nonce);
+
+                        // our included sandwiches leave dust of the tokens they bought behind,
+                        // `withdraw-dust` sells it
+                        let logs: Vec<Log> = receipts
+                            .iter()
+                            .flat_map(|(_, receipt)| receipt.logs.iter().cloned())
+                            .collect();
+                        let (added, removed) = sandwich_state
+                            .update_dust_from_logs(&logs, block_number, &all_pools, &client)
+                            .await;
+                        for token in added {
+                            log::info!("Sandwich contract collected dust of {:?}", token);
+                        }
+                        for token in removed {
+                            log::info!("Sandwich contract has no dust of {:?} left", token);
+                        }
+
+                        let mut bundle_sender_guard = bundle_sender.write().await;
+                        // pools of our sandwiches that landed but reverted are not sandwiched again
+                        for nonce in reverted_nonces {
+                            for pool in bundle_sender_guard.pools_sent_with_nonce(nonce) {
+                                risk_registry.flag_pool(
+                                    pool.address,
+                                    RiskReason::RevertedOnChain,
+                                    format!("nonce {} reverted in block {}", nonce, block_number),
+                                );
+                            }
+                        }
+                        let (mined, replaced) =
+                            bundle_sender_guard.backlog.prune_included(&full_block);
+                        bundle_sender_guard.prune_in_flight(block_number);
+                        mempool_index.write().await.prune_included(&full_block);
+                        victim_admission.write().await.record_block(&full_block);
                         log::info!(
-                            "{}",
-                            format!(
-                                "Recover weth transaction {:x?}",
-                                hex::encode(pending_tx.tx_hash().as_bytes())
-                            )
-                            .black()
-                            .on_white()
+                            "Backlog pruned (mined: {}, replaced: {}, remaining: {})",
+                            mined,
+                            replaced,
+                            bundle_sender_guard.backlog.len()
                         );
-                        // let receipt = pending_tx.await.unwrap().unwrap();
+                        log::info!("Metrics: {}", metrics::summary());
+                    });
+                }
+                {
+                    // treasury runs in the background so it can't hold up the block oracle, a
+                    // rebalance still in progress skips this block
+                    let treasury = treasury.clone();
+                    let sandwich_maker = sandwich_maker.clone();
+                    let client = client.clone();
+                    tokio::spawn(async move {
+                        let mut treasury = match treasury.try_lock() {
+                            Ok(treasury) => treasury,
+                            Err(_) => return,
+                        };
+                        if let Err(e) = treasury
+                            .rebalance(&client, block_number, &sandwich_maker)
+                            .await
+                        {
+                            log::error!("Treasury failed to rebalance: {:?}", e);
+                        }
+                    });
+                }
+            }
+
+            // stream ended because connection was lost, replace it
+            log::error!("Block stream ended, reconnecting");
+            provider_manager.report_failure(&client).await;
+        }
+    });
+}
+
+// Keep pool reserves / prices up to date from pool logs
+//
+// Arguments:
+// * `all_pools`: pools whose logs are tracked (pools added later are picked up automatically)
+// * `pool_state`: tracker to update
+pub fn start_pool_state_oracle(
+    all_pools: Arc<DashMap<Address, Pool>>,
+    pool_state: Arc<PoolStateTracker>,
+) {
+    tokio::spawn(async move {
+        let provider_manager = utils::get_provider_manager();
+        // subscribing by address would need a huge filter, so subscribe by event and filter locally
+        let filter = Filter::new().topic0(PoolStateTracker::event_signatures());
+        let mut last_block: Option<U64> = None;
+        // (block hash, log index) of applied logs since `last_block`, catch-up and the new
+        // subscription overlap with logs that were already applied
+        let mut applied: HashMap<(H256, U256), U64> = HashMap::new();
+
+        // loop so we can reconnect if the websocket connection is lost
+        loop {
+            let client = provider_manager.get_with_retry().await;
+
+            let mut log_stream = match client.subscribe_logs(&filter).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    log::error!("Failed to create pool log stream: {:?}", e);
+                    provider_manager.report_failure(&client).await;
+                    tokio::time::sleep(provider_manager.backoff().initial).await;
+                    continue;
+                }
+            };
+
+            // catch up on logs emitted while disconnected (the last block seen may have been
+            // only partly received)
+            if let Some(from_block) = last_block {
+                let missed_filter = filter.clone().from_block(from_block);
+                match client.get_logs(&missed_filter).await {
+                    Ok(logs) => {
+                        for log in logs {
+                            apply_new_pool_log(
+                                &all_pools,
+                                &pool_state,
+                                &mut applied,
+                                &mut last_block,
+                                &log,
+                            );
+                        }
                     }
+                    Err(e) => log::error!("Failed to fetch missed pool logs: {:?}", e),
                 }
+            }
 
-                // // 10.5 seconds from when new block was detected, caluclate mega sandwich
-                let sandwich_maker = sandwich_maker.clone();
-                let bundle_sender = bundle_sender.clone();
-                let sandwich_state = sandwich_state.clone();
-                tokio::spawn(async move {
-                    tokio::time::sleep(Duration::from_millis(10_500)).await;
-                    let next_block_info = BlockInfo::find_next_block_info(block);
-                    {
-                        let bundle_sender = bundle_sender.clone();
-                        bundle_sender
-                            .write()
-                            .await
-                            .make_mega_sandwich(next_block_info, sandwich_state, sandwich_maker)
-                            .await;
-                    } // lock removed here
-                });
+            while let Some(log) = log_stream.next().await {
+                apply_new_pool_log(&all_pools, &pool_state, &mut applied, &mut last_block, &log);
             }
+
+            log::error!("Pool log stream ended, reconnecting");
+            provider_manager.report_failure(&client).await;
         }
     });
 }
 
-fn get_recover_weth_payload_value(recover_amount: U256) -> (Vec<u8>, U256) {
-    let swap_type = U256::from(124);
-    let (payload, _) = utils::encode_packed(&[utils::PackedToken::NumberWithShift(
-        swap_type,
-        utils::TakeLastXBytes(8),
-    )]);
-    let value = recover_amount / utils::tx_builder::sandwich::get_weth_encode_divisor();
-    (payload, value)
+// Nonces and receipts of searcher txs to the sandwich contract in `block`
+async fn get_sandwich_receipts<M: Middleware>(
+    client: &M,
+    block: &Block<Transaction>,
+    sandwich_maker: &SandwichMaker,
+) -> Vec<(U256, TransactionReceipt)> {
+    let searcher = sandwich_maker.searcher_wallet.address();
+    let mut receipts = vec![];
+
+    for tx in block
+        .transactions
+        .iter()
+        .filter(|tx| tx.from == searcher && tx.to == Some(sandwich_maker.sandwich_address))
+    {
+        match client.get_transaction_receipt(tx.hash).await {
+            Ok(Some(receipt)) => receipts.push((tx.nonce, receipt)),
+            Ok(None) => {}
+            Err(e) => log::error!("Failed to get receipt of {:?}: {:?}", tx.hash, e),
+        }
+    }
+
+    receipts
+}
+
+// Apply a log unless it was already applied (removed logs are always applied to revert them)
+//
+// Arguments:
+// * `all_pools`: known pools
+// * `pool_state`: tracker the log is applied to
+// * `applied`: (block hash, log index) -> block number of logs applied since `last_block`
+// * `last_block`: latest block that logs were received for
+// * `log`: pool event
+fn apply_new_pool_log(
+    all_pools: &DashMap<Address, Pool>,
+    pool_state: &PoolStateTracker,
+    applied: &mut HashMap<(H256, U256), U64>,
+    last_block: &mut Option<U64>,
+    log: &Log,
+) {
+    let (block_hash, log_index, block_number) =
+        match (log.block_hash, log.log_index, log.block_number) {
+            (Some(block_hash), Some(log_index), Some(block_number)) => {
+                (block_hash, log_index, block_number)
+            }
+            // pending logs can't be de-duplicated
+            _ => return apply_pool_log(all_pools, pool_state, log),
+        };
+
+    if log.removed == Some(true) {
+        if applied.remove(&(block_hash, log_index)).is_some() {
+            apply_pool_log(all_pools, pool_state, log);
+        }
+        return;
+    }
+    if applied
+        .insert((block_hash, log_index), block_number)
+        .is_some()
+    {
+        return;
+    }
+
+    if last_block.map_or(true, |last| block_number > last) {
+        *last_block = Some(block_number);
+        applied.retain(|_, applied_block| *applied_block >= block_number);
+    }
+    apply_pool_log(all_pools, pool_state, log);
+}
+
+// Apply a log to the tracker if it was emitted by a known pool
+fn apply_pool_log(all_pools: &DashMap<Address, Pool>, pool_state: &PoolStateTracker, log: &Log) {
+    let pool = match all_pools.get(&log.address) {
+        Some(pool) => *pool,
+        None => return,
+    };
+
+    if pool_state.apply_log(&pool, log) {
+        metrics::POOL_STATE_UPDATES.inc();
+        metrics::POOL_STATES_TRACKED.set(pool_state.len() as u64);
+    }
 }
 
-pub fn start_add_new_pools(all_pools: &mut Arc<DashMap<Address, Pool>>, dexes: Vec<Dex>) {
+// Rank pools every `POOL_RANKING_INTERVAL_SECS` and ignore pools that are not worth simulating
+//
+// Arguments:
+// * `all_pools`: pool registry whose ignored flags are updated
+// * `pool_state`: tracked pool reserves and weth volume
+// * `pool_ranker`: simulation history of every pool
+pub fn start_pool_ranking_oracle(
+    all_pools: Arc<DashMap<Address, Pool>>,
+    pool_state: Arc<PoolStateTracker>,
+    pool_ranker: Arc<PoolRanker>,
+) {
+    tokio::spawn(async move {
+        let provider_manager = utils::get_provider_manager();
+        let interval =
+            std::time::Duration::from_secs(utils::dotenv::get_pool_ranking_interval_secs());
+
+        // pick up where the previous report left off
+        tokio::time::sleep(pool_ranker.next_ranking_in(interval)).await;
+
+        loop {
+            let client = provider_manager.get_with_retry().await;
+
+            match pool_ranker.rank(&*client, &all_pools, &pool_state).await {
+                Ok(report) => {
+                    let ignored = report.entries.iter().filter(|entry| entry.ignored).count();
+                    log::info!(
+                        "{}",
+                        format!(
+                            "ranked {} pools at block {} ({} ignored)",
+                            report.entries.len(),
+                            report.block,
+                            ignored
+                        )
+                        .cyan()
+                    );
+                    if let Err(e) = report.save(POOL_RANKING_FILE) {
+                        log::error!("Failed to write pool ranking: {:?}", e);
+                    }
+                }
+                Err(e) => {
+                    log::error!("Failed to rank pools: {:?}", e);
+                    provider_manager.report_failure(&client).await;
+                    tokio::time::sleep(provider_manager.backoff().initial).await;
+                    continue;
+                }
+            }
+
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+// Reload the salmonella safety policy whenever its file changes
+pub fn start_safety_policy_oracle() {
+    tokio::spawn(async move {
+        let mut watcher = SafetyPolicyWatcher::new(utils::dotenv::get_safety_policy_file());
+        let interval =
+            std::time::Duration::from_secs(utils::dotenv::get_safety_policy_reload_secs());
+
+        loop {
+            tokio::time::sleep(interval).await;
+
+            match watcher.poll() {
+                Ok(true) => log::info!(
+                    "{}",
+                    format!("Reloaded safety policy from {}", watcher.path()).cyan()
+                ),
+                Ok(false) => {}
+                Err(e) => log::error!(
+                    "Invalid safety policy {}, keeping current one: {:?}",
+                    watcher.path(),
+                    e
+                ),
+            }
+        }
+    });
+}
+
+pub fn start_add_new_pools(
+    all_pools: &mut Arc<DashMap<Address, Pool>>,
+    dexes: Vec<Dex>,
+    sandwich_state: Arc<BotState>,
+) {
     let all_pools = all_pools.clone();
 
     tokio::spawn(async move {
+        let provider_manager = utils::get_provider_manager();
+
         // loop so we can reconnect if the websocket connection is lost
         loop {
-            let client = utils::create_websocket_client().await.unwrap();
+            let client = provider_manager.get_with_retry().await;
 
-            let mut block_stream = if let Ok(stream) = client.subscribe_blocks().await {
-                stream
-            } else {
-                panic!("Failed to create new block stream");
+            let mut block_stream = match client.subscribe_blocks().await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    log::error!("Failed to create new block stream: {:?}", e);
+                    provider_manager.report_failure(&client).await;
+                    tokio::time::sleep(provider_manager.backoff().initial).await;
+                    continue;
+                }
             };
 
             let mut counter = 0;
-            let mut current_block_num = client.get_block_number().await.unwrap();
+            let mut current_block_num = match client.get_block_number().await {
+                Ok(block_number) => block_number,
+                Err(e) => {
+                    log::error!("Failed to get block number: {:?}", e);
+                    provider_manager.report_failure(&client).await;
+                    continue;
+                }
+            };
 
             while let Some(block) = block_stream.next().await {
                 counter += 1;
@@ -167,19 +486,28 @@ pub fn start_add_new_pools(all_pools: &mut Arc<DashMap<Address, Pool>>, dexes: V
                 let interval_block_new_pool = utils::dotenv::get_interval_block_new_pool();
                 if counter == interval_block_new_pool {
                     let latest_block_number = block.number.unwrap();
-                    let fetched_new_pools = sync_dex(
+                    let fetched_new_pools = match sync_dex(
                         dexes.clone(),
                         &client,
                         latest_block_number,
                         Some(BlockNumber::Number(current_block_num)),
                     )
                     .await
-                    .unwrap();
+                    {
+                        Ok(pools) => pools,
+                        Err(e) => {
+                            // retry from same block after next interval
+                            log::error!("Failed to sync new pools: {:?}", e);
+                            counter = 0;
+                            continue;
+                        }
+                    };
 
                     let fetched_pools_count = fetched_new_pools.len();
 
                     // turn fetched pools into hashmap
-                    for pool in fetched_new_pools {
+                    for mut pool in fetched_new_pools {
+                        pool.has_dust = sandwich_state.pool_has_dust(&pool).await;
                         // Create hashmap from our vec
                         all_pools.insert(pool.address, pool);
                     }
@@ -189,56 +517,69 @@ pub fn start_add_new_pools(all_pools: &mut Arc<DashMap<Address, Pool>>, dexes: V
                     log::info!("added {} new pools", fetched_pools_count);
                 }
             }
+
+            // stream ended because connection was lost, replace it
+            log::error!("Block stream ended, reconnecting");
+            provider_manager.report_failure(&client).await;
         }
     });
 }
 
-// pub fn start_mega_sandwich_oracle(
-//     bundle_sender: Arc<RwLock<BundleSender>>,
-//     sandwich_state: Arc<BotState>,
-//     sandwich_maker: Arc<SandwichMaker>,
-// ) {
-//     tokio::spawn(async move {
-//         // loop so we can reconnect if the websocket connection is lost
-//         loop {
-//             let client = utils::create_websocket_client().await.unwrap();
-
-//             let mut block_stream = if let Ok(stream) = client.subscribe_blocks().await {
-//                 stream
-//             } else {
-//                 panic!("Failed to create new block stream");
-//             };
-//             while let Some(block) = block_stream.next().await {
-//                 //update searcher nonce
-//                 sandwich_maker.update_searcher_nonce().await;
-//                 // clear all recipes
-//                 // enchanement: don't do this step but keep recipes because they can be used in future
-//                 {
-//                     let bundle_sender = bundle_sender.clone();
-//                     let mut bundle_sender_guard = bundle_sender.write().await;
-//                     bundle_sender_guard.pending_sandwiches.clear();
-//                 } // lock removed here
-//                 let sandwich_maker = sandwich_maker.clone();
-//                 let bundle_sender = bundle_sender.clone();
-//                 let sandwich_state = sandwich_state.clone();
-//                 tokio::spawn(async move {
-//                     tokio::time::sleep(Duration::from_millis(10_500)).await;
-//                     let next_block_info = BlockInfo::find_next_block_info(block);
-//                     {
-//                         let bundle_sender = bundle_sender.clone();
-//                         bundle_sender
-//                             .write()
-//                             .await
-//                             .make_mega_sandwich(
-//                                 next_block_info,
-//                                 sandwich_state,
-//                                 sandwich_maker,
-//                             )
-//                             .await;
-//                     } // lock removed here
-//                 });
-//                 // 10.5 seconds from when new block was detected, caluclate mega sandwich
-//             }
-//         }
-//     });
-// }
+// Compute mega sandwich for every slot, right before relays stop accepting bundles
+//
+// Arguments:
+// * `bundle_sender`: holds recipe backlog used to make mega sandwich
+// * `oracle`: latest and next block info
+// * `scheduler`: decides when computation starts and when it gets cancelled
+// * `state_cache`: state shared by simulations of the same block
+pub fn start_mega_sandwich_oracle(
+    bundle_sender: Arc<RwLock<BundleSender>>,
+    oracle: Arc<RwLock<BlockOracle>>,
+    sandwich_state: Arc<BotState>,
+    sandwich_maker: Arc<SandwichMaker>,
+    scheduler: Arc<SlotScheduler>,
+    state_cache: Arc<SharedStateCache>,
+) {
+    tokio::spawn(async move {
+        loop {
+            let plan = scheduler.next_plan();
+            scheduler.sleep_until(plan.fire_at_ms).await;
+
+            // target block is derived from the slot, not from when the latest block arrived
+            let next_block_info = {
+                let read_lock = oracle.read().await;
+                BlockInfo::new(
+                    read_lock.latest_block.number + 1,
+                    U256::from(plan.slot_timestamp),
+                    read_lock.next_block.base_fee,
+                )
+            };
+            let target_block = next_block_info.number;
+
+            let computation = BundleSender::make_mega_sandwich(
+                bundle_sender.clone(),
+                oracle.clone(),
+                next_block_info,
+                sandwich_state.clone(),
+                sandwich_maker.clone(),
+                state_cache.clone(),
+                scheduler.clone(),
+            );
+
+            if scheduler
+                .run_before_deadline(plan.deadline_ms, computation)
+                .await
+                .is_none()
+            {
+                log::info!(
+                    "{}",
+                    format!(
+                        "Cancelled mega sandwich for {} (slot {}), missed relay cutoff",
+                        target_block, plan.slot
+                    )
+                    .yellow()
+                );
+            }
+        }
+    });
+}
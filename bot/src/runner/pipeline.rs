@@ -0,0 +1,477 @@
+use colored::Colorize;
+use dashmap::DashMap;
+use ethers::prelude::*;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+
+use crate::metrics;
+use crate::prelude::fork_factory::ForkFactory;
+use crate::prelude::prefetch::HotStatePrefetcher;
+use crate::prelude::sandwich_types::{OptimalRecipe, RawIngredients};
+use crate::prelude::state_cache::SharedStateCache;
+use crate::prelude::{make_sandwich, Pool, PoolStateTracker};
+use crate::simulate::helpers::get_sandwich_weth_balance_evm;
+use crate::types::BlockOracle;
+use crate::utils;
+use crate::utils::state_diff::SandwichablePool;
+use crate::utils::swap_decoder;
+use crate::utils::transport::RpcClient;
+use crate::utils::tx_builder::SandwichMaker;
+
+use super::bundle_sender::{self, BundleSender};
+use super::pool_ranking::PoolRanker;
+use super::risk_registry::RiskRegistry;
+use super::slot_scheduler::SlotScheduler;
+use super::state::BotState;
+use super::work_queue::{WorkItem, WorkQueue};
+
+/// Shared handles used by pipeline workers
+#[derive(Clone)]
+pub struct PipelineContext {
+    pub client: Arc<RpcClient>,
+    pub all_pools: Arc<DashMap<Address, Pool>>,
+    pub latest_block_oracle: Arc<RwLock<BlockOracle>>,
+    pub sandwich_state: Arc<BotState>,
+    pub sandwich_maker: Arc<SandwichMaker>,
+    pub bundle_sender: Arc<RwLock<BundleSender>>,
+    pub hot_state: Arc<HotStatePrefetcher>,
+    pub state_cache: Arc<SharedStateCache>,
+    pub pool_state: Arc<PoolStateTracker>,
+    pub pool_ranker: Arc<PoolRanker>,
+    pub risk_registry: Arc<RiskRegistry>,
+    pub slot_scheduler: Arc<SlotScheduler>,
+}
+
+/// Victim with the state diffs it produces
+struct TracedVictim {
+    victim: Transaction,
+    state_diffs: BTreeMap<Address, AccountDiff>,
+}
+
+/// Single pool of a victim to find the optimal sandwich for
+struct OptimizeJob {
+    victim: Transaction,
+    sandwichable_pool: SandwichablePool,
+    state_diffs: BTreeMap<Address, AccountDiff>,
+    fork_factory: ForkFactory,
+    sandwich_balance: U256,
+}
+
+/// Profitable sandwich waiting to be sent to relays
+struct SendJob {
+    recipe: OptimalRecipe,
+}
+
+/// Processes admitted victims in stages connected by bounded priority queues
+///
+/// trace (state diffs) -> extract (pools + fork db) -> optimize (simulations) -> send (relays)
+///
+/// Every stage runs `workers` tasks, work for blocks that already passed is dropped
+pub struct Pipeline {
+    trace_queue: Arc<WorkQueue<Transaction>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Pipeline {
+    // Start all pipeline workers
+    //
+    // Arguments:
+    // * `context`: shared handles used by workers
+    // * `workers`: number of worker tasks per stage
+    // * `queue_capacity`: max number of items waiting in front of each stage
+    //
+    // Returns: a running `Pipeline` (workers are stopped when dropped)
+    pub fn start(context: PipelineContext, workers: usize, queue_capacity: usize) -> Self {
+        let trace_queue = Arc::new(WorkQueue::new(queue_capacity, &metrics::QUEUE_DEPTH_TRACE));
+        let extract_queue = Arc::new(WorkQueue::new(
+            queue_capacity,
+            &metrics::QUEUE_DEPTH_EXTRACT,
+        ));
+        let optimize_queue = Arc::new(WorkQueue::new(
+            queue_capacity,
+            &metrics::QUEUE_DEPTH_OPTIMIZE,
+        ));
+        let send_queue = Arc::new(WorkQueue::new(queue_capacity, &metrics::QUEUE_DEPTH_SEND));
+
+        let mut handles = vec![];
+        for _ in 0..workers.max(1) {
+            handles.push(tokio::spawn(trace_worker(
+                context.clone(),
+                trace_queue.clone(),
+                extract_queue.clone(),
+            )));
+            handles.push(tokio::spawn(extract_worker(
+                context.clone(),
+                extract_queue.clone(),
+                optimize_queue.clone(),
+            )));
+            handles.push(tokio::spawn(optimize_worker(
+                context.clone(),
+                optimize_queue.clone(),
+                send_queue.clone(),
+            )));
+            handles.push(tokio::spawn(send_worker(
+                context.clone(),
+                send_queue.clone(),
+            )));
+        }
+
+        Self {
+            trace_queue,
+            workers: handles,
+        }
+    }
+
+    // Queue an admitted victim for processing
+    //
+    // Arguments:
+    // * `victim`: pending tx (with `from` recovered)
+    // * `inclusion_probability`: chance victim lands in `target_block`
+    // * `target_block`: block the victim is sandwiched for
+    //
+    // Returns:
+    // true: if victim was queued
+    // false: if victim was dropped because the queue is full of bigger victims
+    pub fn submit(
+        &self,
+        victim: Transaction,
+        inclusion_probability: f64,
+        target_block: U64,
+    ) -> bool {
+        let priority = expected_victim_size(&victim, inclusion_probability);
+        self.trace_queue.try_push(WorkItem {
+            target_block,
+            priority,
+            job: victim,
+        })
+    }
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        for worker in self.workers.iter() {
+            worker.abort();
+        }
+    }
+}
+
+// Get all state diffs that a victim produces
+async fn trace_worker(
+    context: PipelineContext,
+    trace_queue: Arc<WorkQueue<Transaction>>,
+    extract_queue: Arc<WorkQueue<TracedVictim>>,
+) {
+    loop {
+        let item = trace_queue.pop().await;
+        let block_oracle = match fresh_block_oracle(&context, item.target_block).await {
+            Some(block_oracle) => block_oracle,
+            None => continue,
+        };
+
+        let state_diffs = if let Some(sd) = utils::state_diff::get_from_txs(
+            &context.client,
+            &vec![item.job.clone()],
+            BlockNumber::Number(block_oracle.latest_block.number),
+        )
+        .await
+        {
+            sd
+        } else {
+            continue;
+        };
+
+        extract_queue
+            .push(WorkItem {
+                target_block: item.target_block,
+                priority: item.priority,
+                job: TracedVictim {
+                    victim: item.job,
+                    state_diffs,
+                },
+            })
+            .await;
+    }
+}
+
+// Find pools that a victim swaps on and create the evm simulation handler for them
+async fn extract_worker(
+    context: PipelineContext,
+    extract_queue: Arc<WorkQueue<TracedVictim>>,
+    optimize_queue: Arc<WorkQueue<OptimizeJob>>,
+) {
+    loop {
+        let item = extract_queue.pop().await;
+        let block_oracle = match fresh_block_oracle(&context, item.target_block).await {
+            Some(block_oracle) => block_oracle,
+            None => continue,
+        };
+        let TracedVictim {
+            victim,
+            state_diffs,
+        } = item.job;
+
+        // if tx has statediff on pool addr then record it in `sandwichable_pools`
+        // enhancement: increase opportunities by handling swaps in pools with stables
+        let sandwichable_pools: Vec<SandwichablePool> =
+            match utils::state_diff::extract_pools(&state_diffs, &context.all_pools) {
+                Some(sp) => sp
+                    .into_iter()
+                    .filter(|sp| sp.is_weth_input && !sp.pool.ignored)
+                    .filter(|sp| !is_drained(&context.pool_state, &sp.pool))
+                    .filter(|sp| !is_risky(&context.risk_registry, &sp.pool))
+                    .collect(),
+                None => continue,
+            };
+        if sandwichable_pools.is_empty() {
+            continue;
+        }
+
+        // state of pools that get sandwiched is prefetched from next block on
+        for sandwichable_pool in sandwichable_pools.iter() {
+            context.hot_state.mark_hot(sandwichable_pool.pool);
+        }
+        let state_cache = context
+            .state_cache
+            .for_block(block_oracle.next_block.number);
+
+        let fork_block = Some(BlockId::Number(BlockNumber::Number(
+            block_oracle.next_block.number,
+        )));
+
+        // create evm simulation handler by setting up `fork_factory`
+        let initial_db = match utils::state_diff::to_cache_db(
+            &state_diffs,
+            fork_block,
+            &context.client,
+            Some(&state_cache),
+        )
+        .await
+        {
+            Ok(db) => db,
+            Err(e) => {
+                log::error!("Failed to create cache db for {:?}: {:?}", victim.hash, e);
+                continue;
+            }
+        };
+        let fork_factory = ForkFactory::new_sandbox_factory(
+            context.client.clone(),
+            initial_db,
+            fork_block,
+            Some(state_cache),
+        );
+
+        // update sandwich weth balance
+        let fork_db = fork_factory.new_sandbox_fork();
+        let sandwich_balance =
+            match get_sandwich_weth_balance_evm(&block_oracle.next_block, fork_db) {
+                Ok(balance) => balance,
+                Err(e) => {
+                    log::error!("Failed to read sandwich balance: {:?}", e);
+                    continue;
+                }
+            };
+        context
+            .sandwich_state
+            .update_weth_balance(sandwich_balance)
+            .await;
+
+        for sandwichable_pool in sandwichable_pools {
+            optimize_queue
+                .push(WorkItem {
+                    target_block: item.target_block,
+                    priority: item.priority,
+                    job: OptimizeJob {
+                        victim: victim.clone(),
+                        sandwichable_pool,
+                        state_diffs: state_diffs.clone(),
+                        fork_factory: fork_factory.clone(),
+                        sandwich_balance,
+                    },
+                })
+                .await;
+        }
+    }
+}
+
+// Find the optimal sandwich for a victim on a single pool
+async fn optimize_worker(
+    context: PipelineContext,
+    optimize_queue: Arc<WorkQueue<OptimizeJob>>,
+    send_queue: Arc<WorkQueue<SendJob>>,
+) {
+    loop {
+        let item = optimize_queue.pop().await;
+        let block_oracle = match fresh_block_oracle(&context, item.target_block).await {
+            Some(block_oracle) => block_oracle,
+            None => continue,
+        };
+        let OptimizeJob {
+            victim,
+            sandwichable_pool,
+            state_diffs,
+            mut fork_factory,
+            sandwich_balance,
+        } = item.job;
+
+        let input_token = utils::constants::get_weth_address();
+        let victim_hash = victim.hash;
+        // variables used when searching for opportunity
+        let raw_ingredients = if let Ok(data) = RawIngredients::new(
+            &sandwichable_pool.pool,
+            vec![victim],
+            input_token,
+            state_diffs,
+        )
+        .await
+        {
+            data
+        } else {
+            log::error!("Failed to create raw ingredients for: {:?}", &victim_hash);
+            continue;
+        };
+
+        // find optimal input to sandwich tx
+        let optimal_sandwich = match make_sandwich::create_optimal_sandwich(
+            &mut vec![raw_ingredients],
+            sandwich_balance,
+            &block_oracle.next_block,
+            &mut fork_factory,
+            &context.sandwich_maker,
+        )
+        .await
+        {
+            Ok(optimal) => optimal,
+            Err(e) => {
+                log::info!(
+                    "{}",
+                    format!("[{:?}] sim failed due to {:?}", &victim_hash, e).yellow()
+                );
+                context
+                    .pool_ranker
+                    .record_simulation(sandwichable_pool.pool.address, false);
+                context
+                    .risk_registry
+                    .record_simulation_error(&sandwichable_pool.pool, &e);
+                continue;
+            }
+        };
+
+        let profitable = !optimal_sandwich.revenue.is_zero();
+        context
+            .pool_ranker
+            .record_simulation(sandwichable_pool.pool.address, profitable);
+        if !profitable {
+            continue;
+        }
+
+        // keep recipe around for mega sandwiches in future slots
+        context
+            .bundle_sender
+            .write()
+            .await
+            .add_recipe(
+                optimal_sandwich.clone(),
+                sandwichable_pool.pool,
+                block_oracle.next_block.number,
+            )
+            .await;
+
+        send_queue
+            .push(WorkItem {
+                target_block: item.target_block,
+                priority: optimal_sandwich.revenue,
+                job: SendJob {
+                    recipe: optimal_sandwich,
+                },
+            })
+            .await;
+    }
+}
+
+// Send sandwich bundles to relays
+async fn send_worker(context: PipelineContext, send_queue: Arc<WorkQueue<SendJob>>) {
+    loop {
+        let item = send_queue.pop().await;
+        let block_oracle = match fresh_block_oracle(&context, item.target_block).await {
+            Some(block_oracle) => block_oracle,
+            None => continue,
+        };
+        let recipe = item.job.recipe;
+
+        match bundle_sender::send_bundle(
+            &recipe,
+            block_oracle.next_block,
+            context.sandwich_maker.clone(),
+            &context.slot_scheduler,
+        )
+        .await
+        {
+            Ok(nonce) => context.bundle_sender.write().await.track_in_flight(
+                &recipe,
+                item.target_block,
+                nonce,
+            ),
+            Err(e) => {
+                log::info!(
+                    "{}",
+                    format!(
+                        "{:?} failed to send bundle, due to {:?}",
+                        recipe.print_meats(),
+                        e
+                    )
+                    .bright_magenta()
+                );
+            }
+        };
+    }
+}
+
+// Current block oracle if work for `target_block` is still relevant (counts dropped work otherwise)
+async fn fresh_block_oracle(context: &PipelineContext, target_block: U64) -> Option<BlockOracle> {
+    let block_oracle = context.latest_block_oracle.read().await.clone();
+    if block_oracle.next_block.number > target_block {
+        metrics::WORK_DROPPED_STALE.inc();
+        return None;
+    }
+    Some(block_oracle)
+}
+
+// Check if the tracked state says a pool has no liquidity to sandwich against
+// (pools without tracked state yet are always simulated)
+fn is_drained(pool_state: &PoolStateTracker, pool: &Pool) -> bool {
+    pool_state
+        .get(&pool.address)
+        .map_or(false, |tracked| tracked.state.is_drained())
+}
+
+// Check if the risk registry holds a verdict against a pool or its token (counts skipped pools)
+fn is_risky(risk_registry: &RiskRegistry, pool: &Pool) -> bool {
+    let is_risky = risk_registry.check_pool(pool).is_some();
+    if is_risky {
+        metrics::POOLS_SKIPPED_RISK.inc();
+    }
+    is_risky
+}
+
+// Estimate how much weth a victim swaps in, scaled by the chance that it lands
+//
+// Arguments:
+// * `victim`: pending tx
+// * `inclusion_probability`: chance victim lands in next block
+//
+// Returns:
+// U256: priority of victim (unknown sizes fall back to the tx's value)
+fn expected_victim_size(victim: &Transaction, inclusion_probability: f64) -> U256 {
+    let size = match swap_decoder::decode_swap(victim) {
+        Some(swap) if swap.path.first() == Some(&utils::constants::get_weth_address()) => swap
+            .amount_in
+            .or(swap.amount_in_max)
+            .unwrap_or(victim.value),
+        _ => victim.value,
+    };
+
+    let probability_bps = (inclusion_probability.clamp(0.0, 1.0) * 10_000.0) as u64;
+    size.saturating_mul(U256::from(probability_bps)) / 10_000
+}
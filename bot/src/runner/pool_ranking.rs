@@ -0,0 +1,585 @@
+use colored::Colorize;
+use dashmap::DashMap;
+use ethers::prelude::*;
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::fs::OpenOptions;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::metrics;
+use crate::prelude::{Pool, PoolStateTracker, PoolVariant};
+use crate::utils;
+
+/// File that the latest ranking is written to (also read back at startup)
+pub const POOL_RANKING_FILE: &str = "pool_ranking.json";
+
+// Max number of concurrent weth balance reads while ranking (only pools without tracked state
+// are read, kept low to not compete with the bot for the shared connections)
+const LIQUIDITY_FETCH_CONCURRENCY: usize = 4;
+// Weight of historical success rate (0..=1) next to log scaled liquidity and volume
+const SUCCESS_WEIGHT: f64 = 4.0;
+
+/// Ranking of a single pool
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PoolRankingEntry {
+    pub pool: Address,
+    pub pool_variant: PoolVariant,
+    /// weth held by the pool
+    pub weth_liquidity: U256,
+    /// weth swapped through the pool since previous ranking
+    pub weth_volume: U256,
+    /// number of victims simulated on the pool (all time)
+    pub simulated: u64,
+    /// number of simulations that found a profitable sandwich (all time)
+    pub profitable: u64,
+    /// simulations since the pool was last given another chance
+    #[serde(default)]
+    pub recent_simulated: u64,
+    /// profitable simulations since the pool was last given another chance
+    #[serde(default)]
+    pub recent_profitable: u64,
+    /// when the pool was first ignored for not being profitable (unix seconds)
+    #[serde(default)]
+    pub unprofitable_since: Option<u64>,
+    pub score: f64,
+    pub ignored: bool,
+    /// why the pool is ignored
+    pub reason: Option<String>,
+}
+
+/// Ranking of all weth pools, best pool first
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PoolRankingReport {
+    /// block that liquidity was read at
+    pub block: U64,
+    /// unix timestamp (seconds)
+    pub generated_at: u64,
+    pub entries: Vec<PoolRankingEntry>,
+}
+
+impl PoolRankingReport {
+    // Read report from `path`
+    pub fn load(path: &str) -> eyre::Result<Self> {
+        let reader = OpenOptions::new().read(true).open(path)?;
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    // Write report to `path` (replacing previous report)
+    pub fn save(&self, path: &str) -> eyre::Result<()> {
+        let writer = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    // Human readable table of the `limit` best ranked pools
+    pub fn table(&self, limit: usize) -> String {
+        let ignored = self.entries.iter().filter(|entry| entry.ignored).count();
+        let mut table = format!(
+            "pools ranked at block {} ({} ranked, {} ignored)\n",
+            self.block,
+            self.entries.len(),
+            ignored
+        );
+        let _ = writeln!(
+            table,
+            "{:>5}  {:<42}  {:<10}  {:>14}  {:>14}  {:>9}  {:>10}  {:>7}  status",
+            "rank", "pool", "variant", "liquidity", "volume", "simulated", "profitable", "score"
+        );
+
+        for (rank, entry) in self.entries.iter().take(limit).enumerate() {
+            let status = match &entry.reason {
+                Some(reason) if entry.ignored => format!("ignored ({})", reason),
+                _ => "active".to_string(),
+            };
+            let _ = writeln!(
+                table,
+                "{:>5}  {:<42}  {:<10}  {:>14.4}  {:>14.4}  {:>9}  {:>10}  {:>7.3}  {}",
+                rank + 1,
+                format!("{:?}", entry.pool),
+                format!("{:?}", entry.pool_variant),
+                to_ether(entry.weth_liquidity),
+                to_ether(entry.weth_volume),
+                entry.simulated,
+                entry.profitable,
+                entry.score,
+                status
+            );
+        }
+        table
+    }
+}
+
+/// Simulation history of a pool
+#[derive(Clone, Copy, Debug, Default)]
+struct SimulationStats {
+    simulated: u64,
+    profitable: u64,
+    recent_simulated: u64,
+    recent_profitable: u64,
+    unprofitable_since: Option<u64>,
+}
+
+impl SimulationStats {
+    // Has the pool been simulated often enough since its last chance without any profit
+    fn is_unprofitable(&self, min_simulations: u64) -> bool {
+        self.recent_simulated >= min_simulations && self.recent_profitable == 0
+    }
+}
+
+/// Periodically scores weth pools by liquidity, recent volume and historical sandwich success,
+/// pools that are not worth simulating are marked as ignored in the pool registry
+pub struct PoolRanker {
+    stats: DashMap<Address, SimulationStats>,
+    /// pools holding less weth are ignored
+    min_weth_liquidity: U256,
+    /// pools that were simulated this many times without a single profitable sandwich are ignored
+    min_simulations: u64,
+    /// pools ignored for not being profitable are simulated again after this long
+    unprofitable_ttl: Duration,
+    /// when the loaded report was generated (unix seconds)
+    last_ranked_at: u64,
+}
+
+impl PoolRanker {
+    // Create a new `PoolRanker`
+    //
+    // Arguments:
+    // * `min_weth_liquidity`: pools holding less weth are ignored
+    // * `min_simulations`: simulations without profit after which a pool is ignored
+    // * `unprofitable_ttl`: how long a pool stays ignored for not being profitable
+    //
+    // Returns: a new `PoolRanker` instance without history
+    pub fn new(min_weth_liquidity: U256, min_simulations: u64, unprofitable_ttl: Duration) -> Self {
+        Self {
+            stats: DashMap::new(),
+            min_weth_liquidity,
+            min_simulations,
+            unprofitable_ttl,
+            last_ranked_at: 0,
+        }
+    }
+
+    // Create a new `PoolRanker` using thresholds set in env and history of the last report
+    //
+    // Arguments:
+    // * `all_pools`: pool registry, ignored flags of the last report are applied to it
+    //
+    // Returns: a new `PoolRanker` instance
+    pub fn from_env(all_pools: &DashMap<Address, Pool>) -> Self {
+        let mut ranker = Self::new(
+            utils::dotenv::get_pool_min_weth_liquidity(),
+            utils::dotenv::get_pool_min_simulations(),
+            Duration::from_secs(utils::dotenv::get_pool_unprofitable_ttl_secs()),
+        );
+
+        let report = match PoolRankingReport::load(POOL_RANKING_FILE) {
+            Ok(report) => report,
+            Err(e) => {
+                log::info!("No previous pool ranking loaded: {:?}", e);
+                return ranker;
+            }
+        };
+
+        for entry in report.entries.iter() {
+            ranker.stats.insert(
+                entry.pool,
+                SimulationStats {
+                    simulated: entry.simulated,
+                    profitable: entry.profitable,
+                    recent_simulated: entry.recent_simulated,
+                    recent_profitable: entry.recent_profitable,
+                    unprofitable_since: entry.unprofitable_since,
+                },
+            );
+            if let Some(mut pool) = all_pools.get_mut(&entry.pool) {
+                pool.ignored = entry.ignored;
+            }
+        }
+        ranker.last_ranked_at = report.generated_at;
+        metrics::POOLS_IGNORED.set(all_pools.iter().filter(|pool| pool.ignored).count() as u64);
+
+        ranker
+    }
+
+    // Time until next ranking is due
+    pub fn next_ranking_in(&self, interval: Duration) -> Duration {
+        let elapsed = now().saturating_sub(self.last_ranked_at);
+        interval.saturating_sub(Duration::from_secs(elapsed))
+    }
+
+    // Record outcome of a simulation on `pool`
+    pub fn record_simulation(&self, pool: Address, profitable: bool) {
+        let mut stats = self.stats.entry(pool).or_default();
+        stats.simulated += 1;
+        stats.recent_simulated += 1;
+        if profitable {
+            stats.profitable += 1;
+            stats.recent_profitable += 1;
+        }
+    }
+
+    // Rank all weth pools and update their ignored flag
+    //
+    // Arguments:
+    // * `client`: client used to read weth balances of pools
+    // * `all_pools`: pool registry to update
+    // * `pool_state`: tracked pool reserves and weth volume
+    //
+    // Returns:
+    // Ok(PoolRankingReport): ranking of all weth pools, best pool first
+    // Err(M::Error): if block number could not be fetched
+    pub async fn rank<M: Middleware>(
+        &self,
+        client: &M,
+        all_pools: &DashMap<Address, Pool>,
+        pool_state: &PoolStateTracker,
+    ) -> Result<PoolRankingReport, M::Error> {
+        let block = client.get_block_number().await?;
+        let weth = utils::constants::get_weth_address();
+        let volumes = pool_state.take_weth_volumes();
+
+        // only pools with weth can be sandwiched
+        let pools: Vec<Pool> = all_pools
+            .iter()
+            .map(|pool| *pool.value())
+            .filter(|pool| pool.token_0 == weth || pool.token_1 == weth)
+            .collect();
+
+        // tracked state needs no rpc call, only remaining pools are read from the node
+        let (mut liquidities, untracked): (Vec<(Pool, Option<U256>)>, Vec<(Pool, Option<U256>)>) =
+            pools
+                .into_iter()
+                .map(|pool| (pool, tracked_weth_liquidity(pool_state, &pool)))
+                .partition(|(_, liquidity)| liquidity.is_some());
+        let fetched: Vec<(Pool, Option<U256>)> = stream::iter(untracked)
+            .map(|(pool, _)| async move {
+                let liquidity = fetch_weth_liquidity(client, &pool, block).await;
+                (pool, liquidity)
+            })
+            .buffer_unordered(LIQUIDITY_FETCH_CONCURRENCY)
+            .collect()
+            .await;
+        liquidities.extend(fetched);
+
+        let mut entries = vec![];
+        for (pool, liquidity) in liquidities {
+            // keep current flag if liquidity is unknown
+            let weth_liquidity = match liquidity {
+                Some(liquidity) => liquidity,
+                None => continue,
+            };
+            let weth_volume = volumes.get(&pool.address).copied().unwrap_or_default();
+            let stats = self.update_profitability(pool.address);
+
+            let reason = if weth_liquidity < self.min_weth_liquidity {
+                Some(format!(
+                    "weth liquidity below {}",
+                    to_ether(self.min_weth_liquidity)
+                ))
+            } else if stats.is_unprofitable(self.min_simulations) {
+                Some(format!(
+                    "no profit in {} simulations",
+                    stats.recent_simulated
+                ))
+            } else {
+                None
+            };
+
+            entries.push(PoolRankingEntry {
+                pool: pool.address,
+                pool_variant: pool.pool_variant,
+                weth_liquidity,
+                weth_volume,
+                simulated: stats.simulated,
+                profitable: stats.profitable,
+                recent_simulated: stats.recent_simulated,
+                recent_profitable: stats.recent_profitable,
+                unprofitable_since: stats.unprofitable_since,
+                score: score(weth_liquidity, weth_volume, stats),
+                ignored: reason.is_some(),
+                reason,
+            });
+        }
+        entries.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+        // pools are un-ignored again once they recover
+        for entry in entries.iter() {
+            if let Some(mut pool) = all_pools.get_mut(&entry.pool) {
+                pool.ignored = entry.ignored;
+            }
+        }
+        metrics::POOLS_IGNORED.set(all_pools.iter().filter(|pool| pool.ignored).count() as u64);
+
+        Ok(PoolRankingReport {
+            block,
+            generated_at: now(),
+            entries,
+        })
+    }
+
+    // Start or expire the unprofitable verdict of `pool`, ignored pools are never simulated so
+    // their counts are reset once the verdict is `unprofitable_ttl` old to give them another chance
+    //
+    // Returns:
+    // SimulationStats: stats of the pool after the update
+    fn update_profitability(&self, pool: Address) -> SimulationStats {
+        let mut stats = self.stats.entry(pool).or_default();
+        let now = now();
+
+        if let Some(since) = stats.unprofitable_since {
+            if now.saturating_sub(since) >= self.unprofitable_ttl.as_secs() {
+                stats.recent_simulated = 0;
+                stats.recent_profitable = 0;
+                stats.unprofitable_since = None;
+            }
+        }
+
+        match stats.is_unprofitable(self.min_simulations) {
+            true => {
+                stats.unprofitable_since.get_or_insert(now);
+            }
+            false => stats.unprofitable_since = None,
+        }
+
+        *stats
+    }
+}
+
+// Weth liquidity of `pool` from tracked state (v2 reserve, v3 virtual reserve of in range
+// liquidity), None if the pool has no tracked state yet
+fn tracked_weth_liquidity(pool_state: &PoolStateTracker, pool: &Pool) -> Option<U256> {
+    pool_state.get(&pool.address)?.state.weth_reserve(pool)
+}
+
+// Weth balance of `pool` at `block`
+async fn fetch_weth_liquidity<M: Middleware>(client: &M, pool: &Pool, block: U64) -> Option<U256> {
+    let slot = H256::from_uint(&utils::get_weth_balance_slot(pool.address));
+    match client
+        .get_storage_at(
+            utils::constants::get_weth_address(),
+            slot,
+            Some(BlockId::Number(BlockNumber::Number(block))),
+        )
+        .await
+    {
+        Ok(balance) => Some(balance.into_uint()),
+        Err(e) => {
+            log::error!(
+                "{}",
+                format!(
+                    "Failed to read weth liquidity of {:?}: {:?}",
+                    pool.address, e
+                )
+                .red()
+            );
+            None
+        }
+    }
+}
+
+// Higher is better: log scaled liquidity + volume (in weth) and smoothed success rate
+fn score(weth_liquidity: U256, weth_volume: U256, stats: SimulationStats) -> f64 {
+    let success_rate = (stats.profitable as f64 + 1.0) / (stats.simulated as f64 + 2.0);
+    to_ether(weth_liquidity).ln_1p() + to_ether(weth_volume).ln_1p() + SUCCESS_WEIGHT * success_rate
+}
+
+fn to_ether(amount: U256) -> f64 {
+    ethers::utils::format_ether(amount)
+        .parse()
+        .unwrap_or(f64::MAX)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::abi::{encode, Token};
+
+    use super::*;
+    use crate::abi::uniswap_v3_pool;
+
+    fn weth_pool() -> Pool {
+        Pool::new(
+            Address::from_low_u64_be(1),
+            Address::from_low_u64_be(2),
+            utils::constants::get_weth_address(),
+            U256::from(3000),
+            PoolVariant::UniswapV3,
+        )
+    }
+
+    fn registry() -> DashMap<Address, Pool> {
+        let all_pools = DashMap::new();
+        let no_weth = Pool::new(
+            Address::from_low_u64_be(3),
+            Address::from_low_u64_be(4),
+            Address::from_low_u64_be(5),
+            U256::from(3000),
+            PoolVariant::UniswapV3,
+        );
+        all_pools.insert(weth_pool().address, weth_pool());
+        all_pools.insert(no_weth.address, no_weth);
+        all_pools
+    }
+
+    // rank with `liquidity` as weth balance of the weth pool
+    async fn rank(
+        ranker: &PoolRanker,
+        all_pools: &DashMap<Address, Pool>,
+        liquidity: U256,
+    ) -> PoolRankingReport {
+        let (provider, mock) = Provider::mocked();
+        // mocked responses are returned last in first out
+        mock.push(H256::from_uint(&liquidity)).unwrap();
+        mock.push(U64::from(100)).unwrap();
+        ranker
+            .rank(&provider, all_pools, &PoolStateTracker::new(1))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn v3_liquidity_is_read_from_tracked_state() {
+        let ranker = PoolRanker::new(
+            ethers::utils::parse_ether("0.5").unwrap(),
+            2,
+            Duration::from_secs(3600),
+        );
+        let all_pools = registry();
+        let pool = weth_pool();
+
+        // price 1, so weth (token 1) virtual reserve equals liquidity
+        let pool_state = PoolStateTracker::new(1);
+        let swap = Log {
+            address: pool.address,
+            topics: vec![
+                uniswap_v3_pool::SwapFilter::signature(),
+                H256::zero(),
+                H256::zero(),
+            ],
+            data: encode(&[
+                Token::Int(U256::zero()),
+                Token::Int(U256::zero()),
+                Token::Uint(U256::one() << 96),
+                Token::Uint(ethers::utils::parse_ether(1).unwrap()),
+                Token::Int(U256::zero()),
+            ])
+            .into(),
+            block_number: Some(U64::from(99)),
+            block_hash: Some(H256::from_low_u64_be(99)),
+            ..Default::default()
+        };
+        assert!(pool_state.apply_log(&pool, &swap));
+
+        // only the block number is requested
+        let (provider, mock) = Provider::mocked();
+        mock.push(U64::from(100)).unwrap();
+        let report = ranker
+            .rank(&provider, &all_pools, &pool_state)
+            .await
+            .unwrap();
+        assert_eq!(
+            report.entries[0].weth_liquidity,
+            ethers::utils::parse_ether(1).unwrap()
+        );
+        assert!(!report.entries[0].ignored);
+    }
+
+    #[tokio::test]
+    async fn ignores_pools_without_liquidity_or_profit() {
+        let ranker = PoolRanker::new(
+            ethers::utils::parse_ether("0.5").unwrap(),
+            2,
+            Duration::from_secs(3600),
+        );
+        let all_pools = registry();
+        let pool = weth_pool().address;
+
+        let report = rank(&ranker, &all_pools, ethers::utils::parse_ether(1).unwrap()).await;
+        // pools without weth are not ranked
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.block, U64::from(100));
+        assert!(!report.entries[0].ignored);
+        assert!(!all_pools.get(&pool).unwrap().ignored);
+
+        let report = rank(
+            &ranker,
+            &all_pools,
+            ethers::utils::parse_ether("0.1").unwrap(),
+        )
+        .await;
+        assert!(report.entries[0].ignored);
+        assert!(all_pools.get(&pool).unwrap().ignored);
+
+        ranker.record_simulation(pool, false);
+        ranker.record_simulation(pool, false);
+        let report = rank(&ranker, &all_pools, ethers::utils::parse_ether(1).unwrap()).await;
+        assert_eq!(report.entries[0].simulated, 2);
+        assert_eq!(
+            report.entries[0].reason.as_deref(),
+            Some("no profit in 2 simulations")
+        );
+
+        // pool recovers after a profitable simulation
+        ranker.record_simulation(pool, true);
+        let report = rank(&ranker, &all_pools, ethers::utils::parse_ether(1).unwrap()).await;
+        assert!(!report.entries[0].ignored);
+        assert!(!all_pools.get(&pool).unwrap().ignored);
+    }
+
+    #[tokio::test]
+    async fn unprofitable_verdicts_expire() {
+        let all_pools = registry();
+        let pool = weth_pool().address;
+        let liquidity = ethers::utils::parse_ether(1).unwrap();
+
+        // verdict is kept within its ttl
+        let ranker = PoolRanker::new(U256::zero(), 2, Duration::from_secs(3600));
+        ranker.record_simulation(pool, false);
+        ranker.record_simulation(pool, false);
+        assert!(rank(&ranker, &all_pools, liquidity).await.entries[0].ignored);
+        let report = rank(&ranker, &all_pools, liquidity).await;
+        assert!(report.entries[0].ignored);
+        assert!(report.entries[0].unprofitable_since.is_some());
+
+        // expired verdict gives the pool another `min_simulations` tries
+        let ranker = PoolRanker::new(U256::zero(), 2, Duration::ZERO);
+        ranker.record_simulation(pool, false);
+        ranker.record_simulation(pool, false);
+        assert!(rank(&ranker, &all_pools, liquidity).await.entries[0].ignored);
+        let report = rank(&ranker, &all_pools, liquidity).await;
+        assert!(!report.entries[0].ignored);
+        assert!(!all_pools.get(&pool).unwrap().ignored);
+        assert_eq!(report.entries[0].simulated, 2);
+        assert_eq!(report.entries[0].recent_simulated, 0);
+
+        ranker.record_simulation(pool, false);
+        ranker.record_simulation(pool, false);
+        assert!(rank(&ranker, &all_pools, liquidity).await.entries[0].ignored);
+    }
+
+    #[test]
+    fn scores_liquid_and_successful_pools_higher() {
+        let liquid = U256::exp10(20);
+        let shallow = U256::exp10(18);
+        let stats = SimulationStats::default();
+        let successful = SimulationStats {
+            simulated: 10,
+            profitable: 8,
+            ..Default::default()
+        };
+
+        assert!(score(liquid, U256::zero(), stats) > score(shallow, U256::zero(), stats));
+        assert!(score(shallow, U256::zero(), successful) > score(shallow, U256::zero(), stats));
+    }
+}
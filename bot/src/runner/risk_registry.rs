@@ -0,0 +1,520 @@
+use colored::Colorize;
+use dashmap::DashMap;
+use ethers::prelude::*;
+use hashbrown::HashSet;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::metrics;
+use crate::prelude::Pool;
+use crate::types::SimulationError;
+use crate::utils;
+
+/// Why a token or pool is not sandwiched
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RiskReason {
+    /// simulation flagged by `SalmonellaInspectoooor`
+    SuspiciousOpcodes,
+    /// sandwich contract received less than the pool sent (fee on transfer)
+    TaxDetected,
+    /// one of our sandwich txs landed and reverted
+    RevertedOnChain,
+    /// flagged by operator
+    Manual,
+}
+
+impl fmt::Display for RiskReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RiskReason::SuspiciousOpcodes => write!(f, "suspicious opcodes"),
+            RiskReason::TaxDetected => write!(f, "tax detected"),
+            RiskReason::RevertedOnChain => write!(f, "reverted on chain"),
+            RiskReason::Manual => write!(f, "manual"),
+        }
+    }
+}
+
+/// Recorded verdict on a token or pool
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RiskVerdict {
+    pub reason: RiskReason,
+    pub detail: String,
+    /// unix timestamp (seconds)
+    pub recorded_at: u64,
+    /// unix timestamp (seconds) from which the verdict no longer applies, None never expires
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+}
+
+impl RiskVerdict {
+    // Check if the verdict no longer applies at unix timestamp `now`
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at
+            .map_or(false, |expires_at| now >= expires_at)
+    }
+}
+
+/// Tokens and pools flagged by operator through config, take precedence over recorded verdicts
+#[derive(Clone, Debug, Default)]
+pub struct RiskOverrides {
+    pub allowed_tokens: HashSet<Address>,
+    pub blocked_tokens: HashSet<Address>,
+    pub allowed_pools: HashSet<Address>,
+    pub blocked_pools: HashSet<Address>,
+}
+
+impl RiskOverrides {
+    // Overrides set in env
+    pub fn from_env() -> Self {
+        Self {
+            allowed_tokens: utils::dotenv::get_risk_allowed_tokens()
+                .into_iter()
+                .collect(),
+            blocked_tokens: utils::dotenv::get_risk_blocked_tokens()
+                .into_iter()
+                .collect(),
+            allowed_pools: utils::dotenv::get_risk_allowed_pools()
+                .into_iter()
+                .collect(),
+            blocked_pools: utils::dotenv::get_risk_blocked_pools()
+                .into_iter()
+                .collect(),
+        }
+    }
+}
+
+/// On disk format of the registry
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct RiskRegistryFile {
+    tokens: BTreeMap<Address, RiskVerdict>,
+    pools: BTreeMap<Address, RiskVerdict>,
+}
+
+/// Remembers tokens and pools that must not be sandwiched (honeypots, taxed tokens, pools
+/// whose sandwiches reverted) so they are skipped before simulating instead of for every victim
+pub struct RiskRegistry {
+    tokens: DashMap<Address, RiskVerdict>,
+    pools: DashMap<Address, RiskVerdict>,
+    overrides: RiskOverrides,
+    /// how long verdicts taken from simulations apply, they can be false positives
+    simulation_verdict_ttl: Duration,
+    /// file that verdicts are persisted to (None keeps them in memory only)
+    path: Option<String>,
+    /// version of the latest snapshot taken for `path`
+    snapshot_version: AtomicU64,
+    /// version of the snapshot on disk, also serializes writes to `path`
+    written_version: Arc<Mutex<u64>>,
+}
+
+impl RiskRegistry {
+    // Create a new `RiskRegistry`
+    //
+    // Arguments:
+    // * `path`: file to load verdicts from and persist them to (None keeps them in memory only)
+    // * `overrides`: operator overrides
+    // * `simulation_verdict_ttl`: how long verdicts recorded for simulation errors apply
+    //
+    // Returns: a new `RiskRegistry` holding all unexpired verdicts found at `path`
+    pub fn new(
+        path: Option<String>,
+        overrides: RiskOverrides,
+        simulation_verdict_ttl: Duration,
+    ) -> Self {
+        let file = match &path {
+            Some(path) if !Path::new(path).exists() => {
+                log::info!("No risk registry at {}, starting empty", path);
+                RiskRegistryFile::default()
+            }
+            Some(path) => load(path).unwrap_or_else(|e| {
+                log::error!(
+                    "{}",
+                    format!(
+                        "Failed to load risk registry from {}, starting empty: {:?}",
+                        path, e
+                    )
+                    .red()
+                );
+                RiskRegistryFile::default()
+            }),
+            None => RiskRegistryFile::default(),
+        };
+
+        let now = now();
+        let registry = Self {
+            tokens: file
+                .tokens
+                .into_iter()
+                .filter(|(_, verdict)| !verdict.is_expired(now))
+                .collect(),
+            pools: file
+                .pools
+                .into_iter()
+                .filter(|(_, verdict)| !verdict.is_expired(now))
+                .collect(),
+            overrides,
+            simulation_verdict_ttl,
+            path,
+            snapshot_version: AtomicU64::new(0),
+            written_version: Arc::new(Mutex::new(0)),
+        };
+        metrics::RISK_VERDICTS.set((registry.tokens.len() + registry.pools.len()) as u64);
+
+        registry
+    }
+
+    // Create a new `RiskRegistry` using file and overrides set in env
+    pub fn from_env() -> Self {
+        Self::new(
+            Some(utils::dotenv::get_risk_registry_file()),
+            RiskOverrides::from_env(),
+            Duration::from_secs(utils::dotenv::get_risk_simulation_verdict_ttl_secs()),
+        )
+    }
+
+    // Check if `pool` or its non weth token must not be sandwiched
+    //
+    // Returns:
+    // Some(RiskVerdict): verdict that blocks the pool
+    // None: if pool can be sandwiched
+    pub fn check_pool(&self, pool: &Pool) -> Option<RiskVerdict> {
+        if self.overrides.allowed_pools.contains(&pool.address) {
+            return None;
+        }
+        if self.overrides.blocked_pools.contains(&pool.address) {
+            return Some(manual_override());
+        }
+        if let Some(verdict) = active(&self.pools, pool.address) {
+            return Some(verdict);
+        }
+
+        [pool.token_0, pool.token_1]
+            .into_iter()
+            .filter(|token| *token != utils::constants::get_weth_address())
+            .find_map(|token| self.check_token(token))
+    }
+
+    // Check if `token` must not be sandwiched
+    pub fn check_token(&self, token: Address) -> Option<RiskVerdict> {
+        if self.overrides.allowed_tokens.contains(&token) {
+            return None;
+        }
+        if self.overrides.blocked_tokens.contains(&token) {
+            return Some(manual_override());
+        }
+        active(&self.tokens, token)
+    }
+
+    // Record that `token` must not be sandwiched (persisted right away)
+    pub fn flag_token(&self, token: Address, reason: RiskReason, detail: String) {
+        self.record_token(token, verdict(reason, detail, None));
+    }
+
+    fn record_token(&self, token: Address, verdict: RiskVerdict) {
+        let expiry = match verdict.expires_at {
+            Some(expires_at) => format!(", expires at {}", expires_at),
+            None => String::new(),
+        };
+        log::info!(
+            "{}",
+            format!(
+                "Flagged token {:?} ({}: {}{})",
+                token, verdict.reason, verdict.detail, expiry
+            )
+            .red()
+        );
+        self.tokens.insert(token, verdict);
+        self.persist();
+    }
+
+    // Record that `pool` must not be sandwiched (persisted right away)
+    pub fn flag_pool(&self, pool: Address, reason: RiskReason, detail: String) {
+        log::info!(
+            "{}",
+            format!("Flagged pool {:?} ({}: {})", pool, reason, detail).red()
+        );
+        self.pools.insert(pool, verdict(reason, detail, None));
+        self.persist();
+    }
+
+    // Record verdict for simulation errors that show a pool's token can't be sandwiched
+    //
+    // Findings are heuristics so these verdicts expire after `simulation_verdict_ttl`
+    //
+    // Arguments:
+    // * `pool`: pool that was simulated
+    // * `error`: error that the simulation failed with
+    //
+    // Returns:
+    // bool: true if the error led to a new verdict
+    pub fn record_simulation_error(&self, pool: &Pool, error: &SimulationError) -> bool {
+        let token = match pool.token_0 == utils::constants::get_weth_address() {
+            true => pool.token_1,
+            false => pool.token_0,
+        };
+
+        match error {
+            SimulationError::FrontrunNotSafu(report) | SimulationError::BackrunNotSafu(report) => {
+                let reason = match report.is_tax() {
+                    true => RiskReason::TaxDetected,
+                    false => RiskReason::SuspiciousOpcodes,
+                };
+                let verdict = verdict(
+                    reason,
+                    report.to_string(),
+                    Some(self.simulation_verdict_ttl),
+                );
+                self.record_token(token, verdict);
+                true
+            }
+            SimulationError::TaxDetected(taxed) => {
+                let verdict = verdict(
+                    RiskReason::TaxDetected,
+                    format!("pool {:?}", pool.address),
+                    Some(self.simulation_verdict_ttl),
+                );
+                self.record_token(*taxed, verdict);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Write all recorded verdicts to disk, on a blocking thread when called from the async runtime
+    fn persist(&self) {
+        metrics::RISK_VERDICTS.set((self.tokens.len() + self.pools.len()) as u64);
+
+        let path = match &self.path {
+            Some(path) => path.clone(),
+            None => return,
+        };
+
+        // versioned so that a snapshot never overwrites a newer one that was written first
+        let version = self.snapshot_version.fetch_add(1, Ordering::SeqCst) + 1;
+        let file = RiskRegistryFile {
+            tokens: self
+                .tokens
+                .iter()
+                .map(|e| (*e.key(), e.value().clone()))
+                .collect(),
+            pools: self
+                .pools
+                .iter()
+                .map(|e| (*e.key(), e.value().clone()))
+                .collect(),
+        };
+
+        let written_version = self.written_version.clone();
+        let write = move || {
+            let mut written_version = written_version.lock().unwrap();
+            if *written_version >= version {
+                return;
+            }
+            match write_atomically(&path, &file) {
+                Ok(()) => *written_version = version,
+                Err(e) => log::error!("Failed to write risk registry to {}: {:?}", path, e),
+            }
+        };
+
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn_blocking(write);
+            }
+            Err(_) => write(),
+        }
+    }
+}
+
+fn load(path: &str) -> eyre::Result<RiskRegistryFile> {
+    let reader = OpenOptions::new().read(true).open(path)?;
+    Ok(serde_json::from_reader(reader)?)
+}
+
+// Write `file` next to `path` and rename it over `path`, so a crash mid write never leaves a
+// truncated registry behind
+fn write_atomically(path: &str, file: &RiskRegistryFile) -> eyre::Result<()> {
+    let tmp_path = format!("{}.tmp", path);
+    let mut writer = File::create(&tmp_path)?;
+    writer.write_all(&serde_json::to_vec_pretty(file)?)?;
+    writer.sync_all()?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+// Verdict on `address` if there is one that hasn't expired
+fn active(verdicts: &DashMap<Address, RiskVerdict>, address: Address) -> Option<RiskVerdict> {
+    verdicts
+        .get(&address)
+        .filter(|verdict| !verdict.is_expired(now()))
+        .map(|verdict| verdict.clone())
+}
+
+// Current unix timestamp (seconds)
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default()
+}
+
+fn verdict(reason: RiskReason, detail: String, ttl: Option<Duration>) -> RiskVerdict {
+    let recorded_at = now();
+    RiskVerdict {
+        reason,
+        detail,
+        recorded_at,
+        expires_at: ttl.map(|ttl| recorded_at.saturating_add(ttl.as_secs())),
+    }
+}
+
+fn manual_override() -> RiskVerdict {
+    RiskVerdict {
+        reason: RiskReason::Manual,
+        detail: "blocked in config".to_string(),
+        recorded_at: 0,
+        expires_at: None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pool() -> Pool {
+        Pool::new(
+            Address::from_low_u64_be(1),
+            Address::from_low_u64_be(2),
+            utils::constants::get_weth_address(),
+            U256::from(3000),
+            crate::prelude::PoolVariant::UniswapV2,
+        )
+    }
+
+    fn ttl() -> Duration {
+        Duration::from_secs(3600)
+    }
+
+    fn registry_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("{}-{}.json", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn verdicts_are_persisted() {
+        let path = registry_path("risk-registry");
+        let token = Address::from_low_u64_be(2);
+
+        let registry = RiskRegistry::new(Some(path.clone()), RiskOverrides::default(), ttl());
+        assert!(registry.check_pool(&pool()).is_none());
+        registry.flag_token(token, RiskReason::TaxDetected, "test".to_string());
+        assert_eq!(
+            registry.check_pool(&pool()).unwrap().reason,
+            RiskReason::TaxDetected
+        );
+
+        let reloaded = RiskRegistry::new(Some(path.clone()), RiskOverrides::default(), ttl());
+        assert_eq!(
+            reloaded.check_token(token).unwrap().reason,
+            RiskReason::TaxDetected
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn persist_replaces_file_atomically() {
+        let path = registry_path("risk-registry-atomic");
+        let token = Address::from_low_u64_be(2);
+
+        // unreadable registry is not loaded but gets replaced on the next write
+        std::fs::write(&path, "{\"tokens\": ").unwrap();
+        let registry = RiskRegistry::new(Some(path.clone()), RiskOverrides::default(), ttl());
+        assert!(registry.check_token(token).is_none());
+
+        registry.flag_token(token, RiskReason::TaxDetected, "test".to_string());
+        assert!(!Path::new(&format!("{}.tmp", path)).exists());
+        assert!(load(&path).unwrap().tokens.contains_key(&token));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn persists_off_the_runtime() {
+        let path = registry_path("risk-registry-blocking");
+        let registry = RiskRegistry::new(Some(path.clone()), RiskOverrides::default(), ttl());
+        for i in 1..=5 {
+            registry.flag_pool(
+                Address::from_low_u64_be(i),
+                RiskReason::RevertedOnChain,
+                "test".to_string(),
+            );
+        }
+
+        // latest snapshot holds every verdict, older snapshots never overwrite it
+        for _ in 0..100 {
+            if *registry.written_version.lock().unwrap() == 5 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(*registry.written_version.lock().unwrap(), 5);
+        assert_eq!(load(&path).unwrap().pools.len(), 5);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn overrides_take_precedence() {
+        let token = Address::from_low_u64_be(2);
+        let mut overrides = RiskOverrides::default();
+        overrides.allowed_tokens.insert(token);
+        overrides.blocked_pools.insert(Address::from_low_u64_be(7));
+
+        let registry = RiskRegistry::new(None, overrides, ttl());
+        registry.flag_token(token, RiskReason::SuspiciousOpcodes, "test".to_string());
+        assert!(registry.check_pool(&pool()).is_none());
+
+        registry.flag_pool(
+            pool().address,
+            RiskReason::RevertedOnChain,
+            "test".to_string(),
+        );
+        assert_eq!(
+            registry.check_pool(&pool()).unwrap().reason,
+            RiskReason::RevertedOnChain
+        );
+
+        let mut blocked = pool();
+        blocked.address = Address::from_low_u64_be(7);
+        assert_eq!(
+            registry.check_pool(&blocked).unwrap().reason,
+            RiskReason::Manual
+        );
+    }
+
+    #[test]
+    fn simulation_verdicts_expire() {
+        let taxed = SimulationError::TaxDetected(Address::from_low_u64_be(2));
+
+        let registry = RiskRegistry::new(None, RiskOverrides::default(), ttl());
+        assert!(registry.record_simulation_error(&pool(), &taxed));
+        let verdict = registry.check_pool(&pool()).unwrap();
+        assert_eq!(verdict.reason, RiskReason::TaxDetected);
+        assert_eq!(verdict.expires_at, Some(verdict.recorded_at + 3600));
+
+        let registry = RiskRegistry::new(None, RiskOverrides::default(), Duration::ZERO);
+        assert!(registry.record_simulation_error(&pool(), &taxed));
+        assert!(registry.check_pool(&pool()).is_none());
+        // verdicts flagged directly never expire
+        registry.flag_pool(
+            pool().address,
+            RiskReason::RevertedOnChain,
+            "test".to_string(),
+        );
+        assert!(registry.check_pool(&pool()).unwrap().expires_at.is_none());
+    }
+}
@@ -0,0 +1,351 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{relay, utils};
+
+// Beacon chain genesis (mainnet)
+pub const GENESIS_TIMESTAMP: u64 = 1606824023;
+pub const SECONDS_PER_SLOT: u64 = 12;
+const MS_PER_SLOT: u64 = SECONDS_PER_SLOT * 1000;
+
+/// Source of wall clock time (swappable for tests)
+pub trait Clock: Send + Sync {
+    // Milliseconds since unix epoch
+    fn now_ms(&self) -> u64;
+}
+
+/// Clock backed by system time
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_ms(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default()
+    }
+}
+
+/// Last moment a relay accepts bundles for a slot
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RelayCutoff {
+    pub relay_name: String,
+    /// milliseconds relative to start of target slot (negative means before slot start)
+    pub cutoff_ms: i64,
+}
+
+/// When to compute and submit the mega sandwich for a given slot
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SlotPlan {
+    pub slot: u64,
+    /// timestamp (seconds) of the block proposed in this slot
+    pub slot_timestamp: u64,
+    /// unix ms at which computation should start
+    pub fire_at_ms: u64,
+    /// unix ms after which no relay accepts bundles for this slot
+    pub deadline_ms: u64,
+    /// (relay name, unix ms cutoff) for each relay
+    pub relay_deadlines: Vec<(String, u64)>,
+}
+
+impl SlotPlan {
+    // Relays that still accept bundles at `now_ms`
+    pub fn open_relays(&self, now_ms: u64) -> Vec<&str> {
+        self.relay_deadlines
+            .iter()
+            .filter(|(_, deadline)| *deadline > now_ms)
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+}
+
+pub struct SlotScheduler {
+    clock: Arc<dyn Clock>,
+    relay_cutoffs: Vec<RelayCutoff>,
+    fire_offset_ms: u64,
+}
+
+impl SlotScheduler {
+    // Create a new `SlotScheduler`
+    //
+    // Arguments:
+    // * `clock`: source of current time
+    // * `relay_cutoffs`: cutoff of each relay relative to the target slot's start
+    // * `fire_offset_ms`: how long before the earliest relay cutoff computation starts
+    //
+    // Returns: a new `SlotScheduler` instance
+    pub fn new(clock: Arc<dyn Clock>, relay_cutoffs: Vec<RelayCutoff>, fire_offset_ms: u64) -> Self {
+        Self {
+            clock,
+            relay_cutoffs,
+            fire_offset_ms,
+        }
+    }
+
+    // Create a `SlotScheduler` using system time and cutoffs/offset from env
+    pub fn from_env() -> Self {
+        let configured = utils::dotenv::get_relay_cutoffs_ms();
+
+        let relay_cutoffs = relay::get_relay_names()
+            .into_iter()
+            .map(|name| {
+                let cutoff_ms = configured
+                    .iter()
+                    .find(|(configured_name, _)| configured_name == name)
+                    .map(|(_, cutoff)| *cutoff)
+                    .unwrap_or(0);
+                RelayCutoff {
+                    relay_name: name.to_string(),
+                    cutoff_ms,
+                }
+            })
+            .collect();
+
+        Self::new(
+            Arc::new(SystemClock),
+            relay_cutoffs,
+            utils::dotenv::get_mega_sandwich_fire_offset_ms(),
+        )
+    }
+
+    // Current time in unix ms
+    pub fn now_ms(&self) -> u64 {
+        self.clock.now_ms()
+    }
+
+    // Build the schedule for `slot`
+    pub fn plan_slot(&self, slot: u64) -> SlotPlan {
+        let slot_start = slot_start_ms(slot) as i64;
+
+        let relay_deadlines: Vec<(String, u64)> = self
+            .relay_cutoffs
+            .iter()
+            .map(|relay| {
+                let deadline = (slot_start + relay.cutoff_ms).max(0) as u64;
+                (relay.relay_name.clone(), deadline)
+            })
+            .collect();
+
+        let earliest_cutoff = relay_deadlines
+            .iter()
+            .map(|(_, deadline)| *deadline)
+            .min()
+            .unwrap_or(slot_start as u64);
+        let deadline_ms = relay_deadlines
+            .iter()
+            .map(|(_, deadline)| *deadline)
+            .max()
+            .unwrap_or(slot_start as u64);
+
+        SlotPlan {
+            slot,
+            slot_timestamp: slot_start as u64 / 1000,
+            fire_at_ms: earliest_cutoff.saturating_sub(self.fire_offset_ms),
+            deadline_ms,
+            relay_deadlines,
+        }
+    }
+
+    // Relays that still accept bundles for the block proposed at `block_timestamp` (seconds)
+    pub fn open_relays_for(&self, block_timestamp: u64) -> Vec<String> {
+        self.plan_slot(slot_at_ms(block_timestamp * 1000))
+            .open_relays(self.now_ms())
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    // Find the first upcoming slot whose fire time has not passed yet
+    pub fn next_plan(&self) -> SlotPlan {
+        let now = self.now_ms();
+        let mut slot = slot_at_ms(now);
+
+        loop {
+            let plan = self.plan_slot(slot);
+            if plan.fire_at_ms > now {
+                return plan;
+            }
+            slot += 1;
+        }
+    }
+
+    // Sleep until `timestamp_ms` (returns immediately if already passed)
+    pub async fn sleep_until(&self, timestamp_ms: u64) {
+        let now = self.now_ms();
+        if timestamp_ms > now {
+            tokio::time::sleep(Duration::from_millis(timestamp_ms - now)).await;
+        }
+    }
+
+    // Run `fut` and cancel it if it does not finish before `deadline_ms`
+    //
+    // Arguments:
+    // * `deadline_ms`: unix ms after which the result is useless
+    // * `fut`: computation to run
+    //
+    // Returns:
+    // Some(F::Output): if computation finished in time
+    // None: if computation got cancelled
+    pub async fn run_before_deadline<F: Future>(
+        &self,
+        deadline_ms: u64,
+        fut: F,
+    ) -> Option<F::Output> {
+        let now = self.now_ms();
+        if now >= deadline_ms {
+            return None;
+        }
+
+        tokio::time::timeout(Duration::from_millis(deadline_ms - now), fut)
+            .await
+            .ok()
+    }
+}
+
+// Slot that `timestamp_ms` falls in
+pub fn slot_at_ms(timestamp_ms: u64) -> u64 {
+    timestamp_ms.saturating_sub(GENESIS_TIMESTAMP * 1000) / MS_PER_SLOT
+}
+
+// Unix ms at which `slot` starts
+pub fn slot_start_ms(slot: u64) -> u64 {
+    GENESIS_TIMESTAMP * 1000 + slot * MS_PER_SLOT
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use tokio::runtime::Runtime;
+
+    struct MockClock {
+        now_ms: AtomicU64,
+    }
+
+    impl MockClock {
+        fn new(now_ms: u64) -> Arc<Self> {
+            Arc::new(Self {
+                now_ms: AtomicU64::new(now_ms),
+            })
+        }
+
+        fn set(&self, now_ms: u64) {
+            self.now_ms.store(now_ms, Ordering::SeqCst);
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now_ms(&self) -> u64 {
+            self.now_ms.load(Ordering::SeqCst)
+        }
+    }
+
+    fn cutoffs() -> Vec<RelayCutoff> {
+        vec![
+            RelayCutoff {
+                relay_name: "flashbots".to_string(),
+                cutoff_ms: -500,
+            },
+            RelayCutoff {
+                relay_name: "beaverbuild".to_string(),
+                cutoff_ms: 1000,
+            },
+        ]
+    }
+
+    #[test]
+    fn slot_boundaries_from_genesis() {
+        let genesis_ms = GENESIS_TIMESTAMP * 1000;
+
+        assert_eq!(slot_at_ms(genesis_ms), 0);
+        assert_eq!(slot_at_ms(genesis_ms + MS_PER_SLOT - 1), 0);
+        assert_eq!(slot_at_ms(genesis_ms + MS_PER_SLOT), 1);
+        assert_eq!(slot_at_ms(0), 0);
+        assert_eq!(slot_start_ms(10), genesis_ms + 10 * MS_PER_SLOT);
+    }
+
+    #[test]
+    fn plan_fires_before_earliest_cutoff() {
+        let clock = MockClock::new(slot_start_ms(100));
+        let scheduler = SlotScheduler::new(clock, cutoffs(), 1500);
+
+        let plan = scheduler.plan_slot(101);
+        let slot_start = slot_start_ms(101);
+
+        assert_eq!(plan.slot_timestamp, slot_start / 1000);
+        assert_eq!(plan.fire_at_ms, slot_start - 500 - 1500);
+        assert_eq!(plan.deadline_ms, slot_start + 1000);
+        assert_eq!(plan.open_relays(slot_start), vec!["beaverbuild"]);
+        assert_eq!(
+            plan.open_relays(slot_start - 600),
+            vec!["flashbots", "beaverbuild"]
+        );
+    }
+
+    #[test]
+    fn open_relays_for_block_of_slot() {
+        let clock = MockClock::new(slot_start_ms(101) - 600);
+        let scheduler = SlotScheduler::new(clock.clone(), cutoffs(), 1500);
+        let block_timestamp = slot_start_ms(101) / 1000;
+
+        assert_eq!(
+            scheduler.open_relays_for(block_timestamp),
+            vec!["flashbots", "beaverbuild"]
+        );
+        clock.set(slot_start_ms(101));
+        assert_eq!(
+            scheduler.open_relays_for(block_timestamp),
+            vec!["beaverbuild"]
+        );
+        clock.set(slot_start_ms(101) + 1_000);
+        assert!(scheduler.open_relays_for(block_timestamp).is_empty());
+    }
+
+    #[test]
+    fn next_plan_skips_slots_whose_fire_time_passed() {
+        let clock = MockClock::new(slot_start_ms(100) + 1_000);
+        let scheduler = SlotScheduler::new(clock.clone(), cutoffs(), 1500);
+
+        // next slot's fire time is still ahead
+        assert_eq!(scheduler.next_plan().slot, 101);
+
+        // late in the slot, fire time for slot 101 already passed
+        clock.set(slot_start_ms(101) - 1_000);
+        assert_eq!(scheduler.next_plan().slot, 102);
+
+        // a missed slot does not change the schedule, it is purely time based
+        clock.set(slot_start_ms(104) + 5);
+        assert_eq!(scheduler.next_plan().slot, 105);
+    }
+
+    #[test]
+    fn cancel_computation_after_deadline() {
+        let rt = Runtime::new().unwrap();
+        let clock = MockClock::new(slot_start_ms(100));
+        let scheduler = SlotScheduler::new(clock.clone(), cutoffs(), 1500);
+
+        rt.block_on(async {
+            // deadline already passed, computation never starts
+            let result = scheduler
+                .run_before_deadline(slot_start_ms(100) - 1, async { 1 })
+                .await;
+            assert_eq!(result, None);
+
+            // computation that can't finish before the deadline is cancelled
+            let result = scheduler
+                .run_before_deadline(slot_start_ms(100) + 50, async {
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                    1
+                })
+                .await;
+            assert_eq!(result, None);
+
+            // computation that finishes in time returns its output
+            let result = scheduler
+                .run_before_deadline(slot_start_ms(100) + 1_000, async { 1 })
+                .await;
+            assert_eq!(result, Some(1));
+        });
+    }
+}
@@ -1,3 +1,4 @@
+use dashmap::DashMap;
 use hashbrown::HashSet;
 use std::sync::Arc;
 
@@ -5,12 +6,17 @@ use ethers::prelude::*;
 use eyre::Result;
 use tokio::sync::RwLock;
 
-use crate::{prelude::Erc20, utils};
+use crate::metrics;
+use crate::{
+    prelude::{Erc20, Pool},
+    utils,
+};
 
 #[derive(Clone, Debug)]
 /// Holds the state of the bot
 pub struct BotState {
-    pub token_dust: Arc<RwLock<Vec<Address>>>,
+    /// tokens the sandwich contract holds a non zero balance of
+    pub token_dust: Arc<RwLock<HashSet<Address>>>,
     pub weth_balance: Arc<RwLock<U256>>,
 }
 
@@ -19,14 +25,18 @@ impl BotState {
     //
     // Arguments:
     // * `sandwich_inception_block`: block number sandwich was deployed
-    // * `client`: websocket provider to use for fetching data
+    // * `client`: provider to use for fetching data
     //
     // Returns:
     // Ok(BotState) if successful
     // Err(eyre::Error) if failed to create instance
-    pub async fn new(sandwich_inception_block: U64, client: &Arc<Provider<Ws>>) -> Result<Self> {
+    pub async fn new<M: Middleware + 'static>(
+        sandwich_inception_block: U64,
+        client: &Arc<M>,
+    ) -> Result<Self> {
         let token_dust = Self::find_all_dust(sandwich_inception_block, client).await?;
-        let token_dust = Arc::new(RwLock::new(token_dust));
+        metrics::DUST_TOKENS.set(token_dust.len() as u64);
+        let token_dust = Arc::new(RwLock::new(token_dust.into_iter().collect()));
 
         let weth_contract =
             utils::contracts::get_erc20_contract(&utils::constants::get_weth_address(), client);
@@ -42,27 +52,127 @@ impl BotState {
         })
     }
 
-    // // Check if contract has dust for specific token
-    // //
-    // // Arguments:
-    // // * `&self`: refernce to `BotState` instance
-    // // * `token`: token to check dust for
-    // //
-    // // Returns:
-    // // bool: true if contract has dust for token, false otherwise
-    // pub async fn has_dust(&self, token: &Address) -> bool {
-    //     self.token_dust.read().await.contains(token)
-    // }
-
-    // // Add dust to contract
-    // //
-    // // Arguments:
-    // // * `&self`: reference to `BotState` instance
-    // // * `token`: token to add dust for
-    // pub async fn add_dust(&self, token: Address) {
-    //     let mut dust = self.token_dust.write().await;
-    //     dust.push(token);
-    // }
+    // Check if contract has dust for specific token
+    //
+    // Arguments:
+    // * `&self`: refernce to `BotState` instance
+    // * `token`: token to check dust for
+    //
+    // Returns:
+    // bool: true if contract has dust for token, false otherwise
+    pub async fn has_dust(&self, token: &Address) -> bool {
+        self.token_dust.read().await.contains(token)
+    }
+
+    // Add dust to contract
+    //
+    // Arguments:
+    // * `&self`: reference to `BotState` instance
+    // * `token`: token to add dust for
+    //
+    // Returns:
+    // bool: true if contract had no dust for token before, false otherwise
+    pub async fn add_dust(&self, token: Address) -> bool {
+        let mut dust = self.token_dust.write().await;
+        let added = dust.insert(token);
+        metrics::DUST_TOKENS.set(dust.len() as u64);
+        added
+    }
+
+    // Check if contract has dust for the token `pool` trades against weth
+    //
+    // Arguments:
+    // * `&self`: reference to `BotState` instance
+    // * `pool`: pool to check dust for
+    //
+    // Returns:
+    // bool: true if contract has dust for the pool's non weth token, false otherwise
+    pub async fn pool_has_dust(&self, pool: &Pool) -> bool {
+        self.has_dust(&dust_token(pool)).await
+    }
+
+    // Set `has_dust` of every pool from the tokens the contract has dust for
+    //
+    // Arguments:
+    // * `&self`: reference to `BotState` instance
+    // * `all_pools`: pools to update
+    //
+    // Returns:
+    // usize: number of pools flagged as having dust
+    pub async fn flag_pools_with_dust(&self, all_pools: &DashMap<Address, Pool>) -> usize {
+        let dust = self.token_dust.read().await;
+        let mut flagged = 0;
+        for mut pool in all_pools.iter_mut() {
+            pool.has_dust = dust.contains(&dust_token(&pool));
+            flagged += pool.has_dust as usize;
+        }
+        flagged
+    }
+
+    // Remove dust from contract
+    //
+    // Arguments:
+    // * `&self`: reference to `BotState` instance
+    // * `token`: token to remove dust for
+    //
+    // Returns:
+    // bool: true if contract had dust for token before, false otherwise
+    pub async fn remove_dust(&self, token: &Address) -> bool {
+        let mut dust = self.token_dust.write().await;
+        let removed = dust.remove(token);
+        metrics::DUST_TOKENS.set(dust.len() as u64);
+        removed
+    }
+
+    // Update dust from the `Transfer` logs of our own txs after inclusion. Tokens the contract
+    // received are added (the backrun never sells the full amount the frontrun bought), tokens
+    // it sent are removed if its balance is zero afterwards (e.g. sold by `withdraw-dust`)
+    //
+    // Arguments:
+    // * `&self`: reference to `BotState` instance
+    // * `logs`: logs of txs included in `block`
+    // * `block`: block the txs were included in
+    // * `all_pools`: pools whose `has_dust` is updated when dust changed
+    // * `client`: provider to read token balances from
+    //
+    // Returns:
+    // (Vec<Address>, Vec<Address>): tokens the contract had no dust for before, and tokens
+    // whose dust is gone
+    pub async fn update_dust_from_logs<M: Middleware + 'static>(
+        &self,
+        logs: &[Log],
+        block: U64,
+        all_pools: &DashMap<Address, Pool>,
+        client: &Arc<M>,
+    ) -> (Vec<Address>, Vec<Address>) {
+        let sandwich = utils::dotenv::get_sandwich_contract_address();
+        let mut added = vec![];
+        for token in tokens_received(logs, sandwich) {
+            if self.add_dust(token).await {
+                added.push(token);
+            }
+        }
+
+        let mut removed = vec![];
+        for token in tokens_sent(logs, sandwich) {
+            let erc20 = Erc20::new(token, client.clone());
+            match erc20.balance_of(sandwich).block(block).call().await {
+                Ok(balance) if balance.is_zero() => {
+                    if self.remove_dust(&token).await {
+                        removed.push(token);
+                    }
+                }
+                Ok(_) => {}
+                // keep the dust, overpaying a little is better than leaving nothing behind
+                Err(e) => log::error!("Failed to get dust balance of {:?}: {:?}", token, e),
+            }
+        }
+
+        if !added.is_empty() || !removed.is_empty() {
+            self.flag_pools_with_dust(all_pools).await;
+        }
+        (added, removed)
+    }
 
     // Update the WETH balance of the contract
     //
@@ -79,12 +189,15 @@ impl BotState {
     //
     // Arguments:
     // * `start_block`: block to start searching for dust
-    // * `client`: websocket provider to use for fetching data
+    // * `client`: provider to use for fetching data
     //
     // Returns:
     // `Ok(Vec<Address>)`: address of token dust collected by bot
     // `Err(eyre::Error)`: failed to find dust
-    async fn find_all_dust(start_block: U64, client: &Arc<Provider<Ws>>) -> Result<Vec<Address>> {
+    async fn find_all_dust<M: Middleware + 'static>(
+        start_block: U64,
+        client: &Arc<M>,
+    ) -> Result<Vec<Address>> {
         // Define the step for searching a range of block logs for transfer events
         let step = 10000;
 
@@ -156,6 +269,44 @@ impl BotState {
     }
 }
 
+// Token of a weth pool that the contract collects dust of
+fn dust_token(pool: &Pool) -> Address {
+    if pool.token_0 == utils::constants::get_weth_address() {
+        pool.token_1
+    } else {
+        pool.token_0
+    }
+}
+
+// Tokens (other than weth) that `recipient` received according to erc20 `Transfer` logs
+fn tokens_received(logs: &[Log], recipient: Address) -> Vec<Address> {
+    tokens_transferred(logs, 2, recipient)
+}
+
+// Tokens (other than weth) that `sender` sent according to erc20 `Transfer` logs
+fn tokens_sent(logs: &[Log], sender: Address) -> Vec<Address> {
+    tokens_transferred(logs, 1, sender)
+}
+
+// Tokens (other than weth) of `Transfer` logs whose `topic` (1 = from, 2 = to) is `account`
+fn tokens_transferred(logs: &[Log], topic: usize, account: Address) -> Vec<Address> {
+    let transfer = utils::constants::get_erc20_transfer_event_signature();
+    let weth = utils::constants::get_weth_address();
+    let mut tokens: Vec<Address> = logs
+        .iter()
+        .filter(|log| {
+            log.topics.len() == 3
+                && log.topics[0] == transfer
+                && Address::from(log.topics[topic]) == account
+                && log.address != weth
+        })
+        .map(|log| log.address)
+        .collect();
+    tokens.sort();
+    tokens.dedup();
+    tokens
+}
+
 //#[cfg(test)]
 //mod test_get_dust {
 //    use dotenv::dotenv;
@@ -193,3 +344,58 @@ impl BotState {
 //        }
 //    }
 //}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn transfer_log(token: Address, from: Address, to: Address) -> Log {
+        Log {
+            address: token,
+            topics: vec![
+                utils::constants::get_erc20_transfer_event_signature(),
+                H256::from(from),
+                H256::from(to),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn finds_tokens_transferred_by_contract() {
+        let sandwich = Address::repeat_byte(0x11);
+        let pair = Address::repeat_byte(0x22);
+        let token = Address::repeat_byte(0x33);
+        let weth = utils::constants::get_weth_address();
+
+        let logs = vec![
+            // frontrun: weth in, token out
+            transfer_log(weth, sandwich, pair),
+            transfer_log(token, pair, sandwich),
+            // backrun: token in, weth out
+            transfer_log(token, sandwich, pair),
+            transfer_log(weth, pair, sandwich),
+            // unrelated transfer in the same tx
+            transfer_log(Address::repeat_byte(0x44), pair, Address::repeat_byte(0x55)),
+        ];
+
+        assert_eq!(tokens_received(&logs, sandwich), vec![token]);
+        assert!(tokens_received(&logs[..1], sandwich).is_empty());
+        assert_eq!(tokens_sent(&logs, sandwich), vec![token]);
+        assert!(tokens_sent(&logs[1..2], sandwich).is_empty());
+    }
+
+    #[test]
+    fn dust_token_is_the_non_weth_side() {
+        let weth = utils::constants::get_weth_address();
+        let token = Address::repeat_byte(0x33);
+        let pool = Pool::new(
+            Address::repeat_byte(0x22),
+            weth,
+            token,
+            U256::from(3000),
+            crate::prelude::PoolVariant::UniswapV2,
+        );
+        assert_eq!(dust_token(&pool), token);
+    }
+}
@@ -0,0 +1,355 @@
+use std::fmt;
+use std::sync::Arc;
+
+use colored::Colorize;
+use ethers::prelude::*;
+use ethers::utils::format_ether;
+use eyre::eyre;
+
+use crate::utils;
+use crate::utils::transport::RpcClient;
+use crate::utils::tx_builder::sandwich::get_weth_encode_divisor;
+use crate::utils::tx_builder::SandwichMaker;
+
+use super::admin::{self, AdminCommand};
+use super::ledger::{self, LedgerEvent};
+
+/// Balance the treasury keeps an account within
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BalanceBounds {
+    /// below this the account is topped up
+    pub min: U256,
+    /// balance the account is moved back to
+    pub target: U256,
+    /// above this the excess is moved away
+    pub max: U256,
+}
+
+impl BalanceBounds {
+    // Create new bounds, panics if they are not ordered `min <= target <= max`
+    pub fn new(min: U256, target: U256, max: U256) -> Self {
+        assert!(
+            min <= target && target <= max,
+            "treasury bounds have to be min <= target <= max (got {} / {} / {})",
+            format_ether(min),
+            format_ether(target),
+            format_ether(max)
+        );
+        Self { min, target, max }
+    }
+}
+
+/// Balances the treasury keeps the sandwich contract (weth) and the searcher (eth for gas) at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreasuryConfig {
+    pub contract: BalanceBounds,
+    pub searcher: BalanceBounds,
+}
+
+/// Transfer between sandwich contract and searcher
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreasuryMove {
+    /// unwrap weth held by contract and send it to searcher
+    Withdraw(U256),
+    /// send eth from searcher to contract which wraps it into weth
+    Deposit(U256),
+}
+
+impl TreasuryMove {
+    // Admin command that performs the transfer
+    fn command(&self) -> AdminCommand {
+        match *self {
+            TreasuryMove::Withdraw(amount) => AdminCommand::WithdrawWeth {
+                amount: Some(amount),
+            },
+            TreasuryMove::Deposit(amount) => AdminCommand::DepositEth { amount },
+        }
+    }
+}
+
+impl fmt::Display for TreasuryMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TreasuryMove::Withdraw(amount) => write!(f, "withdraw {} weth", format_ether(*amount)),
+            TreasuryMove::Deposit(amount) => write!(f, "deposit {} eth", format_ether(*amount)),
+        }
+    }
+}
+
+impl TreasuryConfig {
+    // Create a `TreasuryConfig` with bounds from env
+    pub fn from_env() -> Self {
+        Self {
+            contract: BalanceBounds::new(
+                utils::dotenv::get_treasury_contract_min_weth(),
+                utils::dotenv::get_treasury_contract_target_weth(),
+                utils::dotenv::get_treasury_contract_max_weth(),
+            ),
+            searcher: BalanceBounds::new(
+                utils::dotenv::get_treasury_searcher_min_eth(),
+                utils::dotenv::get_treasury_searcher_target_eth(),
+                utils::dotenv::get_treasury_searcher_max_eth(),
+            ),
+        }
+    }
+
+    // Decide which transfer (if any) moves balances back within their bounds, gas money for the
+    // searcher comes first as the bot can't send bundles without it
+    //
+    // Arguments:
+    // * `contract_weth`: weth held by sandwich contract
+    // * `searcher_eth`: eth held by searcher
+    //
+    // Returns:
+    // Some(TreasuryMove): transfer to make
+    // None: if both balances are within bounds (or nothing can be moved)
+    pub fn plan(&self, contract_weth: U256, searcher_eth: U256) -> Option<TreasuryMove> {
+        let (contract, searcher) = (&self.contract, &self.searcher);
+
+        let planned = if searcher_eth < searcher.min {
+            // contract can only send what it holds
+            TreasuryMove::Withdraw((searcher.target - searcher_eth).min(contract_weth))
+        } else if contract_weth > contract.max {
+            TreasuryMove::Withdraw(contract_weth - contract.target)
+        } else if (contract_weth < contract.min || searcher_eth > searcher.max)
+            && contract_weth < contract.target
+            && searcher_eth > searcher.target
+        {
+            // never dip into the searcher's gas money
+            TreasuryMove::Deposit(
+                (searcher_eth - searcher.target).min(contract.target - contract_weth),
+            )
+        } else {
+            return None;
+        };
+
+        match planned {
+            // contract only unwraps multiples of the divisor
+            TreasuryMove::Withdraw(amount) => {
+                let divisor = get_weth_encode_divisor();
+                let amount = amount / divisor * divisor;
+                (!amount.is_zero()).then_some(TreasuryMove::Withdraw(amount))
+            }
+            TreasuryMove::Deposit(amount) => {
+                (!amount.is_zero()).then_some(TreasuryMove::Deposit(amount))
+            }
+        }
+    }
+}
+
+/// Treasury transfer sent to relays that has not been checked yet
+struct PendingMove {
+    movement: TreasuryMove,
+    tx: H256,
+    target_block: U64,
+}
+
+/// Keeps sandwich contract and searcher balances within their bounds by sending transfers between
+/// them as private bundles
+pub struct Treasury {
+    config: TreasuryConfig,
+    pending: Option<PendingMove>,
+}
+
+impl Treasury {
+    // Create a `Treasury` with bounds from env
+    pub fn from_env() -> Self {
+        Self {
+            config: TreasuryConfig::from_env(),
+            pending: None,
+        }
+    }
+
+    // Check the last transfer, then send a new one targeting the block after `latest_block` if
+    // balances are out of bounds
+    //
+    // Arguments:
+    // * `&mut self`: mutable reference to self
+    // * `client`: provider to read balances and fork state from
+    // * `latest_block`: block that was just mined
+    // * `sandwich_maker`: holds the jump table and the searcher nonce (reserved for the transfer)
+    //
+    // Returns:
+    // Ok(()): if balances are within bounds, or a transfer was sent / is still pending
+    // Err(eyre::Error): if balances can't be read or the transfer can't be simulated or sent
+    pub async fn rebalance(
+        &mut self,
+        client: &Arc<RpcClient>,
+        latest_block: U64,
+        sandwich_maker: &SandwichMaker,
+    ) -> eyre::Result<()> {
+        if let Some(pending) = &self.pending {
+            if pending.target_block > latest_block {
+                return Ok(());
+            }
+            match client.get_transaction_receipt(pending.tx).await? {
+                Some(receipt) => {
+                    let block = receipt.block_number.unwrap_or_default();
+                    log::info!(
+                        "{}",
+                        format!(
+                            "Treasury {} included in block {} ({:?})",
+                            pending.movement, block, pending.tx
+                        )
+                        .green()
+                    );
+                    ledger::global().record(LedgerEvent::TreasuryIncluded {
+                        block,
+                        movement: pending.movement.to_string(),
+                        tx: pending.tx,
+                    });
+                }
+                // balances are read again below, so the transfer is planned from scratch
+                None => log::info!(
+                    "Treasury {} not included in block {}",
+                    pending.movement,
+                    pending.target_block
+                ),
+            }
+            self.pending = None;
+        }
+
+        let searcher = sandwich_maker.searcher_wallet.address();
+        let contract_weth =
+            utils::contracts::get_erc20_contract(&utils::constants::get_weth_address(), client)
+                .balance_of(sandwich_maker.sandwich_address)
+                .call()
+                .await?;
+        let searcher_eth = client.get_balance(searcher, None).await?;
+
+        let movement = match self.config.plan(contract_weth, searcher_eth) {
+            Some(movement) => movement,
+            None => return Ok(()),
+        };
+
+        let target_block = latest_block + 1;
+        let nonce = match sandwich_maker.reserve_nonces(1, target_block).await {
+            Some(nonce) => nonce,
+            // another bundle holds the nonce, try again next block
+            None => return Ok(()),
+        };
+
+        let command = movement.command();
+        let simulated =
+            admin::simulate_in_next_block(client, &command, &sandwich_maker.jump_table, nonce)
+                .await?;
+        if simulated.next_block.number != target_block {
+            // a block was mined while simulating, the reservation runs out before the bundle lands
+            return Err(eyre!(
+                "Treasury {} simulated in block {} instead of {}",
+                movement,
+                simulated.next_block.number,
+                target_block
+            ));
+        }
+
+        // fails if every relay rejected the bundle, nothing is pending then
+        let (txs, rejected) = admin::send_txs(
+            &simulated.txs,
+            &simulated.next_block,
+            nonce,
+            &sandwich_maker.searcher_wallet,
+        )
+        .await?;
+        for rejection in rejected {
+            log::error!("Treasury {}", rejection);
+        }
+        let tx = txs[0];
+
+        ledger::global().record(LedgerEvent::TreasurySubmitted {
+            target_block,
+            nonce,
+            movement: movement.to_string(),
+            contract_weth,
+            searcher_eth,
+            tx,
+        });
+        log::info!(
+            "{}",
+            format!(
+                "Treasury {} sent targeting block {} (contract {} weth, searcher {} eth)",
+                movement,
+                target_block,
+                format_ether(contract_weth),
+                format_ether(searcher_eth)
+            )
+            .black()
+            .on_white()
+        );
+
+        self.pending = Some(PendingMove {
+            movement,
+            tx,
+            target_block,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn eth(amount: &str) -> U256 {
+        ethers::utils::parse_ether(amount).unwrap()
+    }
+
+    // largest amount the contract can unwrap that is not above `amount`
+    fn encodable(amount: U256) -> U256 {
+        amount / get_weth_encode_divisor() * get_weth_encode_divisor()
+    }
+
+    fn config() -> TreasuryConfig {
+        TreasuryConfig {
+            contract: BalanceBounds::new(eth("1"), eth("4"), eth("4.5")),
+            searcher: BalanceBounds::new(eth("0.1"), eth("0.3"), eth("1")),
+        }
+    }
+
+    #[test]
+    fn plans_transfers_back_within_bounds() {
+        let config = config();
+
+        // within bounds
+        assert_eq!(config.plan(eth("3"), eth("0.5")), None);
+        // profits above max are withdrawn down to target
+        assert_eq!(
+            config.plan(eth("5"), eth("0.5")),
+            Some(TreasuryMove::Withdraw(encodable(eth("1"))))
+        );
+        // searcher low on gas money is topped up from contract first
+        assert_eq!(
+            config.plan(eth("0.5"), eth("0.05")),
+            Some(TreasuryMove::Withdraw(encodable(eth("0.25"))))
+        );
+        // contract low on weth is refilled without dipping below searcher target
+        assert_eq!(
+            config.plan(eth("0.5"), eth("0.5")),
+            Some(TreasuryMove::Deposit(eth("0.2")))
+        );
+        // searcher excess refills contract up to its target
+        assert_eq!(
+            config.plan(eth("3.5"), eth("2")),
+            Some(TreasuryMove::Deposit(eth("0.5")))
+        );
+        // nothing to spare
+        assert_eq!(config.plan(eth("0.5"), eth("0.2")), None);
+        assert_eq!(config.plan(U256::zero(), eth("0.05")), None);
+        // less than the contract can encode
+        assert_eq!(config.plan(U256::from(1), eth("0.05")), None);
+    }
+
+    #[test]
+    fn withdrawals_are_encodable() {
+        let config = config();
+        let divisor = get_weth_encode_divisor();
+
+        match config.plan(eth("5") + divisor - 1, eth("0.5")) {
+            Some(TreasuryMove::Withdraw(amount)) => {
+                assert_eq!(amount % divisor, U256::zero());
+                assert_eq!(amount, encodable(eth("1") + divisor - 1));
+            }
+            planned => panic!("expected withdrawal, got {:?}", planned),
+        }
+    }
+}
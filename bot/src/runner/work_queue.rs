@@ -0,0 +1,246 @@
+use ethers::prelude::*;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+use crate::metrics::{self, Gauge};
+
+/// Unit of work passed between pipeline stages
+pub struct WorkItem<T> {
+    /// block that the work is computed for, dropped once this block has passed
+    pub target_block: U64,
+    /// higher priority work is taken first
+    pub priority: U256,
+    pub job: T,
+}
+
+// (priority, insertion order) so equal priorities are taken oldest first
+type QueueKey = (U256, Reverse<u64>);
+
+/// Bounded priority queue shared between the producer and worker tasks of a pipeline stage
+pub struct WorkQueue<T> {
+    capacity: usize,
+    items: Mutex<BTreeMap<QueueKey, WorkItem<T>>>,
+    next_seq: AtomicU64,
+    item_added: Notify,
+    item_removed: Notify,
+    depth: &'static Gauge,
+}
+
+impl<T> WorkQueue<T> {
+    // Create a new `WorkQueue`
+    //
+    // Arguments:
+    // * `capacity`: max number of items held
+    // * `depth`: gauge that the queue length is reported to
+    //
+    // Returns: a new `WorkQueue` instance
+    pub fn new(capacity: usize, depth: &'static Gauge) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            items: Mutex::new(BTreeMap::new()),
+            next_seq: AtomicU64::new(0),
+            item_added: Notify::new(),
+            item_removed: Notify::new(),
+            depth,
+        }
+    }
+
+    // Add item without waiting, if queue is full items for blocks before `item.target_block` are
+    // dropped first, then the lowest priority item
+    //
+    // Returns:
+    // true: if item was queued
+    // false: if item was dropped (queue full of higher priority items)
+    pub fn try_push(&self, item: WorkItem<T>) -> bool {
+        let key = self.next_key(item.priority);
+        let mut items = self.items.lock().unwrap();
+
+        let mut stale = 0;
+        if items.len() >= self.capacity {
+            let queued = items.len();
+            items.retain(|_, queued_item| queued_item.target_block >= item.target_block);
+            stale = queued - items.len();
+            metrics::WORK_DROPPED_STALE.add(stale as u64);
+        }
+
+        let queued = match items.first_key_value() {
+            Some((lowest, _)) if items.len() >= self.capacity => {
+                // either the lowest queued item or the new item is dropped
+                metrics::WORK_DROPPED_QUEUE_FULL.inc();
+                if *lowest < key {
+                    items.pop_first();
+                    items.insert(key, item);
+                    true
+                } else {
+                    false
+                }
+            }
+            _ => {
+                items.insert(key, item);
+                true
+            }
+        };
+        self.depth.set(items.len() as u64);
+        drop(items);
+
+        if queued {
+            self.item_added.notify_one();
+        }
+        // evicting stale items may have made space for waiting producers
+        if stale > 1 || (stale == 1 && !queued) {
+            self.item_removed.notify_one();
+        }
+        queued
+    }
+
+    // Add item, waiting for space if queue is full (backpressure on the producing stage)
+    pub async fn push(&self, item: WorkItem<T>) {
+        let key = self.next_key(item.priority);
+
+        loop {
+            {
+                let mut items = self.items.lock().unwrap();
+                if items.len() < self.capacity {
+                    items.insert(key, item);
+                    self.depth.set(items.len() as u64);
+                    // let other waiting producers know if there is still space
+                    if items.len() < self.capacity {
+                        self.item_removed.notify_one();
+                    }
+                    break;
+                }
+            }
+            self.item_removed.notified().await;
+        }
+
+        self.item_added.notify_one();
+    }
+
+    // Take highest priority item, waiting until one is available
+    pub async fn pop(&self) -> WorkItem<T> {
+        loop {
+            {
+                let mut items = self.items.lock().unwrap();
+                if let Some((_, item)) = items.pop_last() {
+                    self.depth.set(items.len() as u64);
+                    // let other waiting workers know if there is more work
+                    if !items.is_empty() {
+                        self.item_added.notify_one();
+                    }
+                    drop(items);
+                    self.item_removed.notify_one();
+                    return item;
+                }
+            }
+            self.item_added.notified().await;
+        }
+    }
+
+    // Number of queued items
+    pub fn len(&self) -> usize {
+        self.items.lock().unwrap().len()
+    }
+
+    // Is the queue empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn next_key(&self, priority: U256) -> QueueKey {
+        (
+            priority,
+            Reverse(self.next_seq.fetch_add(1, Ordering::Relaxed)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+
+    static TEST_DEPTH: Gauge = Gauge::new("test_work_queue_depth");
+
+    fn item(priority: u64, job: &'static str) -> WorkItem<&'static str> {
+        WorkItem {
+            target_block: U64::from(100),
+            priority: U256::from(priority),
+            job,
+        }
+    }
+
+    #[tokio::test]
+    async fn pops_highest_priority_first() {
+        let queue = WorkQueue::new(10, &TEST_DEPTH);
+        queue.push(item(1, "low")).await;
+        queue.push(item(5, "high")).await;
+        queue.push(item(3, "first")).await;
+        queue.push(item(3, "second")).await;
+        assert_eq!(queue.len(), 4);
+
+        // equal priorities are taken oldest first
+        for job in ["high", "first", "second", "low"] {
+            assert_eq!(queue.pop().await.job, job);
+        }
+        assert!(queue.is_empty());
+    }
+
+    #[tokio::test]
+    async fn full_queue_evicts_lowest_priority() {
+        let queue = WorkQueue::new(2, &TEST_DEPTH);
+        assert!(queue.try_push(item(2, "a")));
+        assert!(queue.try_push(item(3, "b")));
+
+        assert!(queue.try_push(item(4, "c")));
+        // not above the lowest queued priority
+        assert!(!queue.try_push(item(3, "d")));
+        assert_eq!(queue.len(), 2);
+
+        assert_eq!(queue.pop().await.job, "c");
+        assert_eq!(queue.pop().await.job, "b");
+    }
+
+    #[tokio::test]
+    async fn full_queue_evicts_stale_items_first() {
+        let queue = WorkQueue::new(2, &TEST_DEPTH);
+        let stale = WorkItem {
+            target_block: U64::from(99),
+            ..item(5, "stale")
+        };
+        assert!(queue.try_push(stale));
+        assert!(queue.try_push(item(4, "a")));
+
+        // lower priority than everything queued, but the stale item goes first
+        assert!(queue.try_push(item(1, "b")));
+        assert_eq!(queue.len(), 2);
+
+        assert_eq!(queue.pop().await.job, "a");
+        assert_eq!(queue.pop().await.job, "b");
+    }
+
+    #[tokio::test]
+    async fn push_waits_for_space() {
+        let queue = Arc::new(WorkQueue::new(1, &TEST_DEPTH));
+        queue.push(item(1, "queued")).await;
+
+        let producer = {
+            let queue = queue.clone();
+            tokio::spawn(async move { queue.push(item(2, "waiting")).await })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!producer.is_finished());
+        assert_eq!(queue.len(), 1);
+
+        assert_eq!(queue.pop().await.job, "queued");
+        tokio::time::timeout(Duration::from_secs(1), producer)
+            .await
+            .expect("push should complete once space is freed")
+            .unwrap();
+        assert_eq!(queue.pop().await.job, "waiting");
+    }
+}
@@ -0,0 +1,174 @@
+use revm::db::DatabaseRef;
+use revm::primitives::{EVMError, ExecutionResult, B160 as rAddress, U256 as rU256};
+use revm::EVM;
+
+/// Access list of a slice with entries that don't pay for themselves removed
+#[derive(Debug, Clone, Default)]
+pub struct PrunedAccessList {
+    pub access_list: Vec<(rAddress, Vec<rU256>)>,
+    /// gas saved compared to sending the slice without access list
+    pub gas_saved: u64,
+}
+
+// Keep only access list entries that lower the gas used by the tx set in `evm.env.tx`
+//
+// Listing an address costs 2400 gas and a slot 1900, a cold access costs 2600 / 2100, so an
+// entry only pays off if it is accessed and not already warm (e.g. `tx.to` or precompiles).
+// Entries are dropped one by one as long as gas used does not go up, first whole addresses
+// then single slots.
+//
+// Arguments:
+// * `evm`: evm holding the state of the bundle right before the tx (bundle context)
+// * `access_list`: full access list of the tx
+//
+// Returns:
+// Ok(PrunedAccessList): net saving entries, also set as `evm.env.tx.access_list`
+// Err(EVMError): if the database failed during simulation
+pub fn prune_access_list<DB: DatabaseRef>(
+    evm: &mut EVM<DB>,
+    access_list: Vec<(rAddress, Vec<rU256>)>,
+) -> Result<PrunedAccessList, EVMError<DB::Error>> {
+    let without_access_list = gas_used_with(evm, vec![])?;
+    let mut kept = access_list;
+    let mut gas_used = match gas_used_with(evm, kept.clone())? {
+        Some(gas_used) => gas_used,
+        // nothing to compare against, reverts are reported by the caller
+        None => {
+            evm.env.tx.access_list = kept.clone();
+            return Ok(PrunedAccessList {
+                access_list: kept,
+                gas_saved: 0,
+            });
+        }
+    };
+
+    let mut entry = 0;
+    while entry < kept.len() {
+        let mut candidate = kept.clone();
+        candidate.remove(entry);
+        match gas_used_with(evm, candidate.clone())? {
+            Some(candidate_gas_used) if candidate_gas_used <= gas_used => {
+                kept = candidate;
+                gas_used = candidate_gas_used;
+            }
+            _ => entry += 1,
+        }
+    }
+
+    for entry in 0..kept.len() {
+        let mut slot = 0;
+        while slot < kept[entry].1.len() {
+            let mut candidate = kept.clone();
+            candidate[entry].1.remove(slot);
+            match gas_used_with(evm, candidate.clone())? {
+                Some(candidate_gas_used) if candidate_gas_used <= gas_used => {
+                    kept = candidate;
+                    gas_used = candidate_gas_used;
+                }
+                _ => slot += 1,
+            }
+        }
+    }
+
+    evm.env.tx.access_list = kept.clone();
+    Ok(PrunedAccessList {
+        access_list: kept,
+        gas_saved: without_access_list
+            .map(|without| without.saturating_sub(gas_used))
+            .unwrap_or_default(),
+    })
+}
+
+// Gas used by tx with `access_list` without committing (None if tx does not succeed)
+fn gas_used_with<DB: DatabaseRef>(
+    evm: &mut EVM<DB>,
+    access_list: Vec<(rAddress, Vec<rU256>)>,
+) -> Result<Option<u64>, EVMError<DB::Error>> {
+    evm.env.tx.access_list = access_list;
+    match evm.transact_ref() {
+        Ok(result) => match result.result {
+            ExecutionResult::Success { gas_used, .. } => Ok(Some(gas_used)),
+            _ => Ok(None),
+        },
+        Err(EVMError::Database(e)) => Err(EVMError::Database(e)),
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use revm::db::{CacheDB, EmptyDB};
+    use revm::primitives::{AccountInfo, Bytecode, TransactTo};
+
+    use super::*;
+
+    const PARENT: u64 = 0x1234;
+    const CHILD: u64 = 0x5678;
+
+    // SLOAD(0) SLOAD(1) CALL(GAS, CHILD, 0, 0, 0, 0, 0)
+    fn parent_code() -> Vec<u8> {
+        let mut code = vec![0x60, 0x00, 0x54, 0x50, 0x60, 0x01, 0x54, 0x50];
+        code.extend([
+            0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x73,
+        ]);
+        code.extend(rAddress::from_low_u64_be(CHILD).as_bytes());
+        code.extend([0x5a, 0xf1, 0x50, 0x00]);
+        code
+    }
+
+    // SLOAD(7)
+    fn child_code() -> Vec<u8> {
+        vec![0x60, 0x07, 0x54, 0x50, 0x00]
+    }
+
+    fn evm() -> EVM<CacheDB<EmptyDB>> {
+        let mut db = CacheDB::new(EmptyDB::default());
+        for (address, code) in [(PARENT, parent_code()), (CHILD, child_code())] {
+            db.insert_account_info(
+                rAddress::from_low_u64_be(address),
+                AccountInfo::new(rU256::ZERO, 0, Bytecode::new_raw(code.into())),
+            );
+        }
+
+        let mut evm = EVM::new();
+        evm.database(db);
+        evm.env.tx.caller = rAddress::from_low_u64_be(0xbeef);
+        evm.env.tx.transact_to = TransactTo::Call(rAddress::from_low_u64_be(PARENT));
+        evm.env.tx.gas_limit = 1_000_000;
+        evm
+    }
+
+    #[test]
+    fn keeps_only_saving_entries() {
+        let parent = rAddress::from_low_u64_be(PARENT);
+        let child = rAddress::from_low_u64_be(CHILD);
+        let mut evm = evm();
+
+        let pruned = prune_access_list(
+            &mut evm,
+            vec![
+                // tx.to is warm already, listing it costs more than its two slots save
+                (parent, vec![rU256::from(0), rU256::from(1)]),
+                // cold call + cold slot, saves 100 gas each
+                (child, vec![rU256::from(7)]),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(pruned.access_list, vec![(child, vec![rU256::from(7)])]);
+        assert_eq!(pruned.gas_saved, 200);
+        assert_eq!(evm.env.tx.access_list, pruned.access_list);
+    }
+
+    #[test]
+    fn drops_untouched_slots() {
+        let child = rAddress::from_low_u64_be(CHILD);
+        let pruned = prune_access_list(
+            &mut evm(),
+            vec![(child, vec![rU256::from(7), rU256::from(8)])],
+        )
+        .unwrap();
+
+        assert_eq!(pruned.access_list, vec![(child, vec![rU256::from(7)])]);
+    }
+}
@@ -0,0 +1,521 @@
+use ethers::prelude::*;
+use hashbrown::{HashMap, HashSet};
+use std::collections::VecDeque;
+
+use crate::prelude::fork_factory::ForkFactory;
+use crate::prelude::sandwich_types::RawIngredients;
+use crate::types::sandwich_types::OptimalRecipe;
+use crate::types::{BlockInfo, SimulationError};
+use crate::utils::tx_builder::SandwichMaker;
+
+use super::make_sandwich::{
+    encoded_weth, evaluate_sandwich_revenue, juiced_quadratic_search, sanity_check,
+};
+use super::{slippage_bound, victim_order};
+
+// Number of extra points sampled on a pool's revenue curve when pools compete for the budget
+const CURVE_SAMPLES: usize = 12;
+// Max number of pool/victim combinations simulated before giving up
+const MAX_SUBSET_ATTEMPTS: usize = 16;
+
+/// Revenue produced by a pool as a function of the frontrun amount
+///
+/// Stored as the upper concave envelope of sampled points, starting at (0, 0) and ending at the
+/// sample with highest revenue, so marginal revenue is decreasing along the curve
+#[derive(Clone, Debug, Default)]
+pub struct RevenueCurve {
+    points: Vec<(U256, U256)>,
+}
+
+impl RevenueCurve {
+    // Build curve from (amount_in, revenue) samples
+    pub fn from_samples(mut samples: Vec<(U256, U256)>) -> Self {
+        samples.push((U256::zero(), U256::zero()));
+        samples.sort();
+        samples.dedup_by(|a, b| a.0 == b.0);
+
+        // upper hull (monotone chain), drop points that are below the line of their neighbours
+        let mut hull: Vec<(U256, U256)> = vec![];
+        for point in samples {
+            while hull.len() >= 2
+                && !is_right_turn(hull[hull.len() - 2], hull[hull.len() - 1], point)
+            {
+                hull.pop();
+            }
+            hull.push(point);
+        }
+
+        // nothing to gain past the highest revenue
+        let best = hull
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.1.cmp(&b.1))
+            .map(|(i, _)| i)
+            .unwrap_or_default();
+        hull.truncate(best + 1);
+
+        Self { points: hull }
+    }
+
+    // Amount in and revenue at the curve's maximum
+    pub fn optimal(&self) -> (U256, U256) {
+        self.points.last().copied().unwrap_or_default()
+    }
+
+    // Revenue when frontrunning with `amount_in` (linear between samples)
+    pub fn revenue_at(&self, amount_in: U256) -> U256 {
+        for window in self.points.windows(2) {
+            let ((x0, y0), (x1, y1)) = (window[0], window[1]);
+            if amount_in <= x1 {
+                return y0 + ((y1 - y0) * (amount_in - x0)) / (x1 - x0);
+            }
+        }
+        self.optimal().1
+    }
+
+    // (width, gain) of each segment with positive gain
+    fn segments(&self) -> Vec<(U256, U256)> {
+        self.points
+            .windows(2)
+            .map(|w| (w[1].0 - w[0].0, w[1].1 - w[0].1))
+            .filter(|(width, gain)| !width.is_zero() && !gain.is_zero())
+            .collect()
+    }
+}
+
+// Is o -> a -> b a clockwise turn
+fn is_right_turn(o: (U256, U256), a: (U256, U256), b: (U256, U256)) -> bool {
+    let signed = |v: U256| I256::from_raw(v);
+    let cross = (signed(a.0) - signed(o.0)) * (signed(b.1) - signed(o.1))
+        - (signed(a.1) - signed(o.1)) * (signed(b.0) - signed(o.0));
+    cross < I256::zero()
+}
+
+// Split `budget` across pools so that total revenue is maximized
+//
+// Arguments:
+// * `curves`: revenue curve of each pool
+// * `budget`: total amount of weth that can be used for frontruns
+//
+// Returns:
+// Vec<U256>: frontrun amount for each pool (same order as `curves`)
+pub fn allocate(curves: &[RevenueCurve], budget: U256) -> Vec<U256> {
+    let optimals: Vec<U256> = curves.iter().map(|curve| curve.optimal().0).collect();
+    let total = optimals
+        .iter()
+        .fold(U256::zero(), |acc, x| acc.saturating_add(*x));
+    if total <= budget {
+        return optimals;
+    }
+
+    // every curve is concave, so taking segments by decreasing marginal revenue keeps each
+    // curve's segments in order and ends with marginal revenue equal across pools
+    let mut segments: Vec<(usize, U256, U256)> = curves
+        .iter()
+        .enumerate()
+        .flat_map(|(i, curve)| {
+            curve
+                .segments()
+                .into_iter()
+                .map(move |(width, gain)| (i, width, gain))
+        })
+        .collect();
+    segments.sort_by(|a, b| (b.2.full_mul(a.1)).cmp(&a.2.full_mul(b.1)));
+
+    let mut allocation = vec![U256::zero(); curves.len()];
+    let mut remaining = budget;
+    for (i, width, _) in segments {
+        if remaining.is_zero() {
+            break;
+        }
+        let take = width.min(remaining);
+        allocation[i] += take;
+        remaining -= take;
+    }
+
+    allocation
+}
+
+// Find the revenue curve of a single pool
+//
+// Arguments:
+// * `ingredients`: holds onchain information about opportunity
+// * `sandwich_balance`: upper bound of frontrun amount
+// * `next_block`: holds information about next block
+// * `fork_factory`: used to create new forked evm instances for simulations
+// * `optimum`: (amount_in, revenue) already found while ordering the victims, skips the search
+// * `detailed`: sample the whole curve instead of only its optimum
+//
+// Returns:
+// Ok(RevenueCurve): curve of the pool
+// Err(SimulationError): if error during calculation
+async fn find_revenue_curve(
+    ingredients: &RawIngredients,
+    sandwich_balance: U256,
+    next_block: &BlockInfo,
+    fork_factory: &mut ForkFactory,
+    optimum: Option<(U256, U256)>,
+    detailed: bool,
+) -> Result<RevenueCurve, SimulationError> {
+    // victims' slippage caps the frontrun, no need to simulate past it
+    let upper_bound = slippage_bound::find_frontrun_upper_bound(
+        ingredients,
+        sandwich_balance,
+        next_block,
+        fork_factory,
+    );
+    if upper_bound.is_zero() {
+        return Ok(RevenueCurve::from_samples(vec![]));
+    }
+
+    let (optimal_in, optimal_revenue) = match optimum {
+        Some(optimum) if optimum.0 <= upper_bound => optimum,
+        _ => {
+            juiced_quadratic_search(
+                ingredients,
+                U256::zero(),
+                upper_bound,
+                next_block,
+                fork_factory,
+            )
+            .await?
+        }
+    };
+
+    let mut samples = vec![(optimal_in, optimal_revenue)];
+    if !detailed || optimal_in.is_zero() {
+        return Ok(RevenueCurve::from_samples(samples));
+    }
+
+    let mut sims = Vec::new();
+    for i in 1..=CURVE_SAMPLES {
+        let amount_in = encoded_weth((optimal_in * i) / (CURVE_SAMPLES + 1), ingredients);
+        let sim = tokio::task::spawn(evaluate_sandwich_revenue(
+            amount_in,
+            ingredients.clone(),
+            next_block.clone(),
+            fork_factory.new_sandbox_fork(),
+        ));
+        sims.push((amount_in, sim));
+    }
+
+    for (amount_in, sim) in sims {
+        let revenue = match sim.await {
+            Ok(revenue) => revenue.unwrap_or_default(),
+            Err(_) => U256::zero(),
+        };
+        samples.push((amount_in, revenue));
+    }
+
+    Ok(RevenueCurve::from_samples(samples))
+}
+
+// Identifies a pool + victims combination
+fn combination_key(ingredients: &RawIngredients) -> (Address, Vec<TxHash>) {
+    let mut hashes: Vec<TxHash> = ingredients.meats.iter().map(|meat| meat.hash).collect();
+    hashes.sort();
+    (ingredients.target_pool.address, hashes)
+}
+
+// Can dropping pools or victims fix this error
+fn is_combination_error(error: &SimulationError) -> bool {
+    !matches!(
+        error,
+        SimulationError::FrontrunEvmError(_)
+            | SimulationError::BackrunEvmError(_)
+            | SimulationError::EvmError(_)
+    )
+}
+
+// Jointly pick frontrun amounts for all pools and the combination of pools/victims to include
+//
+// Arguments:
+// * `multi_ingredients`: one entry per pool that can be sandwiched
+// * `sandwich_balance`: weth budget shared by all frontruns
+// * `next_block`: holds information about next block
+// * `fork_factory`: used to create new forked evm instances for simulations
+// * `sandwich_maker`: handles encoding of transaction for sandwich contract
+//
+// Returns:
+// Ok(OptimalRecipe): best combination that passed the sanity check
+// Err(SimulationError): if no combination produces a valid sandwich
+pub async fn create_joint_sandwich(
+    multi_ingredients: &[RawIngredients],
+    sandwich_balance: U256,
+    next_block: &BlockInfo,
+    fork_factory: &mut ForkFactory,
+    sandwich_maker: &SandwichMaker,
+) -> Result<OptimalRecipe, SimulationError> {
+    let mut curves: HashMap<(Address, Vec<TxHash>), RevenueCurve> = HashMap::new();
+    let mut detailed_curves: HashSet<(Address, Vec<TxHash>)> = HashSet::new();
+    let mut failed_curves: HashSet<(Address, Vec<TxHash>)> = HashSet::new();
+    // optimum found for each pool's chosen victim ordering, reused instead of searching again
+    let mut orderings: HashMap<(Address, Vec<TxHash>), (U256, U256)> = HashMap::new();
+    let mut visited: HashSet<Vec<(Address, Vec<TxHash>)>> = HashSet::new();
+    let mut queue: VecDeque<Vec<RawIngredients>> = VecDeque::new();
+    let mut last_error = SimulationError::ZeroOptimal();
+
+    // victims of the same pool are ordered (and filtered) before pools are combined, a pool
+    // without a working ordering is dropped instead of failing the others
+    let mut ordered_ingredients = Vec::with_capacity(multi_ingredients.len());
+    for ingredients in multi_ingredients.iter() {
+        match victim_order::order_victims(ingredients, sandwich_balance, next_block, fork_factory)
+            .await
+        {
+            Ok(ordering) => {
+                if let Some(optimum) = ordering.optimum {
+                    orderings.insert(combination_key(&ordering.ingredients), optimum);
+                }
+                ordered_ingredients.push(ordering.ingredients);
+            }
+            Err(e) => {
+                log::debug!(
+                    "Dropping pool {:?} from joint sandwich: {:?}",
+                    ingredients.target_pool.address,
+                    e
+                );
+                last_error = e;
+            }
+        }
+    }
+    if ordered_ingredients.is_empty() {
+        return Err(last_error);
+    }
+    queue.push_back(ordered_ingredients);
+
+    let mut attempts = 0;
+    while let Some(combination) = queue.pop_front() {
+        if attempts == MAX_SUBSET_ATTEMPTS {
+            break;
+        }
+
+        let mut key: Vec<_> = combination.iter().map(combination_key).collect();
+        key.sort();
+        if !visited.insert(key) {
+            continue;
+        }
+
+        // only sample full curves if the pools compete for the budget, a pool whose curve can't
+        // be found is dropped instead of failing the others
+        let mut curved: Vec<(RawIngredients, RevenueCurve)> = vec![];
+        for ingredients in combination.into_iter() {
+            let key = combination_key(&ingredients);
+            if failed_curves.contains(&key) {
+                continue;
+            }
+            let curve = match curves.get(&key) {
+                Some(curve) => curve.clone(),
+                None => match find_revenue_curve(
+                    &ingredients,
+                    sandwich_balance,
+                    next_block,
+                    fork_factory,
+                    orderings.get(&key).copied(),
+                    false,
+                )
+                .await
+                {
+                    Ok(curve) => {
+                        curves.insert(key, curve.clone());
+                        curve
+                    }
+                    Err(e) => {
+                        log::debug!(
+                            "Dropping pool {:?} from joint sandwich: {:?}",
+                            ingredients.target_pool.address,
+                            e
+                        );
+                        failed_curves.insert(key);
+                        last_error = e;
+                        continue;
+                    }
+                },
+            };
+            curved.push((ingredients, curve));
+        }
+        let total_optimal = curved.iter().fold(U256::zero(), |acc, (_, c)| {
+            acc.saturating_add(c.optimal().0)
+        });
+        if total_optimal > sandwich_balance {
+            let mut detailed = Vec::with_capacity(curved.len());
+            for (ingredients, curve) in curved.into_iter() {
+                let key = combination_key(&ingredients);
+                if detailed_curves.contains(&key) {
+                    detailed.push((ingredients, curve));
+                    continue;
+                }
+                match find_revenue_curve(
+                    &ingredients,
+                    sandwich_balance,
+                    next_block,
+                    fork_factory,
+                    Some(curve.optimal()),
+                    true,
+                )
+                .await
+                {
+                    Ok(curve) => {
+                        curves.insert(key.clone(), curve.clone());
+                        detailed_curves.insert(key);
+                        detailed.push((ingredients, curve));
+                    }
+                    Err(e) => {
+                        log::debug!(
+                            "Dropping pool {:?} from joint sandwich: {:?}",
+                            ingredients.target_pool.address,
+                            e
+                        );
+                        failed_curves.insert(key);
+                        last_error = e;
+                    }
+                }
+            }
+            curved = detailed;
+        }
+        let (combination, combination_curves): (Vec<RawIngredients>, Vec<RevenueCurve>) =
+            curved.into_iter().unzip();
+
+        // drop pools that don't get any budget once their share is rounded down to what the
+        // contract can encode
+        let allocation = allocate(&combination_curves, sandwich_balance);
+        let (mut good_ingredients, frontrun_ins): (Vec<RawIngredients>, Vec<U256>) = combination
+            .into_iter()
+            .zip(allocation)
+            .map(|(ingredients, amount_in)| {
+                let amount_in = encoded_weth(amount_in, &ingredients);
+                (ingredients, amount_in)
+            })
+            .filter(|(_, amount_in)| !amount_in.is_zero())
+            .unzip();
+        if good_ingredients.is_empty() {
+            continue;
+        }
+
+        attempts += 1;
+        let error = match sanity_check(
+            sandwich_balance,
+            frontrun_ins,
+            &mut good_ingredients,
+            next_block,
+            sandwich_maker,
+            fork_factory.new_sandbox_fork(),
+        ) {
+            Ok(recipe) => return Ok(recipe),
+            Err(e) => e,
+        };
+
+        if !is_combination_error(&error) {
+            return Err(error);
+        }
+        last_error = error;
+
+        // retry without one of the pools (least profitable dropped last), then without one of the victims
+        let mut without_pool: Vec<(U256, Vec<RawIngredients>)> = (0..good_ingredients.len())
+            .filter(|_| good_ingredients.len() > 1)
+            .map(|skip| {
+                let remaining: Vec<RawIngredients> = good_ingredients
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| *i != skip)
+                    .map(|(_, ingredients)| ingredients.clone())
+                    .collect();
+                let estimate = remaining.iter().fold(U256::zero(), |acc, ingredients| {
+                    let revenue = curves
+                        .get(&combination_key(ingredients))
+                        .map(|curve| curve.optimal().1)
+                        .unwrap_or_default();
+                    acc.saturating_add(revenue)
+                });
+                (estimate, remaining)
+            })
+            .collect();
+        without_pool.sort_by(|a, b| b.0.cmp(&a.0));
+        queue.extend(without_pool.into_iter().map(|(_, remaining)| remaining));
+
+        for (i, ingredients) in good_ingredients.iter().enumerate() {
+            if ingredients.meats.len() <= 1 {
+                continue;
+            }
+            for skip in 0..ingredients.meats.len() {
+                let mut remaining = good_ingredients.clone();
+                remaining[i].meats.remove(skip);
+                queue.push_back(remaining);
+            }
+        }
+    }
+
+    Err(last_error)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn points(curve: &RevenueCurve) -> Vec<(u64, u64)> {
+        curve
+            .points
+            .iter()
+            .map(|(x, y)| (x.as_u64(), y.as_u64()))
+            .collect()
+    }
+
+    fn curve(samples: &[(u64, u64)]) -> RevenueCurve {
+        RevenueCurve::from_samples(
+            samples
+                .iter()
+                .map(|(x, y)| (U256::from(*x), U256::from(*y)))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn curve_is_concave_envelope_up_to_max_revenue() {
+        // (20, 25) is below the line between (10, 20) and (30, 40), points past the max are dropped
+        let curve = curve(&[(30, 40), (10, 20), (20, 25), (40, 35)]);
+        assert_eq!(points(&curve), vec![(0, 0), (10, 20), (30, 40)]);
+        assert_eq!(curve.optimal(), (U256::from(30), U256::from(40)));
+
+        // empty curve
+        let curve = RevenueCurve::from_samples(vec![]);
+        assert_eq!(points(&curve), vec![(0, 0)]);
+        assert_eq!(curve.optimal(), (U256::zero(), U256::zero()));
+    }
+
+    #[test]
+    fn revenue_is_interpolated_between_samples() {
+        let curve = curve(&[(10, 20), (30, 40)]);
+        assert_eq!(curve.revenue_at(U256::zero()), U256::zero());
+        assert_eq!(curve.revenue_at(U256::from(5)), U256::from(10));
+        assert_eq!(curve.revenue_at(U256::from(10)), U256::from(20));
+        assert_eq!(curve.revenue_at(U256::from(20)), U256::from(30));
+        // flat past the optimum
+        assert_eq!(curve.revenue_at(U256::from(100)), U256::from(40));
+    }
+
+    #[test]
+    fn allocates_budget_by_marginal_revenue() {
+        // marginal revenue: a = 2 then 1, b = 1.5
+        let a = curve(&[(10, 20), (30, 40)]);
+        let b = curve(&[(20, 30)]);
+        let curves = vec![a, b];
+
+        // enough budget for every optimum
+        assert_eq!(
+            allocate(&curves, U256::from(100)),
+            vec![U256::from(30), U256::from(20)]
+        );
+        // best segment first, then the next best one
+        assert_eq!(
+            allocate(&curves, U256::from(25)),
+            vec![U256::from(10), U256::from(15)]
+        );
+        assert_eq!(
+            allocate(&curves, U256::from(40)),
+            vec![U256::from(20), U256::from(20)]
+        );
+        assert_eq!(
+            allocate(&curves, U256::zero()),
+            vec![U256::zero(), U256::zero()]
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use revm::db::DatabaseRef;
+use revm::primitives::{EVMError, ExecutionResult};
+use revm::EVM;
+
+use crate::prelude::call_depth::CallDepthInspector;
+
+// stop searching once the minimal gas limit is known within this many gas
+const GAS_ESTIMATE_TOLERANCE: u64 = 100;
+
+// Find the lowest gas limit that the tx set in `evm.env.tx` succeeds with (uses its access list)
+//
+// Arguments:
+// * `evm`: evm holding the state before the tx, `env.tx.gas_limit` is used as upper bound
+// * `margin_bps`: margin added on top of the minimal gas limit
+//
+// Returns:
+// Ok(u64): minimal gas limit plus margin (upper bound if tx fails even with it)
+// Err(EVMError): if the database failed during simulation
+pub fn estimate_gas_limit<DB: DatabaseRef>(
+    evm: &mut EVM<DB>,
+    margin_bps: u64,
+) -> Result<u64, EVMError<DB::Error>> {
+    let max_gas_limit = evm.env.tx.gas_limit;
+    let estimate = search_gas_limit(evm, max_gas_limit);
+    evm.env.tx.gas_limit = max_gas_limit;
+
+    let gas_limit = estimate?;
+    Ok((gas_limit + gas_limit * margin_bps / 10000).min(max_gas_limit))
+}
+
+// Binary search the minimal successful gas limit between gas used and `max_gas_limit`
+fn search_gas_limit<DB: DatabaseRef>(
+    evm: &mut EVM<DB>,
+    max_gas_limit: u64,
+) -> Result<u64, EVMError<DB::Error>> {
+    let mut inspector = CallDepthInspector::default();
+    let result = evm.inspect_ref(&mut inspector)?.result;
+    if !result.is_success() {
+        return Ok(max_gas_limit);
+    }
+
+    // refunds are only paid out at the end, so gas limit can't be lower than gas used
+    let mut highest_failing = result.gas_used().saturating_sub(1);
+    let mut lowest_passing = max_gas_limit;
+
+    // every nested call holds back 1/64 of the gas left, start right above that
+    let guess = eip150_gas_limit(result.gas_used(), inspector.max_nested_depth());
+    if guess < lowest_passing {
+        match succeeds_with(evm, guess)? {
+            true => lowest_passing = guess,
+            false => highest_failing = guess,
+        }
+    }
+
+    while lowest_passing - highest_failing > GAS_ESTIMATE_TOLERANCE {
+        let gas_limit = highest_failing + (lowest_passing - highest_failing) / 2;
+        match succeeds_with(evm, gas_limit)? {
+            true => lowest_passing = gas_limit,
+            false => highest_failing = gas_limit,
+        }
+    }
+
+    Ok(lowest_passing)
+}
+
+// Gas limit that leaves `gas_used` available after `depth` nested calls took their 1/64 cut
+fn eip150_gas_limit(gas_used: u64, depth: u64) -> u64 {
+    (0..depth).fold(gas_used, |gas, _| gas + gas / 63 + 1)
+}
+
+// Run tx with `gas_limit` without committing
+fn succeeds_with<DB: DatabaseRef>(
+    evm: &mut EVM<DB>,
+    gas_limit: u64,
+) -> Result<bool, EVMError<DB::Error>> {
+    evm.env.tx.gas_limit = gas_limit;
+    match evm.transact_ref() {
+        Ok(result) => Ok(matches!(result.result, ExecutionResult::Success { .. })),
+        Err(EVMError::Database(e)) => Err(EVMError::Database(e)),
+        // e.g. gas limit below intrinsic gas
+        Err(_) => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use revm::db::{CacheDB, EmptyDB};
+    use revm::primitives::{AccountInfo, Bytecode, TransactTo, B160, U256 as rU256};
+
+    use super::*;
+
+    const PARENT: u64 = 0x1234;
+    const CHILD: u64 = 0x5678;
+
+    // reads 10 cold slots
+    fn child_code() -> Vec<u8> {
+        let mut code = vec![];
+        for slot in 0..10u8 {
+            code.extend([0x60, slot, 0x54, 0x50]);
+        }
+        code.push(0x00);
+        code
+    }
+
+    // CALL(GAS, CHILD, 0, 0, 0, 0, 0) and revert if the call failed
+    fn parent_code() -> Vec<u8> {
+        let mut code = vec![
+            0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x73,
+        ];
+        code.extend(B160::from_low_u64_be(CHILD).as_bytes());
+        code.extend([
+            0x5a, 0xf1, 0x60, 0x28, 0x57, 0x60, 0x00, 0x80, 0xfd, 0x5b, 0x00,
+        ]);
+        code
+    }
+
+    fn evm() -> EVM<CacheDB<EmptyDB>> {
+        let mut db = CacheDB::new(EmptyDB::default());
+        for (address, code) in [(PARENT, parent_code()), (CHILD, child_code())] {
+            db.insert_account_info(
+                B160::from_low_u64_be(address),
+                AccountInfo::new(rU256::ZERO, 0, Bytecode::new_raw(code.into())),
+            );
+        }
+
+        let mut evm = EVM::new();
+        evm.database(db);
+        evm.env.tx.caller = B160::from_low_u64_be(0xbeef);
+        evm.env.tx.transact_to = TransactTo::Call(B160::from_low_u64_be(PARENT));
+        evm.env.tx.gas_limit = 1_000_000;
+        evm
+    }
+
+    #[test]
+    fn finds_minimal_gas_limit() {
+        let mut evm = evm();
+        let gas_used = evm.transact_ref().unwrap().result.gas_used();
+
+        let gas_limit = estimate_gas_limit(&mut evm, 0).unwrap();
+        assert_eq!(evm.env.tx.gas_limit, 1_000_000);
+
+        // child only gets 63/64 of what is left, so gas used alone is not enough
+        assert!(gas_limit > gas_used);
+        assert!(gas_limit <= eip150_gas_limit(gas_used, 1) + GAS_ESTIMATE_TOLERANCE);
+        assert!(succeeds_with(&mut evm, gas_limit).unwrap());
+        assert!(!succeeds_with(&mut evm, gas_limit - GAS_ESTIMATE_TOLERANCE - 1).unwrap());
+    }
+
+    #[test]
+    fn adds_margin() {
+        let minimal = estimate_gas_limit(&mut evm(), 0).unwrap();
+        let with_margin = estimate_gas_limit(&mut evm(), 200).unwrap();
+        assert_eq!(with_margin, minimal + minimal * 200 / 10000);
+    }
+}
@@ -54,14 +54,28 @@ pub fn attach_braindance_module(fork_factory: &mut ForkFactory) {
 //
 // Returns: This function returns nothing
 pub fn inject_sando(fork_factory: &mut ForkFactory, starting_weth_balance: U256) {
+    inject_sando_at(
+        fork_factory,
+        get_sandwich_contract_address(),
+        get_searcher_wallet().address(),
+        starting_weth_balance,
+    );
+}
+
+// Inject test sandwich code at `sandwich`, encoders in tests use its jump table
+// (`injected_jump_table`) so that their payloads match the simulated code
+fn inject_sando_at(
+    fork_factory: &mut ForkFactory,
+    sandwich: Address,
+    searcher: Address,
+    starting_weth_balance: U256,
+) {
     // give searcher some balance to pay for gas fees
-    let searcher = get_searcher_wallet().address();
     let gas_money = parse_ether(100).unwrap();
     let account = revm::primitives::AccountInfo::new(gas_money.into(), 0, Bytecode::default());
     fork_factory.insert_account_info(searcher.0.into(), account);
 
     // setup sandwich contract
-    let sandwich = get_sandwich_contract_address();
     let account = revm::primitives::AccountInfo::new(
         rU256::from(0),
         0,
@@ -143,7 +157,33 @@ pub fn get_amount_out_evm_v2(
     token_out: Address,
     evm: &mut EVM<ForkDB>,
 ) -> Result<U256, SimulationError> {
-    // get reserves
+    let (reserve_in, reserve_out) = get_reserves_evm(target_pool, token_in, token_out, evm)?;
+
+    let a_in_with_fee: U256 = amount_in * 997;
+    let numerator: U256 = a_in_with_fee * reserve_out;
+    let denominator: U256 = reserve_in * 1000 + a_in_with_fee;
+    let amount_out: U256 = numerator.checked_div(denominator).unwrap_or(U256::zero());
+
+    Ok(amount_out)
+}
+
+// Read reserves of a uniswap v2 style pool ordered by swap direction
+//
+// Arguments:
+// * `target_pool`: address of pool
+// * `token_in`: token sent to pool
+// * `token_out`: token received from pool
+// * `evm`: evm instance holding pool state
+//
+// Returns:
+// Ok((reserve_in, reserve_out)): if pool returned reserves
+// Err(SimulationError): if call to pool failed
+pub fn get_reserves_evm(
+    target_pool: Address,
+    token_in: Address,
+    token_out: Address,
+    evm: &mut EVM<ForkDB>,
+) -> Result<(U256, U256), SimulationError> {
     evm.env.tx.transact_to = TransactTo::Call(target_pool.0.into());
     evm.env.tx.caller = utils::constants::get_eth_dev().0.into();
     evm.env.tx.value = rU256::ZERO;
@@ -174,17 +214,10 @@ pub fn get_amount_out_evm_v2(
     let reserves_0 = tokens[0].clone().into_uint().unwrap();
     let reserves_1 = tokens[1].clone().into_uint().unwrap();
 
-    let (reserve_in, reserve_out) = match token_in < token_out {
-        true => (reserves_0, reserves_1),
-        false => (reserves_1, reserves_0),
-    };
-
-    let a_in_with_fee: U256 = amount_in * 997;
-    let numerator: U256 = a_in_with_fee * reserve_out;
-    let denominator: U256 = reserve_in * 1000 + a_in_with_fee;
-    let amount_out: U256 = numerator.checked_div(denominator).unwrap_or(U256::zero());
-
-    Ok(amount_out)
+    match token_in < token_out {
+        true => Ok((reserves_0, reserves_1)),
+        false => Ok((reserves_1, reserves_0)),
+    }
 }
 
 pub fn get_amount_out_evm_v3(
@@ -347,3 +380,47 @@ pub fn braindance_controller_address() -> rAddress {
 pub fn braindance_starting_balance() -> U256 {
     parse_ether(420).unwrap()
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use ethers::providers::Provider;
+    use revm::db::{CacheDB, EmptyDB};
+    use revm::primitives::AccountInfo;
+    use revm::Database;
+
+    use super::*;
+    use crate::utils::tx_builder::sandwich::{injected_gate_labels, injected_jump_table};
+    use crate::utils::tx_builder::JumpTable;
+
+    #[test]
+    fn encoders_use_jump_table_of_injected_code() {
+        // weth is known locally, so nothing is fetched from the (empty) mock
+        let mut initial_db = CacheDB::new(EmptyDB::default());
+        initial_db.insert_account_info(
+            constants::get_weth_address().0.into(),
+            AccountInfo::default(),
+        );
+        let (provider, _) = Provider::mocked();
+        let mut fork_factory =
+            ForkFactory::new_sandbox_factory(Arc::new(provider), initial_db, None, None);
+
+        let sandwich = Address::from_low_u64_be(0x5a5d);
+        inject_sando_at(
+            &mut fork_factory,
+            sandwich,
+            Address::from_low_u64_be(0x5eac),
+            parse_ether(1).unwrap(),
+        );
+
+        let code = fork_factory
+            .new_sandbox_fork()
+            .basic(sandwich.0.into())
+            .unwrap()
+            .and_then(|info| info.code)
+            .unwrap();
+        let simulated = JumpTable::from_bytecode(&code.original_bytes(), &injected_gate_labels());
+        assert_eq!(simulated, Ok(injected_jump_table()));
+    }
+}
@@ -0,0 +1,46 @@
+use revm::{
+    interpreter::{CallInputs, Gas, InstructionResult},
+    primitives::Bytes,
+    Database, EVMData, Inspector,
+};
+
+// An [Inspector] that records how deep calls are nested, every nested call only gets 63/64 of
+// the remaining gas (EIP-150) so deeper txs need a gas limit further above their gas used
+#[derive(Default, Debug)]
+pub struct CallDepthInspector {
+    depth: u64,
+    max_depth: u64,
+}
+
+impl CallDepthInspector {
+    // Deepest nesting of calls below the tx's own call frame
+    pub fn max_nested_depth(&self) -> u64 {
+        self.max_depth.saturating_sub(1)
+    }
+}
+
+impl<DB: Database> Inspector<DB> for CallDepthInspector {
+    fn call(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        _inputs: &mut CallInputs,
+        _is_static: bool,
+    ) -> (InstructionResult, Gas, Bytes) {
+        self.depth += 1;
+        self.max_depth = self.max_depth.max(self.depth);
+        (InstructionResult::Continue, Gas::new(0), Bytes::new())
+    }
+
+    fn call_end(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        _inputs: &CallInputs,
+        remaining_gas: Gas,
+        ret: InstructionResult,
+        out: Bytes,
+        _is_static: bool,
+    ) -> (InstructionResult, Gas, Bytes) {
+        self.depth = self.depth.saturating_sub(1);
+        (ret, remaining_gas, out)
+    }
+}
@@ -1,12 +1,141 @@
+use std::fmt;
+use std::sync::Arc;
+
+use ethers::abi::{self, Token};
+use ethers::types::{Address, U256};
+use ethers::utils::keccak256;
+use hashbrown::{HashMap, HashSet};
 use revm::interpreter::{opcode, Interpreter};
 use revm::interpreter::{CallInputs, CreateInputs, Gas, InstructionResult};
-use revm::primitives::{Bytes, B160};
+use revm::primitives::{Bytes, B160, B256, U256 as rU256};
 use revm::{Database, EVMData, Inspector};
 
+use crate::prelude::safety_policy::{self, SafetyAction, SafetyPolicy};
+use crate::utils::constants::get_weth_address;
+
+// number of storage slots (mapping indices) checked for our contract's token balance
+const BALANCE_SLOT_CANDIDATES: u64 = 16;
+// max steps between loading ORIGIN/CALLER and branching on a comparison with it
+const IDENTITY_BRANCH_WINDOW: u64 = 12;
+// max steps after the branch in which a transfer or revert counts as guarded by it
+const IDENTITY_GUARD_WINDOW: u64 = 256;
+// selectors of `transfer(address,uint256)` and `transferFrom(address,address,uint256)`
+const TRANSFER_SELECTORS: [[u8; 4]; 2] = [[0xa9, 0x05, 0x9c, 0xbb], [0x23, 0xb8, 0x72, 0xdd]];
+
 #[derive(Debug)]
 pub enum IsSandoSafu {
-    Safu,
-    NotSafu(Vec<OpCode>),
+    Safu(SalmonellaReport),
+    /// safu enough to send, but bribe is cut by bps to price in the risk
+    Haircut(SalmonellaReport, u64),
+    NotSafu(SalmonellaReport),
+}
+
+/// Everything that looked off during a simulation
+#[derive(Debug, Clone, Default)]
+pub struct SalmonellaReport {
+    pub findings: Vec<Salmonella>,
+    /// how sure we are that the sandwich gets poisoned on mainnet (0..=1)
+    pub confidence: f64,
+}
+
+impl SalmonellaReport {
+    // create a new report, combines the severity of each finding into a confidence score
+    //
+    // Arguments:
+    // * `findings`: findings collected during simulation
+    // * `policy`: policy that assigns each finding a severity
+    //
+    // Returns:
+    // `SalmonellaReport`: report where confidence is `1 - Π(1 - severity)`
+    pub fn new(findings: Vec<Salmonella>, policy: &SafetyPolicy) -> Self {
+        let confidence = 1.0
+            - findings
+                .iter()
+                .map(|finding| 1.0 - policy.severity(finding))
+                .product::<f64>();
+
+        Self {
+            findings,
+            confidence,
+        }
+    }
+
+    // true if any of the findings shows that the token takes a fee
+    pub fn is_tax(&self) -> bool {
+        self.findings.iter().any(|finding| {
+            matches!(
+                finding,
+                Salmonella::TransferTax { .. } | Salmonella::FreshSellLoss { .. }
+            )
+        })
+    }
+}
+
+impl fmt::Display for SalmonellaReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let findings = self
+            .findings
+            .iter()
+            .map(|finding| finding.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(f, "confidence {:.2} [{}]", self.confidence, findings)
+    }
+}
+
+/// Single reason to believe that a sandwich behaves differently on mainnet
+#[derive(Debug, Clone)]
+pub enum Salmonella {
+    /// opcode that can tell a local simulation apart from mainnet execution
+    SuspiciousOpcode(OpCode),
+    /// more GAS than CALL opcodes, contract is checking gas left
+    GasCheck,
+    /// `to` received less of `token` than the pool sent (fee on transfer)
+    TransferTax {
+        token: B160,
+        to: B160,
+        expected: rU256,
+        received: rU256,
+    },
+    /// `contract` compared ORIGIN/CALLER against an address and branched into a transfer or revert
+    IdentityBranch { contract: B160, opcode: OpCode },
+    /// `writer` wrote to a storage slot keyed by our contract's address in `contract`
+    ThirdPartyBalanceWrite { contract: B160, writer: B160 },
+    /// token bought from a fresh address can't be sold again
+    FreshSellReverted { token: B160 },
+    /// buying and selling token from a fresh address loses more than pool fees
+    FreshSellLoss { token: B160, loss_bps: u64 },
+}
+
+impl fmt::Display for Salmonella {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Salmonella::SuspiciousOpcode(opcode) => write!(f, "opcode {}", opcode.name),
+            Salmonella::GasCheck => write!(f, "gas check"),
+            Salmonella::TransferTax {
+                token,
+                to,
+                expected,
+                received,
+            } => write!(
+                f,
+                "tax on {:?} to {:?} (expected {}, received {})",
+                token, to, expected, received
+            ),
+            Salmonella::IdentityBranch { contract, opcode } => {
+                write!(f, "{:?} branches on {}", contract, opcode.name)
+            }
+            Salmonella::ThirdPartyBalanceWrite { contract, writer } => {
+                write!(f, "{:?} wrote our balance in {:?}", writer, contract)
+            }
+            Salmonella::FreshSellReverted { token } => {
+                write!(f, "fresh address can't sell {:?}", token)
+            }
+            Salmonella::FreshSellLoss { token, loss_bps } => {
+                write!(f, "fresh address loses {}bps on {:?}", loss_bps, token)
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -31,42 +160,257 @@ impl OpCode {
 
         OpCode { code, name }
     }
+
+    pub fn code(&self) -> u8 {
+        self.code
+    }
+}
+
+// ORIGIN/CALLER value that was pushed on the stack and is being followed
+struct IdentityLoad {
+    contract: B160,
+    opcode: OpCode,
+    value: rU256,
+    steps: u64,
+    compared: bool,
+    // branched on the comparison, steps are counted from the branch
+    branched: bool,
 }
 
 pub struct SalmonellaInspectoooor {
-    suspicious_opcodes: Vec<OpCode>,
+    sandwich_contract: B160,
+    suspicious_opcodes: HashMap<u8, OpCode>,
     gas_opcode_counter: u64,
     call_opcode_counter: u64,
+    // (token, to) => amount that `to` should receive
+    expected_transfers: HashMap<(B160, B160), rU256>,
+    // (token, account) => (amount in, amount out) seen in transfer logs
+    seen_transfers: HashMap<(B160, B160), (rU256, rU256)>,
+    // tokens, pools, weth and our contract, allowed to touch our balances
+    trusted: HashSet<B160>,
+    // slots where our contract's balance can live in a token (`mapping(address => ..)` at index i)
+    balance_slots: HashSet<rU256>,
+    identity_load: Option<IdentityLoad>,
+    transfer_topic: B256,
+    // findings that are not observed by this inspector (fresh address probe)
+    external_findings: Vec<Salmonella>,
+    findings: Vec<Salmonella>,
+    policy: Arc<SafetyPolicy>,
 }
 
 impl SalmonellaInspectoooor {
+    // create new salmonella inspector using the current safety policy
+    //
+    // Arguments:
+    // * `sandwich_contract`: address of our sandwich contract, its own code is never flagged
+    pub fn new(sandwich_contract: Address) -> Self {
+        Self::with_policy(sandwich_contract, safety_policy::current())
+    }
+
     // create new salmonella inspector
-    pub fn new() -> Self {
+    //
+    // Arguments:
+    // * `sandwich_contract`: address of our sandwich contract, its own code is never flagged
+    // * `policy`: decides which opcodes are flagged and what the findings lead to
+    pub fn with_policy(sandwich_contract: Address, policy: Arc<SafetyPolicy>) -> Self {
+        let balance_slots = (0..BALANCE_SLOT_CANDIDATES)
+            .map(|index| {
+                let slot = keccak256(abi::encode(&[
+                    Token::Address(sandwich_contract),
+                    Token::Uint(U256::from(index)),
+                ]));
+                rU256::from_be_bytes(slot)
+            })
+            .collect();
+        let sandwich_contract: B160 = sandwich_contract.0.into();
+
         Self {
-            suspicious_opcodes: Vec::new(),
+            sandwich_contract,
+            suspicious_opcodes: HashMap::new(),
             gas_opcode_counter: 0,
             call_opcode_counter: 0,
+            expected_transfers: HashMap::new(),
+            seen_transfers: HashMap::new(),
+            trusted: [sandwich_contract, get_weth_address().0.into()]
+                .into_iter()
+                .collect(),
+            balance_slots,
+            identity_load: None,
+            transfer_topic: B256::from(keccak256("Transfer(address,address,uint256)")),
+            external_findings: Vec::new(),
+            findings: Vec::new(),
+            policy,
         }
     }
 
+    // register a token transfer that the simulated tx should make
+    //
+    // Arguments:
+    // * `token`: token that is transferred
+    // * `from`: sender (pool or our contract)
+    // * `to`: receiver (pool or our contract)
+    // * `amount`: amount that `to` should receive
+    pub fn expect_transfer(&mut self, token: Address, from: Address, to: Address, amount: U256) {
+        let (token, from, to): (B160, B160, B160) = (token.0.into(), from.0.into(), to.0.into());
+        *self
+            .expected_transfers
+            .entry((token, to))
+            .or_insert(rU256::ZERO) += rU256::from(amount);
+        self.trusted.extend([token, from, to]);
+    }
+
+    // add a finding observed outside of the inspected tx
+    pub fn add_finding(&mut self, finding: Salmonella) {
+        self.external_findings.push(finding);
+    }
+
     // checks if opportunity is safu
     //
     // Arguments:
     // `self`: consumes self during calculation
     //
     // Returns:
-    // IsSandoSafu: enum that is either Safu or NotSafu
+    // IsSandoSafu: what the safety policy decided, holding a report of all findings and combined confidence
     pub fn is_sando_safu(self) -> IsSandoSafu {
-        // if more gas opcodes used then call then we know that the contract is checking gas_used
-        let mut suspicious_opcodes = self.suspicious_opcodes.clone();
-        if self.gas_opcode_counter < self.call_opcode_counter {
-            let gas_opcode = OpCode::new_from_code(opcode::GAS);
-            suspicious_opcodes.insert(0, gas_opcode);
+        let mut findings = self.external_findings;
+
+        // calls forward gas with one GAS each, any GAS on top means the contract is checking gas_used
+        if self.policy.checks_gas() && self.gas_opcode_counter > self.call_opcode_counter {
+            findings.push(Salmonella::GasCheck);
+        }
+        findings.extend(
+            self.suspicious_opcodes
+                .into_values()
+                .map(Salmonella::SuspiciousOpcode),
+        );
+
+        // only compare against transfers that emitted logs, tokens without logs can't be checked
+        for ((token, to), expected) in self.expected_transfers {
+            if let Some((amount_in, amount_out)) = self.seen_transfers.get(&(token, to)) {
+                let received = amount_in.saturating_sub(*amount_out);
+                if received < expected {
+                    findings.push(Salmonella::TransferTax {
+                        token,
+                        to,
+                        expected,
+                        received,
+                    });
+                }
+            }
+        }
+        findings.extend(self.findings);
+
+        let report = SalmonellaReport::new(findings, &self.policy);
+        match self.policy.action(report.confidence) {
+            Some(SafetyAction::Reject) => IsSandoSafu::NotSafu(report),
+            Some(SafetyAction::Haircut { bps }) => IsSandoSafu::Haircut(report, bps),
+            Some(SafetyAction::LogOnly) => {
+                log::info!("Salmonella findings (log only): {}", report);
+                IsSandoSafu::Safu(report)
+            }
+            None => IsSandoSafu::Safu(report),
+        }
+    }
+
+    // follow ORIGIN/CALLER from being pushed to a comparison to a JUMPI
+    //
+    // The branch is only flagged once it leads to a transfer or revert (see `flag_guarded`),
+    // branches guarding anything else (e.g. owner only setters) are legit
+    fn follow_identity(&mut self, interp: &Interpreter, executed_opcode: u8, origin: B160) {
+        let contract = interp.contract.address;
+        if contract == self.sandwich_contract {
+            return;
+        }
+
+        match executed_opcode {
+            opcode::ORIGIN | opcode::CALLER => {
+                let identity = match executed_opcode {
+                    opcode::ORIGIN => origin,
+                    _ => interp.contract.caller,
+                };
+                self.identity_load = Some(IdentityLoad {
+                    contract,
+                    opcode: OpCode::new_from_code(executed_opcode),
+                    value: address_word(identity),
+                    steps: 0,
+                    compared: false,
+                    branched: false,
+                });
+                return;
+            }
+            _ => {}
+        }
+
+        let identity = match &mut self.identity_load {
+            Some(identity) if identity.contract == contract => identity,
+            _ => return,
+        };
+        identity.steps += 1;
+        let window = match identity.branched {
+            true => IDENTITY_GUARD_WINDOW,
+            false => IDENTITY_BRANCH_WINDOW,
+        };
+        if identity.steps > window {
+            self.identity_load = None;
+            return;
+        }
+
+        match executed_opcode {
+            opcode::EQ if !identity.branched => {
+                let (a, b) = match (interp.stack().peek(0), interp.stack().peek(1)) {
+                    (Ok(a), Ok(b)) => (a, b),
+                    _ => return,
+                };
+                let other = match (a == identity.value, b == identity.value) {
+                    (true, false) => b,
+                    (false, true) => a,
+                    _ => return,
+                };
+                // compared against a hardcoded or stored address
+                identity.compared = !other.is_zero() && other.bit_len() <= 160;
+            }
+            opcode::JUMPI if identity.compared && !identity.branched => {
+                identity.branched = true;
+                identity.steps = 0;
+            }
+            opcode::REVERT | opcode::INVALID => self.flag_guarded(contract),
+            _ => {}
+        }
+    }
+
+    // flag the identity branch of `contract` if execution is still on the path it guards
+    fn flag_guarded(&mut self, contract: B160) {
+        match &self.identity_load {
+            Some(identity) if identity.branched && identity.contract == contract => {
+                let finding = Salmonella::IdentityBranch {
+                    contract,
+                    opcode: identity.opcode.clone(),
+                };
+                self.findings.push(finding);
+                self.identity_load = None;
+            }
+            _ => {}
+        }
+    }
+
+    // flag writes to our balance slots that don't come from a token transfer we expect
+    fn check_balance_write(&mut self, interp: &Interpreter) {
+        let contract = interp.contract.address;
+        if contract == self.sandwich_contract {
+            return;
+        }
+        let slot = match interp.stack().peek(0) {
+            Ok(slot) => slot,
+            Err(_) => return,
+        };
+        if !self.balance_slots.contains(&slot) {
+            return;
         }
 
-        match self.suspicious_opcodes.len() == 0 {
-            true => IsSandoSafu::Safu,
-            false => IsSandoSafu::NotSafu(suspicious_opcodes),
+        let writer = interp.contract.caller;
+        if !self.trusted.contains(&contract) || !self.trusted.contains(&writer) {
+            self.findings
+                .push(Salmonella::ThirdPartyBalanceWrite { contract, writer });
         }
     }
 }
@@ -86,45 +430,69 @@ impl<DB: Database> Inspector<DB> for SalmonellaInspectoooor {
     fn step(
         &mut self,
         interp: &mut Interpreter,
-        _data: &mut EVMData<'_, DB>,
+        data: &mut EVMData<'_, DB>,
         _is_static: bool,
     ) -> InstructionResult {
         let executed_opcode = interp.current_opcode();
 
-        let mut add_suspicious = |opcode: OpCode| self.suspicious_opcodes.push(opcode);
-        let mut increment_call_counter = || self.call_opcode_counter += 1;
-
-        let executed_opcode = OpCode::new_from_code(executed_opcode);
-
-        match executed_opcode.code {
-            // these opcodes can be used to divert execution flow when ran locally vs on mainnet
-            // extra safe version, can easily ignore half of these checks if ur up for it
-            opcode::BALANCE => add_suspicious(executed_opcode.clone()),
-            opcode::GASPRICE => add_suspicious(executed_opcode.clone()),
-            opcode::EXTCODEHASH => add_suspicious(executed_opcode.clone()),
-            opcode::BLOCKHASH => add_suspicious(executed_opcode.clone()),
-            opcode::COINBASE => add_suspicious(executed_opcode.clone()),
-            opcode::DIFFICULTY => add_suspicious(executed_opcode.clone()),
-            opcode::GASLIMIT => add_suspicious(executed_opcode.clone()),
-            opcode::SELFBALANCE => add_suspicious(executed_opcode.clone()),
-            opcode::BASEFEE => add_suspicious(executed_opcode.clone()),
-            opcode::CREATE => add_suspicious(executed_opcode.clone()),
-            opcode::CREATE2 => add_suspicious(executed_opcode.clone()),
-            opcode::SELFDESTRUCT => add_suspicious(executed_opcode.clone()),
+        self.follow_identity(interp, executed_opcode, data.env.tx.caller);
+
+        // opcodes listed in the safety policy can be used to divert execution flow when ran
+        // locally vs on mainnet, on their own they are weak signals as legit tokens use them too
+        let contract = interp.contract.address;
+        if self
+            .policy
+            .opcode_severity(contract, executed_opcode)
+            .is_some()
+        {
+            self.suspicious_opcodes
+                .entry(executed_opcode)
+                .or_insert_with(|| OpCode::new_from_code(executed_opcode));
+        }
+
+        match executed_opcode {
             // add one to call counter
-            opcode::CALL => increment_call_counter(),
-            opcode::DELEGATECALL => increment_call_counter(),
-            opcode::STATICCALL => increment_call_counter(),
+            opcode::CALL | opcode::DELEGATECALL | opcode::STATICCALL => {
+                self.call_opcode_counter += 1
+            }
             // add one to gas opcode counter
             opcode::GAS => self.gas_opcode_counter += 1,
+            opcode::SSTORE => self.check_balance_write(interp),
             _ => { /* this opcode is safu */ }
         }
 
-        if &executed_opcode.name == "UNKNOWN" {
-            add_suspicious(executed_opcode);
+        InstructionResult::Continue
+    }
+
+    // keep track of erc20 transfers to compare against expected amounts
+    fn log(&mut self, _data: &mut EVMData<'_, DB>, address: &B160, topics: &[B256], data: &Bytes) {
+        if topics.first() == Some(&self.transfer_topic) {
+            self.flag_guarded(*address);
+        }
+        if topics.len() != 3 || topics[0] != self.transfer_topic || data.len() < 32 {
+            return;
         }
 
-        InstructionResult::Continue
+        let from: B160 = topics[1].into();
+        let to: B160 = topics[2].into();
+        let mut amount = [0u8; 32];
+        amount.copy_from_slice(&data[..32]);
+        let amount = rU256::from_be_bytes(amount);
+
+        if self.expected_transfers.contains_key(&(*address, to)) {
+            let (amount_in, _) = self
+                .seen_transfers
+                .entry((*address, to))
+                .or_insert((rU256::ZERO, rU256::ZERO));
+            *amount_in = amount_in.saturating_add(amount);
+        }
+        if self.expected_transfers.contains_key(&(*address, from)) {
+            let (_, amount_out) = self
+                .seen_transfers
+                .entry((*address, from))
+                .or_insert((rU256::ZERO, rU256::ZERO));
+            *amount_out = amount_out.saturating_add(amount);
+        }
     }
 
     fn step_end(
@@ -140,9 +508,16 @@ impl<DB: Database> Inspector<DB> for SalmonellaInspectoooor {
     fn call(
         &mut self,
         _data: &mut EVMData<'_, DB>,
-        _inputs: &mut CallInputs,
+        inputs: &mut CallInputs,
         _is_static: bool,
     ) -> (InstructionResult, Gas, Bytes) {
+        let is_transfer = inputs.input.len() >= 4
+            && TRANSFER_SELECTORS
+                .iter()
+                .any(|selector| inputs.input[..4] == selector[..]);
+        if is_transfer {
+            self.flag_guarded(inputs.context.caller);
+        }
         (InstructionResult::Continue, Gas::new(0), Bytes::new())
     }
 
@@ -170,3 +545,220 @@ impl<DB: Database> Inspector<DB> for SalmonellaInspectoooor {
         (ret, address, remaining_gas, out)
     }
 }
+
+// left pad address to a stack word
+fn address_word(address: B160) -> rU256 {
+    let mut word = [0u8; 32];
+    word[12..].copy_from_slice(address.as_bytes());
+    rU256::from_be_bytes(word)
+}
+
+#[cfg(test)]
+mod test {
+    use revm::db::{CacheDB, EmptyDB};
+    use revm::primitives::{AccountInfo, Bytecode, TransactTo};
+
+    use super::*;
+    use crate::prelude::safety_policy::SafetyPolicyConfig;
+
+    const TOKEN: u64 = 0x1234;
+    const CALLER: u64 = 0xbeef;
+    const POOL: u64 = 0x9001;
+    const SANDWICH: u64 = 0x5a5d;
+
+    // CALLER, `padding` JUMPDESTs, PUSH20 0x..01 EQ PUSH1 dest JUMPI `guarded` JUMPDEST STOP
+    // (caller isn't 0x..01 so `guarded` runs)
+    fn branches_on_caller(padding: usize, guarded: Vec<u8>) -> Vec<u8> {
+        let mut code = vec![opcode::CALLER];
+        code.extend(vec![opcode::JUMPDEST; padding]);
+        code.push(opcode::PUSH20);
+        code.extend(B160::from_low_u64_be(1).as_bytes());
+        let dest = code.len() + 4 + guarded.len();
+        code.extend([opcode::EQ, opcode::PUSH1, dest as u8, opcode::JUMPI]);
+        code.extend(guarded);
+        code.extend([opcode::JUMPDEST, opcode::STOP]);
+        code
+    }
+
+    // REVERT(0, 0)
+    fn reverts() -> Vec<u8> {
+        vec![opcode::PUSH1, 0x00, opcode::DUP1, opcode::REVERT]
+    }
+
+    // `calls` STATICCALLs to POOL forwarding GAS, followed by `extra_gas` GAS POPs
+    fn calls(calls: usize, extra_gas: usize) -> Vec<u8> {
+        let mut code = vec![];
+        for _ in 0..calls {
+            code.extend([opcode::PUSH1, 0x00].repeat(4));
+            code.push(opcode::PUSH20);
+            code.extend(B160::from_low_u64_be(POOL).as_bytes());
+            code.extend([opcode::GAS, opcode::STATICCALL, opcode::POP]);
+        }
+        code.extend([opcode::GAS, opcode::POP].repeat(extra_gas));
+        code.push(opcode::STOP);
+        code
+    }
+
+    // SSTORE(slot, 1)
+    fn writes_slot(slot: rU256) -> Vec<u8> {
+        let mut code = vec![opcode::PUSH1, 0x01, opcode::PUSH32];
+        code.extend(slot.to_be_bytes::<32>());
+        code.extend([opcode::SSTORE, opcode::STOP]);
+        code
+    }
+
+    // emit Transfer(POOL, SANDWICH, amount)
+    fn transfers(amount: u8) -> Vec<u8> {
+        let mut code = vec![opcode::PUSH1, amount, opcode::PUSH1, 0x00, opcode::MSTORE];
+        for address in [SANDWICH, POOL] {
+            code.push(opcode::PUSH20);
+            code.extend(B160::from_low_u64_be(address).as_bytes());
+        }
+        code.push(opcode::PUSH32);
+        code.extend(keccak256("Transfer(address,address,uint256)"));
+        code.extend([
+            opcode::PUSH1,
+            0x20,
+            opcode::PUSH1,
+            0x00,
+            opcode::LOG3,
+            opcode::STOP,
+        ]);
+        code
+    }
+
+    // slot holding our contract's balance in a `mapping(address => uint256)` at index 0
+    fn balance_slot() -> rU256 {
+        rU256::from_be_bytes(keccak256(abi::encode(&[
+            Token::Address(Address::from_low_u64_be(SANDWICH)),
+            Token::Uint(U256::zero()),
+        ])))
+    }
+
+    // run `code` as TOKEN and return findings (policy without actions so nothing is rejected)
+    fn findings(
+        code: Vec<u8>,
+        sandwich_contract: u64,
+        setup: impl FnOnce(&mut SalmonellaInspectoooor),
+    ) -> Vec<Salmonella> {
+        let token = B160::from_low_u64_be(TOKEN);
+        let mut db = CacheDB::new(EmptyDB::default());
+        db.insert_account_info(
+            token,
+            AccountInfo::new(rU256::ZERO, 0, Bytecode::new_raw(code.into())),
+        );
+
+        let mut evm = revm::EVM::new();
+        evm.database(db);
+        evm.env.tx.caller = B160::from_low_u64_be(CALLER);
+        evm.env.tx.transact_to = TransactTo::Call(token);
+        evm.env.tx.gas_limit = 100_000;
+
+        let config: SafetyPolicyConfig =
+            serde_json::from_str(r#"{ "opcodes": {}, "actions": [] }"#).unwrap();
+        let policy = SafetyPolicy::from_config(config).unwrap();
+        let mut inspector = SalmonellaInspectoooor::with_policy(
+            Address::from_low_u64_be(sandwich_contract),
+            Arc::new(policy),
+        );
+        setup(&mut inspector);
+        // findings are collected whether or not the code reverts
+        evm.inspect_commit(&mut inspector).unwrap();
+
+        match inspector.is_sando_safu() {
+            IsSandoSafu::Safu(report) => report.findings,
+            verdict => panic!("expected Safu, got {:?}", verdict),
+        }
+    }
+
+    #[test]
+    fn flags_caller_branch() {
+        for guarded in [transfers(1), reverts()] {
+            let found = findings(branches_on_caller(0, guarded), SANDWICH, |_| {});
+            assert!(matches!(
+                found.as_slice(),
+                [Salmonella::IdentityBranch { contract, opcode: loaded }]
+                    if *contract == B160::from_low_u64_be(TOKEN) && loaded.code() == opcode::CALLER
+            ));
+        }
+
+        // owner only setter
+        let setter = writes_slot(rU256::from(1));
+        assert_eq!(
+            findings(branches_on_caller(0, setter), SANDWICH, |_| {}).len(),
+            0
+        );
+        // branch is too far from loading the caller
+        let padding = IDENTITY_BRANCH_WINDOW as usize - 3;
+        assert_eq!(
+            findings(branches_on_caller(padding, transfers(1)), SANDWICH, |_| {}).len(),
+            0
+        );
+        // our own contract is allowed to check its caller
+        assert_eq!(
+            findings(branches_on_caller(0, transfers(1)), TOKEN, |_| {}).len(),
+            0
+        );
+    }
+
+    #[test]
+    fn flags_gas_check() {
+        // every call forwards gas with a GAS of its own
+        assert_eq!(findings(calls(3, 0), SANDWICH, |_| {}).len(), 0);
+
+        let found = findings(calls(1, 2), SANDWICH, |_| {});
+        assert!(matches!(found.as_slice(), [Salmonella::GasCheck]));
+    }
+
+    #[test]
+    fn flags_untrusted_balance_write() {
+        let found = findings(writes_slot(balance_slot()), SANDWICH, |_| {});
+        assert!(matches!(
+            found.as_slice(),
+            [Salmonella::ThirdPartyBalanceWrite { contract, writer }]
+                if *contract == B160::from_low_u64_be(TOKEN)
+                    && *writer == B160::from_low_u64_be(CALLER)
+        ));
+
+        // any other slot
+        let found = findings(writes_slot(rU256::from(1)), SANDWICH, |_| {});
+        assert_eq!(found.len(), 0);
+
+        // token transfer we expect
+        let found = findings(writes_slot(balance_slot()), SANDWICH, |inspector| {
+            inspector.expect_transfer(
+                Address::from_low_u64_be(TOKEN),
+                Address::from_low_u64_be(CALLER),
+                Address::from_low_u64_be(SANDWICH),
+                U256::one(),
+            )
+        });
+        assert_eq!(found.len(), 0);
+    }
+
+    #[test]
+    fn flags_transfer_tax() {
+        let expect_100 = |inspector: &mut SalmonellaInspectoooor| {
+            inspector.expect_transfer(
+                Address::from_low_u64_be(TOKEN),
+                Address::from_low_u64_be(POOL),
+                Address::from_low_u64_be(SANDWICH),
+                U256::from(100),
+            )
+        };
+
+        let found = findings(transfers(90), SANDWICH, expect_100);
+        assert!(matches!(
+            found.as_slice(),
+            [Salmonella::TransferTax { token, to, expected, received }]
+                if *token == B160::from_low_u64_be(TOKEN)
+                    && *to == B160::from_low_u64_be(SANDWICH)
+                    && *expected == rU256::from(100)
+                    && *received == rU256::from(90)
+        ));
+
+        assert_eq!(findings(transfers(100), SANDWICH, expect_100).len(), 0);
+        // transfers nobody expects are not checked
+        assert_eq!(findings(transfers(90), SANDWICH, |_| {}).len(), 0);
+    }
+}
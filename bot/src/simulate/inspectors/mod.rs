@@ -1,2 +1,3 @@
 pub mod access_list;
+pub mod call_depth;
 pub mod is_sando_safu;
@@ -1,11 +1,13 @@
 use ethers::prelude::*;
 use revm::primitives::{ExecutionResult, Output, TransactTo, B160 as rAddress, U256 as rU256};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
 
 use crate::prelude::access_list::AccessListInspector;
+use crate::prelude::access_list_pruner::prune_access_list;
 use crate::prelude::fork_db::ForkDB;
 use crate::prelude::fork_factory::ForkFactory;
-use crate::prelude::is_sando_safu::{IsSandoSafu, SalmonellaInspectoooor};
+use crate::prelude::gas_estimator::estimate_gas_limit;
+use crate::prelude::is_sando_safu::{IsSandoSafu, Salmonella, SalmonellaInspectoooor};
 use crate::prelude::sandwich_types::RawIngredients;
 use crate::prelude::{
     convert_access_list, get_amount_out_evm_v2, get_amount_out_evm_v3, get_balance_of_evm,
@@ -13,16 +15,19 @@ use crate::prelude::{
 };
 use crate::types::sandwich_types::OptimalRecipe;
 use crate::types::{BlockInfo, SimulationError};
-use crate::utils::constants::{get_end_of_multi_payload, get_weth_address};
+use crate::utils::constants::get_weth_address;
 use crate::utils::dotenv;
 use crate::utils::tx_builder::{self, braindance, SandwichMaker};
 use hex::ToHex;
 
 use super::{
-    attach_braindance_module, braindance_address, braindance_controller_address,
+    allocator, attach_braindance_module, braindance_address, braindance_controller_address,
     braindance_starting_balance, setup_block_state,
 };
 
+// max weth lost buying and selling right back from a fresh address before the token counts as taxed
+const MAX_ROUND_TRIP_LOSS_BPS: u64 = 500;
+
 // Calculate amount in that produces highest revenue and performs honeypot checks
 //
 // Arguments:
@@ -42,48 +47,22 @@ pub async fn create_optimal_sandwich(
     fork_factory: &mut ForkFactory,
     sandwich_maker: &SandwichMaker,
 ) -> Result<OptimalRecipe, SimulationError> {
-    let mut optimals: Vec<U256> = vec![];
-    let mut upper_bound = sandwich_balance;
-
     #[cfg(test)]
     {
         // if running test, setup contract sandwich to allow for backtest
         // can also inject new sandwich code for testing
-        crate::prelude::inject_sando(fork_factory, upper_bound);
-    }
-    let mut good_ingredients: Vec<RawIngredients> = vec![];
-    for ingredients in multi_ingredients.iter() {
-        let optimal = juiced_quadratic_search(
-            ingredients,
-            U256::zero(),
-            upper_bound,
-            next_block,
-            fork_factory,
-        )
-        .await?;
-        if optimal.is_zero() {
-            continue;
-        }
-        upper_bound = match upper_bound.checked_sub(optimal) {
-            Some(amount) => amount,
-            None => U256::zero(),
-        };
-        good_ingredients.push(ingredients.to_owned());
-        optimals.push(optimal);
-    }
-
-    if good_ingredients.is_empty() {
-        return Err(SimulationError::ZeroOptimal());
+        crate::prelude::inject_sando(fork_factory, sandwich_balance);
     }
 
-    sanity_check(
+    // pools share the same weth balance, so amounts (and which pools/victims to include) are picked jointly
+    allocator::create_joint_sandwich(
+        multi_ingredients,
         sandwich_balance,
-        optimals,
-        &mut good_ingredients,
         next_block,
+        fork_factory,
         sandwich_maker,
-        fork_factory.new_sandbox_fork(),
     )
+    .await
 }
 
 // Roided implementation of https://research.ijcaonline.org/volume65/number14/pxc3886165.pdf
@@ -97,15 +76,15 @@ pub async fn create_optimal_sandwich(
 // * `fork_factory`: used to create new forked evm instances for simulations
 //
 // Returns:
-// Ok(U256): optimal amount in, if no errors during calculation
+// Ok((U256, U256)): optimal amount in and its revenue, if no errors during calculation
 // Err(SimulationError): if error during calculation
-async fn juiced_quadratic_search(
+pub(super) async fn juiced_quadratic_search(
     ingredients: &RawIngredients,
     mut lower_bound: U256,
     mut upper_bound: U256,
     next_block: &BlockInfo,
     mut fork_factory: &mut ForkFactory,
-) -> Result<U256, SimulationError> {
+) -> Result<(U256, U256), SimulationError> {
     //
     //            [EXAMPLE WITH 10 BOUND INTERVALS]
     //
@@ -129,7 +108,9 @@ async fn juiced_quadratic_search(
     let base = U256::from(1000000u64);
     let tolerance = U256::from(1u64);
 
-    let tolerance = (tolerance * ((upper_bound + lower_bound) / 2)) / base;
+    // contract can't receive amounts finer than the weth encoding, no point searching below it
+    let tolerance = ((tolerance * ((upper_bound + lower_bound) / 2)) / base)
+        .max(tx_builder::sandwich::get_weth_encode_divisor());
 
     // initialize variables for search
     let left_interval_lower = |i: usize, intervals: &Vec<U256>| intervals[i - 1].clone() + 1;
@@ -150,6 +131,7 @@ async fn juiced_quadratic_search(
         false
     };
     let mut highest_sando_input = U256::zero();
+    let mut highest_sando_revenue = U256::zero();
     let number_of_intervals = 15;
     let mut counter = 0;
 
@@ -160,11 +142,13 @@ async fn juiced_quadratic_search(
             break;
         }
 
-        // split search range into intervals
+        // split search range into intervals (only amounts that can be encoded for the contract)
         let mut intervals = Vec::new();
         for i in 0..=number_of_intervals {
-            intervals.push(lower_bound + (((upper_bound - lower_bound) * i) / number_of_intervals));
+            let bound = lower_bound + (((upper_bound - lower_bound) * i) / number_of_intervals);
+            intervals.push(encoded_weth(bound, ingredients));
         }
+        intervals.dedup();
 
         // calculate revenue at each interval concurrently
         let mut revenues = Vec::new();
@@ -186,19 +170,20 @@ async fn juiced_quadratic_search(
             .collect::<Vec<_>>();
 
         // find interval that produces highest revenue
-        let (highest_revenue_index, _highest_revenue) = revenues
+        let (highest_revenue_index, highest_revenue) = revenues
             .iter()
             .enumerate()
             .max_by(|(_, a), (_, b)| a.cmp(&b))
             .unwrap();
 
         highest_sando_input = intervals[highest_revenue_index];
+        highest_sando_revenue = *highest_revenue;
 
         // enhancement: find better way to increase finding opps incase of all rev=0
         if revenues[highest_revenue_index] == U256::zero() {
             // most likely there is no sandwich possibility
             if counter == 10 {
-                return Ok(U256::zero());
+                return Ok((U256::zero(), U256::zero()));
             }
             // no revenue found, most likely small optimal so decrease range
             upper_bound = intervals[intervals.len() / 3]
@@ -224,7 +209,7 @@ async fn juiced_quadratic_search(
         upper_bound = right_interval_upper(highest_revenue_index, &intervals);
     }
 
-    Ok(highest_sando_input)
+    Ok((highest_sando_input, highest_sando_revenue))
 }
 
 // Perform simulation using sandwich contract and check for salmonella
@@ -240,7 +225,7 @@ async fn juiced_quadratic_search(
 // Returns:
 // Ok(OptimalRecipe): params to pass to sandwich contract to capture opportunity
 // Err(SimulationError): error encountered during simulation
-fn sanity_check(
+pub(super) fn sanity_check(
     sandwich_start_balance: U256,
     frontrun_ins: Vec<U256>,
     multi_ingredients: &mut [RawIngredients],
@@ -248,24 +233,34 @@ fn sanity_check(
     sandwich_maker: &SandwichMaker,
     fork_db: ForkDB,
 ) -> Result<OptimalRecipe, SimulationError> {
-    // setup evm simulation
+    // setup evm simulation (probes run on untouched copies of the fork)
+    let probe_db = fork_db.clone();
     let mut evm = revm::EVM::new();
     evm.database(fork_db);
     setup_block_state(&mut evm, &next_block);
     let weth_address = get_weth_address();
     let searcher = dotenv::get_searcher_wallet().address();
     let sandwich_contract = dotenv::get_sandwich_contract_address();
-    let end_of_multi_payload = get_end_of_multi_payload();
+    let end_of_multi_payload = sandwich_maker.jump_table.end_of_multi_payload();
+    let gas_limit_margin_bps = dotenv::get_gas_limit_margin_bps();
     let mut frontrun_data: Vec<u8> = Vec::new();
     let mut frontrun_value: U256 = U256::from(0);
     let ingredients_len: u64 = multi_ingredients.len() as u64;
     let is_multiple = ingredients_len > 1;
-    let mut backrun_ins: Vec<U256> = vec![];
+    // indexed by pool, `None` for pools whose frontrun encodes to zero
+    let mut backrun_ins: Vec<Option<U256>> = vec![];
+    // intermediary tokens the pool would have sent if amounts weren't truncated by encoding
+    let mut unclaimed_frontrun_outs: Vec<U256> = vec![];
+    // (token, balance sandwich contract should hold after frontrun)
+    let mut expected_balances: Vec<(Address, U256)> = vec![];
+    let mut salmonella_inspector = SalmonellaInspectoooor::new(sandwich_contract);
+    // highest bribe haircut asked for by the safety policy
+    let mut bribe_haircut_bps = 0;
     let block_number = U256::from(next_block.number.as_u64());
 
     // prepare frontrun data and value
     for (index, ingredients) in multi_ingredients.iter_mut().enumerate() {
-        let is_first = index == 0;
+        let is_first = frontrun_data.is_empty();
         let pool_variant = ingredients.target_pool.pool_variant;
 
         // *´:°•.°+.*•´.*:˚.°*.˚•´.°:°•.°•.*•´.*:˚.°*.˚•´.°:°•.°+.*•´.*:*/
@@ -273,11 +268,10 @@ fn sanity_check(
         // *.•°:°.´+˚.*°.˚:*.´•*.+°.•°:´*.´•*.•°.•°:°.´:•˚°.*°.˚:*.´+°.•*/
         //
         // encode frontrun_in before passing to sandwich contract
-        let frontrun_in = match pool_variant {
-            PoolVariant::UniswapV2 => tx_builder::v2::encode_weth(frontrun_ins[index]),
-            PoolVariant::UniswapV3 => tx_builder::v3::encode_weth(frontrun_ins[index]),
-        };
+        let frontrun_in = encoded_weth(frontrun_ins[index], ingredients);
         if frontrun_in.is_zero() {
+            backrun_ins.push(None);
+            unclaimed_frontrun_outs.push(U256::zero());
             continue;
         }
 
@@ -294,21 +288,33 @@ fn sanity_check(
         if token_out_balance > U256::zero() {
             ingredients.target_pool.has_dust = true;
         }
-        let frontrun_out = match pool_variant {
+        let amount_out = match pool_variant {
             PoolVariant::UniswapV2 => {
                 let target_pool = ingredients.target_pool.address;
-                let amount_out =
-                    get_amount_out_evm_v2(frontrun_in, target_pool, token_in, token_out, &mut evm)?;
-                tx_builder::v2::decode_intermediary(amount_out, true, token_out)
+                get_amount_out_evm_v2(frontrun_in, target_pool, token_in, token_out, &mut evm)?
             }
             PoolVariant::UniswapV3 => {
                 let swap_fee = ingredients.target_pool.swap_fee;
-                let amount_out =
-                    get_amount_out_evm_v3(frontrun_in, token_in, token_out, swap_fee, &mut evm)?;
-                tx_builder::v3::decode_intermediary(amount_out)
+                get_amount_out_evm_v3(frontrun_in, token_in, token_out, swap_fee, &mut evm)?
             }
         };
-        backrun_ins.push(frontrun_out);
+        // contract can only ask for the encoded amount, the rest stays in the pool
+        let frontrun_out = encoded_frontrun_out(amount_out, ingredients);
+        backrun_ins.push(Some(frontrun_out));
+        unclaimed_frontrun_outs.push(amount_out - frontrun_out);
+        expected_balances.push((token_out, token_out_balance + frontrun_out));
+        salmonella_inspector.expect_transfer(
+            token_out,
+            ingredients.target_pool.address,
+            sandwich_contract,
+            frontrun_out,
+        );
+        let probe_in = frontrun_ins[index].min(braindance_starting_balance());
+        if let Some(finding) =
+            probe_fresh_address_sell(probe_in, ingredients, next_block, probe_db.clone())
+        {
+            salmonella_inspector.add_finding(finding);
+        }
         // create tx.data and tx.value for frontrun_in
         let (data, value) = match pool_variant {
             PoolVariant::UniswapV2 => {
@@ -375,12 +381,17 @@ fn sanity_check(
     evm.inspect_ref(&mut access_list_inspector)
         .map_err(|e| SimulationError::FrontrunEvmError(e))
         .unwrap();
-    let frontrun_access_list = access_list_inspector.into_access_list();
-    evm.env.tx.access_list = frontrun_access_list.clone();
+    // only keep entries that save gas in bundle context
+    let frontrun_access_list =
+        prune_access_list(&mut evm, access_list_inspector.into_access_list())
+            .map_err(SimulationError::FrontrunEvmError)?;
+    let frontrun_access_list_gas_saved = frontrun_access_list.gas_saved;
+    let frontrun_access_list = frontrun_access_list.access_list;
+    let frontrun_gas_limit = estimate_gas_limit(&mut evm, gas_limit_margin_bps)
+        .map_err(SimulationError::FrontrunEvmError)?;
 
     // run again but now with access list (so that we get accurate gas used)
-    // run with a salmonella inspector to flag `suspicious` opcodes
-    let mut salmonella_inspector = SalmonellaInspectoooor::new();
+    // run with a salmonella inspector to flag honeypot behaviour
     let frontrun_result = match evm.inspect_commit(&mut salmonella_inspector) {
         Ok(result) => result,
         Err(e) => return Err(SimulationError::FrontrunEvmError(e)),
@@ -398,9 +409,15 @@ fn sanity_check(
         }
     };
     match salmonella_inspector.is_sando_safu() {
-        IsSandoSafu::Safu => { /* continue operation */ }
-        IsSandoSafu::NotSafu(not_safu_opcodes) => {
-            return Err(SimulationError::FrontrunNotSafu(not_safu_opcodes))
+        IsSandoSafu::Safu(_) => { /* continue operation */ }
+        IsSandoSafu::Haircut(_, bps) => bribe_haircut_bps = bribe_haircut_bps.max(bps),
+        IsSandoSafu::NotSafu(report) => return Err(SimulationError::FrontrunNotSafu(report)),
+    }
+    // tokens that take a fee on transfer leave sandwich contract with less than the pool sent
+    for (token, expected_balance) in expected_balances {
+        let balance = get_balance_of_evm(token, sandwich_contract, next_block, &mut evm)?;
+        if balance < expected_balance {
+            return Err(SimulationError::TaxDetected(token));
         }
     }
 
@@ -449,8 +466,9 @@ fn sanity_check(
             }
         }
     }
-    good_meats.sort_by(|a, b| a.hash.cmp(&b.hash));
-    good_meats.dedup();
+    // keep the simulated order, the bundle has to include meats in the same order
+    let mut seen_meats = HashSet::new();
+    good_meats.retain(|meat: &Transaction| seen_meats.insert(meat.hash));
     if good_meats.is_empty() {
         return Err(SimulationError::NoMeat());
     }
@@ -459,34 +477,44 @@ fn sanity_check(
     // *.•°:°.´+˚.*°.˚:*.´•*.+°.•°:´*.´•*.•°.•°:°.´:•˚°.*°.˚:*.´+°.•*/
     let mut backrun_data: Vec<u8> = Vec::new();
     let mut backrun_value: U256 = U256::from(0);
+    // expected revenue lost because amounts are truncated when encoded for the contract
+    let mut encoding_loss = U256::zero();
+    let mut salmonella_inspector = SalmonellaInspectoooor::new(sandwich_contract);
     for (index, ingredients) in multi_ingredients.iter().enumerate() {
-        let is_first = index == 0;
+        let frontrun_out = match backrun_ins[index] {
+            Some(frontrun_out) => frontrun_out,
+            None => continue,
+        };
+        let is_first = backrun_data.is_empty();
         // encode backrun_in before passing to sandwich contract
         let token_in = ingredients.intermediary_token;
         let token_out = ingredients.startend_token;
         // let balance = get_balance_of_evm(token_in, sandwich_contract, next_block, &mut evm)?;
         let pool_variant = ingredients.target_pool.pool_variant;
-        let backrun_in = match pool_variant {
-            PoolVariant::UniswapV2 => {
-                tx_builder::v2::encode_intermediary_token(backrun_ins[index], false, token_in)
-            }
-            PoolVariant::UniswapV3 => tx_builder::v3::encode_intermediary_token(backrun_ins[index]),
-        };
+        let backrun_in = encoded_backrun_in(frontrun_out, ingredients);
+        salmonella_inspector.expect_transfer(
+            token_in,
+            sandwich_contract,
+            ingredients.target_pool.address,
+            backrun_in,
+        );
         // caluclate backrun_out using encoded backrun_in
-        let backrun_out = match pool_variant {
+        let amount_out = match pool_variant {
             PoolVariant::UniswapV2 => {
                 let target_pool = ingredients.target_pool.address;
-                let amount_out =
-                    get_amount_out_evm_v2(backrun_in, target_pool, token_in, token_out, &mut evm)?;
-                tx_builder::v2::encode_weth(amount_out)
+                get_amount_out_evm_v2(backrun_in, target_pool, token_in, token_out, &mut evm)?
             }
             PoolVariant::UniswapV3 => {
                 let swap_fee = ingredients.target_pool.swap_fee;
-                let amount_out =
-                    get_amount_out_evm_v3(backrun_in, token_in, token_out, swap_fee, &mut evm)?;
-                tx_builder::v3::encode_weth(amount_out)
+                get_amount_out_evm_v3(backrun_in, token_in, token_out, swap_fee, &mut evm)?
             }
         };
+        let backrun_out = encoded_weth(amount_out, ingredients);
+        // weth left in the pool plus unclaimed frontrun tokens valued at the backrun price
+        encoding_loss += amount_out - backrun_out;
+        if !backrun_in.is_zero() {
+            encoding_loss += unclaimed_frontrun_outs[index] * amount_out / backrun_in;
+        }
         // create tx.data and tx.value for backrun_in
         let (data, value) = match pool_variant {
             PoolVariant::UniswapV2 => {
@@ -551,12 +579,16 @@ fn sanity_check(
     evm.inspect_ref(&mut access_list_inspector)
         .map_err(|e| SimulationError::BackrunEvmError(e))
         .unwrap();
-    let backrun_access_list = access_list_inspector.into_access_list();
-    evm.env.tx.access_list = backrun_access_list.clone();
+    // only keep entries that save gas in bundle context
+    let backrun_access_list = prune_access_list(&mut evm, access_list_inspector.into_access_list())
+        .map_err(SimulationError::BackrunEvmError)?;
+    let backrun_access_list_gas_saved = backrun_access_list.gas_saved;
+    let backrun_access_list = backrun_access_list.access_list;
+    let backrun_gas_limit = estimate_gas_limit(&mut evm, gas_limit_margin_bps)
+        .map_err(SimulationError::BackrunEvmError)?;
 
     // run again but now with access list (so that we get accurate gas used)
-    // run with a salmonella inspector to flag `suspicious` opcodes
-    let mut salmonella_inspector = SalmonellaInspectoooor::new();
+    // run with a salmonella inspector to flag honeypot behaviour
     let backrun_result = match evm.inspect_commit(&mut salmonella_inspector) {
         Ok(result) => result,
         Err(e) => {
@@ -576,10 +608,9 @@ fn sanity_check(
         ExecutionResult::Halt { reason, .. } => return Err(SimulationError::BackrunHalted(reason)),
     };
     match salmonella_inspector.is_sando_safu() {
-        IsSandoSafu::Safu => { /* continue operation */ }
-        IsSandoSafu::NotSafu(not_safu_opcodes) => {
-            return Err(SimulationError::BackrunNotSafu(not_safu_opcodes))
-        }
+        IsSandoSafu::Safu(_) => { /* continue operation */ }
+        IsSandoSafu::Haircut(_, bps) => bribe_haircut_bps = bribe_haircut_bps.max(bps),
+        IsSandoSafu::NotSafu(report) => return Err(SimulationError::BackrunNotSafu(report)),
     }
 
     let backrun_gas_used = backrun_result.gas_used();
@@ -616,30 +647,185 @@ fn sanity_check(
 
     let target_pools = multi_ingredients.iter().map(|x| x.target_pool).collect();
 
-    Ok(OptimalRecipe::new(
+    let mut recipe = OptimalRecipe::new(
         frontrun_data.into(),
         frontrun_value,
         frontrun_gas_used,
+        frontrun_gas_limit,
         convert_access_list(frontrun_access_list),
         backrun_data.into(),
         backrun_value,
         backrun_gas_used,
+        backrun_gas_limit,
         convert_access_list(backrun_access_list),
         good_meats,
         revenue,
         target_pools,
         combined_state_diffs.clone(),
-    ))
+    );
+    recipe.bribe_haircut_bps = bribe_haircut_bps;
+    recipe.access_list_gas_saved = frontrun_access_list_gas_saved + backrun_access_list_gas_saved;
+    recipe.encoding_loss = encoding_loss;
+
+    Ok(recipe)
+}
+
+// Weth amount the sandwich contract swaps (weth amounts are encoded in units of 2^32)
+pub(super) fn encoded_weth(amount: U256, ingredients: &RawIngredients) -> U256 {
+    match ingredients.target_pool.pool_variant {
+        PoolVariant::UniswapV2 => tx_builder::v2::encode_weth(amount),
+        PoolVariant::UniswapV3 => tx_builder::v3::encode_weth(amount),
+    }
+}
+
+// Intermediary amount the sandwich contract asks the pool for in the frontrun
+fn encoded_frontrun_out(amount_out: U256, ingredients: &RawIngredients) -> U256 {
+    match ingredients.target_pool.pool_variant {
+        PoolVariant::UniswapV2 => {
+            tx_builder::v2::decode_intermediary(amount_out, true, ingredients.intermediary_token)
+        }
+        PoolVariant::UniswapV3 => tx_builder::v3::decode_intermediary(amount_out),
+    }
+}
+
+// Intermediary amount the sandwich contract sells in the backrun (keeps some dust)
+fn encoded_backrun_in(amount_in: U256, ingredients: &RawIngredients) -> U256 {
+    match ingredients.target_pool.pool_variant {
+        PoolVariant::UniswapV2 => tx_builder::v2::encode_intermediary_token(
+            amount_in,
+            false,
+            ingredients.intermediary_token,
+        ),
+        PoolVariant::UniswapV3 => tx_builder::v3::encode_intermediary_token(amount_in),
+    }
+}
+
+// Buy token from a fresh address (braindance) and sell it right back, honeypots that only let
+// whitelisted holders sell or tax sells can't be caught by simulating our own contract
+//
+// Arguments:
+// `amount_in`: amount of weth to buy with
+// `ingredients`: holds information about opportunity
+// `next_block`: holds information about next block
+// `fork_db`: fork db used for evm simulations, with braindance module attached
+//
+// Returns:
+// Some(Salmonella): if selling reverted or lost more than `MAX_ROUND_TRIP_LOSS_BPS`
+// None: if round trip went through (or buying failed, which the sandwich itself will show)
+fn probe_fresh_address_sell(
+    amount_in: U256,
+    ingredients: &RawIngredients,
+    next_block: &BlockInfo,
+    fork_db: ForkDB,
+) -> Option<Salmonella> {
+    let mut evm = revm::EVM::new();
+    evm.database(fork_db);
+    setup_block_state(&mut evm, next_block);
+
+    let weth = ingredients.startend_token;
+    let token = ingredients.intermediary_token;
+    let (_, bought) =
+        braindance_swap(amount_in, ingredients, weth, token, next_block, &mut evm).ok()?;
+    if amount_in.is_zero() || bought.is_zero() {
+        return None;
+    }
+
+    let token: rAddress = token.0.into();
+    let weth_balance = match braindance_swap(
+        bought,
+        ingredients,
+        ingredients.intermediary_token,
+        weth,
+        next_block,
+        &mut evm,
+    ) {
+        Ok((_, weth_balance)) => weth_balance,
+        // a balance too large to swap can't be sold either
+        Err(SimulationError::EvmReverted(_))
+        | Err(SimulationError::EvmHalted(_))
+        | Err(SimulationError::AmountOverflow(_)) => {
+            return Some(Salmonella::FreshSellReverted { token })
+        }
+        Err(_) => return None,
+    };
+
+    let weth_back = weth_balance.saturating_sub(braindance_starting_balance() - amount_in);
+    let loss_bps = (amount_in.saturating_sub(weth_back) * 10000 / amount_in).as_u64();
+    match loss_bps > MAX_ROUND_TRIP_LOSS_BPS {
+        true => Some(Salmonella::FreshSellLoss { token, loss_bps }),
+        false => None,
+    }
+}
+
+// Swap through braindance on `ingredients.target_pool` and commit the result
+//
+// Returns:
+// Ok((amount_out, balance_after)): amount the pool sent and braindance's `token_out` balance after the swap
+// Err(SimulationError): if swap failed
+fn braindance_swap(
+    amount_in: U256,
+    ingredients: &RawIngredients,
+    token_in: Address,
+    token_out: Address,
+    next_block: &BlockInfo,
+    evm: &mut revm::EVM<ForkDB>,
+) -> Result<(U256, U256), SimulationError> {
+    let pool_variant = ingredients.target_pool.pool_variant;
+    let data = match pool_variant {
+        PoolVariant::UniswapV2 => braindance::build_swap_v2_data(
+            amount_in,
+            ingredients.target_pool.address,
+            token_in,
+            token_out,
+        ),
+        // amount is read from a token balance, which can be anything for a malicious token
+        PoolVariant::UniswapV3 if amount_in > U256::from(u128::MAX) => {
+            return Err(SimulationError::AmountOverflow(amount_in))
+        }
+        PoolVariant::UniswapV3 => braindance::build_swap_v3_data(
+            amount_in.as_u128().into(),
+            ingredients.target_pool.address,
+            token_in,
+            token_out,
+        ),
+    };
+
+    evm.env.tx.caller = braindance_controller_address();
+    evm.env.tx.transact_to = TransactTo::Call(braindance_address().0.into());
+    evm.env.tx.data = data.0;
+    evm.env.tx.value = rU256::ZERO;
+    evm.env.tx.gas_limit = 700000;
+    evm.env.tx.gas_price = next_block.base_fee.into();
+
+    let output = match evm.transact_commit() {
+        Ok(ExecutionResult::Success { output, .. }) => match output {
+            Output::Call(o) => o,
+            Output::Create(o, _) => o,
+        },
+        Ok(ExecutionResult::Revert { output, .. }) => {
+            return Err(SimulationError::EvmReverted(output))
+        }
+        Ok(ExecutionResult::Halt { reason, .. }) => return Err(SimulationError::EvmHalted(reason)),
+        Err(e) => return Err(SimulationError::EvmError(e)),
+    };
+    match pool_variant {
+        PoolVariant::UniswapV2 => braindance::decode_swap_v2_result(output.into()),
+        PoolVariant::UniswapV3 => braindance::decode_swap_v3_result(output.into()),
+    }
+    .map_err(SimulationError::FailedToDecodeOutput)
 }
 
 /// Sandwich simulation using BrainDance contract (modified router contract)
 ///
+/// Swaps the amounts the sandwich contract would after encoding, so revenue matches what the
+/// contract captures (weth and tokens truncated by encoding are not counted)
+///
 /// Arguments:
-/// * `frontrun_in`: amount of to frontrun with
+/// * `frontrun_in`: amount of to frontrun with (rounded down to an encodable amount)
 /// * `ingredients`: ingredients of the sandwich
 /// * `next_block`: block info of the next block
 /// * `fork_db`: database instance used for evm simulations
-async fn evaluate_sandwich_revenue(
+pub(super) async fn evaluate_sandwich_revenue(
     frontrun_in: U256,
     ingredients: RawIngredients,
     next_block: BlockInfo,
@@ -650,6 +836,7 @@ async fn evaluate_sandwich_revenue(
     setup_block_state(&mut evm, &next_block);
 
     let pool_variant = ingredients.target_pool.pool_variant;
+    let frontrun_in = encoded_weth(frontrun_in, &ingredients);
 
     /*´:°•.°+.*•´.*:˚.°*.˚•´.°:°•.°•.*•´.*:˚.°*.˚•´.°:°•.°+.*•´.*:*/
     /*                    FRONTRUN TRANSACTION                    */
@@ -692,7 +879,7 @@ async fn evaluate_sandwich_revenue(
             return Err(SimulationError::FrontrunHalted(reason))
         }
     };
-    let (_frontrun_out, backrun_in) = match pool_variant {
+    let (_frontrun_out, frontrun_balance) = match pool_variant {
         PoolVariant::UniswapV2 => {
             match tx_builder::braindance::decode_swap_v2_result(output.into()) {
                 Ok(output) => output,
@@ -707,6 +894,12 @@ async fn evaluate_sandwich_revenue(
         }
     };
 
+    // sell what the contract would hold after the frontrun, minus the dust it keeps
+    let backrun_in = encoded_backrun_in(
+        encoded_frontrun_out(frontrun_balance, &ingredients),
+        &ingredients,
+    );
+
     /*´:°•.°+.*•´.*:˚.°*.˚•´.°:°•.°•.*•´.*:˚.°*.˚•´.°:°•.°+.*•´.*:*/
     /*                     MEAT TRANSACTION/s                     */
     /*.•°:°.´+˚.*°.˚:*.´•*.+°.•°:´*.´•*.•°.•°:°.´:•˚°.*°.˚:*.´+°.•*/
@@ -735,7 +928,11 @@ async fn evaluate_sandwich_revenue(
             }
         }
 
-        let _res = evm.transact_commit();
+        // victims have to survive the frontrun, otherwise the bundle is useless
+        match evm.transact_commit() {
+            Ok(result) if result.is_success() => {}
+            _ => return Err(SimulationError::MeatReverted(meat.hash)),
+        }
     }
 
     /*´:°•.°+.*•´.*:˚.°*.˚•´.°:°•.°•.*•´.*:˚.°*.˚•´.°:°•.°+.*•´.*:*/
@@ -777,7 +974,7 @@ async fn evaluate_sandwich_revenue(
         }
         ExecutionResult::Halt { reason, .. } => return Err(SimulationError::BackrunHalted(reason)),
     };
-    let (_backrun_out, post_sandwich_balance) = match pool_variant {
+    let (backrun_out, post_sandwich_balance) = match pool_variant {
         PoolVariant::UniswapV2 => {
             match tx_builder::braindance::decode_swap_v2_result(output.into()) {
                 Ok(output) => output,
@@ -792,8 +989,10 @@ async fn evaluate_sandwich_revenue(
         }
     };
 
+    // contract only asks the pool for the encoded weth amount
+    let unclaimed_weth = backrun_out - encoded_weth(backrun_out, &ingredients);
     let revenue = post_sandwich_balance
-        .checked_sub(braindance_starting_balance())
+        .checked_sub(braindance_starting_balance() + unclaimed_weth)
         .unwrap_or_default();
 
     Ok(revenue)
@@ -871,6 +1070,7 @@ mod test {
             &combined_state_diffs,
             Some(BlockId::Number(BlockNumber::Number(fork_block_num.into()))),
             &ws_provider,
+            None,
         )
         .await
         .unwrap();
@@ -878,6 +1078,7 @@ mod test {
             ws_provider.clone(),
             initial_db,
             Some(fork_block_num.into()),
+            None,
         );
 
         match super::create_optimal_sandwich(
@@ -885,7 +1086,7 @@ mod test {
             ethers::utils::parse_ether("10").unwrap(),
             &testhelper::get_next_block_info(fork_block_num, &ws_provider).await,
             &mut db,
-            &SandwichMaker::new().await,
+            &SandwichMaker::new().await.unwrap(),
         )
         .await
         {
@@ -1,6 +1,12 @@
+pub mod access_list_pruner;
+pub mod allocator;
+pub mod gas_estimator;
 pub mod helpers;
 pub mod inspectors;
 pub mod make_sandwich;
+pub mod safety_policy;
+pub mod slippage_bound;
+pub mod victim_order;
 
 pub use helpers::*;
 pub use inspectors::*;
@@ -0,0 +1,535 @@
+use ethers::types::Address;
+use hashbrown::{HashMap, HashSet};
+use revm::interpreter::opcode;
+use revm::primitives::B160;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::SystemTime;
+
+use crate::prelude::is_sando_safu::Salmonella;
+use crate::types::SafetyPolicyError;
+use crate::utils;
+
+/// What to do with a sandwich once the salmonella confidence reaches `min_confidence`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SafetyAction {
+    /// drop the sandwich
+    Reject,
+    /// send the sandwich but cut the bribe by `bps` to price in the risk
+    Haircut { bps: u64 },
+    /// send the sandwich, only log the findings
+    LogOnly,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct ActionRule {
+    pub min_confidence: f64,
+    pub action: SafetyAction,
+}
+
+/// Opcodes that are not flagged when executed by `address` (all opcodes if `opcodes` is empty)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Exemption {
+    pub address: Address,
+    #[serde(default)]
+    pub opcodes: Vec<String>,
+}
+
+/// Severity (0..=1) of findings that are not an opcode
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct FindingSeverities {
+    /// more GAS than CALL opcodes, 0 disables the heuristic
+    pub gas_check: f64,
+    pub transfer_tax: f64,
+    pub origin_branch: f64,
+    pub caller_branch: f64,
+    pub third_party_balance_write: f64,
+    pub fresh_sell_reverted: f64,
+    pub fresh_sell_loss: f64,
+}
+
+impl Default for FindingSeverities {
+    fn default() -> Self {
+        Self {
+            gas_check: 0.4,
+            transfer_tax: 0.9,
+            origin_branch: 0.6,
+            caller_branch: 0.35,
+            third_party_balance_write: 0.8,
+            fresh_sell_reverted: 0.95,
+            fresh_sell_loss: 0.8,
+        }
+    }
+}
+
+/// On disk format of the safety policy
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SafetyPolicyConfig {
+    /// opcode name => severity, only listed opcodes are flagged (`UNKNOWN` covers undefined opcodes)
+    pub opcodes: BTreeMap<String, f64>,
+    pub findings: FindingSeverities,
+    pub exemptions: Vec<Exemption>,
+    /// highest matching `min_confidence` wins, no match means safu
+    pub actions: Vec<ActionRule>,
+}
+
+impl Default for SafetyPolicyConfig {
+    fn default() -> Self {
+        let opcodes = [
+            ("BALANCE", 0.1),
+            ("SELFBALANCE", 0.1),
+            ("EXTCODEHASH", 0.15),
+            ("BLOCKHASH", 0.2),
+            ("DIFFICULTY", 0.2),
+            ("GASLIMIT", 0.2),
+            ("BASEFEE", 0.2),
+            ("GASPRICE", 0.3),
+            ("CREATE", 0.3),
+            ("CREATE2", 0.3),
+            ("COINBASE", 0.4),
+            ("SELFDESTRUCT", 0.5),
+            ("UNKNOWN", 0.5),
+        ];
+
+        Self {
+            opcodes: opcodes
+                .into_iter()
+                .map(|(name, severity)| (name.to_string(), severity))
+                .collect(),
+            findings: FindingSeverities::default(),
+            exemptions: vec![],
+            actions: vec![ActionRule {
+                min_confidence: utils::dotenv::get_salmonella_min_confidence(),
+                action: SafetyAction::Reject,
+            }],
+        }
+    }
+}
+
+/// Decides how much each salmonella finding weighs and what happens to a sandwich because of them
+#[derive(Clone, Debug)]
+pub struct SafetyPolicy {
+    opcodes: HashMap<u8, f64>,
+    unknown_opcode: Option<f64>,
+    findings: FindingSeverities,
+    /// address => exempted opcodes (None exempts all opcodes)
+    exemptions: HashMap<B160, Option<HashSet<u8>>>,
+    /// sorted by `min_confidence` descending
+    actions: Vec<ActionRule>,
+}
+
+impl Default for SafetyPolicy {
+    fn default() -> Self {
+        Self::from_config(SafetyPolicyConfig::default()).expect("default safety policy is valid")
+    }
+}
+
+impl SafetyPolicy {
+    // Create a new `SafetyPolicy` from its config
+    //
+    // Returns:
+    // Ok(SafetyPolicy): policy with opcode names resolved
+    // Err(SafetyPolicyError): if an opcode is unknown or a severity is out of range
+    pub fn from_config(config: SafetyPolicyConfig) -> Result<Self, SafetyPolicyError> {
+        let mut opcodes = HashMap::new();
+        let mut unknown_opcode = None;
+        for (name, severity) in config.opcodes {
+            check_severity(&name, severity)?;
+            match name.to_uppercase().as_str() {
+                "UNKNOWN" => unknown_opcode = Some(severity),
+                _ => {
+                    opcodes.insert(opcode_from_name(&name)?, severity);
+                }
+            }
+        }
+
+        let findings = config.findings;
+        for (name, severity) in [
+            ("gas_check", findings.gas_check),
+            ("transfer_tax", findings.transfer_tax),
+            ("origin_branch", findings.origin_branch),
+            ("caller_branch", findings.caller_branch),
+            (
+                "third_party_balance_write",
+                findings.third_party_balance_write,
+            ),
+            ("fresh_sell_reverted", findings.fresh_sell_reverted),
+            ("fresh_sell_loss", findings.fresh_sell_loss),
+        ] {
+            check_severity(name, severity)?;
+        }
+
+        let mut exemptions: HashMap<B160, Option<HashSet<u8>>> = HashMap::new();
+        for exemption in config.exemptions {
+            let address: B160 = exemption.address.0.into();
+            if exemption.opcodes.is_empty() {
+                exemptions.insert(address, None);
+                continue;
+            }
+            let codes = exemption
+                .opcodes
+                .iter()
+                .map(|name| opcode_from_name(name))
+                .collect::<Result<HashSet<u8>, _>>()?;
+            if let Some(exempted) = exemptions.entry(address).or_insert(Some(HashSet::new())) {
+                exempted.extend(codes);
+            }
+        }
+
+        let mut actions = config.actions;
+        actions.sort_by(|a, b| b.min_confidence.total_cmp(&a.min_confidence));
+
+        Ok(Self {
+            opcodes,
+            unknown_opcode,
+            findings,
+            exemptions,
+            actions,
+        })
+    }
+
+    // Load a `SafetyPolicy` from a json file
+    pub fn load(path: &str) -> Result<Self, SafetyPolicyError> {
+        let reader = OpenOptions::new().read(true).open(path)?;
+        let config: SafetyPolicyConfig = serde_json::from_reader(reader)?;
+        Self::from_config(config)
+    }
+
+    // Severity of `code` executed by `contract`
+    //
+    // Returns:
+    // Some(f64): if opcode is flagged
+    // None: if opcode is not listed or `contract` is exempted from it
+    pub fn opcode_severity(&self, contract: B160, code: u8) -> Option<f64> {
+        let severity = self.listed_opcode(code)?;
+
+        match self.exemptions.get(&contract) {
+            Some(None) => None,
+            Some(Some(exempted)) if exempted.contains(&code) => None,
+            _ => Some(severity),
+        }
+    }
+
+    // Severity of `code` if it is listed in the policy
+    fn listed_opcode(&self, code: u8) -> Option<f64> {
+        match opcode::OPCODE_JUMPMAP[code as usize] {
+            Some(_) => self.opcodes.get(&code).copied(),
+            None => self.unknown_opcode,
+        }
+    }
+
+    // true if the "more GAS than CALL opcodes" heuristic is enabled
+    pub fn checks_gas(&self) -> bool {
+        self.findings.gas_check > 0.0
+    }
+
+    // How much `finding` on its own says about the token being a honeypot (0..=1)
+    pub fn severity(&self, finding: &Salmonella) -> f64 {
+        match finding {
+            Salmonella::SuspiciousOpcode(opcode) => {
+                self.listed_opcode(opcode.code()).unwrap_or_default()
+            }
+            Salmonella::GasCheck => self.findings.gas_check,
+            Salmonella::TransferTax { .. } => self.findings.transfer_tax,
+            Salmonella::IdentityBranch { opcode, .. } => match opcode.code() {
+                opcode::ORIGIN => self.findings.origin_branch,
+                _ => self.findings.caller_branch,
+            },
+            Salmonella::ThirdPartyBalanceWrite { .. } => self.findings.third_party_balance_write,
+            Salmonella::FreshSellReverted { .. } => self.findings.fresh_sell_reverted,
+            Salmonella::FreshSellLoss { .. } => self.findings.fresh_sell_loss,
+        }
+    }
+
+    // Action for a sandwich whose findings add up to `confidence`
+    //
+    // Returns:
+    // Some(SafetyAction): action of the rule with the highest `min_confidence` <= `confidence`
+    // None: if no rule matches (sandwich is safu)
+    pub fn action(&self, confidence: f64) -> Option<SafetyAction> {
+        self.actions
+            .iter()
+            .find(|rule| confidence >= rule.min_confidence)
+            .map(|rule| rule.action)
+    }
+}
+
+fn check_severity(name: &str, severity: f64) -> Result<(), SafetyPolicyError> {
+    match (0.0..=1.0).contains(&severity) {
+        true => Ok(()),
+        false => Err(SafetyPolicyError::InvalidSeverity(
+            name.to_string(),
+            severity,
+        )),
+    }
+}
+
+fn opcode_from_name(name: &str) -> Result<u8, SafetyPolicyError> {
+    let upper = name.to_uppercase();
+    // renamed in the merge
+    if upper == "PREVRANDAO" || upper == "DIFFICULTY" {
+        return Ok(opcode::DIFFICULTY);
+    }
+    (0..=u8::MAX)
+        .find(|code| opcode::OPCODE_JUMPMAP[*code as usize] == Some(upper.as_str()))
+        .ok_or_else(|| SafetyPolicyError::UnknownOpcode(name.to_string()))
+}
+
+fn policy_slot() -> &'static RwLock<Arc<SafetyPolicy>> {
+    static POLICY: OnceLock<RwLock<Arc<SafetyPolicy>>> = OnceLock::new();
+    POLICY.get_or_init(|| {
+        let path = utils::dotenv::get_safety_policy_file();
+        let policy = match Path::new(&path).exists() {
+            true => SafetyPolicy::load(&path).unwrap_or_else(|e| {
+                log::error!("Invalid safety policy {}, using default: {:?}", path, e);
+                SafetyPolicy::default()
+            }),
+            false => SafetyPolicy::default(),
+        };
+        RwLock::new(Arc::new(policy))
+    })
+}
+
+// Policy that new salmonella inspectors use
+pub fn current() -> Arc<SafetyPolicy> {
+    policy_slot().read().unwrap().clone()
+}
+
+// Replace the policy used by new salmonella inspectors
+pub fn install(policy: SafetyPolicy) {
+    *policy_slot().write().unwrap() = Arc::new(policy);
+}
+
+/// Reloads the safety policy whenever its file changes
+pub struct SafetyPolicyWatcher {
+    path: String,
+    modified: Option<SystemTime>,
+}
+
+impl SafetyPolicyWatcher {
+    // Create a new `SafetyPolicyWatcher` for `path`
+    pub fn new(path: String) -> Self {
+        let modified = modified_at(&path);
+        Self { path, modified }
+    }
+
+    // Reload and install the policy if its file changed since the last poll
+    //
+    // Returns:
+    // Ok(true): if a new policy was installed
+    // Ok(false): if file did not change (or does not exist)
+    // Err(SafetyPolicyError): if changed file is invalid, current policy is kept
+    pub fn poll(&mut self) -> Result<bool, SafetyPolicyError> {
+        let modified = modified_at(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return Ok(false);
+        }
+        self.modified = modified;
+
+        install(SafetyPolicy::load(&self.path)?);
+        Ok(true)
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+fn modified_at(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+#[cfg(test)]
+mod test {
+    use revm::db::{CacheDB, EmptyDB};
+    use revm::primitives::{AccountInfo, Bytecode, TransactTo, U256 as rU256};
+
+    use super::*;
+    use crate::prelude::is_sando_safu::{IsSandoSafu, SalmonellaInspectoooor};
+
+    const CONTRACT: u64 = 0x1234;
+    const CALLER: u64 = 0xbeef;
+
+    // ADDRESS BALANCE POP STOP
+    const READS_BALANCE: [u8; 4] = [0x30, 0x31, 0x50, 0x00];
+
+    // ORIGIN PUSH20 0x..01 EQ PUSH1 69 JUMPI LOG3(0, 0, Transfer, 0, 0) STOP JUMPDEST STOP
+    fn branches_on_origin() -> Vec<u8> {
+        let mut code = vec![0x32, 0x73];
+        code.extend([0u8; 19]);
+        code.push(0x01);
+        code.extend([0x14, 0x60, 69, 0x57]);
+        code.extend([0x60, 0x00, 0x60, 0x00, 0x7f]);
+        code.extend(ethers::utils::keccak256(
+            "Transfer(address,address,uint256)",
+        ));
+        code.extend([0x60, 0x00, 0x60, 0x00, 0xa3, 0x00, 0x5b, 0x00]);
+        code
+    }
+
+    fn policy(json: &str) -> SafetyPolicy {
+        SafetyPolicy::from_config(serde_json::from_str(json).unwrap()).unwrap()
+    }
+
+    fn simulate(code: Vec<u8>, policy: SafetyPolicy) -> IsSandoSafu {
+        let contract = B160::from_low_u64_be(CONTRACT);
+        let mut db = CacheDB::new(EmptyDB::default());
+        db.insert_account_info(
+            contract,
+            AccountInfo::new(rU256::ZERO, 0, Bytecode::new_raw(code.into())),
+        );
+
+        let mut evm = revm::EVM::new();
+        evm.database(db);
+        evm.env.tx.caller = B160::from_low_u64_be(CALLER);
+        evm.env.tx.transact_to = TransactTo::Call(contract);
+        evm.env.tx.gas_limit = 100_000;
+
+        let mut inspector =
+            SalmonellaInspectoooor::with_policy(Address::from_low_u64_be(0x5a5d), Arc::new(policy));
+        assert!(evm.inspect_commit(&mut inspector).unwrap().is_success());
+        inspector.is_sando_safu()
+    }
+
+    #[test]
+    fn config_is_parsed() {
+        let policy = policy(
+            r#"{
+                "opcodes": { "balance": 0.2, "UNKNOWN": 0.5 },
+                "findings": { "gas_check": 0 },
+                "exemptions": [{ "address": "0x0000000000000000000000000000000000001234", "opcodes": ["BALANCE"] }],
+                "actions": [
+                    { "min_confidence": 0.3, "action": "log_only" },
+                    { "min_confidence": 0.9, "action": "reject" },
+                    { "min_confidence": 0.6, "action": { "haircut": { "bps": 2500 } } }
+                ]
+            }"#,
+        );
+
+        let other = B160::from_low_u64_be(1);
+        assert_eq!(policy.opcode_severity(other, opcode::BALANCE), Some(0.2));
+        assert_eq!(policy.opcode_severity(other, opcode::COINBASE), None);
+        assert_eq!(policy.opcode_severity(other, 0x0c), Some(0.5));
+        assert_eq!(
+            policy.opcode_severity(B160::from_low_u64_be(CONTRACT), opcode::BALANCE),
+            None
+        );
+        assert!(!policy.checks_gas());
+        // unset finding severities keep their default
+        assert_eq!(policy.findings.transfer_tax, 0.9);
+
+        assert_eq!(policy.action(0.1), None);
+        assert_eq!(policy.action(0.3), Some(SafetyAction::LogOnly));
+        assert_eq!(
+            policy.action(0.7),
+            Some(SafetyAction::Haircut { bps: 2500 })
+        );
+        assert_eq!(policy.action(0.95), Some(SafetyAction::Reject));
+    }
+
+    #[test]
+    fn invalid_config_is_rejected() {
+        let unknown: SafetyPolicyConfig =
+            serde_json::from_str(r#"{ "opcodes": { "NOT_AN_OPCODE": 0.5 } }"#).unwrap();
+        assert!(matches!(
+            SafetyPolicy::from_config(unknown),
+            Err(SafetyPolicyError::UnknownOpcode(_))
+        ));
+
+        let out_of_range: SafetyPolicyConfig =
+            serde_json::from_str(r#"{ "opcodes": { "BALANCE": 2 } }"#).unwrap();
+        assert!(matches!(
+            SafetyPolicy::from_config(out_of_range),
+            Err(SafetyPolicyError::InvalidSeverity(_, _))
+        ));
+    }
+
+    #[test]
+    fn flagged_opcode_is_rejected() {
+        let verdict = simulate(
+            READS_BALANCE.to_vec(),
+            policy(
+                r#"{ "opcodes": { "BALANCE": 0.9 }, "actions": [{ "min_confidence": 0.5, "action": "reject" }] }"#,
+            ),
+        );
+        match verdict {
+            IsSandoSafu::NotSafu(report) => {
+                assert_eq!(report.findings.len(), 1);
+                assert!((report.confidence - 0.9).abs() < 1e-9);
+            }
+            verdict => panic!("expected NotSafu, got {:?}", verdict),
+        }
+    }
+
+    #[test]
+    fn exempted_address_is_safu() {
+        let verdict = simulate(
+            READS_BALANCE.to_vec(),
+            policy(
+                r#"{
+                    "opcodes": { "BALANCE": 0.9 },
+                    "exemptions": [{ "address": "0x0000000000000000000000000000000000001234" }],
+                    "actions": [{ "min_confidence": 0.5, "action": "reject" }]
+                }"#,
+            ),
+        );
+        match verdict {
+            IsSandoSafu::Safu(report) => assert!(report.findings.is_empty()),
+            verdict => panic!("expected Safu, got {:?}", verdict),
+        }
+    }
+
+    #[test]
+    fn unlisted_opcode_is_not_flagged() {
+        let verdict = simulate(
+            READS_BALANCE.to_vec(),
+            policy(
+                r#"{ "opcodes": { "COINBASE": 1 }, "actions": [{ "min_confidence": 0.01, "action": "reject" }] }"#,
+            ),
+        );
+        match verdict {
+            IsSandoSafu::Safu(report) => assert!(report.findings.is_empty()),
+            verdict => panic!("expected Safu, got {:?}", verdict),
+        }
+    }
+
+    #[test]
+    fn haircut_action() {
+        let verdict = simulate(
+            READS_BALANCE.to_vec(),
+            policy(
+                r#"{
+                    "opcodes": { "BALANCE": 0.6 },
+                    "actions": [
+                        { "min_confidence": 0.9, "action": "reject" },
+                        { "min_confidence": 0.5, "action": { "haircut": { "bps": 2500 } } }
+                    ]
+                }"#,
+            ),
+        );
+        assert!(matches!(verdict, IsSandoSafu::Haircut(_, 2500)));
+    }
+
+    #[test]
+    fn origin_branch_is_flagged() {
+        let verdict = simulate(
+            branches_on_origin(),
+            policy(r#"{ "actions": [{ "min_confidence": 0.5, "action": "reject" }] }"#),
+        );
+        match verdict {
+            IsSandoSafu::NotSafu(report) => assert!(report.findings.iter().any(|finding| {
+                matches!(finding, Salmonella::IdentityBranch { contract, .. }
+                    if *contract == B160::from_low_u64_be(CONTRACT))
+            })),
+            verdict => panic!("expected NotSafu, got {:?}", verdict),
+        }
+    }
+}
@@ -0,0 +1,377 @@
+use ethers::prelude::*;
+
+use crate::prelude::fork_factory::ForkFactory;
+use crate::prelude::sandwich_types::RawIngredients;
+use crate::prelude::PoolVariant;
+use crate::types::BlockInfo;
+use crate::utils::swap_decoder::{self, DecodedSwap};
+
+use super::{get_reserves_evm, setup_block_state};
+
+// Denominator of `Pool::swap_fee` (3000 is a 0.3% fee)
+const FEE_DENOMINATOR: u64 = 1_000_000;
+
+/// Victim swap through the target pool (in the same direction as the frontrun)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VictimSwap {
+    ExactIn {
+        amount_in: U256,
+        amount_out_min: U256,
+    },
+    ExactOut {
+        amount_out: U256,
+        amount_in_max: U256,
+    },
+}
+
+// Find the largest frontrun that keeps every victim within their slippage tolerance
+//
+// Only single hop victims on uniswap v2 pools can be bounded analytically, for everything
+// else the sandwich balance is returned so the search falls back to simulations
+//
+// Arguments:
+// * `ingredients`: holds onchain information about opportunity
+// * `sandwich_balance`: max amount available for the frontrun
+// * `next_block`: holds information about next block
+// * `fork_factory`: used to read pool reserves
+//
+// Returns:
+// U256: upper bound for the frontrun amount (never more than `sandwich_balance`)
+pub fn find_frontrun_upper_bound(
+    ingredients: &RawIngredients,
+    sandwich_balance: U256,
+    next_block: &BlockInfo,
+    fork_factory: &mut ForkFactory,
+) -> U256 {
+    if ingredients.target_pool.pool_variant != PoolVariant::UniswapV2 {
+        return sandwich_balance;
+    }
+
+    let mut victims = vec![];
+    for meat in ingredients.meats.iter() {
+        let decoded = match swap_decoder::decode_swap(meat) {
+            Some(decoded) => decoded,
+            None => return sandwich_balance,
+        };
+
+        // victim reverts no matter the frontrun
+        if decoded.deadline.map_or(false, |d| d < next_block.timestamp) {
+            return U256::zero();
+        }
+
+        match victim_swap_for_pool(&decoded, ingredients) {
+            Some(victim) => victims.push(victim),
+            None => return sandwich_balance,
+        }
+    }
+
+    let mut evm = revm::EVM::new();
+    evm.database(fork_factory.new_sandbox_fork());
+    setup_block_state(&mut evm, next_block);
+
+    let (reserve_in, reserve_out) = match get_reserves_evm(
+        ingredients.target_pool.address,
+        ingredients.startend_token,
+        ingredients.intermediary_token,
+        &mut evm,
+    ) {
+        Ok(reserves) => reserves,
+        Err(_) => return sandwich_balance,
+    };
+
+    max_frontrun_v2(
+        reserve_in,
+        reserve_out,
+        ingredients.target_pool.swap_fee,
+        &victims,
+        sandwich_balance,
+    )
+}
+
+// Convert a decoded swap into the swap it performs on the target pool
+//
+// Arguments:
+// * `decoded`: swap decoded from victim calldata
+// * `ingredients`: holds target pool and sandwich direction
+//
+// Returns:
+// Some(VictimSwap): if the swap is a single hop through the target pool in the frontrun direction
+// None: otherwise
+pub fn victim_swap_for_pool(
+    decoded: &DecodedSwap,
+    ingredients: &RawIngredients,
+) -> Option<VictimSwap> {
+    let direct_hop = if !decoded.pools.is_empty() {
+        decoded.pools == vec![ingredients.target_pool.address]
+            && decoded.path.first() == Some(&ingredients.startend_token)
+    } else {
+        decoded.router.routes_by_path()
+            && decoded.path == vec![ingredients.startend_token, ingredients.intermediary_token]
+    };
+
+    if !direct_hop {
+        return None;
+    }
+
+    match decoded.is_exact_out() {
+        true => Some(VictimSwap::ExactOut {
+            amount_out: decoded.amount_out?,
+            amount_in_max: decoded.amount_in_max?,
+        }),
+        false => Some(VictimSwap::ExactIn {
+            amount_in: decoded.amount_in?,
+            amount_out_min: decoded.amount_out_min?,
+        }),
+    }
+}
+
+// Binary search the largest frontrun on a uniswap v2 pool that lets every victim succeed
+//
+// Arguments:
+// * `reserve_in`: pool reserve of token being sold
+// * `reserve_out`: pool reserve of token being bought
+// * `swap_fee`: fee taken by the pool (in `FEE_DENOMINATOR` units)
+// * `victims`: victim swaps in the order they land after the frontrun
+// * `upper_bound`: largest frontrun considered
+//
+// Returns:
+// U256: largest frontrun amount where no victim exceeds their slippage
+pub fn max_frontrun_v2(
+    reserve_in: U256,
+    reserve_out: U256,
+    swap_fee: U256,
+    victims: &[VictimSwap],
+    upper_bound: U256,
+) -> U256 {
+    let succeed = |frontrun_in: U256| {
+        victims_succeed(reserve_in, reserve_out, swap_fee, victims, frontrun_in)
+    };
+
+    if succeed(upper_bound) {
+        return upper_bound;
+    }
+    if !succeed(U256::zero()) {
+        return U256::zero();
+    }
+
+    // invariant: lower succeeds, upper fails
+    let mut lower = U256::zero();
+    let mut upper = upper_bound;
+    while upper - lower > U256::one() {
+        let mid = lower + (upper - lower) / 2;
+        match succeed(mid) {
+            true => lower = mid,
+            false => upper = mid,
+        }
+    }
+
+    lower
+}
+
+// Apply frontrun then each victim to the reserves, checking victim slippage along the way
+// (a swap that overflows the pool's math counts as a failed victim)
+fn victims_succeed(
+    reserve_in: U256,
+    reserve_out: U256,
+    swap_fee: U256,
+    victims: &[VictimSwap],
+    frontrun_in: U256,
+) -> bool {
+    let apply_swaps = || -> Option<()> {
+        let frontrun_out = v2_amount_out(frontrun_in, reserve_in, reserve_out, swap_fee)?;
+        let mut reserve_in = reserve_in.checked_add(frontrun_in)?;
+        let mut reserve_out = reserve_out.checked_sub(frontrun_out)?;
+
+        for victim in victims {
+            let (amount_in, amount_out) = match *victim {
+                VictimSwap::ExactIn {
+                    amount_in,
+                    amount_out_min,
+                } => {
+                    let amount_out = v2_amount_out(amount_in, reserve_in, reserve_out, swap_fee)?;
+                    if amount_out < amount_out_min {
+                        return None;
+                    }
+                    (amount_in, amount_out)
+                }
+                VictimSwap::ExactOut {
+                    amount_out,
+                    amount_in_max,
+                } => {
+                    let amount_in = v2_amount_in(amount_out, reserve_in, reserve_out, swap_fee)?;
+                    if amount_in > amount_in_max {
+                        return None;
+                    }
+                    (amount_in, amount_out)
+                }
+            };
+            reserve_in = reserve_in.checked_add(amount_in)?;
+            reserve_out = reserve_out.checked_sub(amount_out)?;
+        }
+        Some(())
+    };
+
+    apply_swaps().is_some()
+}
+
+// UniswapV2Library.getAmountOut with the pool's fee (None on overflow)
+fn v2_amount_out(
+    amount_in: U256,
+    reserve_in: U256,
+    reserve_out: U256,
+    swap_fee: U256,
+) -> Option<U256> {
+    let fee_multiplier = U256::from(FEE_DENOMINATOR).checked_sub(swap_fee)?;
+    let amount_in_with_fee = amount_in.full_mul(fee_multiplier);
+    let numerator = amount_in_with_fee.checked_mul(U512::from(reserve_out))?;
+    let denominator = reserve_in
+        .full_mul(U256::from(FEE_DENOMINATOR))
+        .checked_add(amount_in_with_fee)?;
+    match denominator.is_zero() {
+        true => Some(U256::zero()),
+        false => U256::try_from(numerator / denominator).ok(),
+    }
+}
+
+// UniswapV2Library.getAmountIn with the pool's fee (None if pool can't provide `amount_out` or
+// on overflow)
+fn v2_amount_in(
+    amount_out: U256,
+    reserve_in: U256,
+    reserve_out: U256,
+    swap_fee: U256,
+) -> Option<U256> {
+    if amount_out >= reserve_out {
+        return None;
+    }
+    let fee_multiplier = U256::from(FEE_DENOMINATOR).checked_sub(swap_fee)?;
+    let numerator = reserve_in
+        .full_mul(amount_out)
+        .checked_mul(U512::from(FEE_DENOMINATOR))?;
+    let denominator = (reserve_out - amount_out).full_mul(fee_multiplier);
+    if denominator.is_zero() {
+        return None;
+    }
+    U256::try_from(numerator / denominator)
+        .ok()?
+        .checked_add(U256::one())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn eth(amount: u64) -> U256 {
+        ethers::utils::parse_ether(amount).unwrap()
+    }
+
+    // 0.3% fee
+    fn v2_fee() -> U256 {
+        U256::from(3000)
+    }
+
+    #[test]
+    fn amounts_match_uniswap_v2_library() {
+        assert_eq!(
+            v2_amount_out(eth(1), eth(100), eth(100), v2_fee()),
+            Some(U256::from(987158034397061298u64))
+        );
+        assert_eq!(
+            v2_amount_in(
+                U256::from(987158034397061298u64),
+                eth(100),
+                eth(100),
+                v2_fee()
+            ),
+            Some(eth(1))
+        );
+        assert_eq!(v2_amount_in(eth(100), eth(100), eth(100), v2_fee()), None);
+
+        // higher fee, less out
+        assert!(
+            v2_amount_out(eth(1), eth(100), eth(100), U256::from(10000)).unwrap()
+                < v2_amount_out(eth(1), eth(100), eth(100), v2_fee()).unwrap()
+        );
+    }
+
+    #[test]
+    fn frontrun_is_bounded_by_victim_slippage() {
+        let (reserve_in, reserve_out) = (eth(100), eth(100));
+        let no_frontrun_out = v2_amount_out(eth(1), reserve_in, reserve_out, v2_fee()).unwrap();
+
+        // 5% slippage
+        let victims = [VictimSwap::ExactIn {
+            amount_in: eth(1),
+            amount_out_min: no_frontrun_out * 95 / 100,
+        }];
+        let bound = max_frontrun_v2(reserve_in, reserve_out, v2_fee(), &victims, eth(50));
+        assert!(!bound.is_zero() && bound < eth(50));
+        assert!(victims_succeed(
+            reserve_in,
+            reserve_out,
+            v2_fee(),
+            &victims,
+            bound
+        ));
+        assert!(!victims_succeed(
+            reserve_in,
+            reserve_out,
+            v2_fee(),
+            &victims,
+            bound + 1
+        ));
+
+        // exact out victims are bounded the same way
+        let victims = [VictimSwap::ExactOut {
+            amount_out: no_frontrun_out,
+            amount_in_max: eth(1) * 105 / 100,
+        }];
+        let bound = max_frontrun_v2(reserve_in, reserve_out, v2_fee(), &victims, eth(50));
+        assert!(!bound.is_zero() && bound < eth(50));
+
+        // no slippage limit
+        let victims = [VictimSwap::ExactIn {
+            amount_in: eth(1),
+            amount_out_min: U256::zero(),
+        }];
+        assert_eq!(
+            max_frontrun_v2(reserve_in, reserve_out, v2_fee(), &victims, eth(50)),
+            eth(50)
+        );
+
+        // victim fails without a frontrun
+        let victims = [VictimSwap::ExactIn {
+            amount_in: eth(1),
+            amount_out_min: eth(1),
+        }];
+        assert_eq!(
+            max_frontrun_v2(reserve_in, reserve_out, v2_fee(), &victims, eth(50)),
+            U256::zero()
+        );
+    }
+
+    #[test]
+    fn overflowing_victims_fail_instead_of_panicking() {
+        let victims = [VictimSwap::ExactIn {
+            amount_in: U256::MAX,
+            amount_out_min: U256::zero(),
+        }];
+        assert_eq!(
+            max_frontrun_v2(eth(100), eth(100), v2_fee(), &victims, eth(50)),
+            U256::zero()
+        );
+
+        let victims = [VictimSwap::ExactOut {
+            amount_out: eth(1),
+            amount_in_max: U256::MAX,
+        }];
+        assert_eq!(
+            max_frontrun_v2(U256::MAX, eth(100), v2_fee(), &victims, eth(50)),
+            U256::zero()
+        );
+
+        // frontrun itself can't overflow the reserves
+        let bound = max_frontrun_v2(eth(100), eth(100), v2_fee(), &[], U256::MAX);
+        assert_eq!(bound, U256::MAX - eth(100));
+    }
+}
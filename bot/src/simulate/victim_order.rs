@@ -0,0 +1,336 @@
+use ethers::prelude::*;
+
+use crate::prelude::fork_factory::ForkFactory;
+use crate::prelude::sandwich_types::RawIngredients;
+use crate::types::{BlockInfo, SimulationError};
+
+use super::attach_braindance_module;
+use super::make_sandwich::{evaluate_sandwich_revenue, juiced_quadratic_search};
+
+// Up to this many victims every ordering of every subset is evaluated
+const EXACT_SEARCH_LIMIT: usize = 4;
+// Number of frontrun amounts (halving from sandwich balance) each candidate is screened at
+const SCREENING_POINTS: usize = 10;
+// Number of best screened candidates that get a full frontrun search
+const REFINED_CANDIDATES: usize = 3;
+
+/// Victims of a pool in the order chosen by `order_victims`
+#[derive(Clone, Debug)]
+pub struct VictimOrdering {
+    /// ingredients holding the ordered victims
+    pub ingredients: RawIngredients,
+    /// (amount_in, revenue) found by the full frontrun search of this ordering, if one was run
+    pub optimum: Option<(U256, U256)>,
+}
+
+impl VictimOrdering {
+    // Ordering that was not searched
+    fn unsearched(ingredients: &RawIngredients) -> Self {
+        Self {
+            ingredients: ingredients.clone(),
+            optimum: None,
+        }
+    }
+}
+
+// Find the order and subset of victims in a single pool that produces the most revenue
+// while every included victim still succeeds
+//
+// Arguments:
+// * `ingredients`: holds onchain information about opportunity (all victims of the pool)
+// * `sandwich_balance`: upper bound of frontrun amount
+// * `next_block`: holds information about next block
+// * `fork_factory`: used to create new forked evm instances for simulations
+//
+// Returns:
+// Ok(VictimOrdering): best ordered victims (unchanged if nothing better found) and their optimum
+// Err(SimulationError): if error during calculation
+pub async fn order_victims(
+    ingredients: &RawIngredients,
+    sandwich_balance: U256,
+    next_block: &BlockInfo,
+    fork_factory: &mut ForkFactory,
+) -> Result<VictimOrdering, SimulationError> {
+    if ingredients.meats.len() <= 1 {
+        return Ok(VictimOrdering::unsearched(ingredients));
+    }
+
+    attach_braindance_module(fork_factory);
+    let amounts = screening_amounts(sandwich_balance);
+
+    let candidates = if ingredients.meats.len() <= EXACT_SEARCH_LIMIT {
+        all_orderings(&ingredients.meats)
+    } else {
+        greedy_insertion(ingredients, &amounts, next_block, fork_factory).await
+    };
+
+    // cheap screening at a few frontrun amounts
+    let mut screened = vec![];
+    for meats in candidates {
+        let candidate = with_meats(ingredients, meats);
+        let revenue = screen(&candidate, &amounts, next_block, fork_factory).await;
+        if !revenue.is_zero() {
+            screened.push((revenue, candidate));
+        }
+    }
+    screened.sort_by(|a, b| b.0.cmp(&a.0));
+    screened.truncate(REFINED_CANDIDATES);
+
+    // full search on the most promising candidates
+    let mut best: Option<VictimOrdering> = None;
+    for (_, candidate) in screened {
+        let (amount_in, revenue) = juiced_quadratic_search(
+            &candidate,
+            U256::zero(),
+            sandwich_balance,
+            next_block,
+            fork_factory,
+        )
+        .await?;
+
+        let is_better = match best.as_ref().and_then(|best| best.optimum) {
+            Some((_, best_revenue)) => revenue > best_revenue,
+            None => !revenue.is_zero(),
+        };
+        if is_better {
+            best = Some(VictimOrdering {
+                ingredients: candidate,
+                optimum: Some((amount_in, revenue)),
+            });
+        }
+    }
+
+    Ok(best.unwrap_or_else(|| VictimOrdering::unsearched(ingredients)))
+}
+
+// Frontrun amounts used to compare candidates, halving from `sandwich_balance`
+fn screening_amounts(sandwich_balance: U256) -> Vec<U256> {
+    (0..SCREENING_POINTS)
+        .map(|i| sandwich_balance >> i)
+        .filter(|amount| !amount.is_zero())
+        .collect()
+}
+
+// Copy of `ingredients` sandwiching `meats` instead
+fn with_meats(ingredients: &RawIngredients, meats: Vec<Transaction>) -> RawIngredients {
+    let mut candidate = ingredients.clone();
+    candidate.meats = meats;
+    candidate
+}
+
+// Highest revenue over `amounts` (zero if a victim reverts at every amount)
+async fn screen(
+    candidate: &RawIngredients,
+    amounts: &[U256],
+    next_block: &BlockInfo,
+    fork_factory: &mut ForkFactory,
+) -> U256 {
+    let mut sims = Vec::new();
+    for amount in amounts {
+        let sim = tokio::task::spawn(evaluate_sandwich_revenue(
+            *amount,
+            candidate.clone(),
+            next_block.clone(),
+            fork_factory.new_sandbox_fork(),
+        ));
+        sims.push(sim);
+    }
+
+    futures::future::join_all(sims)
+        .await
+        .into_iter()
+        .map(|r| match r {
+            Ok(revenue) => revenue.unwrap_or_default(),
+            Err(_) => U256::zero(),
+        })
+        .max()
+        .unwrap_or_default()
+}
+
+// Can `ordering` land onchain, a sender's victims have to be included by increasing nonce and
+// without skipping any of that sender's lower nonce victims in `meats`
+fn respects_nonce_order(meats: &[Transaction], ordering: &[Transaction]) -> bool {
+    ordering.iter().enumerate().all(|(i, tx)| {
+        meats.iter().all(|other| {
+            other.from != tx.from
+                || other.nonce >= tx.nonce
+                || ordering[..i]
+                    .iter()
+                    .any(|included| included.hash == other.hash)
+        })
+    })
+}
+
+// Reorder `meats` so that each sender's victims keep their positions but follow nonce order
+fn sort_nonces_per_sender(meats: &mut [Transaction]) {
+    for i in 0..meats.len() {
+        for j in i + 1..meats.len() {
+            if meats[j].from == meats[i].from && meats[j].nonce < meats[i].nonce {
+                meats.swap(i, j);
+            }
+        }
+    }
+}
+
+// Every ordering of every non empty subset of `meats` that respects nonce order
+fn all_orderings(meats: &[Transaction]) -> Vec<Vec<Transaction>> {
+    fn extend(
+        meats: &[Transaction],
+        used: &mut Vec<bool>,
+        current: &mut Vec<Transaction>,
+        orderings: &mut Vec<Vec<Transaction>>,
+    ) {
+        if !current.is_empty() {
+            orderings.push(current.clone());
+        }
+        for i in 0..meats.len() {
+            if used[i] {
+                continue;
+            }
+            current.push(meats[i].clone());
+            // every prefix of a valid ordering is valid, so invalid branches are cut early
+            if respects_nonce_order(meats, current) {
+                used[i] = true;
+                extend(meats, used, current, orderings);
+                used[i] = false;
+            }
+            current.pop();
+        }
+    }
+
+    let mut orderings = vec![];
+    extend(
+        meats,
+        &mut vec![false; meats.len()],
+        &mut vec![],
+        &mut orderings,
+    );
+    orderings
+}
+
+// Build an ordering by inserting victims (most profitable alone first) at their best position,
+// victims that don't increase revenue are left out
+async fn greedy_insertion(
+    ingredients: &RawIngredients,
+    amounts: &[U256],
+    next_block: &BlockInfo,
+    fork_factory: &mut ForkFactory,
+) -> Vec<Vec<Transaction>> {
+    let mut singles = vec![];
+    for meat in ingredients.meats.iter() {
+        let candidate = with_meats(ingredients, vec![meat.clone()]);
+        let revenue = screen(&candidate, amounts, next_block, fork_factory).await;
+        singles.push((revenue, meat.clone()));
+    }
+    singles.sort_by(|a, b| b.0.cmp(&a.0));
+    let mut queue: Vec<Transaction> = singles.into_iter().map(|(_, meat)| meat).collect();
+    sort_nonces_per_sender(&mut queue);
+
+    let mut order: Vec<Transaction> = vec![];
+    let mut order_revenue = U256::zero();
+    for meat in queue {
+        let mut best_insert: Option<(U256, Vec<Transaction>)> = None;
+        for position in 0..=order.len() {
+            let mut meats = order.clone();
+            meats.insert(position, meat.clone());
+            if !respects_nonce_order(&ingredients.meats, &meats) {
+                continue;
+            }
+            let revenue = screen(
+                &with_meats(ingredients, meats.clone()),
+                amounts,
+                next_block,
+                fork_factory,
+            )
+            .await;
+            if best_insert.as_ref().map_or(true, |(r, _)| revenue > *r) {
+                best_insert = Some((revenue, meats));
+            }
+        }
+
+        if let Some((revenue, meats)) = best_insert {
+            if revenue > order_revenue {
+                order = meats;
+                order_revenue = revenue;
+            }
+        }
+    }
+
+    if order.is_empty() {
+        return vec![];
+    }
+    vec![order]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn meat(sender: u64, nonce: u64, id: u64) -> Transaction {
+        Transaction {
+            hash: H256::from_low_u64_be(id),
+            from: Address::from_low_u64_be(sender),
+            nonce: U256::from(nonce),
+            ..Default::default()
+        }
+    }
+
+    fn ids(orderings: &[Vec<Transaction>]) -> Vec<Vec<u64>> {
+        orderings
+            .iter()
+            .map(|meats| meats.iter().map(|m| m.hash.to_low_u64_be()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn orders_every_subset_of_independent_victims() {
+        let meats = vec![meat(1, 0, 1), meat(2, 0, 2), meat(3, 0, 3)];
+        let orderings = all_orderings(&meats);
+
+        // 3 singles, 6 ordered pairs, 6 permutations
+        assert_eq!(orderings.len(), 15);
+        let orderings = ids(&orderings);
+        assert!(orderings.contains(&vec![2]));
+        assert!(orderings.contains(&vec![3, 1]));
+        assert!(orderings.contains(&vec![3, 2, 1]));
+
+        assert_eq!(ids(&all_orderings(&[])), Vec::<Vec<u64>>::new());
+    }
+
+    #[test]
+    fn orderings_keep_sender_nonces_in_order() {
+        // victims 1 and 2 are sent by the same account
+        let meats = vec![meat(1, 5, 1), meat(1, 6, 2), meat(2, 0, 3)];
+        let mut orderings = ids(&all_orderings(&meats));
+        orderings.sort();
+
+        assert_eq!(
+            orderings,
+            vec![
+                vec![1],
+                vec![1, 2],
+                vec![1, 2, 3],
+                vec![1, 3],
+                vec![1, 3, 2],
+                vec![3],
+                vec![3, 1],
+                vec![3, 1, 2],
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_and_restores_nonce_order() {
+        let meats = vec![meat(1, 5, 1), meat(2, 0, 2), meat(1, 6, 3)];
+        assert!(respects_nonce_order(&meats, &meats));
+        assert!(!respects_nonce_order(&meats, &[meats[2].clone()]));
+        assert!(!respects_nonce_order(
+            &meats,
+            &[meats[2].clone(), meats[0].clone()]
+        ));
+
+        let mut reordered = vec![meats[2].clone(), meats[1].clone(), meats[0].clone()];
+        sort_nonces_per_sender(&mut reordered);
+        assert_eq!(ids(&[reordered]), vec![vec![1, 2, 3]]);
+    }
+}
@@ -44,16 +44,16 @@ pub struct BlockOracle {
 
 impl BlockOracle {
     // Create new latest block oracle
-    pub async fn new(client: &Arc<Provider<Ws>>) -> Result<Self, ProviderError> {
+    pub async fn new<M: Middleware + 'static>(client: &Arc<M>) -> eyre::Result<Self> {
         let latest_block = match client.get_block(BlockNumber::Latest).await {
             Ok(b) => b,
-            Err(e) => return Err(e),
+            Err(e) => return Err(e.into()),
         };
 
         let lb = if let Some(b) = latest_block {
             b
         } else {
-            return Err(ProviderError::CustomError("Block not found".to_string()));
+            eyre::bail!("Block not found");
         };
 
         // latets block info
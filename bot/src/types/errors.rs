@@ -1,21 +1,21 @@
 use std::fmt;
 
-use ethers::prelude::{AbiError, ContractError};
-use ethers::providers::{Provider, ProviderError, Ws};
+use ethers::prelude::AbiError;
+use ethers::providers::ProviderError;
 use ethers::signers::WalletError;
-use ethers::types::H160;
+use ethers::types::{H160, H256, U256, U64};
 use thiserror::Error;
 use tokio::task::JoinError;
 
-use crate::prelude::is_sando_safu::OpCode;
+use crate::prelude::is_sando_safu::SalmonellaReport;
 use crate::prelude::DatabaseError;
 
 #[derive(Error, Debug)]
 pub enum PairSyncError {
     #[error("Provider error")]
     ProviderError(#[from] ProviderError),
-    #[error("Contract error")]
-    ContractError(#[from] ContractError<Provider<Ws>>),
+    #[error("Middleware error: {0}")]
+    MiddlewareError(Box<dyn std::error::Error + Send + Sync>),
     #[error("ABI error")]
     ABIError(#[from] AbiError),
     #[error("Join error")]
@@ -38,6 +38,76 @@ pub enum SendBundleError {
     FailedToSendBundle(),
     #[error("Revenue does not cover frontrun gas fees")]
     FrontrunGasFeesNotCovered(),
+    #[error("No provider available")]
+    NoProvider(#[from] ProviderManagerError),
+    #[error("Searcher nonce is reserved for block {0}")]
+    NonceReserved(U64),
+    #[error("All relays stopped accepting bundles for block {0}")]
+    RelayCutoffPassed(U64),
+    #[error("Failed to set up relays")]
+    RelaySetup(#[from] RelayError),
+}
+
+#[derive(Error, Debug)]
+pub enum RelayError {
+    #[error("No provider available")]
+    NoProvider(#[from] ProviderManagerError),
+    #[error("{0} is not set")]
+    MissingKey(&'static str),
+    #[error("{0} is not a valid private key")]
+    InvalidKey(&'static str, #[source] WalletError),
+    #[error("Invalid endpoint of relay {0}")]
+    InvalidEndpoint(String, #[source] url::ParseError),
+}
+
+#[derive(Error, Debug)]
+pub enum ProviderManagerError {
+    #[error("No rpc urls configured")]
+    NoUrlsConfigured(),
+    #[error("Failed to connect to {0}: {1}")]
+    ConnectionFailed(String, String),
+    #[error("Failed to connect to any of {0} rpc urls")]
+    AllUrlsFailed(usize),
+}
+
+#[derive(Error, Debug)]
+pub enum SafetyPolicyError {
+    #[error("Failed to read safety policy")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to parse safety policy")]
+    Json(#[from] serde_json::Error),
+    #[error("Unknown opcode {0}")]
+    UnknownOpcode(String),
+    #[error("Severity of {0} is not within 0..=1: {1}")]
+    InvalidSeverity(String, f64),
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum PayloadDecodeError {
+    #[error("Calldata of {0} bytes ends in the middle of a payload")]
+    UnexpectedEnd(usize),
+    #[error("Unknown swap type {0}")]
+    UnknownSwapType(u8),
+    #[error("Invalid memory offset {0}")]
+    InvalidMemOffset(u8),
+    #[error("{0} trailing bytes after single payload")]
+    TrailingBytes(usize),
+    #[error("Multi payload is not terminated")]
+    MissingEndOfMultiPayload(),
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum JumpTableError {
+    #[error("No jump table found in sandwich contract bytecode")]
+    NotFound(),
+    #[error("sandwich.huff has {0} gates but the contract has {1}")]
+    GateCountMismatch(usize, usize),
+    #[error("Gate of {0} at {1} does not fit in one calldata byte")]
+    JumpDestTooLarge(String, usize),
+    #[error("Contract has no gate for {0}")]
+    MissingLabel(String),
+    #[error("Contract does not embed {0} address {1:?}")]
+    MissingAddress(String, H160),
 }
 
 #[derive(Debug)]
@@ -45,11 +115,11 @@ pub enum SimulationError {
     FrontrunEvmError(revm::primitives::EVMError<DatabaseError>),
     FrontrunHalted(revm::primitives::Halt),
     FrontrunReverted(revm::primitives::Bytes),
-    FrontrunNotSafu(Vec<OpCode>),
+    FrontrunNotSafu(SalmonellaReport),
     BackrunEvmError(revm::primitives::EVMError<DatabaseError>),
     BackrunHalted(revm::primitives::Halt),
     BackrunReverted(revm::primitives::Bytes),
-    BackrunNotSafu(Vec<OpCode>),
+    BackrunNotSafu(SalmonellaReport),
     FailedToDecodeOutput(AbiError),
     EvmError(revm::primitives::EVMError<DatabaseError>),
     EvmHalted(revm::primitives::Halt),
@@ -57,6 +127,9 @@ pub enum SimulationError {
     AbiError(AbiError),
     ZeroOptimal(),
     NoMeat(),
+    MeatReverted(H256),
+    TaxDetected(H160),
+    AmountOverflow(U256),
 }
 
 impl fmt::Display for SimulationError {
@@ -72,7 +145,7 @@ impl fmt::Display for SimulationError {
                 write!(f, "Frontrun reverted and returned : {}", hex::encode(bytes))
             }
             SimulationError::FrontrunNotSafu(sus) => {
-                write!(f, "Frontrun not safu because of the opcodes : {:?}", sus)
+                write!(f, "Frontrun not safu with {}", sus)
             }
             SimulationError::BackrunEvmError(db_err) => {
                 write!(f, "Backrun ran into an EVM error : {:?}", db_err)
@@ -84,7 +157,7 @@ impl fmt::Display for SimulationError {
                 write!(f, "Backrun reverted and returned : {}", hex::encode(bytes))
             }
             SimulationError::BackrunNotSafu(sus) => {
-                write!(f, "Backrun not safu because of the opcodes : {:?}", sus)
+                write!(f, "Backrun not safu with {}", sus)
             }
             SimulationError::FailedToDecodeOutput(error_reason) => {
                 write!(f, "Failed to decode output : {:?}", error_reason)
@@ -107,6 +180,15 @@ impl fmt::Display for SimulationError {
             SimulationError::NoMeat() => {
                 write!(f, "No meat to sandwich")
             }
+            SimulationError::MeatReverted(hash) => {
+                write!(f, "Meat {:?} reverted", hash)
+            }
+            SimulationError::TaxDetected(token) => {
+                write!(f, "Token {:?} takes a fee on transfer", token)
+            }
+            SimulationError::AmountOverflow(amount) => {
+                write!(f, "Amount {} does not fit into a swap", amount)
+            }
         }
     }
 }
@@ -9,15 +9,23 @@ pub struct OptimalRecipe {
     pub frontrun_data: Bytes,
     pub frontrun_value: U256,
     pub frontrun_gas_used: u64,
+    pub frontrun_gas_limit: u64,
     pub frontrun_access_list: AccessList,
     pub backrun_data: Bytes,
     pub backrun_value: U256,
     pub backrun_gas_used: u64,
+    pub backrun_gas_limit: u64,
     pub backrun_access_list: AccessList,
     pub meats: Vec<Transaction>,
     pub target_pools: Vec<Pool>,
     pub revenue: U256,
     pub state_diffs: BTreeMap<H160, AccountDiff>,
+    // bribe is cut by this many bps when the safety policy lets a risky sandwich through
+    pub bribe_haircut_bps: u64,
+    // gas saved by pruning access list entries that cost more than they save (both slices)
+    pub access_list_gas_saved: u64,
+    // expected revenue (wei) lost because amounts are truncated when encoded for the contract
+    pub encoding_loss: U256,
 }
 
 impl OptimalRecipe {
@@ -26,10 +34,12 @@ impl OptimalRecipe {
         frontrun_data: Bytes,
         frontrun_value: U256,
         frontrun_gas_used: u64,
+        frontrun_gas_limit: u64,
         frontrun_access_list: AccessList,
         backrun_data: Bytes,
         backrun_value: U256,
         backrun_gas_used: u64,
+        backrun_gas_limit: u64,
         backrun_access_list: AccessList,
         meats: Vec<Transaction>,
         revenue: U256,
@@ -40,16 +50,21 @@ impl OptimalRecipe {
             frontrun_data,
             frontrun_value,
             frontrun_gas_used,
+            frontrun_gas_limit,
             frontrun_access_list,
             backrun_data,
             backrun_value,
             backrun_gas_used,
+            backrun_gas_limit,
             backrun_access_list,
             meats,
             revenue,
             target_pools,
             // has_dust: false,
             state_diffs,
+            bribe_haircut_bps: 0,
+            access_list_gas_saved: 0,
+            encoding_loss: U256::zero(),
         }
     }
 
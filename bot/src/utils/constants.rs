@@ -21,6 +21,11 @@ pub fn get_sqrt_price_limit_x96(is_zero_for_one: bool) -> U256 {
     }
 }
 
+// Return address of the metamorphic contract factory the sandwich contract is deployed with
+pub fn get_metamorphic_factory_address() -> Address {
+    Address::from_str("0x00000000e82eb0431756271F0d00CFB143685e7B").unwrap()
+}
+
 // Return the ethdev address (used if we need funds)
 pub fn get_eth_dev() -> Address {
     Address::from_str("0x5AbFEc25f74Cd88437631a7731906932776356f9").unwrap()
@@ -41,14 +46,6 @@ pub fn get_erc20_transfer_event_signature() -> H256 {
     H256::from_str("0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef").unwrap()
 }
 
-pub fn get_end_of_multi_payload() -> u8 {
-    37_u8
-}
-
-pub fn get_prepare_stack_payload() -> u8 {
-    94_u8
-}
-
 pub fn get_banner() -> &'static str {
     let banner = indoc! {
 r#"
@@ -1,21 +1,18 @@
 use std::sync::Arc;
 
-use ethers::{prelude::*, providers};
+use ethers::prelude::*;
 
 use crate::prelude::{Erc20, UniswapV2Pair};
 
 /// Create erc20 contract that we can interact with
-pub fn get_erc20_contract(
-    erc20_address: &Address,
-    client: &Arc<Provider<Ws>>,
-) -> Erc20<providers::Provider<Ws>> {
+pub fn get_erc20_contract<M: Middleware>(erc20_address: &Address, client: &Arc<M>) -> Erc20<M> {
     Erc20::new(*erc20_address, client.clone())
 }
 
 /// Create v2 pair contract that we can interact with
-pub fn get_pair_v2_contract(
+pub fn get_pair_v2_contract<M: Middleware>(
     pair_address: &Address,
-    client: &Arc<Provider<Ws>>,
-) -> UniswapV2Pair<providers::Provider<Ws>> {
+    client: &Arc<M>,
+) -> UniswapV2Pair<M> {
     UniswapV2Pair::new(*pair_address, client.clone())
 }
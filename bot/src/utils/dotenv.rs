@@ -62,13 +62,257 @@ pub fn read_env_vars() -> Vec<(String, String)> {
     env_vars
 }
 
-/// Return a new ws provider
-pub async fn get_ws_provider() -> Provider<Ws> {
-    let url =
-        dotenv::var("RPC_URL_WSS").expect("Required environment variable \"RPC_URL_WSS\" not set");
-    Provider::<Ws>::connect(&url)
-        .await
-        .expect("RPC Connection Error")
+/// Return rpc urls in order of preference, `RPC_URL_WSS` followed by the comma separated `RPC_FALLBACK_URLS_WSS`
+/// Transport is picked by scheme: `ws(s)://`, `http(s)://`, anything else is an ipc path
+pub fn get_rpc_urls() -> Vec<String> {
+    let mut urls = vec![dotenv::var("RPC_URL_WSS")
+        .expect("Required environment variable \"RPC_URL_WSS\" not set")];
+
+    if let Ok(fallbacks) = dotenv::var("RPC_FALLBACK_URLS_WSS") {
+        urls.extend(
+            fallbacks
+                .split(',')
+                .map(|url| url.trim().to_string())
+                .filter(|url| !url.is_empty()),
+        );
+    }
+
+    urls
+}
+
+/// Return number of persistent rpc connections shared by the bot (default 4)
+pub fn get_provider_pool_size() -> usize {
+    match dotenv::var("PROVIDER_POOL_SIZE") {
+        Ok(size) => size
+            .parse()
+            .expect("PROVIDER_POOL_SIZE is not a valid usize"),
+        Err(_) => 4,
+    }
+}
+
+/// Return (initial, max) delay in milliseconds between rpc reconnect attempts (default 250, 10000)
+pub fn get_reconnect_backoff_ms() -> (u64, u64) {
+    let initial = match dotenv::var("RECONNECT_BACKOFF_INITIAL_MS") {
+        Ok(initial) => initial
+            .parse()
+            .expect("RECONNECT_BACKOFF_INITIAL_MS is not a valid u64"),
+        Err(_) => 250,
+    };
+    let max = match dotenv::var("RECONNECT_BACKOFF_MAX_MS") {
+        Ok(max) => max
+            .parse()
+            .expect("RECONNECT_BACKOFF_MAX_MS is not a valid u64"),
+        Err(_) => 10_000,
+    };
+    (initial, max)
+}
+
+/// Return whether missing state is fetched with `eth_getProof` instead of individual calls (default true)
+pub fn get_state_fetch_with_proof() -> bool {
+    match dotenv::var("STATE_FETCH_WITH_PROOF") {
+        Ok(with_proof) => with_proof
+            .parse()
+            .expect("STATE_FETCH_WITH_PROOF is not a valid bool"),
+        Err(_) => true,
+    }
+}
+
+/// Return max number of recently sandwiched pools whose hot slots are prefetched every block (default 128)
+pub fn get_prefetch_hot_pools() -> usize {
+    match dotenv::var("PREFETCH_HOT_POOLS") {
+        Ok(pools) => pools
+            .parse()
+            .expect("PREFETCH_HOT_POOLS is not a valid usize"),
+        Err(_) => 128,
+    }
+}
+
+/// Return max number of cached accounts + slots that are carried over to next block (default 1000000)
+pub fn get_state_cache_max_entries() -> usize {
+    match dotenv::var("STATE_CACHE_MAX_ENTRIES") {
+        Ok(entries) => entries
+            .parse()
+            .expect("STATE_CACHE_MAX_ENTRIES is not a valid usize"),
+        Err(_) => 1_000_000,
+    }
+}
+
+/// Return seconds between pool rankings (default 3600)
+pub fn get_pool_ranking_interval_secs() -> u64 {
+    match dotenv::var("POOL_RANKING_INTERVAL_SECS") {
+        Ok(interval) => interval
+            .parse()
+            .expect("POOL_RANKING_INTERVAL_SECS is not a valid u64"),
+        Err(_) => 3600,
+    }
+}
+
+/// Return min weth (in ether) a pool needs to hold to not be ignored (default 0.5)
+pub fn get_pool_min_weth_liquidity() -> U256 {
+    match dotenv::var("POOL_MIN_WETH_LIQUIDITY") {
+        Ok(liquidity) => ethers::utils::parse_ether(liquidity)
+            .expect("POOL_MIN_WETH_LIQUIDITY is not a valid ether amount"),
+        Err(_) => ethers::utils::parse_ether("0.5").unwrap(),
+    }
+}
+
+/// Return number of simulations without profit after which a pool is ignored (default 50)
+pub fn get_pool_min_simulations() -> u64 {
+    match dotenv::var("POOL_MIN_SIMULATIONS") {
+        Ok(simulations) => simulations
+            .parse()
+            .expect("POOL_MIN_SIMULATIONS is not a valid u64"),
+        Err(_) => 50,
+    }
+}
+
+/// Return seconds after which a pool ignored for not being profitable is simulated again (default 86400)
+pub fn get_pool_unprofitable_ttl_secs() -> u64 {
+    match dotenv::var("POOL_UNPROFITABLE_TTL_SECS") {
+        Ok(ttl) => ttl
+            .parse()
+            .expect("POOL_UNPROFITABLE_TTL_SECS is not a valid u64"),
+        Err(_) => 86400,
+    }
+}
+
+/// Return seconds that a token flagged by a simulation stays skipped (default 86400)
+pub fn get_risk_simulation_verdict_ttl_secs() -> u64 {
+    match dotenv::var("RISK_SIMULATION_VERDICT_TTL_SECS") {
+        Ok(ttl) => ttl
+            .parse()
+            .expect("RISK_SIMULATION_VERDICT_TTL_SECS is not a valid u64"),
+        Err(_) => 86400,
+    }
+}
+
+/// Return file that token / pool risk verdicts are persisted to (default risk_registry.json)
+pub fn get_risk_registry_file() -> String {
+    dotenv::var("RISK_REGISTRY_FILE").unwrap_or_else(|_| "risk_registry.json".to_string())
+}
+
+/// Return tokens that are always sandwiched, even if flagged (comma separated `RISK_ALLOWED_TOKENS`)
+pub fn get_risk_allowed_tokens() -> Vec<Address> {
+    get_address_list("RISK_ALLOWED_TOKENS")
+}
+
+/// Return tokens that are never sandwiched (comma separated `RISK_BLOCKED_TOKENS`)
+pub fn get_risk_blocked_tokens() -> Vec<Address> {
+    get_address_list("RISK_BLOCKED_TOKENS")
+}
+
+/// Return pools that are always sandwiched, even if flagged (comma separated `RISK_ALLOWED_POOLS`)
+pub fn get_risk_allowed_pools() -> Vec<Address> {
+    get_address_list("RISK_ALLOWED_POOLS")
+}
+
+/// Return pools that are never sandwiched (comma separated `RISK_BLOCKED_POOLS`)
+pub fn get_risk_blocked_pools() -> Vec<Address> {
+    get_address_list("RISK_BLOCKED_POOLS")
+}
+
+/// Return the confidence (0..=1) from which salmonella findings make a sandwich not safu when the
+/// safety policy doesn't list actions (default 0.5)
+pub fn get_salmonella_min_confidence() -> f64 {
+    match dotenv::var("SALMONELLA_MIN_CONFIDENCE") {
+        Ok(confidence) => confidence
+            .parse()
+            .expect("SALMONELLA_MIN_CONFIDENCE is not a valid f64"),
+        Err(_) => 0.5,
+    }
+}
+
+/// Return file that the salmonella safety policy is loaded from (default safety_policy.json)
+pub fn get_safety_policy_file() -> String {
+    dotenv::var("SAFETY_POLICY_FILE").unwrap_or_else(|_| "safety_policy.json".to_string())
+}
+
+/// Return how often the safety policy file is checked for changes (default: 5)
+pub fn get_safety_policy_reload_secs() -> u64 {
+    match dotenv::var("SAFETY_POLICY_RELOAD_SECS") {
+        Ok(secs) => secs
+            .parse()
+            .expect("SAFETY_POLICY_RELOAD_SECS is not a valid u64"),
+        Err(_) => 5,
+    }
+}
+
+/// Return margin (bps) added on top of the minimal gas limit found in simulation (default: 200)
+pub fn get_gas_limit_margin_bps() -> u64 {
+    match dotenv::var("GAS_LIMIT_MARGIN_BPS") {
+        Ok(margin) => margin
+            .parse()
+            .expect("GAS_LIMIT_MARGIN_BPS is not a valid u64"),
+        Err(_) => 200,
+    }
+}
+
+/// Return file that submitted bundles and fund movements are appended to (default ledger.jsonl)
+pub fn get_ledger_file() -> String {
+    dotenv::var("LEDGER_FILE").unwrap_or_else(|_| "ledger.jsonl".to_string())
+}
+
+/// Return priority fee (gwei) paid by `sando-rs admin` txs (default: 2)
+pub fn get_admin_priority_fee_gwei() -> u64 {
+    match dotenv::var("ADMIN_PRIORITY_FEE_GWEI") {
+        Ok(fee) => fee
+            .parse()
+            .expect("ADMIN_PRIORITY_FEE_GWEI is not a valid u64"),
+        Err(_) => 2,
+    }
+}
+
+/// Return weth balance of the sandwich contract below which the treasury refills it (default: 1 ETH)
+pub fn get_treasury_contract_min_weth() -> U256 {
+    get_ether_amount("TREASURY_CONTRACT_MIN_WETH", "1")
+}
+
+/// Return weth balance the treasury moves the sandwich contract back to (default: 4 ETH)
+pub fn get_treasury_contract_target_weth() -> U256 {
+    get_ether_amount("TREASURY_CONTRACT_TARGET_WETH", "4")
+}
+
+/// Return weth balance of the sandwich contract above which the treasury withdraws profits (default: 4.5 ETH)
+pub fn get_treasury_contract_max_weth() -> U256 {
+    get_ether_amount("TREASURY_CONTRACT_MAX_WETH", "4.5")
+}
+
+/// Return eth balance of the searcher below which the treasury sends it gas money (default: 0.1 ETH)
+pub fn get_treasury_searcher_min_eth() -> U256 {
+    get_ether_amount("TREASURY_SEARCHER_MIN_ETH", "0.1")
+}
+
+/// Return eth balance the treasury keeps on the searcher for gas (default: 0.3 ETH)
+pub fn get_treasury_searcher_target_eth() -> U256 {
+    get_ether_amount("TREASURY_SEARCHER_TARGET_ETH", "0.3")
+}
+
+/// Return eth balance of the searcher above which the treasury refills the contract (default: 1 ETH)
+pub fn get_treasury_searcher_max_eth() -> U256 {
+    get_ether_amount("TREASURY_SEARCHER_MAX_ETH", "1")
+}
+
+// Parse comma separated addresses in `var` (empty if not set)
+fn get_address_list(var: &str) -> Vec<Address> {
+    match dotenv::var(var) {
+        Ok(addresses) => addresses
+            .split(',')
+            .map(|address| address.trim())
+            .filter(|address| !address.is_empty())
+            .map(|address| {
+                Address::from_str(address)
+                    .unwrap_or_else(|_| panic!("{} contains invalid address {}", var, address))
+            })
+            .collect(),
+        Err(_) => vec![],
+    }
+}
+
+// Parse ether amount (e.g. `0.5`) in `var` (`default` if not set)
+fn get_ether_amount(var: &str, default: &str) -> U256 {
+    let amount = dotenv::var(var).unwrap_or_else(|_| default.to_string());
+    ethers::utils::parse_ether(&amount)
+        .unwrap_or_else(|_| panic!("{} is not a valid ether amount", var))
 }
 
 // /// Return a webhook for v2 discord alert channel
@@ -96,3 +340,79 @@ pub fn get_interval_block_new_pool() -> u64 {
         .parse()
         .expect("INTERVAL_BLOCK_NEW_POOL is not a valid u64")
 }
+
+/// Return how many milliseconds before the earliest relay cutoff the mega sandwich is computed (default 1500)
+pub fn get_mega_sandwich_fire_offset_ms() -> u64 {
+    match dotenv::var("MEGA_SANDWICH_FIRE_OFFSET_MS") {
+        Ok(offset) => offset
+            .parse()
+            .expect("MEGA_SANDWICH_FIRE_OFFSET_MS is not a valid u64"),
+        Err(_) => 1500,
+    }
+}
+
+/// Return per relay bundle cutoffs in milliseconds relative to the target slot's start
+/// Format: `RELAY_CUTOFFS_MS=flashbots:-250,beaverbuild:500` (relays not listed use 0)
+pub fn get_relay_cutoffs_ms() -> Vec<(String, i64)> {
+    let cutoffs = match dotenv::var("RELAY_CUTOFFS_MS") {
+        Ok(cutoffs) => cutoffs,
+        Err(_) => return vec![],
+    };
+
+    cutoffs
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let (name, cutoff) = entry
+                .split_once(':')
+                .expect("RELAY_CUTOFFS_MS entries must be formatted as `relay:cutoff_ms`");
+            let cutoff = cutoff
+                .trim()
+                .parse::<i64>()
+                .expect("RELAY_CUTOFFS_MS cutoff is not a valid i64");
+            (name.trim().to_string(), cutoff)
+        })
+        .collect()
+}
+
+/// Return the minimum estimated inclusion probability (0..=1) a victim needs to be simulated (default 0.5)
+pub fn get_min_inclusion_probability() -> f64 {
+    match dotenv::var("MIN_INCLUSION_PROBABILITY") {
+        Ok(probability) => probability
+            .parse()
+            .expect("MIN_INCLUSION_PROBABILITY is not a valid f64"),
+        Err(_) => 0.5,
+    }
+}
+
+/// Return number of worker tasks per mempool processing stage (default: available cpu cores)
+pub fn get_pipeline_workers() -> usize {
+    match dotenv::var("PIPELINE_WORKERS") {
+        Ok(workers) => workers
+            .parse()
+            .expect("PIPELINE_WORKERS is not a valid usize"),
+        Err(_) => std::thread::available_parallelism()
+            .map(|cores| cores.get())
+            .unwrap_or(4),
+    }
+}
+
+/// Return max number of items waiting in front of each mempool processing stage (default 256)
+pub fn get_pipeline_queue_capacity() -> usize {
+    match dotenv::var("PIPELINE_QUEUE_CAPACITY") {
+        Ok(capacity) => capacity
+            .parse()
+            .expect("PIPELINE_QUEUE_CAPACITY is not a valid usize"),
+        Err(_) => 256,
+    }
+}
+
+/// Return max number of stale backlog recipes re-simulated at the same time (default 8)
+pub fn get_revalidation_concurrency() -> usize {
+    match dotenv::var("REVALIDATION_CONCURRENCY") {
+        Ok(concurrency) => concurrency
+            .parse()
+            .expect("REVALIDATION_CONCURRENCY is not a valid usize"),
+        Err(_) => 8,
+    }
+}
@@ -1,4 +1,5 @@
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 
 use ethers::{prelude::*, types::transaction::eip2718::TypedTransaction};
 
@@ -7,12 +8,19 @@ pub mod contracts;
 
 pub mod dotenv;
 pub mod encode_packed;
+pub mod provider_manager;
 pub mod state_diff;
+pub mod swap_decoder;
 pub mod testhelper;
+pub mod transport;
 pub mod tx_builder;
 
 pub use encode_packed::*;
 
+use crate::types::ProviderManagerError;
+use provider_manager::{Backoff, ProviderManager, RpcConnector};
+use transport::RpcClient;
+
 // ========= GENERAL HELPERS
 
 /// Calculate the next block base fee
@@ -50,6 +58,14 @@ pub fn u256_to_h256_be(u: U256) -> H256 {
     h
 }
 
+/// Storage slot of weth's `balanceOf` mapping holding the balance of `owner`
+pub fn get_weth_balance_slot(owner: Address) -> U256 {
+    U256::from(ethers::utils::keccak256(ethers::abi::encode(&[
+        ethers::abi::Token::Address(owner),
+        ethers::abi::Token::Uint(U256::from(3)),
+    ])))
+}
+
 /// Sign eip1559 transactions
 pub async fn sign_eip1559(
     tx: Eip1559TransactionRequest,
@@ -64,8 +80,25 @@ pub async fn sign_eip1559(
     Ok(tx_typed.rlp_signed(&signed_frontrun_tx_sig))
 }
 
-/// Create Websocket Client
-pub async fn create_websocket_client() -> eyre::Result<Arc<Provider<Ws>>> {
-    let client = dotenv::get_ws_provider().await;
-    Ok(Arc::new(client))
+static PROVIDER_MANAGER: OnceLock<ProviderManager<RpcConnector>> = OnceLock::new();
+
+/// Pool of rpc connections shared by the whole bot
+pub fn get_provider_manager() -> &'static ProviderManager<RpcConnector> {
+    PROVIDER_MANAGER.get_or_init(|| {
+        let (initial, max) = dotenv::get_reconnect_backoff_ms();
+        ProviderManager::new(
+            RpcConnector,
+            dotenv::get_rpc_urls(),
+            dotenv::get_provider_pool_size(),
+            Backoff {
+                initial: Duration::from_millis(initial),
+                max: Duration::from_millis(max),
+            },
+        )
+    })
+}
+
+/// Get rpc client from the shared pool
+pub async fn create_rpc_client() -> Result<Arc<RpcClient>, ProviderManagerError> {
+    get_provider_manager().get().await
 }
\ No newline at end of file
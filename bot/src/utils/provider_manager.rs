@@ -0,0 +1,328 @@
+use ethers::prelude::*;
+use futures::future::BoxFuture;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use super::transport::{RpcClient, RpcTransport};
+use crate::types::ProviderManagerError;
+
+/// Opens connections to an rpc url (swappable so tests can use a mock transport)
+pub trait Connector: Send + Sync {
+    type Middleware: Send + Sync;
+
+    // Open a new connection to `url`
+    fn connect<'a>(
+        &'a self,
+        url: &'a str,
+    ) -> BoxFuture<'a, Result<Arc<Self::Middleware>, ProviderManagerError>>;
+}
+
+/// Opens ws, ipc or http connections (picked by url scheme)
+pub struct RpcConnector;
+
+impl Connector for RpcConnector {
+    type Middleware = RpcClient;
+
+    fn connect<'a>(
+        &'a self,
+        url: &'a str,
+    ) -> BoxFuture<'a, Result<Arc<Self::Middleware>, ProviderManagerError>> {
+        Box::pin(async move {
+            RpcTransport::connect(url)
+                .await
+                .map(|transport| Arc::new(Provider::new(transport)))
+                .map_err(|e| ProviderManagerError::ConnectionFailed(url.to_string(), e.to_string()))
+        })
+    }
+}
+
+/// Exponential backoff between reconnect attempts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(250),
+            max: Duration::from_secs(10),
+        }
+    }
+}
+
+impl Backoff {
+    // Delay before reconnect attempt number `attempt` (starting at 0)
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u32 << attempt.min(16);
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+}
+
+struct PooledClient<M> {
+    client: Arc<M>,
+    /// index of url the client is connected to
+    url_index: usize,
+}
+
+/// Keeps a small pool of persistent connections shared by the whole bot
+///
+/// Connections are opened lazily, handed out round robin, and replaced once reported as failed.
+/// When connecting, urls are tried in order starting at the last url that worked (failover).
+pub struct ProviderManager<C: Connector> {
+    connector: C,
+    urls: Vec<String>,
+    slots: Vec<Mutex<Option<PooledClient<C::Middleware>>>>,
+    next_slot: AtomicUsize,
+    preferred_url: AtomicUsize,
+    backoff: Backoff,
+}
+
+impl<C: Connector> ProviderManager<C> {
+    // Create a new `ProviderManager` (no connection is opened until first use)
+    //
+    // Arguments:
+    // * `connector`: opens connections
+    // * `urls`: rpc urls in order of preference
+    // * `pool_size`: number of persistent connections
+    // * `backoff`: delay between reconnect attempts when retrying
+    //
+    // Returns: a new `ProviderManager` instance
+    pub fn new(connector: C, urls: Vec<String>, pool_size: usize, backoff: Backoff) -> Self {
+        Self {
+            connector,
+            urls,
+            slots: (0..pool_size.max(1)).map(|_| Mutex::new(None)).collect(),
+            next_slot: AtomicUsize::new(0),
+            preferred_url: AtomicUsize::new(0),
+            backoff,
+        }
+    }
+
+    // Get a connection from the pool, connecting first if needed
+    //
+    // Returns:
+    // Ok(Arc<C::Middleware>): connected client
+    // Err(ProviderManagerError): if no url could be connected to
+    pub async fn get(&self) -> Result<Arc<C::Middleware>, ProviderManagerError> {
+        if self.urls.is_empty() {
+            return Err(ProviderManagerError::NoUrlsConfigured());
+        }
+
+        let slot = self.next_slot.fetch_add(1, Ordering::Relaxed) % self.slots.len();
+        let mut pooled = self.slots[slot].lock().await;
+
+        if let Some(pooled) = pooled.as_ref() {
+            return Ok(pooled.client.clone());
+        }
+
+        let connected = self.connect_any().await?;
+        let client = connected.client.clone();
+        *pooled = Some(connected);
+        Ok(client)
+    }
+
+    // Get a connection from the pool, retrying with backoff until one is available
+    pub async fn get_with_retry(&self) -> Arc<C::Middleware> {
+        let mut attempt = 0;
+        loop {
+            match self.get().await {
+                Ok(client) => return client,
+                Err(e) => {
+                    let delay = self.backoff.delay(attempt);
+                    log::error!("{}, retrying in {:?}", e, delay);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    // Delays used between reconnect attempts
+    pub fn backoff(&self) -> Backoff {
+        self.backoff
+    }
+
+    // Drop a connection that stopped working, it is replaced on next use
+    //
+    // Arguments:
+    // * `client`: client previously returned by this manager
+    //
+    // Returns: This function returns nothing
+    pub async fn report_failure(&self, client: &Arc<C::Middleware>) {
+        for slot in self.slots.iter() {
+            let mut pooled = slot.lock().await;
+            let url_index = match pooled.as_ref() {
+                Some(pooled) if Arc::ptr_eq(&pooled.client, client) => pooled.url_index,
+                _ => continue,
+            };
+
+            // fail over to next url for the replacement connection
+            self.preferred_url
+                .store((url_index + 1) % self.urls.len(), Ordering::Relaxed);
+            *pooled = None;
+        }
+    }
+
+    // Connect to the first reachable url, starting at the preferred one
+    async fn connect_any(&self) -> Result<PooledClient<C::Middleware>, ProviderManagerError> {
+        let start = self.preferred_url.load(Ordering::Relaxed);
+
+        for offset in 0..self.urls.len() {
+            let url_index = (start + offset) % self.urls.len();
+            match self.connector.connect(&self.urls[url_index]).await {
+                Ok(client) => {
+                    self.preferred_url.store(url_index, Ordering::Relaxed);
+                    return Ok(PooledClient { client, url_index });
+                }
+                Err(e) => log::error!("{}", e),
+            }
+        }
+
+        Err(ProviderManagerError::AllUrlsFailed(self.urls.len()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Mutex as StdMutex;
+    use tokio::runtime::Runtime;
+
+    /// Connects to mocked providers, urls in `down` refuse connections
+    struct MockConnector {
+        down: StdMutex<Vec<String>>,
+        connected_to: StdMutex<Vec<String>>,
+    }
+
+    impl MockConnector {
+        fn new(down: &[&str]) -> Self {
+            Self {
+                down: StdMutex::new(down.iter().map(|url| url.to_string()).collect()),
+                connected_to: StdMutex::new(vec![]),
+            }
+        }
+
+        fn connected_to(&self) -> Vec<String> {
+            self.connected_to.lock().unwrap().clone()
+        }
+    }
+
+    impl Connector for MockConnector {
+        type Middleware = Provider<MockProvider>;
+
+        fn connect<'a>(
+            &'a self,
+            url: &'a str,
+        ) -> BoxFuture<'a, Result<Arc<Self::Middleware>, ProviderManagerError>> {
+            Box::pin(async move {
+                if self.down.lock().unwrap().iter().any(|down| down == url) {
+                    return Err(ProviderManagerError::ConnectionFailed(
+                        url.to_string(),
+                        "connection refused".to_string(),
+                    ));
+                }
+                self.connected_to.lock().unwrap().push(url.to_string());
+                let (provider, _) = Provider::mocked();
+                Ok(Arc::new(provider))
+            })
+        }
+    }
+
+    fn urls() -> Vec<String> {
+        vec!["ws://primary".to_string(), "ws://fallback".to_string()]
+    }
+
+    #[test]
+    fn reuses_pooled_connections() {
+        let rt = Runtime::new().unwrap();
+        let manager = ProviderManager::new(MockConnector::new(&[]), urls(), 2, Backoff::default());
+
+        rt.block_on(async {
+            let first = manager.get().await.unwrap();
+            let second = manager.get().await.unwrap();
+            let third = manager.get().await.unwrap();
+
+            assert!(!Arc::ptr_eq(&first, &second));
+            assert!(Arc::ptr_eq(&first, &third));
+        });
+        assert_eq!(
+            manager.connector.connected_to(),
+            vec!["ws://primary", "ws://primary"]
+        );
+    }
+
+    #[test]
+    fn fails_over_to_next_url() {
+        let rt = Runtime::new().unwrap();
+        let manager = ProviderManager::new(
+            MockConnector::new(&["ws://primary"]),
+            urls(),
+            1,
+            Backoff::default(),
+        );
+
+        rt.block_on(async {
+            manager.get().await.unwrap();
+        });
+        assert_eq!(manager.connector.connected_to(), vec!["ws://fallback"]);
+    }
+
+    #[test]
+    fn reconnects_after_reported_failure() {
+        let rt = Runtime::new().unwrap();
+        let manager = ProviderManager::new(MockConnector::new(&[]), urls(), 1, Backoff::default());
+
+        rt.block_on(async {
+            let broken = manager.get().await.unwrap();
+            manager.report_failure(&broken).await;
+
+            let replacement = manager.get().await.unwrap();
+            assert!(!Arc::ptr_eq(&broken, &replacement));
+        });
+        assert_eq!(
+            manager.connector.connected_to(),
+            vec!["ws://primary", "ws://fallback"]
+        );
+    }
+
+    #[test]
+    fn errors_instead_of_panicking_when_all_urls_down() {
+        let rt = Runtime::new().unwrap();
+        let manager = ProviderManager::new(
+            MockConnector::new(&["ws://primary", "ws://fallback"]),
+            urls(),
+            1,
+            Backoff::default(),
+        );
+        let no_urls = ProviderManager::new(MockConnector::new(&[]), vec![], 1, Backoff::default());
+
+        rt.block_on(async {
+            assert!(matches!(
+                manager.get().await,
+                Err(ProviderManagerError::AllUrlsFailed(2))
+            ));
+            assert!(matches!(
+                no_urls.get().await,
+                Err(ProviderManagerError::NoUrlsConfigured())
+            ));
+        });
+    }
+
+    #[test]
+    fn backoff_doubles_until_max() {
+        let backoff = Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+        };
+
+        assert_eq!(backoff.delay(0), Duration::from_millis(100));
+        assert_eq!(backoff.delay(1), Duration::from_millis(200));
+        assert_eq!(backoff.delay(3), Duration::from_millis(800));
+        assert_eq!(backoff.delay(4), Duration::from_secs(1));
+        assert_eq!(backoff.delay(100), Duration::from_secs(1));
+    }
+}
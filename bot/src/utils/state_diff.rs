@@ -1,14 +1,14 @@
-use crate::{prelude::Pool, utils};
+use crate::forked_db::state_cache::BlockStateCache;
+use crate::forked_db::state_fetcher::{self, FetchedAccount, StateFetchMode};
+use crate::{metrics, prelude::Pool, utils};
 use dashmap::DashMap;
 use ethers::prelude::*;
 use futures::stream::FuturesUnordered;
-use revm::{
-    db::{CacheDB, EmptyDB},
-    primitives::{AccountInfo, Bytecode},
-};
+use revm::db::{CacheDB, EmptyDB};
 use std::{
     collections::{btree_map::Entry, BTreeMap},
     sync::Arc,
+    time::Instant,
 };
 
 /// Holds pools that have the potential to be sandwiched
@@ -31,15 +31,15 @@ impl SandwichablePool {
 // Extract state diffs from a given tx
 //
 // Arguments:
-// * `client`: Websocket provider used for making rpc calls
+// * `client`: Provider used for making rpc calls
 // * `meats`: Vec of transactions to extract state diffs from
 // * `block_num`: Block number of the block the txs are in
 //
 // Returns:
 // Some(BTreeMap<Address, AccountDiff>): State diffs for each address)
 // None: If encountered error or state diffs are non existant
-pub async fn get_from_txs(
-    client: &Arc<Provider<Ws>>,
+pub async fn get_from_txs<M: Middleware>(
+    client: &Arc<M>,
     meats: &Vec<Transaction>,
     block_num: BlockNumber,
 ) -> Option<BTreeMap<Address, AccountDiff>> {
@@ -78,6 +78,38 @@ pub async fn get_from_txs(
     Some(merged_state_diffs)
 }
 
+// Extract state diffs of every tx in a block that landed
+//
+// Arguments:
+// * `client`: Provider used for making rpc calls
+// * `block_num`: Block number of the block to replay
+//
+// Returns:
+// Some(Vec<BTreeMap<Address, AccountDiff>>): State diffs of each tx (in block order)
+// None: If encountered error during rpc call
+pub async fn get_from_block<M: Middleware>(
+    client: &Arc<M>,
+    block_num: BlockNumber,
+) -> Option<Vec<BTreeMap<Address, AccountDiff>>> {
+    let block_traces = match client
+        .trace_replay_block_transactions(block_num, vec![TraceType::StateDiff])
+        .await
+    {
+        Ok(x) => x,
+        Err(e) => {
+            log::error!("Failed to replay block {:?}: {:?}", block_num, e);
+            return None;
+        }
+    };
+
+    Some(
+        block_traces
+            .into_iter()
+            .filter_map(|bt| bt.state_diff.map(|sd| sd.0))
+            .collect(),
+    )
+}
+
 /// Decode statediff to produce Vec of pools interacted with
 ///
 /// Arguments:
@@ -130,53 +162,60 @@ pub fn extract_pools(
 // Arguments:
 // * `state`: Statediffs used as values for creation of cache_db
 // * `block_num`: Block number to get state from
-// * `provider`: Websocket provider used to make rpc calls
+// * `provider`: Provider used to make rpc calls
+// * `shared_cache`: State already fetched at `block_num`, missing accounts are added to it
 //
 // Returns:
 // Ok(CacheDB<EmptyDB>): cacheDB created from statediffs, if no errors
-// Err(ProviderError): If encountered error during rpc calls
-pub async fn to_cache_db(
+// Err(M::Error): If encountered error during rpc calls
+pub async fn to_cache_db<M: Middleware>(
     state: &BTreeMap<Address, AccountDiff>,
     block_num: Option<BlockId>,
-    provider: &Arc<Provider<Ws>>,
-) -> Result<CacheDB<EmptyDB>, ProviderError> {
+    provider: &Arc<M>,
+    shared_cache: Option<&BlockStateCache>,
+) -> Result<CacheDB<EmptyDB>, M::Error> {
     let mut cache_db = CacheDB::new(EmptyDB::default());
 
+    let fetch_mode = StateFetchMode::from_env();
+    let started = Instant::now();
     let mut futures = FuturesUnordered::new();
 
-    for (address, acc_diff) in state.iter() {
-        let nonce_provider = provider.clone();
-        let balance_provider = provider.clone();
-        let code_provider = provider.clone();
-
-        let addy = *address;
+    for address in state.keys() {
+        let cached = shared_cache.and_then(|cache| cache.account(address.0.into()));
+        if let Some(info) = cached {
+            cache_db.insert_account_info(address.0.into(), info);
+            continue;
+        }
 
         let future = async move {
-            let nonce = nonce_provider
-                .get_transaction_count(addy, block_num)
-                .await?;
-
-            let balance = balance_provider.get_balance(addy, block_num).await?;
-
-            let code = code_provider.get_code(addy, block_num).await?;
-
-            Ok::<(AccountDiff, Address, U256, U256, Bytes), ProviderError>((
-                acc_diff.clone(),
+            let fetched = state_fetcher::fetch_account(
+                provider.as_ref(),
                 *address,
-                nonce,
-                balance,
-                code,
-            ))
+                true,
+                &[],
+                block_num,
+                fetch_mode,
+            )
+            .await?;
+            Ok::<(Address, FetchedAccount), M::Error>((*address, fetched))
         };
 
         futures.push(future);
     }
 
     while let Some(result) = futures.next().await {
-        let (acc_diff, address, nonce, balance, code) = result?;
-        let info = AccountInfo::new(balance.into(), nonce.as_u64(), Bytecode::new_raw(code.0));
-        cache_db.insert_account_info(address.0.into(), info);
+        let (address, fetched) = result?;
+        if let Some(cache) = shared_cache {
+            cache.insert_fetched(address.0.into(), &fetched);
+        }
+        if let Some(info) = fetched.info {
+            cache_db.insert_account_info(address.0.into(), info);
+        }
+    }
+    metrics::RPC_LATENCY_CACHE_DB.record(started.elapsed());
 
+    // starting state of touched slots
+    for (address, acc_diff) in state.iter() {
         acc_diff.storage.iter().for_each(|(slot, storage_diff)| {
             let slot_value: U256 = match storage_diff.to_owned() {
                 Diff::Changed(v) => v.from.0.into(),
@@ -0,0 +1,420 @@
+use ethers::abi::ParamType;
+use ethers::prelude::*;
+
+use super::{
+    as_address, as_addresses, as_array, as_bytes, as_tuple, as_uint, decode_call, decode_call_with,
+    decode_v3_path, tuple_params, DecodedSwap, SwapRouter,
+};
+
+// Kyber's SwapDescriptionV2
+// (srcToken, dstToken, srcReceivers, srcAmounts, feeReceivers, feeAmounts, dstReceiver, amount, minReturnAmount, flags, permit)
+const KYBER_SWAP_DESCRIPTION: &str = "(address,address,address[],uint256[],address[],uint256[],address,uint256,uint256,uint256,bytes)";
+
+// Decode a call to 1inch's AggregationRouterV5
+//
+// Arguments:
+// * `data`: calldata of transaction
+// * `value`: ether sent with transaction
+//
+// Returns:
+// Some(DecodedSwap) if calldata is a supported swap, None otherwise
+pub fn decode_one_inch(data: &[u8], _value: U256) -> Option<DecodedSwap> {
+    let router = SwapRouter::OneInchV5;
+
+    // (executor, desc, permit, data)
+    // desc = (srcToken, dstToken, srcReceiver, dstReceiver, amount, minReturnAmount, flags)
+    if let Some(t) = decode_call(
+        "swap(address,(address,address,address,address,uint256,uint256,uint256),bytes,bytes)",
+        data,
+    ) {
+        let desc = as_tuple(&t[1])?;
+        return Some(DecodedSwap::exact_in(
+            router,
+            as_uint(&desc[4])?,
+            as_uint(&desc[5])?,
+            vec![as_address(&desc[0])?, as_address(&desc[1])?],
+            None,
+        ));
+    }
+
+    // unoswap and uniswapV3Swap encode pools (low 160 bits) + flags instead of a token path
+    let (src_token, amount, min_return, pools) = if let Some(t) =
+        decode_call("unoswap(address,uint256,uint256,uint256[])", data)
+    {
+        (
+            Some(as_address(&t[0])?),
+            t[1].clone(),
+            t[2].clone(),
+            t[3].clone(),
+        )
+    } else if let Some(t) =
+        decode_call("unoswapTo(address,address,uint256,uint256,uint256[])", data)
+    {
+        (
+            Some(as_address(&t[1])?),
+            t[2].clone(),
+            t[3].clone(),
+            t[4].clone(),
+        )
+    } else if let Some(t) = decode_call("uniswapV3Swap(uint256,uint256,uint256[])", data) {
+        (None, t[0].clone(), t[1].clone(), t[2].clone())
+    } else if let Some(t) = decode_call("uniswapV3SwapTo(address,uint256,uint256,uint256[])", data)
+    {
+        (None, t[1].clone(), t[2].clone(), t[3].clone())
+    } else {
+        return None;
+    };
+
+    let pools = as_array(&pools)?
+        .iter()
+        .map(|pool| {
+            let mut word = [0u8; 32];
+            as_uint(pool)?.to_big_endian(&mut word);
+            Some(Address::from_slice(&word[12..]))
+        })
+        .collect::<Option<Vec<Address>>>()?;
+
+    let mut swap = DecodedSwap::exact_in(
+        router,
+        as_uint(&amount)?,
+        as_uint(&min_return)?,
+        src_token.into_iter().collect(),
+        None,
+    );
+    swap.pools = pools;
+    Some(swap)
+}
+
+// Decode a call to 0x's ExchangeProxy
+//
+// Arguments:
+// * `data`: calldata of transaction
+// * `value`: ether sent with transaction (amount in for ETH -> token swaps)
+//
+// Returns:
+// Some(DecodedSwap) if calldata is a supported swap, None otherwise
+pub fn decode_zero_ex(data: &[u8], value: U256) -> Option<DecodedSwap> {
+    let router = SwapRouter::ZeroEx;
+
+    // (tokens, sellAmount, minBuyAmount, isSushi)
+    if let Some(t) = decode_call("sellToUniswap(address[],uint256,uint256,bool)", data) {
+        return Some(DecodedSwap::exact_in(
+            router,
+            as_uint(&t[1])?,
+            as_uint(&t[2])?,
+            as_addresses(&t[0])?,
+            None,
+        ));
+    }
+
+    // (encodedPath, minBuyAmount, recipient) with sell amount sent as value
+    if let Some(t) = decode_call("sellEthForTokenToUniswapV3(bytes,uint256,address)", data) {
+        return Some(DecodedSwap::exact_in(
+            router,
+            value,
+            as_uint(&t[1])?,
+            decode_v3_path(&as_bytes(&t[0])?),
+            None,
+        ));
+    }
+
+    // (encodedPath, sellAmount, minBuyAmount, recipient)
+    for signature in [
+        "sellTokenForEthToUniswapV3(bytes,uint256,uint256,address)",
+        "sellTokenForTokenToUniswapV3(bytes,uint256,uint256,address)",
+    ] {
+        if let Some(t) = decode_call(signature, data) {
+            return Some(DecodedSwap::exact_in(
+                router,
+                as_uint(&t[1])?,
+                as_uint(&t[2])?,
+                decode_v3_path(&as_bytes(&t[0])?),
+                None,
+            ));
+        }
+    }
+
+    // (inputToken, outputToken, inputTokenAmount, minOutputTokenAmount, transformations)
+    if let Some(t) = decode_call(
+        "transformERC20(address,address,uint256,uint256,(uint32,bytes)[])",
+        data,
+    ) {
+        return Some(DecodedSwap::exact_in(
+            router,
+            as_uint(&t[2])?,
+            as_uint(&t[3])?,
+            vec![as_address(&t[0])?, as_address(&t[1])?],
+            None,
+        ));
+    }
+
+    None
+}
+
+// Decode a call to KyberSwap's MetaAggregationRouterV2
+//
+// Arguments:
+// * `data`: calldata of transaction
+// * `value`: ether sent with transaction
+//
+// Returns:
+// Some(DecodedSwap) if calldata is a supported swap, None otherwise
+pub fn decode_kyber(data: &[u8], _value: U256) -> Option<DecodedSwap> {
+    let router = SwapRouter::KyberSwap;
+    let desc_params = tuple_params(KYBER_SWAP_DESCRIPTION);
+
+    let desc = if let Some(t) = decode_call(
+        &format!(
+            "swapSimpleMode(address,{},bytes,bytes)",
+            KYBER_SWAP_DESCRIPTION
+        ),
+        data,
+    ) {
+        as_tuple(&t[1])?
+    } else {
+        // swap(SwapExecutionParams) = (callTarget, approveTarget, targetData, desc, clientData)
+        // nested tuples aren't supported by the type parser so params are built by hand
+        let execution_params = ParamType::Tuple(vec![
+            ParamType::Address,
+            ParamType::Address,
+            ParamType::Bytes,
+            ParamType::Tuple(desc_params),
+            ParamType::Bytes,
+        ]);
+        let t = decode_call_with(
+            &format!(
+                "swap((address,address,bytes,{},bytes))",
+                KYBER_SWAP_DESCRIPTION
+            ),
+            &[execution_params],
+            data,
+        )?;
+        as_tuple(&as_tuple(&t[0])?[3])?
+    };
+
+    Some(DecodedSwap::exact_in(
+        router,
+        as_uint(&desc[7])?,
+        as_uint(&desc[8])?,
+        vec![as_address(&desc[0])?, as_address(&desc[1])?],
+        None,
+    ))
+}
+
+// Decode a call to MetaMask's swap router
+//
+// The router forwards to an aggregator adapter whose min amount out is encoded in an
+// aggregator specific blob, so only amount in and token in are decoded
+//
+// Arguments:
+// * `data`: calldata of transaction
+// * `value`: ether sent with transaction
+//
+// Returns:
+// Some(DecodedSwap) if calldata is a swap, None otherwise
+pub fn decode_metamask(data: &[u8], _value: U256) -> Option<DecodedSwap> {
+    // (aggregatorId, tokenFrom, amount, data)
+    let t = decode_call("swap(string,address,uint256,bytes)", data)?;
+
+    let mut swap = DecodedSwap::exact_in(
+        SwapRouter::MetaMask,
+        as_uint(&t[2])?,
+        U256::zero(),
+        vec![as_address(&t[1])?],
+        None,
+    );
+    swap.amount_out_min = None;
+    Some(swap)
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::abi::Token;
+
+    use super::super::{encode_call, encode_v3_path};
+    use super::*;
+    use crate::utils::constants::get_weth_address;
+
+    fn token() -> Address {
+        Address::from_low_u64_be(0xbeef)
+    }
+
+    fn recipient() -> Token {
+        Token::Address(Address::from_low_u64_be(1))
+    }
+
+    fn uint(amount: u64) -> Token {
+        Token::Uint(U256::from(amount))
+    }
+
+    #[test]
+    fn decodes_one_inch_calls() {
+        let weth = get_weth_address();
+
+        // desc = (srcToken, dstToken, srcReceiver, dstReceiver, amount, minReturnAmount, flags)
+        let data = encode_call(
+            "swap(address,(address,address,address,address,uint256,uint256,uint256),bytes,bytes)",
+            &[
+                recipient(),
+                Token::Tuple(vec![
+                    Token::Address(weth),
+                    Token::Address(token()),
+                    recipient(),
+                    recipient(),
+                    uint(100),
+                    uint(90),
+                    uint(0),
+                ]),
+                Token::Bytes(vec![]),
+                Token::Bytes(vec![]),
+            ],
+        );
+        assert_eq!(
+            decode_one_inch(&data, U256::zero()),
+            Some(DecodedSwap::exact_in(
+                SwapRouter::OneInchV5,
+                U256::from(100),
+                U256::from(90),
+                vec![weth, token()],
+                None,
+            ))
+        );
+
+        // pools are packed with flags in the upper bits
+        let pool = Address::from_low_u64_be(0xabcd);
+        let packed = (U256::one() << 255) | U256::from_big_endian(pool.as_bytes());
+        let data = encode_call(
+            "unoswap(address,uint256,uint256,uint256[])",
+            &[
+                Token::Address(weth),
+                uint(100),
+                uint(90),
+                Token::Array(vec![Token::Uint(packed)]),
+            ],
+        );
+        let swap = decode_one_inch(&data, U256::zero()).unwrap();
+        assert_eq!(swap.pools, vec![pool]);
+        assert_eq!(swap.path, vec![weth]);
+        assert_eq!(swap.amount_in, Some(U256::from(100)));
+        assert_eq!(swap.amount_out_min, Some(U256::from(90)));
+    }
+
+    #[test]
+    fn decodes_zero_ex_calls() {
+        let weth = get_weth_address();
+
+        let data = encode_call(
+            "sellToUniswap(address[],uint256,uint256,bool)",
+            &[
+                Token::Array(vec![Token::Address(weth), Token::Address(token())]),
+                uint(100),
+                uint(90),
+                Token::Bool(false),
+            ],
+        );
+        assert_eq!(
+            decode_zero_ex(&data, U256::zero()),
+            Some(DecodedSwap::exact_in(
+                SwapRouter::ZeroEx,
+                U256::from(100),
+                U256::from(90),
+                vec![weth, token()],
+                None,
+            ))
+        );
+
+        // sell amount is sent as value
+        let data = encode_call(
+            "sellEthForTokenToUniswapV3(bytes,uint256,address)",
+            &[
+                Token::Bytes(encode_v3_path(&[weth, token()], 3000)),
+                uint(90),
+                recipient(),
+            ],
+        );
+        assert_eq!(
+            decode_zero_ex(&data, U256::from(100)),
+            Some(DecodedSwap::exact_in(
+                SwapRouter::ZeroEx,
+                U256::from(100),
+                U256::from(90),
+                vec![weth, token()],
+                None,
+            ))
+        );
+    }
+
+    #[test]
+    fn decodes_kyber_calls() {
+        let weth = get_weth_address();
+        let desc = Token::Tuple(vec![
+            Token::Address(weth),
+            Token::Address(token()),
+            Token::Array(vec![]),
+            Token::Array(vec![]),
+            Token::Array(vec![]),
+            Token::Array(vec![]),
+            recipient(),
+            uint(100),
+            uint(90),
+            uint(0),
+            Token::Bytes(vec![]),
+        ]);
+        let expected = Some(DecodedSwap::exact_in(
+            SwapRouter::KyberSwap,
+            U256::from(100),
+            U256::from(90),
+            vec![weth, token()],
+            None,
+        ));
+
+        let data = encode_call(
+            &format!(
+                "swapSimpleMode(address,{},bytes,bytes)",
+                KYBER_SWAP_DESCRIPTION
+            ),
+            &[
+                recipient(),
+                desc.clone(),
+                Token::Bytes(vec![]),
+                Token::Bytes(vec![]),
+            ],
+        );
+        assert_eq!(decode_kyber(&data, U256::zero()), expected);
+
+        let data = encode_call(
+            &format!(
+                "swap((address,address,bytes,{},bytes))",
+                KYBER_SWAP_DESCRIPTION
+            ),
+            &[Token::Tuple(vec![
+                recipient(),
+                recipient(),
+                Token::Bytes(vec![]),
+                desc,
+                Token::Bytes(vec![]),
+            ])],
+        );
+        assert_eq!(decode_kyber(&data, U256::zero()), expected);
+    }
+
+    #[test]
+    fn decodes_metamask_calls() {
+        let data = encode_call(
+            "swap(string,address,uint256,bytes)",
+            &[
+                Token::String("oneInchV5FeeDynamic".to_string()),
+                Token::Address(token()),
+                uint(100),
+                Token::Bytes(vec![0x12, 0x34]),
+            ],
+        );
+
+        let swap = decode_metamask(&data, U256::zero()).unwrap();
+        assert_eq!(swap.router, SwapRouter::MetaMask);
+        assert_eq!(swap.amount_in, Some(U256::from(100)));
+        assert_eq!(swap.path, vec![token()]);
+        // min amount out lives in the aggregator's blob
+        assert_eq!(swap.amount_out_min, None);
+    }
+}
@@ -0,0 +1,340 @@
+use std::str::FromStr;
+
+use ethers::abi::{self, param_type::Reader, ParamType, Token};
+use ethers::prelude::*;
+
+use crate::utils::constants::get_weth_address;
+
+pub mod aggregators;
+pub mod uniswap;
+
+/// Router that a swap was sent through
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SwapRouter {
+    UniswapV2Router02,
+    SwapRouter02,
+    UniversalRouter,
+    OneInchV5,
+    ZeroEx,
+    KyberSwap,
+    MetaMask,
+}
+
+impl SwapRouter {
+    // Does the router swap strictly through the pools implied by the token path
+    // (aggregators may split or reroute a swap so its slippage can't be tied to one pool)
+    pub fn routes_by_path(&self) -> bool {
+        matches!(
+            self,
+            SwapRouter::UniswapV2Router02 | SwapRouter::SwapRouter02 | SwapRouter::UniversalRouter
+        )
+    }
+
+    // Router deployed at `address` (mainnet deployments only)
+    pub fn at(address: Address) -> Option<Self> {
+        known_routers()
+            .into_iter()
+            .find(|(deployment, _)| *deployment == address)
+            .map(|(_, router)| router)
+    }
+
+    // Decode calldata sent to this router
+    fn decode(&self, data: &[u8], value: U256) -> Option<DecodedSwap> {
+        match self {
+            SwapRouter::UniswapV2Router02 => uniswap::decode_v2_router(data, value),
+            SwapRouter::SwapRouter02 => uniswap::decode_swap_router02(data, value),
+            SwapRouter::UniversalRouter => uniswap::decode_universal_router(data, value),
+            SwapRouter::OneInchV5 => aggregators::decode_one_inch(data, value),
+            SwapRouter::ZeroEx => aggregators::decode_zero_ex(data, value),
+            SwapRouter::KyberSwap => aggregators::decode_kyber(data, value),
+            SwapRouter::MetaMask => aggregators::decode_metamask(data, value),
+        }
+    }
+}
+
+// Addresses of the routers we decode swaps for
+fn known_routers() -> Vec<(Address, SwapRouter)> {
+    [
+        // uniswap v2 and sushiswap
+        (
+            "0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D",
+            SwapRouter::UniswapV2Router02,
+        ),
+        (
+            "0xd9e1cE17f2641f24aE83637ab66a2cca9C378B9F",
+            SwapRouter::UniswapV2Router02,
+        ),
+        (
+            "0x68b3465833fb72A70ecDF485E0e4C7bD8665Fc45",
+            SwapRouter::SwapRouter02,
+        ),
+        (
+            "0xEf1c6E67703c7BD7107eed8303Fbe6EC2554BF6B",
+            SwapRouter::UniversalRouter,
+        ),
+        (
+            "0x3fC91A3afd70395Cd496C647d5a6CC9D4B2b7FAD",
+            SwapRouter::UniversalRouter,
+        ),
+        (
+            "0x1111111254EEB25477B68fb85Ed929f73A960582",
+            SwapRouter::OneInchV5,
+        ),
+        (
+            "0xDef1C0ded9bec7F1a1670819833240f027b25EfF",
+            SwapRouter::ZeroEx,
+        ),
+        (
+            "0x6131B5fae19EA4f9D964eAc0408E4408b66337b5",
+            SwapRouter::KyberSwap,
+        ),
+        (
+            "0x881D40237659C251811CEC9c364ef91dC08D300C",
+            SwapRouter::MetaMask,
+        ),
+    ]
+    .into_iter()
+    .map(|(address, router)| (Address::from_str(address).unwrap(), router))
+    .collect()
+}
+
+/// Swap parameters extracted from a router call
+///
+/// Exact input swaps fill `amount_in` + `amount_out_min`,
+/// exact output swaps fill `amount_out` + `amount_in_max`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodedSwap {
+    pub router: SwapRouter,
+    pub amount_in: Option<U256>,
+    pub amount_out_min: Option<U256>,
+    pub amount_out: Option<U256>,
+    pub amount_in_max: Option<U256>,
+    /// tokens in swap order (ether placeholders replaced by weth)
+    pub path: Vec<Address>,
+    /// pools hit by the swap if the router encodes pools instead of tokens
+    pub pools: Vec<Address>,
+    pub deadline: Option<U256>,
+}
+
+impl DecodedSwap {
+    // Create an exact input swap
+    pub fn exact_in(
+        router: SwapRouter,
+        amount_in: U256,
+        amount_out_min: U256,
+        path: Vec<Address>,
+        deadline: Option<U256>,
+    ) -> Self {
+        Self {
+            router,
+            amount_in: Some(amount_in),
+            amount_out_min: Some(amount_out_min),
+            amount_out: None,
+            amount_in_max: None,
+            path: path.into_iter().map(normalize_token).collect(),
+            pools: vec![],
+            deadline,
+        }
+    }
+
+    // Create an exact output swap
+    pub fn exact_out(
+        router: SwapRouter,
+        amount_out: U256,
+        amount_in_max: U256,
+        path: Vec<Address>,
+        deadline: Option<U256>,
+    ) -> Self {
+        Self {
+            router,
+            amount_in: None,
+            amount_out_min: None,
+            amount_out: Some(amount_out),
+            amount_in_max: Some(amount_in_max),
+            path: path.into_iter().map(normalize_token).collect(),
+            pools: vec![],
+            deadline,
+        }
+    }
+
+    // Is the swap specified by its output amount
+    pub fn is_exact_out(&self) -> bool {
+        self.amount_out.is_some()
+    }
+}
+
+// Decode the swap that `tx` performs (if it's sent to a supported router)
+//
+// Arguments:
+// * `tx`: pending transaction
+//
+// Returns:
+// Some(DecodedSwap): if `tx` is sent to a known router and the calldata matches one of its swaps
+// None: otherwise (calldata sent to any other contract can't be trusted to mean the same)
+pub fn decode_swap(tx: &Transaction) -> Option<DecodedSwap> {
+    SwapRouter::at(tx.to?)?.decode(tx.input.as_ref(), tx.value)
+}
+
+// Decode calldata for `signature` (e.g. "swap(address,uint256)")
+//
+// Returns:
+// Some(Vec<Token>): decoded arguments if the selector matches
+// None: if selector doesn't match or decoding failed
+pub(crate) fn decode_call(signature: &str, data: &[u8]) -> Option<Vec<Token>> {
+    let args = &signature[signature.find('(')?..];
+    let params = match Reader::read(args).ok()? {
+        ParamType::Tuple(params) => params,
+        _ => return None,
+    };
+    decode_call_with(signature, &params, data)
+}
+
+// Decode calldata for `signature` using explicit param types (needed for nested tuples)
+pub(crate) fn decode_call_with(
+    signature: &str,
+    params: &[ParamType],
+    data: &[u8],
+) -> Option<Vec<Token>> {
+    if data.len() < 4 || data[..4] != ethers::utils::id(signature) {
+        return None;
+    }
+    abi::decode(params, &data[4..]).ok()
+}
+
+// Parse a type string such as "(address,uint256)" into its param types
+pub(crate) fn tuple_params(types: &str) -> Vec<ParamType> {
+    match Reader::read(types) {
+        Ok(ParamType::Tuple(params)) => params,
+        _ => panic!("Invalid tuple type {}", types),
+    }
+}
+
+// Decode a uniswap v3 encoded path (token, fee, token, fee, token, ...)
+pub fn decode_v3_path(path: &[u8]) -> Vec<Address> {
+    let mut tokens = vec![];
+    let mut offset = 0;
+    while offset + 20 <= path.len() {
+        tokens.push(Address::from_slice(&path[offset..offset + 20]));
+        offset += 23;
+    }
+    tokens
+}
+
+// Replace ether placeholders used by routers with weth
+pub fn normalize_token(token: Address) -> Address {
+    let eth_placeholder = Address::from_str("0xEeeeeEeeeEeEeeEeEeEeeEEEeeeeEeeeeeeeEEeE").unwrap();
+    if token == eth_placeholder || token.is_zero() {
+        get_weth_address()
+    } else {
+        token
+    }
+}
+
+pub(crate) fn as_uint(token: &Token) -> Option<U256> {
+    token.clone().into_uint()
+}
+
+pub(crate) fn as_address(token: &Token) -> Option<Address> {
+    token.clone().into_address()
+}
+
+pub(crate) fn as_bytes(token: &Token) -> Option<Vec<u8>> {
+    token.clone().into_bytes()
+}
+
+pub(crate) fn as_tuple(token: &Token) -> Option<Vec<Token>> {
+    token.clone().into_tuple()
+}
+
+pub(crate) fn as_addresses(token: &Token) -> Option<Vec<Address>> {
+    token.clone().into_array()?.iter().map(as_address).collect()
+}
+
+pub(crate) fn as_array(token: &Token) -> Option<Vec<Token>> {
+    token.clone().into_array()
+}
+
+// Address `router` is deployed at
+#[cfg(test)]
+pub(crate) fn router_address(router: SwapRouter) -> Address {
+    known_routers()
+        .into_iter()
+        .find(|(_, deployment)| *deployment == router)
+        .map(|(address, _)| address)
+        .unwrap()
+}
+
+// Calldata calling `signature` with `args`
+#[cfg(test)]
+pub(crate) fn encode_call(signature: &str, args: &[Token]) -> Vec<u8> {
+    let mut data = ethers::utils::id(signature).to_vec();
+    data.extend(abi::encode(args));
+    data
+}
+
+// Uniswap v3 encoded path through `tokens` (every hop uses `fee`)
+#[cfg(test)]
+pub(crate) fn encode_v3_path(tokens: &[Address], fee: u32) -> Vec<u8> {
+    let mut path = vec![];
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 {
+            path.extend(&fee.to_be_bytes()[1..]);
+        }
+        path.extend(token.as_bytes());
+    }
+    path
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decodes_swaps_sent_to_known_routers() {
+        let token = Address::from_low_u64_be(0xbeef);
+        let data = encode_call(
+            "swapExactTokensForTokens(uint256,uint256,address[],address,uint256)",
+            &[
+                Token::Uint(U256::from(100)),
+                Token::Uint(U256::from(90)),
+                Token::Array(vec![Token::Address(token), Token::Address(Address::zero())]),
+                Token::Address(Address::from_low_u64_be(1)),
+                Token::Uint(U256::from(1_700_000_000)),
+            ],
+        );
+        let mut tx = Transaction {
+            to: Some(router_address(SwapRouter::UniswapV2Router02)),
+            input: data.into(),
+            ..Default::default()
+        };
+
+        let swap = decode_swap(&tx).unwrap();
+        assert_eq!(swap.router, SwapRouter::UniswapV2Router02);
+        // zero address is an ether placeholder
+        assert_eq!(swap.path, vec![token, get_weth_address()]);
+
+        // same calldata sent to a contract we don't know
+        tx.to = Some(Address::from_low_u64_be(0xdead));
+        assert_eq!(decode_swap(&tx), None);
+        // calldata of another router
+        tx.to = Some(router_address(SwapRouter::SwapRouter02));
+        assert_eq!(decode_swap(&tx), None);
+
+        let tx = Transaction {
+            to: Some(router_address(SwapRouter::UniswapV2Router02)),
+            input: vec![0xde, 0xad, 0xbe, 0xef].into(),
+            ..Default::default()
+        };
+        assert_eq!(decode_swap(&tx), None);
+    }
+
+    #[test]
+    fn decodes_v3_paths() {
+        let tokens = vec![
+            get_weth_address(),
+            Address::from_low_u64_be(1),
+            Address::from_low_u64_be(2),
+        ];
+        assert_eq!(decode_v3_path(&encode_v3_path(&tokens, 3000)), tokens);
+    }
+}
@@ -0,0 +1,517 @@
+use ethers::abi::{self, ParamType, Token};
+use ethers::prelude::*;
+
+use super::{
+    as_address, as_addresses, as_array, as_bytes, as_tuple, as_uint, decode_call, decode_v3_path,
+    DecodedSwap, SwapRouter,
+};
+
+// UniversalRouter uses this amount to signal "use the router's current balance"
+fn contract_balance() -> U256 {
+    U256::one() << 255
+}
+
+// Exact input swap through SwapRouter02
+//
+// SwapRouter02 uses an amount in of zero to signal "use the router's current balance", the
+// amount is then only known once the swap is simulated so it's left unbounded
+fn router02_exact_in(amount_in: U256, amount_out_min: U256, path: Vec<Address>) -> DecodedSwap {
+    let mut swap = DecodedSwap::exact_in(
+        SwapRouter::SwapRouter02,
+        amount_in,
+        amount_out_min,
+        path,
+        None,
+    );
+    if amount_in.is_zero() {
+        swap.amount_in = None;
+    }
+    swap
+}
+
+// Decode a call to uniswap's V2 Router02 (and forks such as sushiswap)
+//
+// Arguments:
+// * `data`: calldata of transaction
+// * `value`: ether sent with transaction (amount in for ETH -> token swaps)
+//
+// Returns:
+// Some(DecodedSwap) if calldata is a supported swap, None otherwise
+pub fn decode_v2_router(data: &[u8], value: U256) -> Option<DecodedSwap> {
+    let router = SwapRouter::UniswapV2Router02;
+
+    // (amountIn, amountOutMin, path, to, deadline)
+    for signature in [
+        "swapExactTokensForTokens(uint256,uint256,address[],address,uint256)",
+        "swapExactTokensForETH(uint256,uint256,address[],address,uint256)",
+        "swapExactTokensForTokensSupportingFeeOnTransferTokens(uint256,uint256,address[],address,uint256)",
+        "swapExactTokensForETHSupportingFeeOnTransferTokens(uint256,uint256,address[],address,uint256)",
+    ] {
+        if let Some(t) = decode_call(signature, data) {
+            return Some(DecodedSwap::exact_in(
+                router,
+                as_uint(&t[0])?,
+                as_uint(&t[1])?,
+                as_addresses(&t[2])?,
+                as_uint(&t[4]),
+            ));
+        }
+    }
+
+    // (amountOutMin, path, to, deadline) with amount in sent as value
+    for signature in [
+        "swapExactETHForTokens(uint256,address[],address,uint256)",
+        "swapExactETHForTokensSupportingFeeOnTransferTokens(uint256,address[],address,uint256)",
+    ] {
+        if let Some(t) = decode_call(signature, data) {
+            return Some(DecodedSwap::exact_in(
+                router,
+                value,
+                as_uint(&t[0])?,
+                as_addresses(&t[1])?,
+                as_uint(&t[3]),
+            ));
+        }
+    }
+
+    // (amountOut, amountInMax, path, to, deadline)
+    for signature in [
+        "swapTokensForExactTokens(uint256,uint256,address[],address,uint256)",
+        "swapTokensForExactETH(uint256,uint256,address[],address,uint256)",
+    ] {
+        if let Some(t) = decode_call(signature, data) {
+            return Some(DecodedSwap::exact_out(
+                router,
+                as_uint(&t[0])?,
+                as_uint(&t[1])?,
+                as_addresses(&t[2])?,
+                as_uint(&t[4]),
+            ));
+        }
+    }
+
+    // (amountOut, path, to, deadline) with max amount in sent as value
+    if let Some(t) = decode_call(
+        "swapETHForExactTokens(uint256,address[],address,uint256)",
+        data,
+    ) {
+        return Some(DecodedSwap::exact_out(
+            router,
+            as_uint(&t[0])?,
+            value,
+            as_addresses(&t[1])?,
+            as_uint(&t[3]),
+        ));
+    }
+
+    None
+}
+
+// Decode a call to uniswap's SwapRouter02 (including swaps wrapped in multicall)
+//
+// Arguments:
+// * `data`: calldata of transaction
+// * `value`: ether sent with transaction
+//
+// Returns:
+// Some(DecodedSwap) if calldata is a supported swap, None otherwise
+pub fn decode_swap_router02(data: &[u8], _value: U256) -> Option<DecodedSwap> {
+    let router = SwapRouter::SwapRouter02;
+
+    // multicall holds the deadline, the swap itself is one of the inner calls
+    let multicall = decode_call("multicall(uint256,bytes[])", data)
+        .map(|t| (as_uint(&t[0]), t[1].clone()))
+        .or_else(|| decode_call("multicall(bytes32,bytes[])", data).map(|t| (None, t[1].clone())))
+        .or_else(|| decode_call("multicall(bytes[])", data).map(|t| (None, t[0].clone())));
+    if let Some((deadline, calls)) = multicall {
+        return as_array(&calls)?.iter().find_map(|call| {
+            let mut swap = decode_swap_router02(&as_bytes(call)?, _value)?;
+            swap.deadline = deadline.or(swap.deadline);
+            Some(swap)
+        });
+    }
+
+    if let Some(t) = decode_call(
+        "swapExactTokensForTokens(uint256,uint256,address[],address)",
+        data,
+    ) {
+        return Some(router02_exact_in(
+            as_uint(&t[0])?,
+            as_uint(&t[1])?,
+            as_addresses(&t[2])?,
+        ));
+    }
+
+    if let Some(t) = decode_call(
+        "swapTokensForExactTokens(uint256,uint256,address[],address)",
+        data,
+    ) {
+        return Some(DecodedSwap::exact_out(
+            router,
+            as_uint(&t[0])?,
+            as_uint(&t[1])?,
+            as_addresses(&t[2])?,
+            None,
+        ));
+    }
+
+    // (tokenIn, tokenOut, fee, recipient, amountIn, amountOutMinimum, sqrtPriceLimitX96)
+    if let Some(t) = decode_call(
+        "exactInputSingle((address,address,uint24,address,uint256,uint256,uint160))",
+        data,
+    ) {
+        let params = as_tuple(&t[0])?;
+        return Some(router02_exact_in(
+            as_uint(&params[4])?,
+            as_uint(&params[5])?,
+            vec![as_address(&params[0])?, as_address(&params[1])?],
+        ));
+    }
+
+    // (path, recipient, amountIn, amountOutMinimum)
+    if let Some(t) = decode_call("exactInput((bytes,address,uint256,uint256))", data) {
+        let params = as_tuple(&t[0])?;
+        return Some(router02_exact_in(
+            as_uint(&params[2])?,
+            as_uint(&params[3])?,
+            decode_v3_path(&as_bytes(&params[0])?),
+        ));
+    }
+
+    // (tokenIn, tokenOut, fee, recipient, amountOut, amountInMaximum, sqrtPriceLimitX96)
+    if let Some(t) = decode_call(
+        "exactOutputSingle((address,address,uint24,address,uint256,uint256,uint160))",
+        data,
+    ) {
+        let params = as_tuple(&t[0])?;
+        return Some(DecodedSwap::exact_out(
+            router,
+            as_uint(&params[4])?,
+            as_uint(&params[5])?,
+            vec![as_address(&params[0])?, as_address(&params[1])?],
+            None,
+        ));
+    }
+
+    // (path, recipient, amountOut, amountInMaximum), path is encoded from token out to token in
+    if let Some(t) = decode_call("exactOutput((bytes,address,uint256,uint256))", data) {
+        let params = as_tuple(&t[0])?;
+        let mut path = decode_v3_path(&as_bytes(&params[0])?);
+        path.reverse();
+        return Some(DecodedSwap::exact_out(
+            router,
+            as_uint(&params[2])?,
+            as_uint(&params[3])?,
+            path,
+            None,
+        ));
+    }
+
+    None
+}
+
+// Decode a call to uniswap's UniversalRouter (first swap command is used)
+//
+// Arguments:
+// * `data`: calldata of transaction
+// * `value`: ether sent with transaction (used when a swap spends the router's balance)
+//
+// Returns:
+// Some(DecodedSwap) if calldata holds a supported swap command, None otherwise
+pub fn decode_universal_router(data: &[u8], value: U256) -> Option<DecodedSwap> {
+    let router = SwapRouter::UniversalRouter;
+
+    let (commands, inputs, deadline) = match decode_call("execute(bytes,bytes[],uint256)", data) {
+        Some(t) => (as_bytes(&t[0])?, as_array(&t[1])?, as_uint(&t[2])),
+        None => {
+            let t = decode_call("execute(bytes,bytes[])", data)?;
+            (as_bytes(&t[0])?, as_array(&t[1])?, None)
+        }
+    };
+
+    let v3_params = [
+        ParamType::Address,
+        ParamType::Uint(256),
+        ParamType::Uint(256),
+        ParamType::Bytes,
+        ParamType::Bool,
+    ];
+    let v2_params = [
+        ParamType::Address,
+        ParamType::Uint(256),
+        ParamType::Uint(256),
+        ParamType::Array(Box::new(ParamType::Address)),
+        ParamType::Bool,
+    ];
+
+    let resolve_amount = |amount: U256| match amount == contract_balance() {
+        true => value,
+        false => amount,
+    };
+
+    for (command, input) in commands.iter().zip(inputs.iter()) {
+        let input = as_bytes(input)?;
+        let decode =
+            |params: &[ParamType]| -> Option<Vec<Token>> { abi::decode(params, &input).ok() };
+
+        // upper bits of command are flags
+        match command & 0x3f {
+            // V3_SWAP_EXACT_IN (recipient, amountIn, amountOutMin, path, payerIsUser)
+            0x00 => {
+                let t = decode(&v3_params)?;
+                return Some(DecodedSwap::exact_in(
+                    router,
+                    resolve_amount(as_uint(&t[1])?),
+                    as_uint(&t[2])?,
+                    decode_v3_path(&as_bytes(&t[3])?),
+                    deadline,
+                ));
+            }
+            // V3_SWAP_EXACT_OUT (recipient, amountOut, amountInMax, path, payerIsUser)
+            0x01 => {
+                let t = decode(&v3_params)?;
+                let mut path = decode_v3_path(&as_bytes(&t[3])?);
+                path.reverse();
+                return Some(DecodedSwap::exact_out(
+                    router,
+                    as_uint(&t[1])?,
+                    resolve_amount(as_uint(&t[2])?),
+                    path,
+                    deadline,
+                ));
+            }
+            // V2_SWAP_EXACT_IN (recipient, amountIn, amountOutMin, path, payerIsUser)
+            0x08 => {
+                let t = decode(&v2_params)?;
+                return Some(DecodedSwap::exact_in(
+                    router,
+                    resolve_amount(as_uint(&t[1])?),
+                    as_uint(&t[2])?,
+                    as_addresses(&t[3])?,
+                    deadline,
+                ));
+            }
+            // V2_SWAP_EXACT_OUT (recipient, amountOut, amountInMax, path, payerIsUser)
+            0x09 => {
+                let t = decode(&v2_params)?;
+                return Some(DecodedSwap::exact_out(
+                    router,
+                    as_uint(&t[1])?,
+                    resolve_amount(as_uint(&t[2])?),
+                    as_addresses(&t[3])?,
+                    deadline,
+                ));
+            }
+            _ => continue,
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{encode_call, encode_v3_path};
+    use super::*;
+    use crate::utils::constants::get_weth_address;
+
+    fn token() -> Address {
+        Address::from_low_u64_be(0xbeef)
+    }
+
+    fn recipient() -> Token {
+        Token::Address(Address::from_low_u64_be(1))
+    }
+
+    fn path(tokens: &[Address]) -> Token {
+        Token::Array(tokens.iter().map(|t| Token::Address(*t)).collect())
+    }
+
+    fn uint(amount: u64) -> Token {
+        Token::Uint(U256::from(amount))
+    }
+
+    #[test]
+    fn decodes_v2_router_calls() {
+        let weth = get_weth_address();
+
+        // amount in is sent as value
+        let data = encode_call(
+            "swapExactETHForTokens(uint256,address[],address,uint256)",
+            &[uint(90), path(&[weth, token()]), recipient(), uint(1000)],
+        );
+        assert_eq!(
+            decode_v2_router(&data, U256::from(100)),
+            Some(DecodedSwap::exact_in(
+                SwapRouter::UniswapV2Router02,
+                U256::from(100),
+                U256::from(90),
+                vec![weth, token()],
+                Some(U256::from(1000)),
+            ))
+        );
+
+        let data = encode_call(
+            "swapTokensForExactETH(uint256,uint256,address[],address,uint256)",
+            &[
+                uint(100),
+                uint(110),
+                path(&[token(), weth]),
+                recipient(),
+                uint(1000),
+            ],
+        );
+        assert_eq!(
+            decode_v2_router(&data, U256::zero()),
+            Some(DecodedSwap::exact_out(
+                SwapRouter::UniswapV2Router02,
+                U256::from(100),
+                U256::from(110),
+                vec![token(), weth],
+                Some(U256::from(1000)),
+            ))
+        );
+
+        // max amount in is sent as value
+        let data = encode_call(
+            "swapETHForExactTokens(uint256,address[],address,uint256)",
+            &[uint(100), path(&[weth, token()]), recipient(), uint(1000)],
+        );
+        let swap = decode_v2_router(&data, U256::from(120)).unwrap();
+        assert_eq!(swap.amount_in_max, Some(U256::from(120)));
+
+        assert_eq!(decode_v2_router(&data[..40], U256::zero()), None);
+    }
+
+    #[test]
+    fn decodes_swap_router02_calls() {
+        let weth = get_weth_address();
+
+        // (tokenIn, tokenOut, fee, recipient, amountIn, amountOutMinimum, sqrtPriceLimitX96)
+        let exact_input_single = encode_call(
+            "exactInputSingle((address,address,uint24,address,uint256,uint256,uint160))",
+            &[Token::Tuple(vec![
+                Token::Address(weth),
+                Token::Address(token()),
+                uint(500),
+                recipient(),
+                uint(100),
+                uint(90),
+                uint(0),
+            ])],
+        );
+        // deadline is taken from multicall
+        let data = encode_call(
+            "multicall(uint256,bytes[])",
+            &[
+                uint(1000),
+                Token::Array(vec![Token::Bytes(exact_input_single)]),
+            ],
+        );
+        assert_eq!(
+            decode_swap_router02(&data, U256::zero()),
+            Some(DecodedSwap::exact_in(
+                SwapRouter::SwapRouter02,
+                U256::from(100),
+                U256::from(90),
+                vec![weth, token()],
+                Some(U256::from(1000)),
+            ))
+        );
+
+        // path is encoded from token out to token in
+        let data = encode_call(
+            "exactOutput((bytes,address,uint256,uint256))",
+            &[Token::Tuple(vec![
+                Token::Bytes(encode_v3_path(&[token(), weth], 3000)),
+                recipient(),
+                uint(100),
+                uint(110),
+            ])],
+        );
+        assert_eq!(
+            decode_swap_router02(&data, U256::zero()),
+            Some(DecodedSwap::exact_out(
+                SwapRouter::SwapRouter02,
+                U256::from(100),
+                U256::from(110),
+                vec![weth, token()],
+                None,
+            ))
+        );
+
+        // zero amount in swaps whatever the router holds (e.g. after a wrap in the same multicall)
+        let data = encode_call(
+            "exactInput((bytes,address,uint256,uint256))",
+            &[Token::Tuple(vec![
+                Token::Bytes(encode_v3_path(&[weth, token()], 3000)),
+                recipient(),
+                uint(0),
+                uint(90),
+            ])],
+        );
+        let swap = decode_swap_router02(&data, U256::zero()).unwrap();
+        assert_eq!(swap.amount_in, None);
+        assert_eq!(swap.amount_out_min, Some(U256::from(90)));
+        assert!(!swap.is_exact_out());
+    }
+
+    #[test]
+    fn decodes_universal_router_commands() {
+        let weth = get_weth_address();
+
+        // WRAP_ETH is skipped, V2_SWAP_EXACT_IN spends the router's balance (sent as value)
+        let wrap = abi::encode(&[recipient(), uint(100)]);
+        let swap = abi::encode(&[
+            recipient(),
+            Token::Uint(contract_balance()),
+            uint(90),
+            path(&[weth, token()]),
+            Token::Bool(false),
+        ]);
+        let data = encode_call(
+            "execute(bytes,bytes[],uint256)",
+            &[
+                Token::Bytes(vec![0x0b, 0x08]),
+                Token::Array(vec![Token::Bytes(wrap), Token::Bytes(swap)]),
+                uint(1000),
+            ],
+        );
+        assert_eq!(
+            decode_universal_router(&data, U256::from(100)),
+            Some(DecodedSwap::exact_in(
+                SwapRouter::UniversalRouter,
+                U256::from(100),
+                U256::from(90),
+                vec![weth, token()],
+                Some(U256::from(1000)),
+            ))
+        );
+
+        // V3_SWAP_EXACT_OUT with allow revert flag set, path is reversed
+        let swap = abi::encode(&[
+            recipient(),
+            uint(100),
+            uint(110),
+            Token::Bytes(encode_v3_path(&[token(), weth], 3000)),
+            Token::Bool(true),
+        ]);
+        let data = encode_call(
+            "execute(bytes,bytes[])",
+            &[
+                Token::Bytes(vec![0x81]),
+                Token::Array(vec![Token::Bytes(swap)]),
+            ],
+        );
+        assert_eq!(
+            decode_universal_router(&data, U256::zero()),
+            Some(DecodedSwap::exact_out(
+                SwapRouter::UniversalRouter,
+                U256::from(100),
+                U256::from(110),
+                vec![weth, token()],
+                None,
+            ))
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use async_trait::async_trait;
+use ethers::prelude::*;
+use ethers::providers::{HttpClientError, IpcError, JsonRpcError, RpcError, WsClientError};
+use futures::stream::BoxStream;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::value::RawValue;
+use std::fmt::Debug;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Provider used by the bot, transport is picked from the rpc url
+pub type RpcClient = Provider<RpcTransport>;
+
+/// Transport that the bot talks to its node over
+///
+/// * `ws://` / `wss://`: websocket
+/// * `http://` / `https://`: http (calls only, no block or mempool streams)
+/// * anything else: path to an ipc socket (lowest latency when co-located with the node)
+#[derive(Debug, Clone)]
+pub enum RpcTransport {
+    Ws(Ws),
+    Ipc(Ipc),
+    Http(Http),
+}
+
+#[derive(Error, Debug)]
+pub enum RpcTransportError {
+    #[error(transparent)]
+    Ws(#[from] WsClientError),
+    #[error(transparent)]
+    Ipc(#[from] IpcError),
+    #[error(transparent)]
+    Http(#[from] HttpClientError),
+    #[error("Subscriptions are not supported over http")]
+    SubscriptionsUnsupported(),
+}
+
+impl RpcError for RpcTransportError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            RpcTransportError::Ws(e) => e.as_error_response(),
+            RpcTransportError::Ipc(e) => e.as_error_response(),
+            RpcTransportError::Http(e) => e.as_error_response(),
+            RpcTransportError::SubscriptionsUnsupported() => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            RpcTransportError::Ws(e) => e.as_serde_error(),
+            RpcTransportError::Ipc(e) => e.as_serde_error(),
+            RpcTransportError::Http(e) => e.as_serde_error(),
+            RpcTransportError::SubscriptionsUnsupported() => None,
+        }
+    }
+}
+
+impl From<RpcTransportError> for ProviderError {
+    fn from(err: RpcTransportError) -> Self {
+        match err {
+            RpcTransportError::Ws(e) => e.into(),
+            RpcTransportError::Ipc(e) => e.into(),
+            RpcTransportError::Http(e) => e.into(),
+            e => ProviderError::JsonRpcClientError(Box::new(e)),
+        }
+    }
+}
+
+impl RpcTransport {
+    // Connect to `url` using the transport matching its scheme
+    //
+    // Arguments:
+    // * `url`: websocket/http url or ipc path
+    //
+    // Returns:
+    // Ok(RpcTransport): connected transport
+    // Err(ProviderError): if connection failed
+    pub async fn connect(url: &str) -> Result<Self, ProviderError> {
+        if url.starts_with("ws://") || url.starts_with("wss://") {
+            Ok(RpcTransport::Ws(Ws::connect(url).await?))
+        } else if url.starts_with("http://") || url.starts_with("https://") {
+            let http = Http::from_str(url)
+                .map_err(|e| ProviderError::CustomError(format!("Invalid http url: {}", e)))?;
+            Ok(RpcTransport::Http(http))
+        } else {
+            let path = url.strip_prefix("ipc://").unwrap_or(url);
+            Ok(RpcTransport::Ipc(Ipc::connect(path).await?))
+        }
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for RpcTransport {
+    type Error = RpcTransportError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        match self {
+            RpcTransport::Ws(ws) => Ok(JsonRpcClient::request(ws, method, params).await?),
+            RpcTransport::Ipc(ipc) => Ok(JsonRpcClient::request(ipc, method, params).await?),
+            RpcTransport::Http(http) => Ok(JsonRpcClient::request(http, method, params).await?),
+        }
+    }
+}
+
+impl PubsubClient for RpcTransport {
+    type NotificationStream = BoxStream<'static, Box<RawValue>>;
+
+    fn subscribe<T: Into<U256>>(&self, id: T) -> Result<Self::NotificationStream, Self::Error> {
+        match self {
+            RpcTransport::Ws(ws) => Ok(Box::pin(PubsubClient::subscribe(ws, id)?)),
+            RpcTransport::Ipc(ipc) => Ok(Box::pin(PubsubClient::subscribe(ipc, id)?)),
+            RpcTransport::Http(_) => Err(RpcTransportError::SubscriptionsUnsupported()),
+        }
+    }
+
+    fn unsubscribe<T: Into<U256>>(&self, id: T) -> Result<(), Self::Error> {
+        match self {
+            RpcTransport::Ws(ws) => Ok(PubsubClient::unsubscribe(ws, id)?),
+            RpcTransport::Ipc(ipc) => Ok(PubsubClient::unsubscribe(ipc, id)?),
+            RpcTransport::Http(_) => Err(RpcTransportError::SubscriptionsUnsupported()),
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use ethers::abi::{self, Token};
+use ethers::prelude::*;
+
+use super::{get_weth_encode_divisor, JumpTable};
+
+/// Signature of `IMetamorphicContractFactory`'s deploy from implementation method
+const METAMORPHIC_DEPLOY_SIG: &str =
+    "deployMetamorphicContractFromExistingImplementation(bytes32,address,bytes)";
+
+/// Signature of erc20 `transfer`
+const ERC20_TRANSFER_SIG: &str = "transfer(address,uint256)";
+
+// Payload and call value that wraps `amount` of eth sent along into weth held by the contract
+//
+// Arguments:
+// * `jump_table`: jump table of the deployed contract
+// * `block_number`: block the tx lands in (contract checks its last byte)
+// * `amount`: eth sent with the tx
+//
+// Returns:
+// (Vec<u8>, U256): calldata and call value
+pub fn deposit_weth_payload(
+    jump_table: &JumpTable,
+    block_number: U64,
+    amount: U256,
+) -> (Vec<u8>, U256) {
+    (
+        admin_payload(jump_table, block_number, "deposit_weth"),
+        amount,
+    )
+}
+
+// Payload and call value that unwraps `amount` of weth and sends all eth held by the contract
+// to the searcher
+//
+// Arguments:
+// * `jump_table`: jump table of the deployed contract
+// * `block_number`: block the tx lands in (contract checks its last byte)
+// * `amount`: weth to unwrap, rounded down to a multiple of `get_weth_encode_divisor`
+//
+// Returns:
+// (Vec<u8>, U256): calldata and call value
+pub fn recover_weth_payload(
+    jump_table: &JumpTable,
+    block_number: U64,
+    amount: U256,
+) -> (Vec<u8>, U256) {
+    (
+        admin_payload(jump_table, block_number, "recover_weth"),
+        amount / get_weth_encode_divisor(),
+    )
+}
+
+// Payload that self destructs the contract (sending its eth to the searcher) so that the
+// metamorphic factory can deploy new code to the same address
+//
+// Arguments:
+// * `jump_table`: jump table of the deployed contract
+// * `block_number`: block the tx lands in (contract checks its last byte)
+//
+// Returns:
+// Vec<u8>: calldata (no call value)
+pub fn seppuku_payload(jump_table: &JumpTable, block_number: U64) -> Vec<u8> {
+    admin_payload(jump_table, block_number, "seppuku")
+}
+
+// Creation code that deploys `runtime_code` as is (what `huffc -b` emits for a contract without
+// constructor)
+pub fn init_code(runtime_code: &[u8]) -> Bytes {
+    let len = runtime_code.len() as u16;
+    // PUSH2 len DUP1 PUSH1 0x0c PUSH1 0x00 CODECOPY PUSH1 0x00 RETURN
+    let mut code = vec![0x61];
+    code.extend(len.to_be_bytes());
+    code.extend([0x80, 0x60, 0x0c, 0x60, 0x00, 0x39, 0x60, 0x00, 0xf3]);
+    code.extend(runtime_code);
+    code.into()
+}
+
+// Calldata for the metamorphic factory that clones `implementation` to the address of `salt`
+//
+// Arguments:
+// * `salt`: salt of the metamorphic contract (first 20 bytes have to be the caller)
+// * `implementation`: contract holding the runtime code to clone
+//
+// Returns:
+// Bytes: calldata (without initialization calldata)
+pub fn metamorphic_deploy_calldata(salt: H256, implementation: Address) -> Bytes {
+    let mut calldata = ethers::utils::id(METAMORPHIC_DEPLOY_SIG).to_vec();
+    calldata.extend(abi::encode(&[
+        Token::FixedBytes(salt.as_bytes().to_vec()),
+        Token::Address(implementation),
+        Token::Bytes(vec![]),
+    ]));
+    calldata.into()
+}
+
+// Calldata for an erc20 `transfer` of `amount` to `recipient`
+pub fn erc20_transfer_calldata(recipient: Address, amount: U256) -> Bytes {
+    let mut calldata = ethers::utils::id(ERC20_TRANSFER_SIG).to_vec();
+    calldata.extend(abi::encode(&[
+        Token::Address(recipient),
+        Token::Uint(amount),
+    ]));
+    calldata.into()
+}
+
+// Block byte followed by the jump dest of `label`
+fn admin_payload(jump_table: &JumpTable, block_number: U64, label: &str) -> Vec<u8> {
+    vec![block_number.low_u32() as u8, jump_table.jump_dest(label)]
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::jump_table::test_jump_table;
+    use super::*;
+    use revm::db::{CacheDB, EmptyDB};
+    use revm::primitives::{CreateScheme, ExecutionResult, Output, TransactTo};
+
+    #[test]
+    fn admin_payloads_carry_block_byte_and_gate() {
+        let jump_table = test_jump_table();
+        let block_number = U64::from(17_000_000);
+
+        let (payload, value) = recover_weth_payload(&jump_table, block_number, U256::exp10(18) + 1);
+        assert_eq!(payload, vec![0x40, jump_table.jump_dest("recover_weth")]);
+        assert_eq!(value, U256::exp10(18) / get_weth_encode_divisor());
+
+        let (payload, value) = deposit_weth_payload(&jump_table, block_number, U256::exp10(18));
+        assert_eq!(payload, vec![0x40, jump_table.jump_dest("deposit_weth")]);
+        assert_eq!(value, U256::exp10(18));
+
+        assert_eq!(
+            seppuku_payload(&jump_table, block_number + 1),
+            vec![0x41, jump_table.jump_dest("seppuku")]
+        );
+    }
+
+    #[test]
+    fn metamorphic_calldata_matches_factory_abi() {
+        let calldata =
+            metamorphic_deploy_calldata(H256::repeat_byte(0x11), Address::repeat_byte(0x22));
+        // selector + salt + implementation + offset + length of empty bytes
+        assert_eq!(calldata.len(), 4 + 32 * 4);
+        assert_eq!(&calldata[..4], &ethers::utils::id(METAMORPHIC_DEPLOY_SIG));
+        assert_eq!(&calldata[4..36], &[0x11; 32]);
+        assert_eq!(&calldata[48..68], &[0x22; 20]);
+        assert_eq!(U256::from_big_endian(&calldata[68..100]), U256::from(0x60));
+    }
+
+    #[test]
+    fn init_code_deploys_runtime_code() {
+        let runtime_code = crate::utils::constants::get_test_sandwich_code();
+
+        let mut evm = revm::EVM::new();
+        evm.database(CacheDB::new(EmptyDB::default()));
+        evm.env.tx.transact_to = TransactTo::Create(CreateScheme::Create);
+        evm.env.tx.data = init_code(&runtime_code).0;
+        evm.env.tx.gas_limit = 10_000_000;
+
+        match evm.transact_commit().unwrap() {
+            ExecutionResult::Success {
+                output: Output::Create(deployed, Some(_)),
+                ..
+            } => assert_eq!(deployed, runtime_code.0),
+            result => panic!("deployment failed: {:?}", result),
+        }
+    }
+}
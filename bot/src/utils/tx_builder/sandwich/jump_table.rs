@@ -0,0 +1,390 @@
+use std::fmt;
+
+use ethers::prelude::*;
+use hashbrown::HashMap;
+
+use crate::types::JumpTableError;
+use crate::utils::constants::get_weth_address;
+
+/// Source of the sandwich contract, gates are read from its `MAIN` macro at build time
+const SANDWICH_HUFF: &str = include_str!("../../../../../contract/src/sandwich.huff");
+
+/// Labels the rust encoders jump to, the contract must have a gate for each of them
+pub const ENCODER_LABELS: [&str; 19] = [
+    "v2_input_single",
+    "v2_output0_single",
+    "v2_output1_single",
+    "v2_input_multi_first",
+    "v2_input_multi_next",
+    "v2_output_multi_first",
+    "v2_output_multi_next",
+    "v3_input0",
+    "v3_input1",
+    "v3_output0",
+    "v3_output1",
+    "v3_input0_multi",
+    "v3_input1_multi",
+    "v3_output0_multi",
+    "v3_output1_multi",
+    "prepare_stack",
+    "seppuku",
+    "recover_weth",
+    "deposit_weth",
+];
+
+/// Jump table of the sandwich contract
+///
+/// The second calldata byte is the jump dest of a gate (`JUMPDEST PUSH2 label JUMP`) in `MAIN`,
+/// so the encoders depend on the exact bytecode layout of the deployed contract
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JumpTable {
+    /// label a gate jumps to -> jump dest of that gate
+    labels: HashMap<String, u8>,
+    /// jump dest of `exit`, ends a multi payload
+    exit: u8,
+    /// addresses pushed by the contract (searcher, weth)
+    embedded_addresses: Vec<Address>,
+}
+
+impl JumpTable {
+    // Derive the jump table of the deployed contract using the gates of `sandwich.huff`
+    //
+    // Arguments:
+    // * `code`: runtime bytecode of the sandwich contract
+    //
+    // Returns:
+    // Ok(JumpTable): if the bytecode has a gate for every label in `sandwich.huff`
+    // Err(JumpTableError): if the bytecode and `sandwich.huff` disagree
+    pub fn from_contract(code: &[u8]) -> Result<Self, JumpTableError> {
+        Self::from_bytecode(code, &gate_labels(SANDWICH_HUFF))
+    }
+
+    // Derive the jump table from compiled bytecode
+    //
+    // Arguments:
+    // * `code`: runtime bytecode of the sandwich contract
+    // * `gate_labels`: labels the gates jump to, in the order they appear in `MAIN`
+    //
+    // Returns:
+    // Ok(JumpTable): if bytecode has one gate per label and all `ENCODER_LABELS` are present
+    // Err(JumpTableError): otherwise
+    pub fn from_bytecode(code: &[u8], gate_labels: &[String]) -> Result<Self, JumpTableError> {
+        let ops = disassemble(code);
+
+        // `exit: stop` is directly followed by the gates
+        let (exit_index, exit) = ops
+            .windows(2)
+            .enumerate()
+            .find(|(index, window)| {
+                window[0].opcode == JUMPDEST
+                    && window[1].opcode == STOP
+                    && is_gate(&ops[index + 2..])
+            })
+            .map(|(index, window)| (index, window[0].pc))
+            .ok_or(JumpTableError::NotFound())?;
+
+        let gates: Vec<usize> = ops[exit_index + 2..]
+            .chunks(3)
+            .take_while(|chunk| is_gate(chunk))
+            .map(|chunk| chunk[0].pc)
+            .collect();
+        if gates.len() != gate_labels.len() {
+            return Err(JumpTableError::GateCountMismatch(
+                gate_labels.len(),
+                gates.len(),
+            ));
+        }
+
+        let exit = to_calldata_byte("exit", exit)?;
+        let mut labels = HashMap::new();
+        for (label, pc) in gate_labels.iter().zip(gates) {
+            labels.insert(label.clone(), to_calldata_byte(label, pc)?);
+        }
+        if let Some(missing) = ENCODER_LABELS
+            .iter()
+            .find(|label| !labels.contains_key(**label))
+        {
+            return Err(JumpTableError::MissingLabel(missing.to_string()));
+        }
+
+        let mut embedded_addresses: Vec<Address> = ops
+            .iter()
+            .filter(|op| op.opcode == PUSH20 && op.immediate.len() == 20)
+            .map(|op| Address::from_slice(op.immediate))
+            .collect();
+        embedded_addresses.dedup();
+
+        Ok(Self {
+            labels,
+            exit,
+            embedded_addresses,
+        })
+    }
+
+    // Check that the contract was compiled for this bot
+    //
+    // Arguments:
+    // * `searcher`: address of the searcher wallet sending the sandwiches
+    //
+    // Returns:
+    // Ok(()): if the contract embeds the searcher and weth addresses
+    // Err(JumpTableError): if one of them is missing
+    pub fn verify(&self, searcher: Address) -> Result<(), JumpTableError> {
+        for (name, address) in [("SEARCHER", searcher), ("WETH", get_weth_address())] {
+            if !self.embedded_addresses.contains(&address) {
+                return Err(JumpTableError::MissingAddress(name.to_string(), address));
+            }
+        }
+        Ok(())
+    }
+
+    // Jump dest of the gate jumping to `label` (panics if `label` is not in `ENCODER_LABELS`)
+    pub fn jump_dest(&self, label: &str) -> u8 {
+        self.labels[label]
+    }
+
+    // Label that the gate at `jump_dest` jumps to
+    pub fn label_of(&self, jump_dest: u8) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|(_, dest)| **dest == jump_dest)
+            .map(|(label, _)| label.as_str())
+    }
+
+    // Byte that terminates a multi payload
+    pub fn end_of_multi_payload(&self) -> u8 {
+        self.exit
+    }
+
+    // Byte that prepares the stack before the first v3 multi payload
+    pub fn prepare_stack_payload(&self) -> u8 {
+        self.jump_dest("prepare_stack")
+    }
+}
+
+impl fmt::Display for JumpTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut labels: Vec<(&String, &u8)> = self.labels.iter().collect();
+        labels.sort_by_key(|(_, jump_dest)| **jump_dest);
+        write!(f, "exit={}", self.exit)?;
+        for (label, jump_dest) in labels {
+            write!(f, " {}={}", label, jump_dest)?;
+        }
+        Ok(())
+    }
+}
+
+// Labels jumped to by the gates of `MAIN`, in order
+//
+// Arguments:
+// * `huff_source`: source of `sandwich.huff`
+//
+// Returns:
+// Vec<String>: label pushed by every `*_gate:` (not the gate name, those contain typos)
+pub fn gate_labels(huff_source: &str) -> Vec<String> {
+    let main = huff_source
+        .split("#define macro MAIN()")
+        .nth(1)
+        .unwrap_or_default();
+    let mut lines = main
+        .lines()
+        .map(|line| line.split("//").next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty());
+
+    let mut labels = vec![];
+    while let Some(line) = lines.next() {
+        if line.ends_with("_gate:") {
+            if let Some(label) = lines.next() {
+                labels.push(label.to_string());
+            }
+        }
+    }
+    labels
+}
+
+const STOP: u8 = 0x00;
+const JUMP: u8 = 0x56;
+const JUMPDEST: u8 = 0x5b;
+const PUSH1: u8 = 0x60;
+const PUSH2: u8 = 0x61;
+const PUSH20: u8 = 0x73;
+const PUSH32: u8 = 0x7f;
+
+/// Opcode of the runtime bytecode
+struct Op<'a> {
+    pc: usize,
+    opcode: u8,
+    /// bytes pushed by a PUSHn
+    immediate: &'a [u8],
+}
+
+// Split bytecode into opcodes (truncated trailing pushes are kept as is)
+fn disassemble(code: &[u8]) -> Vec<Op<'_>> {
+    let mut ops = vec![];
+    let mut pc = 0;
+    while pc < code.len() {
+        let opcode = code[pc];
+        let push_len = match opcode {
+            PUSH1..=PUSH32 => (opcode - PUSH1 + 1) as usize,
+            _ => 0,
+        };
+        let end = (pc + 1 + push_len).min(code.len());
+        ops.push(Op {
+            pc,
+            opcode,
+            immediate: &code[pc + 1..end],
+        });
+        pc += 1 + push_len;
+    }
+    ops
+}
+
+// Is `ops` a gate (`JUMPDEST PUSH label JUMP`)
+fn is_gate(ops: &[Op]) -> bool {
+    matches!(
+        ops,
+        [dest, push, jump, ..]
+            if dest.opcode == JUMPDEST
+                && matches!(push.opcode, PUSH1 | PUSH2)
+                && jump.opcode == JUMP
+    )
+}
+
+// The contract reads the jump dest from a single calldata byte
+fn to_calldata_byte(label: &str, pc: usize) -> Result<u8, JumpTableError> {
+    u8::try_from(pc).map_err(|_| JumpTableError::JumpDestTooLarge(label.to_string(), pc))
+}
+
+// Jump table of a dispatcher laid out like `MAIN` (what huffc compiles `sandwich.huff` to)
+#[cfg(test)]
+pub(crate) fn test_jump_table() -> JumpTable {
+    JumpTable::from_contract(&test::dispatcher(
+        &gate_labels(SANDWICH_HUFF),
+        Address::zero(),
+    ))
+    .unwrap()
+}
+
+// Labels of the gates in the code that tests inject into simulations (`inject_sando`), that
+// code predates the arbitrage gates of `sandwich.huff`
+#[cfg(test)]
+pub(crate) fn injected_gate_labels() -> Vec<String> {
+    gate_labels(SANDWICH_HUFF)
+        .into_iter()
+        .filter(|label| !label.starts_with("arbitrage"))
+        .collect()
+}
+
+// Jump table of the code that tests inject into simulations, encoders in tests use it instead of
+// the deployed contract's
+#[cfg(test)]
+pub(crate) fn injected_jump_table() -> JumpTable {
+    let code = crate::utils::constants::get_test_sandwich_code();
+    JumpTable::from_bytecode(&code, &injected_gate_labels()).unwrap()
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::utils::constants::get_test_sandwich_code;
+
+    // Bytecode of `MAIN` up to and including the gates, followed by a callback pushing weth
+    pub(crate) fn dispatcher(gate_labels: &[String], searcher: Address) -> Vec<u8> {
+        // 0x00 calldataload dup1 0x00 byte number 0xff and eq
+        let mut code = vec![0x5f, 0x35, 0x80, 0x5f, 0x1a, 0x43, PUSH1, 0xff, 0x16, 0x14];
+        // [SEARCHER] caller eq and
+        code.push(PUSH20);
+        code.extend(searcher.as_bytes());
+        code.extend([0x33, 0x14, 0x16]);
+        // entry_point jumpi v3_callback jump
+        code.extend([PUSH2, 0x00, 0x2b, 0x57, PUSH2, 0x0f, 0x00, JUMP]);
+        // entry_point: chainid byte jump
+        code.extend([JUMPDEST, 0x46, 0x1a, JUMP]);
+        // exit: stop
+        code.extend([JUMPDEST, STOP]);
+        for index in 0..gate_labels.len() {
+            code.extend([JUMPDEST, PUSH2, 0x10, index as u8, JUMP]);
+        }
+        // v3_callback: [WETH] ...
+        code.extend([JUMPDEST, PUSH20]);
+        code.extend(get_weth_address().as_bytes());
+        code.push(STOP);
+        code
+    }
+
+    #[test]
+    fn gate_labels_follow_huff_source() {
+        let labels = gate_labels(SANDWICH_HUFF);
+        assert_eq!(labels.len(), 22);
+        // gate is misspelled (`v2_input_sigle_gate`), label it jumps to is not
+        assert_eq!(labels[0], "v2_input_single");
+        assert_eq!(labels[21], "deposit_weth");
+        for label in ENCODER_LABELS {
+            assert!(labels.iter().any(|l| l == label), "{} has no gate", label);
+        }
+    }
+
+    #[test]
+    fn derives_jump_dests_from_bytecode() {
+        let searcher = Address::from_low_u64_be(0x5eac);
+        let code = dispatcher(&gate_labels(SANDWICH_HUFF), searcher);
+        let jump_table = JumpTable::from_contract(&code).unwrap();
+
+        assert_eq!(jump_table.end_of_multi_payload(), 46);
+        assert_eq!(jump_table.jump_dest("v2_input_single"), 48);
+        assert_eq!(jump_table.jump_dest("v3_input0"), 63);
+        assert_eq!(jump_table.jump_dest("v2_input_multi_first"), 83);
+        assert_eq!(jump_table.prepare_stack_payload(), 103);
+        assert_eq!(jump_table.jump_dest("v3_input0_multi"), 108);
+        assert_eq!(jump_table.jump_dest("recover_weth"), 148);
+        assert_eq!(jump_table.label_of(53), Some("v2_output0_single"));
+        assert_eq!(jump_table.label_of(47), None);
+
+        assert_eq!(jump_table.verify(searcher), Ok(()));
+        assert_eq!(
+            jump_table.verify(Address::from_low_u64_be(1)),
+            Err(JumpTableError::MissingAddress(
+                "SEARCHER".to_string(),
+                Address::from_low_u64_be(1)
+            ))
+        );
+    }
+
+    #[test]
+    fn rejects_contract_that_disagrees_with_source() {
+        let labels = gate_labels(SANDWICH_HUFF);
+        let code = dispatcher(&labels[..labels.len() - 1], Address::zero());
+        assert_eq!(
+            JumpTable::from_contract(&code),
+            Err(JumpTableError::GateCountMismatch(22, 21))
+        );
+
+        let renamed: Vec<String> = labels
+            .iter()
+            .map(|label| label.replace("prepare_stack", "prepare"))
+            .collect();
+        assert_eq!(
+            JumpTable::from_bytecode(&dispatcher(&renamed, Address::zero()), &renamed),
+            Err(JumpTableError::MissingLabel("prepare_stack".to_string()))
+        );
+
+        assert_eq!(
+            JumpTable::from_contract(&[JUMPDEST, STOP]),
+            Err(JumpTableError::NotFound())
+        );
+    }
+
+    #[test]
+    fn test_sandwich_code_predates_arbitrage_gates() {
+        let code = get_test_sandwich_code();
+        assert_eq!(
+            JumpTable::from_contract(&code),
+            Err(JumpTableError::GateCountMismatch(22, 19))
+        );
+
+        let jump_table = injected_jump_table();
+        assert_eq!(jump_table.end_of_multi_payload(), 37);
+        assert_eq!(jump_table.prepare_stack_payload(), 94);
+        assert_eq!(jump_table.jump_dest("recover_weth"), 124);
+    }
+}
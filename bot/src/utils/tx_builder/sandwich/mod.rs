@@ -1,61 +1,172 @@
 use std::sync::Arc;
 
+use crate::types::PayloadDecodeError;
 use crate::utils;
 use ethers::prelude::{k256::ecdsa::SigningKey, *};
 use tokio::sync::RwLock;
 
+pub mod admin;
+pub mod jump_table;
+pub mod payload;
 pub mod v2;
 pub mod v3;
 
+pub use jump_table::*;
+pub use payload::*;
+
 #[derive(Debug, Clone)]
 pub struct SandwichMaker {
     pub v2: v2::SandwichLogicV2,
     pub v3: v3::SandwichLogicV3,
+    pub jump_table: JumpTable,
     pub sandwich_address: Address,
     pub searcher_wallet: Wallet<SigningKey>,
     pub nonce: Arc<RwLock<U256>>,
+    /// searcher nonces held back from sandwiches while another bundle uses them
+    pub nonce_reservation: Arc<RwLock<Option<NonceReservation>>>,
+}
+
+/// Searcher nonces used by a bundle that is not a sandwich (e.g. treasury transfers)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NonceReservation {
+    /// first reserved nonce
+    pub nonce: U256,
+    /// number of reserved nonces
+    pub count: u64,
+    /// last block the reserving bundle targets
+    pub target_block: U64,
 }
 
 impl SandwichMaker {
     // Create a new `SandwichMaker` instance
-    pub async fn new() -> Self {
+    //
+    // Returns:
+    // Ok(SandwichMaker): if searcher wallet nonce was fetched and the deployed contract matches
+    // the jump table the encoders were built for
+    // Err(eyre::Error): if no provider is available, nonce or contract code could not be
+    // fetched or the deployed contract disagrees with `sandwich.huff`
+    pub async fn new() -> eyre::Result<Self> {
         let sandwich_address = utils::dotenv::get_sandwich_contract_address();
         let searcher_wallet = utils::dotenv::get_searcher_wallet();
 
-        let client = utils::create_websocket_client().await.unwrap();
+        let client = utils::create_rpc_client().await?;
 
-        let nonce = if let Ok(n) = client
+        let nonce = client
             .get_transaction_count(searcher_wallet.address(), None)
             .await
-        {
-            n
-        } else {
-            panic!("Failed to get searcher wallet nonce...");
-        };
+            .map_err(|e| eyre::eyre!("Failed to get searcher wallet nonce: {:?}", e))?;
 
         let nonce = Arc::new(RwLock::new(nonce));
 
-        Self {
-            v2: v2::SandwichLogicV2::new(),
-            v3: v3::SandwichLogicV3::new(),
+        // simulations in tests run against injected code (`inject_sando`), not the deployed contract
+        #[cfg(test)]
+        let jump_table = injected_jump_table();
+
+        // encoders jump into the deployed contract, refuse to run against a different layout
+        #[cfg(not(test))]
+        let jump_table = {
+            let code = client
+                .get_code(sandwich_address, None)
+                .await
+                .map_err(|e| eyre::eyre!("Failed to get sandwich contract code: {:?}", e))?;
+            JumpTable::from_contract(&code)
+                .and_then(|jump_table| {
+                    jump_table.verify(searcher_wallet.address())?;
+                    Ok(jump_table)
+                })
+                .map_err(|e| {
+                    eyre::eyre!(
+                        "Sandwich contract at {:?} does not match sandwich.huff: {}",
+                        sandwich_address,
+                        e
+                    )
+                })?
+        };
+        log::info!("Sandwich contract jump table: {}", jump_table);
+
+        Ok(Self {
+            v2: v2::SandwichLogicV2::new(jump_table.clone()),
+            v3: v3::SandwichLogicV3::new(jump_table.clone()),
+            jump_table,
             sandwich_address,
             searcher_wallet,
             nonce,
+            nonce_reservation: Arc::new(RwLock::new(None)),
+        })
+    }
+
+    // Nonce of the frontrun of a sandwich bundle targeting `target_block`
+    //
+    // Returns:
+    // Some(U256): searcher nonce
+    // None: if the nonce is reserved by another bundle targeting `target_block` (or later)
+    pub async fn sandwich_nonce(&self, target_block: U64) -> Option<U256> {
+        if let Some(reservation) = *self.nonce_reservation.read().await {
+            if reservation.target_block >= target_block {
+                return None;
+            }
         }
+        Some(*self.nonce.read().await)
     }
 
-    pub async fn update_searcher_nonce(&self) {
-        let mut nonce = self.nonce.write().await;
-        let client = utils::create_websocket_client().await.unwrap();
+    // Reserve the next `count` searcher nonces for a bundle targeting up to `target_block`,
+    // sandwiches targeting those blocks are not sent until the reservation is released
+    //
+    // Returns:
+    // Some(U256): first reserved nonce
+    // None: if another reservation is still active
+    pub async fn reserve_nonces(&self, count: u64, target_block: U64) -> Option<U256> {
+        let mut reservation = self.nonce_reservation.write().await;
+        if reservation.is_some() {
+            return None;
+        }
+        let nonce = *self.nonce.read().await;
+        *reservation = Some(NonceReservation {
+            nonce,
+            count,
+            target_block,
+        });
+        Some(nonce)
+    }
 
-        if let Ok(n) = client
-            .get_transaction_count(self.searcher_wallet.address(), None)
-            .await
-        {
-            *nonce = n;
-        } else {
-            panic!("Failed to update searcher wallet nonce...");
+    // Refresh searcher nonce (keeps the last known nonce if it can't be fetched), then release
+    // the nonce reservation once its target block passed
+    //
+    // Arguments:
+    // * `latest_block`: block that was just mined
+    pub async fn update_searcher_nonce(&self, latest_block: U64) {
+        match utils::create_rpc_client().await {
+            Ok(client) => match client
+                .get_transaction_count(self.searcher_wallet.address(), None)
+                .await
+            {
+                Ok(n) => *self.nonce.write().await = n,
+                Err(e) => log::error!("Failed to update searcher wallet nonce: {:?}", e),
+            },
+            Err(e) => log::error!("Failed to update searcher wallet nonce: {}", e),
         };
+
+        let mut reservation = self.nonce_reservation.write().await;
+        if matches!(*reservation, Some(r) if r.target_block <= latest_block) {
+            *reservation = None;
+        }
+    }
+
+    // Decode calldata and call value sent to the sandwich contract back into its payloads
+    pub fn decode_payloads(
+        &self,
+        data: &[u8],
+        value: U256,
+    ) -> Result<Vec<SandwichPayload>, PayloadDecodeError> {
+        payload::decode_payloads(data, value, &self.jump_table)
+    }
+
+    // Explain what calldata sent to the sandwich contract does (used in logs and the ledger)
+    pub fn explain_calldata(&self, data: &[u8], value: U256) -> String {
+        match self.decode_payloads(data, value) {
+            Ok(payloads) => explain_payloads(&payloads),
+            Err(e) => format!("undecodable payload: {}", e),
+        }
     }
 }
 
@@ -0,0 +1,1092 @@
+use std::fmt;
+
+use ethers::prelude::*;
+use ethers::utils::format_units;
+
+use super::v2::{encode_five_bytes, encode_four_bytes};
+use super::v3::encode_num_bytes;
+use super::{get_weth_encode_divisor, EncodedSwapValue, JumpTable};
+use crate::types::PayloadDecodeError;
+
+/// One swap of a frontrun or backrun as read by the sandwich contract (`sandwich.huff`)
+///
+/// Amounts are the real amounts the contract swaps with, i.e. after they went through the
+/// lossy calldata / call value encoding
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SandwichPayload {
+    /// weth -> token on a v2 pair, weth amount is sent as call value
+    V2WethInput {
+        /// last byte of the target block number
+        block_number: u8,
+        pair: Address,
+        weth_is_token0: bool,
+        amount_in: U256,
+        amount_out: U256,
+    },
+    /// token -> weth on a v2 pair, weth amount is sent as call value
+    V2WethOutput {
+        block_number: u8,
+        pair: Address,
+        token: Address,
+        weth_is_token0: bool,
+        amount_in: U256,
+        amount_out: U256,
+    },
+    /// weth -> token on a v2 pair as part of a multi sandwich
+    V2MultiWethInput {
+        block_number: u8,
+        pair: Address,
+        weth_is_token0: bool,
+        is_first: bool,
+        amount_in: U256,
+        amount_out: U256,
+    },
+    /// token -> weth on a v2 pair as part of a multi sandwich
+    V2MultiWethOutput {
+        block_number: u8,
+        pair: Address,
+        token: Address,
+        weth_is_token0: bool,
+        is_first: bool,
+        amount_in: U256,
+        amount_out: U256,
+    },
+    /// weth -> token on a v3 pool, weth amount is sent as call value
+    V3WethInput {
+        block_number: u8,
+        pool: Address,
+        /// input token sorts before output token
+        zero_for_one: bool,
+        /// keccak of (token0, token1, fee), checked in the swap callback
+        pool_key_hash: H256,
+        amount_in: U256,
+        amount_out: U256,
+    },
+    /// token -> weth on a v3 pool, weth amount is sent as call value
+    V3WethOutput {
+        block_number: u8,
+        pool: Address,
+        token: Address,
+        zero_for_one: bool,
+        pool_key_hash: H256,
+        amount_in: U256,
+        amount_out: U256,
+    },
+    /// weth -> token on a v3 pool as part of a multi sandwich
+    V3MultiWethInput {
+        block_number: u8,
+        pool: Address,
+        zero_for_one: bool,
+        /// first payload prepares the stack of the multi sandwich
+        is_first: bool,
+        pool_key_hash: H256,
+        amount_in: U256,
+        amount_out: U256,
+    },
+    /// token -> weth on a v3 pool as part of a multi sandwich
+    V3MultiWethOutput {
+        block_number: u8,
+        pool: Address,
+        token: Address,
+        zero_for_one: bool,
+        is_first: bool,
+        pool_key_hash: H256,
+        amount_in: U256,
+        amount_out: U256,
+    },
+}
+
+impl SandwichPayload {
+    // Name of the jump label in `sandwich.huff` that handles this payload
+    pub fn jump_label(&self) -> &'static str {
+        match self {
+            SandwichPayload::V2WethInput { .. } => "v2_input_single",
+            SandwichPayload::V2WethOutput { weth_is_token0, .. } => match weth_is_token0 {
+                true => "v2_output0_single",
+                false => "v2_output1_single",
+            },
+            SandwichPayload::V2MultiWethInput { is_first, .. } => match is_first {
+                true => "v2_input_multi_first",
+                false => "v2_input_multi_next",
+            },
+            SandwichPayload::V2MultiWethOutput { is_first, .. } => match is_first {
+                true => "v2_output_multi_first",
+                false => "v2_output_multi_next",
+            },
+            SandwichPayload::V3WethInput { zero_for_one, .. } => match zero_for_one {
+                true => "v3_input0",
+                false => "v3_input1",
+            },
+            SandwichPayload::V3WethOutput { zero_for_one, .. } => match zero_for_one {
+                true => "v3_output1",
+                false => "v3_output0",
+            },
+            SandwichPayload::V3MultiWethInput { zero_for_one, .. } => match zero_for_one {
+                true => "v3_input0_multi",
+                false => "v3_input1_multi",
+            },
+            SandwichPayload::V3MultiWethOutput { zero_for_one, .. } => match zero_for_one {
+                true => "v3_output1_multi",
+                false => "v3_output0_multi",
+            },
+        }
+    }
+
+    // Is this payload handled by the v2 part of the contract
+    pub fn is_v2(&self) -> bool {
+        matches!(
+            self,
+            SandwichPayload::V2WethInput { .. }
+                | SandwichPayload::V2WethOutput { .. }
+                | SandwichPayload::V2MultiWethInput { .. }
+                | SandwichPayload::V2MultiWethOutput { .. }
+        )
+    }
+
+    // Is this payload part of a multi sandwich (needs `end_of_multi_payload` after the last one)
+    pub fn is_multi(&self) -> bool {
+        matches!(
+            self,
+            SandwichPayload::V2MultiWethInput { .. }
+                | SandwichPayload::V2MultiWethOutput { .. }
+                | SandwichPayload::V3MultiWethInput { .. }
+                | SandwichPayload::V3MultiWethOutput { .. }
+        )
+    }
+
+    // Encode payload into calldata and call value
+    //
+    // Arguments:
+    // * `jump_table`: jump table of the deployed sandwich contract
+    //
+    // Returns:
+    // (Vec<u8>, U256): calldata and call value (in units of `get_weth_encode_divisor()`)
+    pub fn encode(&self, jump_table: &JumpTable) -> (Vec<u8>, U256) {
+        let swap_type = jump_table.jump_dest(self.jump_label());
+        let mut data = vec![];
+        let mut value = U256::zero();
+        match *self {
+            SandwichPayload::V2WethInput {
+                block_number,
+                pair,
+                weth_is_token0,
+                amount_in,
+                amount_out,
+            } => {
+                let encoded_out = encode_four_bytes(amount_out, true, weth_is_token0);
+                data.extend([
+                    block_number,
+                    swap_type,
+                    encoded_out.mem_offset.low_u32() as u8,
+                ]);
+                data.extend(pair.as_bytes());
+                data.extend(last_bytes(encoded_out.encoded_value, 4));
+                value = amount_in / get_weth_encode_divisor();
+            }
+            SandwichPayload::V2WethOutput {
+                block_number,
+                pair,
+                token,
+                weth_is_token0,
+                amount_in,
+                amount_out,
+            } => {
+                let encoded_in = encode_four_bytes(amount_in, false, weth_is_token0);
+                data.extend([
+                    block_number,
+                    swap_type,
+                    encoded_in.mem_offset.low_u32() as u8,
+                ]);
+                data.extend(pair.as_bytes());
+                data.extend(token.as_bytes());
+                data.extend(last_bytes(encoded_in.encoded_value, 4));
+                value = amount_out / get_weth_encode_divisor();
+            }
+            SandwichPayload::V2MultiWethInput {
+                block_number,
+                pair,
+                weth_is_token0,
+                is_first,
+                amount_in,
+                amount_out,
+            } => {
+                let encoded_out = encode_four_bytes(amount_out, true, weth_is_token0);
+                data.extend([
+                    block_number,
+                    swap_type,
+                    encoded_out.mem_offset.low_u32() as u8,
+                ]);
+                data.extend(pair.as_bytes());
+                data.extend(last_bytes(encoded_out.encoded_value, 4));
+                match is_first {
+                    true => value = amount_in / get_weth_encode_divisor(),
+                    false => data.extend(last_bytes(amount_in / get_weth_encode_divisor(), 5)),
+                }
+            }
+            SandwichPayload::V2MultiWethOutput {
+                block_number,
+                pair,
+                token,
+                weth_is_token0,
+                is_first,
+                amount_in,
+                amount_out,
+            } => {
+                let encoded_in = encode_four_bytes(amount_in, false, weth_is_token0);
+                let encoded_out = encode_five_bytes(amount_out, weth_is_token0);
+                data.extend([
+                    block_number,
+                    swap_type,
+                    encoded_in.mem_offset.low_u32() as u8,
+                ]);
+                data.extend(pair.as_bytes());
+                data.extend(token.as_bytes());
+                data.extend(last_bytes(encoded_in.encoded_value, 4));
+                match is_first {
+                    true => value = encoded_out.encoded_value,
+                    false => data.extend(last_bytes(encoded_out.encoded_value, 5)),
+                }
+                data.push(encoded_out.mem_offset.low_u32() as u8);
+            }
+            SandwichPayload::V3WethInput {
+                block_number,
+                pool,
+                pool_key_hash,
+                amount_in,
+                amount_out,
+                ..
+            } => {
+                data.extend([block_number, swap_type]);
+                data.extend(pool.as_bytes());
+                data.extend(v3_amount(encode_num_bytes(amount_out, 5), 5));
+                data.extend(pool_key_hash.as_bytes());
+                value = amount_in / get_weth_encode_divisor();
+            }
+            SandwichPayload::V3WethOutput {
+                block_number,
+                pool,
+                token,
+                pool_key_hash,
+                amount_in,
+                amount_out,
+                ..
+            } => {
+                data.extend([block_number, swap_type]);
+                data.extend(pool.as_bytes());
+                data.extend(v3_amount(encode_num_bytes(amount_in, 5), 5));
+                data.extend(token.as_bytes());
+                data.extend(pool_key_hash.as_bytes());
+                value = amount_out / get_weth_encode_divisor();
+            }
+            SandwichPayload::V3MultiWethInput {
+                block_number,
+                pool,
+                is_first,
+                pool_key_hash,
+                amount_in,
+                amount_out,
+                ..
+            } => {
+                data.push(block_number);
+                if is_first {
+                    data.push(jump_table.prepare_stack_payload());
+                }
+                data.push(swap_type);
+                data.extend(pool.as_bytes());
+                data.extend(v3_amount(encode_num_bytes(amount_in, 4), 4));
+                data.extend(v3_amount(encode_num_bytes(amount_out, 5), 5));
+                data.extend(pool_key_hash.as_bytes());
+            }
+            SandwichPayload::V3MultiWethOutput {
+                block_number,
+                pool,
+                token,
+                is_first,
+                pool_key_hash,
+                amount_in,
+                amount_out,
+                ..
+            } => {
+                data.push(block_number);
+                if is_first {
+                    data.push(jump_table.prepare_stack_payload());
+                }
+                data.push(swap_type);
+                data.extend(pool.as_bytes());
+                data.extend(v3_amount(encode_num_bytes(amount_out, 4), 4));
+                data.extend(v3_amount(encode_num_bytes(amount_in, 5), 5));
+                data.extend(token.as_bytes());
+                data.extend(pool_key_hash.as_bytes());
+            }
+        }
+        (data, value)
+    }
+}
+
+impl fmt::Display for SandwichPayload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SandwichPayload::V2WethInput {
+                pair,
+                amount_in,
+                amount_out,
+                ..
+            } => write!(
+                f,
+                "v2 {:?}: {} WETH -> {} token",
+                pair,
+                weth(*amount_in),
+                amount_out
+            ),
+            SandwichPayload::V2WethOutput {
+                pair,
+                token,
+                amount_in,
+                amount_out,
+                ..
+            } => write!(
+                f,
+                "v2 {:?}: {} {:?} -> {} WETH",
+                pair,
+                amount_in,
+                token,
+                weth(*amount_out)
+            ),
+            SandwichPayload::V2MultiWethInput {
+                pair,
+                is_first,
+                amount_in,
+                amount_out,
+                ..
+            } => write!(
+                f,
+                "v2 multi{} {:?}: {} WETH -> {} token",
+                first(*is_first),
+                pair,
+                weth(*amount_in),
+                amount_out
+            ),
+            SandwichPayload::V2MultiWethOutput {
+                pair,
+                token,
+                is_first,
+                amount_in,
+                amount_out,
+                ..
+            } => write!(
+                f,
+                "v2 multi{} {:?}: {} {:?} -> {} WETH",
+                first(*is_first),
+                pair,
+                amount_in,
+                token,
+                weth(*amount_out)
+            ),
+            SandwichPayload::V3WethInput {
+                pool,
+                amount_in,
+                amount_out,
+                ..
+            } => write!(
+                f,
+                "v3 {:?}: {} WETH -> {} token",
+                pool,
+                weth(*amount_in),
+                amount_out
+            ),
+            SandwichPayload::V3WethOutput {
+                pool,
+                token,
+                amount_in,
+                amount_out,
+                ..
+            } => write!(
+                f,
+                "v3 {:?}: {} {:?} -> {} WETH",
+                pool,
+                amount_in,
+                token,
+                weth(*amount_out)
+            ),
+            SandwichPayload::V3MultiWethInput {
+                pool,
+                is_first,
+                amount_in,
+                amount_out,
+                ..
+            } => write!(
+                f,
+                "v3 multi{} {:?}: {} WETH -> {} token",
+                first(*is_first),
+                pool,
+                weth(*amount_in),
+                amount_out
+            ),
+            SandwichPayload::V3MultiWethOutput {
+                pool,
+                token,
+                is_first,
+                amount_in,
+                amount_out,
+                ..
+            } => write!(
+                f,
+                "v3 multi{} {:?}: {} {:?} -> {} WETH",
+                first(*is_first),
+                pool,
+                amount_in,
+                token,
+                weth(*amount_out)
+            ),
+        }
+    }
+}
+
+// Decode calldata and call value of a frontrun or backrun back into its payloads
+//
+// Arguments:
+// * `data`: calldata sent to the sandwich contract
+// * `value`: call value sent to the sandwich contract
+// * `jump_table`: jump table of the deployed sandwich contract
+//
+// Returns:
+// Ok(Vec<SandwichPayload>): a single payload or all payloads of a multi sandwich
+// Err(PayloadDecodeError): if calldata is not a valid sandwich payload
+pub fn decode_payloads(
+    data: &[u8],
+    value: U256,
+    jump_table: &JumpTable,
+) -> Result<Vec<SandwichPayload>, PayloadDecodeError> {
+    let mut reader = Reader { data, position: 0 };
+    let mut payloads = vec![];
+
+    loop {
+        let payload = decode_payload(&mut reader, value, jump_table)?;
+        let is_multi = payload.is_multi();
+        payloads.push(payload);
+
+        let remaining = reader.remaining();
+        match (is_multi, remaining) {
+            (false, 0) if payloads.len() == 1 => return Ok(payloads),
+            (false, _) => return Err(PayloadDecodeError::TrailingBytes(remaining)),
+            (true, 0) => return Err(PayloadDecodeError::MissingEndOfMultiPayload()),
+            // a block number byte can equal the terminator, so only the last byte ends it
+            (true, 1) if data[data.len() - 1] == jump_table.end_of_multi_payload() => {
+                return Ok(payloads)
+            }
+            (true, _) => continue,
+        }
+    }
+}
+
+// Decode the payload starting at the reader's position
+fn decode_payload(
+    reader: &mut Reader,
+    value: U256,
+    jump_table: &JumpTable,
+) -> Result<SandwichPayload, PayloadDecodeError> {
+    let block_number = reader.u8()?;
+    let mut swap_type = reader.u8()?;
+    let prepares_stack = swap_type == jump_table.prepare_stack_payload();
+    if prepares_stack {
+        swap_type = reader.u8()?;
+    }
+
+    let label = jump_table
+        .label_of(swap_type)
+        .ok_or(PayloadDecodeError::UnknownSwapType(swap_type))?;
+    if prepares_stack && !(label.starts_with("v3") && label.ends_with("multi")) {
+        return Err(PayloadDecodeError::UnknownSwapType(
+            jump_table.prepare_stack_payload(),
+        ));
+    }
+
+    let payload = match label {
+        "v2_input_single" | "v2_input_multi_first" | "v2_input_multi_next" => {
+            let mem_offset = reader.u8()?;
+            let pair = reader.address()?;
+            let weth_is_token0 = mem_offset > 32;
+            let amount_out = four_bytes(reader.uint(4)?, mem_offset, weth_is_token0)?;
+            match label {
+                "v2_input_single" => SandwichPayload::V2WethInput {
+                    block_number,
+                    pair,
+                    weth_is_token0,
+                    amount_in: value * get_weth_encode_divisor(),
+                    amount_out,
+                },
+                _ => {
+                    let is_first = label == "v2_input_multi_first";
+                    let amount_in = match is_first {
+                        true => value,
+                        false => reader.uint(5)?,
+                    };
+                    SandwichPayload::V2MultiWethInput {
+                        block_number,
+                        pair,
+                        weth_is_token0,
+                        is_first,
+                        amount_in: amount_in * get_weth_encode_divisor(),
+                        amount_out,
+                    }
+                }
+            }
+        }
+        "v2_output0_single" | "v2_output1_single" => {
+            let mem_offset = reader.u8()?;
+            let pair = reader.address()?;
+            let token = reader.address()?;
+            SandwichPayload::V2WethOutput {
+                block_number,
+                pair,
+                token,
+                weth_is_token0: label == "v2_output0_single",
+                amount_in: four_bytes(reader.uint(4)?, mem_offset, true)?,
+                amount_out: value * get_weth_encode_divisor(),
+            }
+        }
+        "v2_output_multi_first" | "v2_output_multi_next" => {
+            let in_mem_offset = reader.u8()?;
+            let pair = reader.address()?;
+            let token = reader.address()?;
+            let amount_in = four_bytes(reader.uint(4)?, in_mem_offset, true)?;
+            let is_first = label == "v2_output_multi_first";
+            let encoded_out = match is_first {
+                true => value,
+                false => reader.uint(5)?,
+            };
+            let out_mem_offset = reader.u8()?;
+            let (weth_is_token0, byte_shift) = match out_mem_offset {
+                40..=68 => (true, 68 - out_mem_offset),
+                72..=100 => (false, 100 - out_mem_offset),
+                _ => return Err(PayloadDecodeError::InvalidMemOffset(out_mem_offset)),
+            };
+            SandwichPayload::V2MultiWethOutput {
+                block_number,
+                pair,
+                token,
+                weth_is_token0,
+                is_first,
+                amount_in,
+                amount_out: shifted(encoded_out, byte_shift),
+            }
+        }
+        "v3_input0" | "v3_input1" => SandwichPayload::V3WethInput {
+            block_number,
+            pool: reader.address()?,
+            zero_for_one: label == "v3_input0",
+            amount_out: reader.v3_amount(5)?,
+            pool_key_hash: reader.h256()?,
+            amount_in: value * get_weth_encode_divisor(),
+        },
+        "v3_output0" | "v3_output1" => SandwichPayload::V3WethOutput {
+            block_number,
+            pool: reader.address()?,
+            amount_in: reader.v3_amount(5)?,
+            token: reader.address()?,
+            pool_key_hash: reader.h256()?,
+            zero_for_one: label == "v3_output1",
+            amount_out: value * get_weth_encode_divisor(),
+        },
+        "v3_input0_multi" | "v3_input1_multi" => SandwichPayload::V3MultiWethInput {
+            block_number,
+            pool: reader.address()?,
+            amount_in: reader.v3_amount(4)?,
+            amount_out: reader.v3_amount(5)?,
+            pool_key_hash: reader.h256()?,
+            zero_for_one: label == "v3_input0_multi",
+            is_first: prepares_stack,
+        },
+        "v3_output0_multi" | "v3_output1_multi" => SandwichPayload::V3MultiWethOutput {
+            block_number,
+            pool: reader.address()?,
+            amount_out: reader.v3_amount(4)?,
+            amount_in: reader.v3_amount(5)?,
+            token: reader.address()?,
+            pool_key_hash: reader.h256()?,
+            zero_for_one: label == "v3_output1_multi",
+            is_first: prepares_stack,
+        },
+        _ => return Err(PayloadDecodeError::UnknownSwapType(swap_type)),
+    };
+
+    Ok(payload)
+}
+
+// Explain what a frontrun or backrun does (used in logs and the ledger)
+pub fn explain_payloads(payloads: &[SandwichPayload]) -> String {
+    payloads
+        .iter()
+        .map(|payload| payload.to_string())
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+/// Cursor over calldata
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], PayloadDecodeError> {
+        if self.remaining() < len {
+            return Err(PayloadDecodeError::UnexpectedEnd(self.data.len()));
+        }
+        let bytes = &self.data[self.position..self.position + len];
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, PayloadDecodeError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn uint(&mut self, len: usize) -> Result<U256, PayloadDecodeError> {
+        Ok(U256::from_big_endian(self.bytes(len)?))
+    }
+
+    fn address(&mut self) -> Result<Address, PayloadDecodeError> {
+        Ok(Address::from_slice(self.bytes(20)?))
+    }
+
+    fn h256(&mut self) -> Result<H256, PayloadDecodeError> {
+        Ok(H256::from_slice(self.bytes(32)?))
+    }
+
+    // v3 amounts are packed as bits to shift left followed by the `len` byte value
+    fn v3_amount(&mut self, len: u8) -> Result<U256, PayloadDecodeError> {
+        let shift_bits = self.u8()?;
+        let byte_shift = (32 - len)
+            .checked_sub(shift_bits / 8)
+            .filter(|_| shift_bits % 8 == 0)
+            .ok_or(PayloadDecodeError::InvalidMemOffset(shift_bits))?;
+        Ok(shifted(self.uint(len as usize)?, byte_shift))
+    }
+}
+
+// Decode a v2 four byte amount from the memory offset it is stored at
+fn four_bytes(
+    encoded: U256,
+    mem_offset: u8,
+    is_weth_token0: bool,
+) -> Result<U256, PayloadDecodeError> {
+    let end: u8 = if is_weth_token0 { 64 } else { 32 };
+    let byte_shift = end
+        .checked_sub(mem_offset)
+        .filter(|shift| *shift < 32)
+        .ok_or(PayloadDecodeError::InvalidMemOffset(mem_offset))?;
+    Ok(shifted(encoded, byte_shift))
+}
+
+fn shifted(encoded: U256, byte_shift: u8) -> U256 {
+    encoded << (8 * byte_shift as usize)
+}
+
+// Bits to shift followed by the value, as read by the v3 part of the contract
+fn v3_amount(encoded: EncodedSwapValue, num_bytes: u8) -> Vec<u8> {
+    let shift_bits = (32 - num_bytes as u64 - encoded.byte_shift.as_u64()) * 8;
+    let mut packed = vec![shift_bits as u8];
+    packed.extend(last_bytes(encoded.encoded_value, num_bytes as usize));
+    packed
+}
+
+fn last_bytes(value: U256, len: usize) -> Vec<u8> {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    bytes[32 - len..].to_vec()
+}
+
+fn weth(amount: U256) -> String {
+    format_units(amount, "ether").unwrap_or_else(|_| amount.to_string())
+}
+
+fn first(is_first: bool) -> &'static str {
+    match is_first {
+        true => " (first)",
+        false => "",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::prelude::rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::super::jump_table::test_jump_table;
+    use super::*;
+
+    const SINGLE_VARIANTS: [u8; 4] = [0, 1, 4, 5];
+    const MULTI_VARIANTS: [u8; 4] = [2, 3, 6, 7];
+
+    fn random_payload(rng: &mut StdRng, variant: u8, is_first: bool) -> SandwichPayload {
+        let block_number = rng.gen();
+        let address = Address::random_using(rng);
+        let token = Address::random_using(rng);
+        let pool_key_hash = H256::random_using(rng);
+        let flag = rng.gen();
+        // anything up to ~4700 weth
+        let amount_in = U256::from(rng.gen::<u64>()) << rng.gen_range(0..8);
+        let amount_out = U256::from(rng.gen::<u64>()) << rng.gen_range(0..8);
+
+        match variant {
+            0 => SandwichPayload::V2WethInput {
+                block_number,
+                pair: address,
+                weth_is_token0: flag,
+                amount_in,
+                amount_out,
+            },
+            1 => SandwichPayload::V2WethOutput {
+                block_number,
+                pair: address,
+                token,
+                weth_is_token0: flag,
+                amount_in,
+                amount_out,
+            },
+            2 => SandwichPayload::V2MultiWethInput {
+                block_number,
+                pair: address,
+                weth_is_token0: flag,
+                is_first,
+                amount_in,
+                amount_out,
+            },
+            3 => SandwichPayload::V2MultiWethOutput {
+                block_number,
+                pair: address,
+                token,
+                weth_is_token0: flag,
+                is_first,
+                amount_in,
+                amount_out,
+            },
+            4 => SandwichPayload::V3WethInput {
+                block_number,
+                pool: address,
+                zero_for_one: flag,
+                pool_key_hash,
+                amount_in,
+                amount_out,
+            },
+            5 => SandwichPayload::V3WethOutput {
+                block_number,
+                pool: address,
+                token,
+                zero_for_one: flag,
+                pool_key_hash,
+                amount_in,
+                amount_out,
+            },
+            6 => SandwichPayload::V3MultiWethInput {
+                block_number,
+                pool: address,
+                zero_for_one: flag,
+                is_first,
+                pool_key_hash,
+                amount_in,
+                amount_out,
+            },
+            _ => SandwichPayload::V3MultiWethOutput {
+                block_number,
+                pool: address,
+                token,
+                zero_for_one: flag,
+                is_first,
+                pool_key_hash,
+                amount_in,
+                amount_out,
+            },
+        }
+    }
+
+    fn amounts(payload: &SandwichPayload) -> (U256, U256) {
+        match *payload {
+            SandwichPayload::V2WethInput {
+                amount_in,
+                amount_out,
+                ..
+            }
+            | SandwichPayload::V2WethOutput {
+                amount_in,
+                amount_out,
+                ..
+            }
+            | SandwichPayload::V2MultiWethInput {
+                amount_in,
+                amount_out,
+                ..
+            }
+            | SandwichPayload::V2MultiWethOutput {
+                amount_in,
+                amount_out,
+                ..
+            }
+            | SandwichPayload::V3WethInput {
+                amount_in,
+                amount_out,
+                ..
+            }
+            | SandwichPayload::V3WethOutput {
+                amount_in,
+                amount_out,
+                ..
+            }
+            | SandwichPayload::V3MultiWethInput {
+                amount_in,
+                amount_out,
+                ..
+            }
+            | SandwichPayload::V3MultiWethOutput {
+                amount_in,
+                amount_out,
+                ..
+            } => (amount_in, amount_out),
+        }
+    }
+
+    // encoding keeps at least 4 significant bytes, weth call values are multiples of the divisor
+    fn assert_close(decoded: U256, original: U256) {
+        assert!(decoded <= original, "{} > {}", decoded, original);
+        let max_loss = (original >> 23).max(get_weth_encode_divisor());
+        assert!(
+            original - decoded <= max_loss,
+            "{} vs {}",
+            decoded,
+            original
+        );
+    }
+
+    #[test]
+    fn single_payloads_round_trip() {
+        let jump_table = test_jump_table();
+        let mut rng = StdRng::seed_from_u64(45);
+
+        for _ in 0..1000 {
+            let variant = SINGLE_VARIANTS[rng.gen_range(0..4)];
+            let payload = random_payload(&mut rng, variant, false);
+
+            let (data, value) = payload.encode(&jump_table);
+            let decoded = decode_payloads(&data, value, &jump_table).unwrap();
+            assert_eq!(decoded.len(), 1);
+
+            // only amounts lose precision, once encoded the payload round trips exactly
+            let decoded = decoded[0].clone();
+            assert_eq!(decoded.jump_label(), payload.jump_label());
+            assert_close(amounts(&decoded).0, amounts(&payload).0);
+            assert_close(amounts(&decoded).1, amounts(&payload).1);
+            let (data, value) = decoded.encode(&jump_table);
+            assert_eq!(
+                decode_payloads(&data, value, &jump_table).unwrap(),
+                vec![decoded]
+            );
+        }
+    }
+
+    #[test]
+    fn multi_payloads_round_trip() {
+        let jump_table = test_jump_table();
+        let mut rng = StdRng::seed_from_u64(46);
+
+        for _ in 0..200 {
+            let payloads: Vec<SandwichPayload> = (0..rng.gen_range(1..5))
+                .map(|index| {
+                    let variant = MULTI_VARIANTS[rng.gen_range(0..4)];
+                    random_payload(&mut rng, variant, index == 0)
+                })
+                .collect();
+
+            let mut data = vec![];
+            let mut value = U256::zero();
+            for payload in payloads.iter() {
+                let (payload_data, payload_value) = payload.encode(&jump_table);
+                data.extend(payload_data);
+                value += payload_value;
+            }
+            data.push(jump_table.end_of_multi_payload());
+
+            let decoded = decode_payloads(&data, value, &jump_table).unwrap();
+            assert_eq!(decoded.len(), payloads.len());
+            for (decoded, payload) in decoded.iter().zip(payloads.iter()) {
+                assert_eq!(decoded.jump_label(), payload.jump_label());
+                assert_close(amounts(decoded).0, amounts(payload).0);
+                assert_close(amounts(decoded).1, amounts(payload).1);
+            }
+        }
+    }
+
+    // calldata and call value the v2.rs / v3.rs encoders produced before payloads were typed,
+    // captured from their `create_*payload*` functions with the pair and amounts below
+    //
+    // Arguments:
+    // * `weth_is_token0`: picks the token sorting after (true) or before (false) weth
+    // * `variant`: payload variant, see `random_payload`
+    // * `is_first`: first payload of a multi sandwich
+    fn golden(weth_is_token0: bool, variant: u8, is_first: bool) -> SandwichPayload {
+        let token = match weth_is_token0 {
+            true => "0xdddddddddddddddddddddddddddddddddddddddd",
+            false => "0x1111111111111111111111111111111111111111",
+        };
+        let token: Address = token.parse().unwrap();
+        let pool: Address = "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+            .parse()
+            .unwrap();
+        // keccak(token0, token1, 3000)
+        let pool_key_hash: H256 = match weth_is_token0 {
+            true => "0xe4e1decfe5763d507c6c466bb95fc4ec181d8a772dd0fef12ce9dc4b643720a9",
+            false => "0x0bb4da60ea3a837e02d33ef84349da313ef22986301296426d79a21c55f31f55",
+        }
+        .parse()
+        .unwrap();
+        // last byte of block 17000123
+        let block_number = 0xbb;
+        let weth = U256::from(1_500_000_000_000_000_000u64);
+        let tokens = U256::from(123_456_789_012_345_678_901u128);
+
+        match variant {
+            0 => SandwichPayload::V2WethInput {
+                block_number,
+                pair: pool,
+                weth_is_token0,
+                amount_in: weth,
+                amount_out: tokens,
+            },
+            1 => SandwichPayload::V2WethOutput {
+                block_number,
+                pair: pool,
+                token,
+                weth_is_token0,
+                amount_in: tokens,
+                amount_out: weth,
+            },
+            2 => SandwichPayload::V2MultiWethInput {
+                block_number,
+                pair: pool,
+                weth_is_token0,
+                is_first,
+                amount_in: weth,
+                amount_out: tokens,
+            },
+            3 => SandwichPayload::V2MultiWethOutput {
+                block_number,
+                pair: pool,
+                token,
+                weth_is_token0,
+                is_first,
+                amount_in: tokens,
+                amount_out: weth,
+            },
+            4 => SandwichPayload::V3WethInput {
+                block_number,
+                pool,
+                zero_for_one: weth_is_token0,
+                pool_key_hash,
+                amount_in: weth,
+                amount_out: tokens,
+            },
+            5 => SandwichPayload::V3WethOutput {
+                block_number,
+                pool,
+                token,
+                zero_for_one: !weth_is_token0,
+                pool_key_hash,
+                amount_in: tokens,
+                amount_out: weth,
+            },
+            6 => SandwichPayload::V3MultiWethInput {
+                block_number,
+                pool,
+                zero_for_one: weth_is_token0,
+                is_first,
+                pool_key_hash,
+                amount_in: weth,
+                amount_out: tokens,
+            },
+            _ => SandwichPayload::V3MultiWethOutput {
+                block_number,
+                pool,
+                token,
+                zero_for_one: !weth_is_token0,
+                is_first,
+                pool_key_hash,
+                amount_in: tokens,
+                amount_out: weth,
+            },
+        }
+    }
+
+    #[test]
+    fn encodes_like_untyped_encoders() {
+        let jump_table = test_jump_table();
+        // (weth_is_token0, variant, is_first, calldata, call value)
+        let vectors: [(bool, u8, bool, &str, u64); 24] = [
+            (true, 0, false, "bb303baaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa06b14e9f", 349245965),
+            (true, 1, false, "bb353baaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaadddddddddddddddddddddddddddddddddddddddd06b14e9f", 349245965),
+            (true, 2, true, "bb533baaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa06b14e9f", 349245965),
+            (true, 2, false, "bb583baaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa06b14e9f0014d1120d", 0),
+            (true, 3, true, "bb5d3baaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaadddddddddddddddddddddddddddddddddddddddd06b14e9f41", 89406967163),
+            (true, 3, false, "bb623baaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaadddddddddddddddddddddddddddddddddddddddd06b14e9f14d1120d7b41", 0),
+            (true, 4, false, "bb3faaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaab806b14e9f81e4e1decfe5763d507c6c466bb95fc4ec181d8a772dd0fef12ce9dc4b643720a9", 349245965),
+            (true, 5, false, "bb49aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaab806b14e9f81dddddddddddddddddddddddddddddddddddddddde4e1decfe5763d507c6c466bb95fc4ec181d8a772dd0fef12ce9dc4b643720a9", 349245965),
+            (true, 6, true, "bb676caaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaac014d1120db806b14e9f81e4e1decfe5763d507c6c466bb95fc4ec181d8a772dd0fef12ce9dc4b643720a9", 0),
+            (true, 6, false, "bb6caaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaac014d1120db806b14e9f81e4e1decfe5763d507c6c466bb95fc4ec181d8a772dd0fef12ce9dc4b643720a9", 0),
+            (true, 7, true, "bb6776aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaac014d1120db806b14e9f81dddddddddddddddddddddddddddddddddddddddde4e1decfe5763d507c6c466bb95fc4ec181d8a772dd0fef12ce9dc4b643720a9", 0),
+            (true, 7, false, "bb76aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaac014d1120db806b14e9f81dddddddddddddddddddddddddddddddddddddddde4e1decfe5763d507c6c466bb95fc4ec181d8a772dd0fef12ce9dc4b643720a9", 0),
+            (false, 0, false, "bb301baaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa06b14e9f", 349245965),
+            (false, 1, false, "bb3a3baaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa111111111111111111111111111111111111111106b14e9f", 349245965),
+            (false, 2, true, "bb531baaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa06b14e9f", 349245965),
+            (false, 2, false, "bb581baaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa06b14e9f0014d1120d", 0),
+            (false, 3, true, "bb5d3baaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa111111111111111111111111111111111111111106b14e9f61", 89406967163),
+            (false, 3, false, "bb623baaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa111111111111111111111111111111111111111106b14e9f14d1120d7b61", 0),
+            (false, 4, false, "bb44aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaab806b14e9f810bb4da60ea3a837e02d33ef84349da313ef22986301296426d79a21c55f31f55", 349245965),
+            (false, 5, false, "bb4eaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaab806b14e9f8111111111111111111111111111111111111111110bb4da60ea3a837e02d33ef84349da313ef22986301296426d79a21c55f31f55", 349245965),
+            (false, 6, true, "bb6771aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaac014d1120db806b14e9f810bb4da60ea3a837e02d33ef84349da313ef22986301296426d79a21c55f31f55", 0),
+            (false, 6, false, "bb71aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaac014d1120db806b14e9f810bb4da60ea3a837e02d33ef84349da313ef22986301296426d79a21c55f31f55", 0),
+            (false, 7, true, "bb677baaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaac014d1120db806b14e9f8111111111111111111111111111111111111111110bb4da60ea3a837e02d33ef84349da313ef22986301296426d79a21c55f31f55", 0),
+            (false, 7, false, "bb7baaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaac014d1120db806b14e9f8111111111111111111111111111111111111111110bb4da60ea3a837e02d33ef84349da313ef22986301296426d79a21c55f31f55", 0),
+        ];
+
+        for (weth_is_token0, variant, is_first, data, value) in vectors {
+            let payload = golden(weth_is_token0, variant, is_first);
+            assert_eq!(
+                payload.encode(&jump_table),
+                (hex::decode(data).unwrap(), U256::from(value)),
+                "{:?}",
+                payload
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_calldata() {
+        let jump_table = test_jump_table();
+        assert_eq!(
+            decode_payloads(&[1, 2, 3], U256::zero(), &jump_table),
+            Err(PayloadDecodeError::UnknownSwapType(2))
+        );
+
+        let payload = SandwichPayload::V2MultiWethInput {
+            block_number: 1,
+            pair: Address::from_low_u64_be(1),
+            weth_is_token0: true,
+            is_first: true,
+            amount_in: U256::exp10(18),
+            amount_out: U256::exp10(20),
+        };
+        let (data, value) = payload.encode(&jump_table);
+        assert_eq!(
+            decode_payloads(&data[..data.len() - 1], value, &jump_table),
+            Err(PayloadDecodeError::UnexpectedEnd(data.len() - 1))
+        );
+        assert_eq!(
+            decode_payloads(&data, value, &jump_table),
+            Err(PayloadDecodeError::MissingEndOfMultiPayload())
+        );
+        let data = [data, vec![jump_table.end_of_multi_payload()]].concat();
+        assert!(decode_payloads(&data, value, &jump_table).is_ok());
+    }
+}
@@ -1,47 +1,26 @@
-use std::ops::{Div, Sub};
+use std::ops::Sub;
 
 use super::*;
-use hashbrown::HashMap;
 
 use crate::{prelude::Pool, utils};
 
 #[derive(Debug, Clone)]
 pub struct SandwichLogicV2 {
-    jump_labels: HashMap<String, u32>,
+    jump_table: JumpTable,
 }
 
 impl SandwichLogicV2 {
-    pub fn new() -> Self {
-        let mut jump_labels: HashMap<String, u32> = HashMap::new();
-
-        // pattern: {input||output}{isWeth0||isWeth1}_{numBytesToEncodeTo}
-        let jump_label_names_single =
-            vec!["v2_input_single", "v2_output0_single", "v2_output1_single"];
-        let jump_label_names_multi = vec![
-            "v2_input_multi_first",
-            "v2_input_multi_next",
-            "v2_output_multi_first",
-            "v2_output_multi_next",
-        ];
-
-        let start_offset_single = 48;
-        let start_offset_multi = 83;
-
-        for x in 0..jump_label_names_single.len() {
-            jump_labels.insert(
-                jump_label_names_single[x].to_string(),
-                start_offset_single + (5 * (x as u32)),
-            );
-        }
-
-        for x in 0..jump_label_names_multi.len() {
-            jump_labels.insert(
-                jump_label_names_multi[x].to_string(),
-                start_offset_multi + (5 * (x as u32)),
-            );
-        }
+    // Create a new `SandwichLogicV2` instance
+    //
+    // Arguments:
+    // * `jump_table`: jump table of the deployed sandwich contract
+    pub fn new(jump_table: JumpTable) -> Self {
+        SandwichLogicV2 { jump_table }
+    }
 
-        SandwichLogicV2 { jump_labels }
+    // Encode a v2 payload into calldata and call value
+    pub fn encode(&self, payload: &SandwichPayload) -> (Vec<u8>, U256) {
+        payload.encode(&self.jump_table)
     }
 
     pub fn create_payload_weth_is_input(
@@ -52,30 +31,13 @@ impl SandwichLogicV2 {
         other_token: Address, // output token
         pair: Pool,
     ) -> (Vec<u8>, U256) {
-        let encoded_swap_value = encode_four_bytes(
+        self.encode(&SandwichPayload::V2WethInput {
+            block_number: block_number.low_u32() as u8,
+            pair: pair.address,
+            weth_is_token0: utils::constants::get_weth_address() < other_token,
+            amount_in,
             amount_out,
-            true,
-            utils::constants::get_weth_address() < other_token,
-        );
-
-        let swap_type = self._find_swap_type(false, false, true, other_token);
-
-        let (payload, _) = utils::encode_packed(&[
-            utils::PackedToken::NumberWithShift(block_number, utils::TakeLastXBytes(8)),
-            utils::PackedToken::NumberWithShift(swap_type, utils::TakeLastXBytes(8)),
-            utils::PackedToken::NumberWithShift(
-                encoded_swap_value.mem_offset,
-                utils::TakeLastXBytes(8),
-            ),
-            utils::PackedToken::Address(pair.address),
-            utils::PackedToken::NumberWithShift(
-                encoded_swap_value.encoded_value,
-                utils::TakeLastXBytes(32),
-            ),
-        ]);
-
-        let encoded_call_value = amount_in.div(get_weth_encode_divisor());
-        (payload, encoded_call_value)
+        })
     }
 
     pub fn create_payload_weth_is_output(
@@ -86,34 +48,14 @@ impl SandwichLogicV2 {
         other_token: Address, // input_token
         pair: Pool,
     ) -> (Vec<u8>, U256) {
-        let encoded_swap_value = encode_four_bytes(
+        self.encode(&SandwichPayload::V2WethOutput {
+            block_number: block_number.low_u32() as u8,
+            pair: pair.address,
+            token: other_token,
+            weth_is_token0: utils::constants::get_weth_address() < other_token,
             amount_in,
-            false,
-            utils::constants::get_weth_address() < other_token,
-        );
-
-        let swap_type = self._find_swap_type(false, false, false, other_token);
-
-        let (payload, _) = utils::encode_packed(&[
-            utils::PackedToken::NumberWithShift(block_number, utils::TakeLastXBytes(8)),
-            utils::PackedToken::NumberWithShift(swap_type, utils::TakeLastXBytes(8)),
-            utils::PackedToken::NumberWithShift(
-                encoded_swap_value.mem_offset,
-                utils::TakeLastXBytes(8),
-            ),
-            utils::PackedToken::Address(pair.address),
-            utils::PackedToken::Address(other_token),
-            utils::PackedToken::NumberWithShift(
-                encoded_swap_value.encoded_value,
-                utils::TakeLastXBytes(32),
-            ),
-        ]);
-
-        let encoded_call_value = amount_out.div(get_weth_encode_divisor());
-        // log::info!("{}", format!("[Backrun payload] {:02x?}", payload));
-        // log::info!("{}", format!("[Backrun value] {:?}", encoded_call_value));
-
-        (payload, encoded_call_value)
+            amount_out,
+        })
     }
 
     pub fn create_multi_payload_weth_is_input(
@@ -125,48 +67,14 @@ impl SandwichLogicV2 {
         pair: Pool,
         is_first: bool,
     ) -> (Vec<u8>, U256) {
-        let encoded_amount_out_swap_value = encode_four_bytes(
+        self.encode(&SandwichPayload::V2MultiWethInput {
+            block_number: block_number.low_u32() as u8,
+            pair: pair.address,
+            weth_is_token0: utils::constants::get_weth_address() < other_token,
+            is_first,
+            amount_in,
             amount_out,
-            true,
-            utils::constants::get_weth_address() < other_token,
-        );
-
-        let swap_type = self._find_swap_type(true, is_first, true, other_token);
-        let encoded_amount_in = amount_in.div(get_weth_encode_divisor());
-        let mut callvalue = U256::from(0);
-        let (payload, _) = if is_first {
-            callvalue = encoded_amount_in;
-            utils::encode_packed(&[
-                utils::PackedToken::NumberWithShift(block_number, utils::TakeLastXBytes(8)),
-                utils::PackedToken::NumberWithShift(swap_type, utils::TakeLastXBytes(8)),
-                utils::PackedToken::NumberWithShift(
-                    encoded_amount_out_swap_value.mem_offset,
-                    utils::TakeLastXBytes(8),
-                ),
-                utils::PackedToken::Address(pair.address),
-                utils::PackedToken::NumberWithShift(
-                    encoded_amount_out_swap_value.encoded_value,
-                    utils::TakeLastXBytes(32),
-                ),
-            ])
-        } else {
-            utils::encode_packed(&[
-                utils::PackedToken::NumberWithShift(block_number, utils::TakeLastXBytes(8)),
-                utils::PackedToken::NumberWithShift(swap_type, utils::TakeLastXBytes(8)),
-                utils::PackedToken::NumberWithShift(
-                    encoded_amount_out_swap_value.mem_offset,
-                    utils::TakeLastXBytes(8),
-                ),
-                utils::PackedToken::Address(pair.address),
-                utils::PackedToken::NumberWithShift(
-                    encoded_amount_out_swap_value.encoded_value,
-                    utils::TakeLastXBytes(32),
-                ),
-                utils::PackedToken::NumberWithShift(encoded_amount_in, utils::TakeLastXBytes(40)),
-            ])
-        };
-
-        (payload, callvalue)
+        })
     }
 
     pub fn create_multi_payload_weth_is_output(
@@ -178,91 +86,15 @@ impl SandwichLogicV2 {
         pair: Pool,
         is_first: bool,
     ) -> (Vec<u8>, U256) {
-        let encoded_amount_out_swap_value = encode_five_bytes(
-            amount_out,
-            utils::constants::get_weth_address() < other_token,
-        );
-
-        let swap_type = self._find_swap_type(true, is_first, false, other_token);
-        let encoded_amount_in_swap_value = encode_four_bytes(
+        self.encode(&SandwichPayload::V2MultiWethOutput {
+            block_number: block_number.low_u32() as u8,
+            pair: pair.address,
+            token: other_token,
+            weth_is_token0: utils::constants::get_weth_address() < other_token,
+            is_first,
             amount_in,
-            false,
-            utils::constants::get_weth_address() < other_token,
-        );
-        let mut callvalue = U256::from(0);
-        let (payload, _) = if is_first {
-            callvalue = encoded_amount_out_swap_value.encoded_value;
-            utils::encode_packed(&[
-                utils::PackedToken::NumberWithShift(block_number, utils::TakeLastXBytes(8)),
-                utils::PackedToken::NumberWithShift(swap_type, utils::TakeLastXBytes(8)),
-                utils::PackedToken::NumberWithShift(
-                    encoded_amount_in_swap_value.mem_offset,
-                    utils::TakeLastXBytes(8),
-                ),
-                utils::PackedToken::Address(pair.address),
-                utils::PackedToken::Address(other_token),
-                utils::PackedToken::NumberWithShift(
-                    encoded_amount_in_swap_value.encoded_value,
-                    utils::TakeLastXBytes(32),
-                ),
-                utils::PackedToken::NumberWithShift(
-                    encoded_amount_out_swap_value.mem_offset,
-                    utils::TakeLastXBytes(8),
-                ),
-            ])
-        } else {
-            utils::encode_packed(&[
-                utils::PackedToken::NumberWithShift(block_number, utils::TakeLastXBytes(8)),
-                utils::PackedToken::NumberWithShift(swap_type, utils::TakeLastXBytes(8)),
-                utils::PackedToken::NumberWithShift(
-                    encoded_amount_in_swap_value.mem_offset,
-                    utils::TakeLastXBytes(8),
-                ),
-                utils::PackedToken::Address(pair.address),
-                utils::PackedToken::Address(other_token),
-                utils::PackedToken::NumberWithShift(
-                    encoded_amount_in_swap_value.encoded_value,
-                    utils::TakeLastXBytes(32),
-                ),
-                utils::PackedToken::NumberWithShift(
-                    encoded_amount_out_swap_value.encoded_value,
-                    utils::TakeLastXBytes(40),
-                ),
-                utils::PackedToken::NumberWithShift(
-                    encoded_amount_out_swap_value.mem_offset,
-                    utils::TakeLastXBytes(8),
-                ),
-            ])
-        };
-
-        (payload, callvalue)
-    }
-
-    fn _find_swap_type(
-        &self,
-        is_multiple: bool,
-        is_first_of_payload: bool,
-        is_weth_input: bool,
-        other_token_addr: Address,
-    ) -> U256 {
-        let weth_addr = utils::constants::get_weth_address();
-
-        let swap_type = if is_multiple {
-            match (is_first_of_payload, is_weth_input) {
-                (true, true) => self.jump_labels["v2_input_multi_first"],
-                (false, true) => self.jump_labels["v2_input_multi_next"],
-                (true, false) => self.jump_labels["v2_output_multi_first"],
-                (false, false) => self.jump_labels["v2_output_multi_next"],
-            }
-        } else {
-            match (is_weth_input, weth_addr < other_token_addr) {
-                (true, _) => self.jump_labels["v2_input_single"],
-                (false, true) => self.jump_labels["v2_output0_single"],
-                (false, false) => self.jump_labels["v2_output1_single"],
-            }
-        };
-
-        U256::from(swap_type)
+            amount_out,
+        })
     }
 }
 
@@ -270,7 +102,11 @@ impl SandwichLogicV2 {
 //
 // Returns:
 // EncodedSwapValue representing 4 byte value, and byteshift
-fn encode_four_bytes(amount: U256, is_weth_input: bool, is_weth_token0: bool) -> EncodedSwapValue {
+pub(super) fn encode_four_bytes(
+    amount: U256,
+    is_weth_input: bool,
+    is_weth_token0: bool,
+) -> EncodedSwapValue {
     let mut byte_shift = 0;
     let mut four_byte_encoded_value = U256::zero();
 
@@ -311,7 +147,7 @@ fn encode_four_bytes(amount: U256, is_weth_input: bool, is_weth_token0: bool) ->
 //
 // Returns:
 // EncodedSwapValue representing 4 byte value, and byteshift
-fn encode_five_bytes(amount: U256, is_weth_token0: bool) -> EncodedSwapValue {
+pub(super) fn encode_five_bytes(amount: U256, is_weth_token0: bool) -> EncodedSwapValue {
     let mut byte_shift = 0;
     let mut five_byte_encoded_value = U256::zero();
 
@@ -1,46 +1,24 @@
-use crate::utils::constants::get_prepare_stack_payload;
-use crate::{prelude::Pool, utils};
+use crate::prelude::Pool;
 
 use super::*;
 
-use hashbrown::HashMap;
-
 #[derive(Debug, Clone)]
 pub struct SandwichLogicV3 {
-    jump_labels: HashMap<String, u32>,
+    jump_table: JumpTable,
 }
 
 impl SandwichLogicV3 {
     // Create a new `SandwichLogicV3` instance
-    pub fn new() -> Self {
-        let mut jump_labels: HashMap<String, u32> = HashMap::new();
-
-        // encachement: turn this into a macro or constant?
-        let jump_label_names_single = vec!["v3_input0", "v3_input1", "v3_output0", "v3_output1"];
-        let jump_label_names_multi = vec![
-            "v3_input0_multi",
-            "v3_input1_multi",
-            "v3_output0_multi",
-            "v3_output1_multi",
-        ];
-        let start_offset_single = 63;
-        let start_offset_multi = 108;
-
-        for x in 0..jump_label_names_single.len() {
-            jump_labels.insert(
-                jump_label_names_single[x].to_string(),
-                start_offset_single + (5 * (x as u32)),
-            );
-        }
-
-        for x in 0..jump_label_names_multi.len() {
-            jump_labels.insert(
-                jump_label_names_multi[x].to_string(),
-                start_offset_multi + (5 * (x as u32)),
-            );
-        }
+    //
+    // Arguments:
+    // * `jump_table`: jump table of the deployed sandwich contract
+    pub fn new(jump_table: JumpTable) -> Self {
+        SandwichLogicV3 { jump_table }
+    }
 
-        SandwichLogicV3 { jump_labels }
+    // Encode a v3 payload into calldata and call value
+    pub fn encode(&self, payload: &SandwichPayload) -> (Vec<u8>, U256) {
+        payload.encode(&self.jump_table)
     }
 
     // Handles creation of tx data field when weth is input
@@ -53,34 +31,14 @@ impl SandwichLogicV3 {
         output: Address,
         pool: Pool,
     ) -> (Vec<u8>, U256) {
-        let (token_0, token_1, fee) = (pool.token_0, pool.token_1, pool.swap_fee);
-        let swap_type = self._find_swap_type(false, true, input, output);
-        let pool_key_hash = ethers::utils::keccak256(abi::encode(&[
-            abi::Token::Address(token_0),
-            abi::Token::Address(token_1),
-            abi::Token::Uint(fee),
-        ]));
-        let encoded_swap_value: EncodedSwapValue =
-            encode_num_bytes(U256::from(amount_out.as_u128()), 5);
-
-        let (payload, _) = utils::encode_packed(&[
-            utils::PackedToken::NumberWithShift(block_number, utils::TakeLastXBytes(8)),
-            utils::PackedToken::NumberWithShift(swap_type, utils::TakeLastXBytes(8)),
-            utils::PackedToken::Address(pool.address),
-            utils::PackedToken::NumberWithShift(
-                U256::from((32 - 5 - encoded_swap_value.byte_shift.as_u64()) * 8),
-                utils::TakeLastXBytes(8),
-            ),
-            utils::PackedToken::NumberWithShift(
-                encoded_swap_value.encoded_value,
-                utils::TakeLastXBytes(40),
-            ),
-            utils::PackedToken::Bytes(&pool_key_hash),
-        ]);
-
-        let encoded_call_value = U256::from(amount_in.as_u128()) / get_weth_encode_divisor();
-
-        (payload, encoded_call_value)
+        self.encode(&SandwichPayload::V3WethInput {
+            block_number: block_number.low_u32() as u8,
+            pool: pool.address,
+            zero_for_one: input < output,
+            pool_key_hash: pool_key_hash(&pool),
+            amount_in: U256::from(amount_in.as_u128()),
+            amount_out: U256::from(amount_out.as_u128()),
+        })
     }
 
     // Handles creation of tx data field when weth is output
@@ -93,34 +51,15 @@ impl SandwichLogicV3 {
         output: Address,
         pool: Pool,
     ) -> (Vec<u8>, U256) {
-        let (token_0, token_1, fee) = (pool.token_0, pool.token_1, pool.swap_fee);
-        let swap_type = self._find_swap_type(false, false, input, output);
-        let encoded_swap_value = encode_num_bytes(U256::from(amount_in.as_u128()), 5);
-        let pool_key_hash = ethers::utils::keccak256(abi::encode(&[
-            abi::Token::Address(token_0),
-            abi::Token::Address(token_1),
-            abi::Token::Uint(fee),
-        ]));
-
-        // use small encoding method (encode amount_in to 6 bytes)
-        let (payload, _) = utils::encode_packed(&vec![
-            utils::PackedToken::NumberWithShift(block_number, utils::TakeLastXBytes(8)),
-            utils::PackedToken::NumberWithShift(swap_type, utils::TakeLastXBytes(8)),
-            utils::PackedToken::Address(pool.address),
-            utils::PackedToken::NumberWithShift(
-                U256::from((32 - 5 - encoded_swap_value.byte_shift.as_u64()) * 8),
-                utils::TakeLastXBytes(8),
-            ),
-            utils::PackedToken::NumberWithShift(
-                encoded_swap_value.encoded_value,
-                utils::TakeLastXBytes(40),
-            ),
-            utils::PackedToken::Address(input),
-            utils::PackedToken::Bytes(&pool_key_hash),
-        ]);
-        let encoded_call_value = U256::from(amount_out.as_u128()) / get_weth_encode_divisor();
-
-        (payload, encoded_call_value)
+        self.encode(&SandwichPayload::V3WethOutput {
+            block_number: block_number.low_u32() as u8,
+            pool: pool.address,
+            token: input,
+            zero_for_one: input < output,
+            pool_key_hash: pool_key_hash(&pool),
+            amount_in: U256::from(amount_in.as_u128()),
+            amount_out: U256::from(amount_out.as_u128()),
+        })
     }
 
     pub fn create_multi_payload_weth_is_input(
@@ -133,60 +72,17 @@ impl SandwichLogicV3 {
         pool: Pool,
         is_first: bool,
     ) -> (Vec<u8>, U256) {
-        let (token_0, token_1, fee) = (pool.token_0, pool.token_1, pool.swap_fee);
-        let swap_type = self._find_swap_type(true, true, input, output);
-        let pool_key_hash = ethers::utils::keccak256(abi::encode(&[
-            abi::Token::Address(token_0),
-            abi::Token::Address(token_1),
-            abi::Token::Uint(fee),
-        ]));
-        let encoded_amount_in_swap_value: EncodedSwapValue =
-            encode_num_bytes(U256::from(amount_in.as_u128()), 4);
-        let encoded_amount_out_swap_value: EncodedSwapValue =
-            encode_num_bytes(U256::from(amount_out.as_u128()), 5);
-
-        let prepare_stack_sig = get_prepare_stack_payload();
-        let (payload_data, _) = utils::encode_packed(&[
-            utils::PackedToken::NumberWithShift(swap_type, utils::TakeLastXBytes(8)),
-            utils::PackedToken::Address(pool.address),
-            utils::PackedToken::NumberWithShift(
-                U256::from((32 - 4 - encoded_amount_in_swap_value.byte_shift.as_u64()) * 8),
-                utils::TakeLastXBytes(8),
-            ),
-            utils::PackedToken::NumberWithShift(
-                encoded_amount_in_swap_value.encoded_value,
-                utils::TakeLastXBytes(32),
-            ),
-            utils::PackedToken::NumberWithShift(
-                U256::from((32 - 5 - encoded_amount_out_swap_value.byte_shift.as_u64()) * 8),
-                utils::TakeLastXBytes(8),
-            ),
-            utils::PackedToken::NumberWithShift(
-                encoded_amount_out_swap_value.encoded_value,
-                utils::TakeLastXBytes(40),
-            ),
-            utils::PackedToken::Bytes(&pool_key_hash),
-        ]);
-        let (payload, _) = if is_first {
-            utils::encode_packed(&[
-                utils::PackedToken::NumberWithShift(block_number, utils::TakeLastXBytes(8)),
-                utils::PackedToken::NumberWithShift(
-                    U256::from(prepare_stack_sig),
-                    utils::TakeLastXBytes(8),
-                ),
-                utils::PackedToken::Bytes(&payload_data),
-            ])
-        } else {
-            utils::encode_packed(&[
-                utils::PackedToken::NumberWithShift(block_number, utils::TakeLastXBytes(8)),
-                utils::PackedToken::Bytes(&payload_data),
-            ])
-        };
-
-        // let encoded_call_value = U256::from(amount_in.as_u128()) / get_weth_encode_divisor();
-
-        (payload, U256::zero())
+        self.encode(&SandwichPayload::V3MultiWethInput {
+            block_number: block_number.low_u32() as u8,
+            pool: pool.address,
+            zero_for_one: input < output,
+            is_first,
+            pool_key_hash: pool_key_hash(&pool),
+            amount_in: U256::from(amount_in.as_u128()),
+            amount_out: U256::from(amount_out.as_u128()),
+        })
     }
+
     pub fn create_multi_payload_weth_is_output(
         &self,
         block_number: U256,
@@ -197,102 +93,33 @@ impl SandwichLogicV3 {
         pool: Pool,
         is_first: bool,
     ) -> (Vec<u8>, U256) {
-        let (token_0, token_1, fee) = (pool.token_0, pool.token_1, pool.swap_fee);
-        let swap_type = self._find_swap_type(true, false, input, output);
-        let pool_key_hash = ethers::utils::keccak256(abi::encode(&[
-            abi::Token::Address(token_0),
-            abi::Token::Address(token_1),
-            abi::Token::Uint(fee),
-        ]));
-        let encoded_amount_in_swap_value: EncodedSwapValue =
-            encode_num_bytes(U256::from(amount_in.as_u128()), 5);
-        let encoded_amount_out_swap_value: EncodedSwapValue =
-            encode_num_bytes(U256::from(amount_out.as_u128()), 4);
-
-        let prepare_stack_sig = get_prepare_stack_payload();
-        let (payload_data, _) = utils::encode_packed(&[
-            utils::PackedToken::NumberWithShift(swap_type, utils::TakeLastXBytes(8)),
-            utils::PackedToken::Address(pool.address),
-            utils::PackedToken::NumberWithShift(
-                U256::from((32 - 4 - encoded_amount_out_swap_value.byte_shift.as_u64()) * 8),
-                utils::TakeLastXBytes(8),
-            ),
-            utils::PackedToken::NumberWithShift(
-                encoded_amount_out_swap_value.encoded_value,
-                utils::TakeLastXBytes(32),
-            ),
-            utils::PackedToken::NumberWithShift(
-                U256::from((32 - 5 - encoded_amount_in_swap_value.byte_shift.as_u64()) * 8),
-                utils::TakeLastXBytes(8),
-            ),
-            utils::PackedToken::NumberWithShift(
-                encoded_amount_in_swap_value.encoded_value,
-                utils::TakeLastXBytes(40),
-            ),
-            utils::PackedToken::Address(input),
-            utils::PackedToken::Bytes(&pool_key_hash),
-        ]);
-        let (payload, _) = if is_first {
-            utils::encode_packed(&[
-                utils::PackedToken::NumberWithShift(block_number, utils::TakeLastXBytes(8)),
-                utils::PackedToken::NumberWithShift(
-                    U256::from(prepare_stack_sig),
-                    utils::TakeLastXBytes(8),
-                ),
-                utils::PackedToken::Bytes(&payload_data),
-            ])
-        } else {
-            utils::encode_packed(&[
-                utils::PackedToken::NumberWithShift(block_number, utils::TakeLastXBytes(8)),
-                utils::PackedToken::Bytes(&payload_data),
-            ])
-        };
-        // let encoded_call_value = U256::from(amount_in.as_u128()) / get_weth_encode_divisor();
-
-        (payload, U256::zero())
+        self.encode(&SandwichPayload::V3MultiWethOutput {
+            block_number: block_number.low_u32() as u8,
+            pool: pool.address,
+            token: input,
+            zero_for_one: input < output,
+            is_first,
+            pool_key_hash: pool_key_hash(&pool),
+            amount_in: U256::from(amount_in.as_u128()),
+            amount_out: U256::from(amount_out.as_u128()),
+        })
     }
+}
 
-    // Internal helper function to find correct JUMPDEST
-    fn _find_swap_type(
-        &self,
-        is_multiple: bool,
-        is_weth_input: bool,
-        input: Address,
-        output: Address,
-    ) -> U256 {
-        let swap_type: u32 = if is_multiple {
-            match (is_weth_input, (input < output)) {
-                // weth is input and token0
-                (true, true) => self.jump_labels["v3_input0_multi"],
-                // weth is input and token1
-                (true, false) => self.jump_labels["v3_input1_multi"],
-                // weth is output and token0
-                (false, true) => self.jump_labels["v3_output1_multi"],
-                // weth is output and token1
-                (false, false) => self.jump_labels["v3_output0_multi"],
-            }
-        } else {
-            match (is_weth_input, (input < output)) {
-                // weth is input and token0
-                (true, true) => self.jump_labels["v3_input0"],
-                // weth is input and token1
-                (true, false) => self.jump_labels["v3_input1"],
-                // weth is output and token0
-                (false, true) => self.jump_labels["v3_output1"],
-                // weth is output and token1
-                (false, false) => self.jump_labels["v3_output0"],
-            }
-        };
-
-        U256::from(swap_type)
-    }
+// Hash of the pool key that the contract checks in the swap callback
+fn pool_key_hash(pool: &Pool) -> H256 {
+    H256::from(ethers::utils::keccak256(abi::encode(&[
+        abi::Token::Address(pool.token_0),
+        abi::Token::Address(pool.token_1),
+        abi::Token::Uint(pool.swap_fee),
+    ])))
 }
 
 // Encode the swap value into number of bytes
 //
 // Returns:
 // EncodedSwapValue representing 5 byte value, and byteshift
-fn encode_num_bytes(amount: U256, num_bytes: u8) -> EncodedSwapValue {
+pub(super) fn encode_num_bytes(amount: U256, num_bytes: u8) -> EncodedSwapValue {
     let mut byte_shift = 0;
     let mut encoded_value = U256::zero();
 